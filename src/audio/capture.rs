@@ -2,11 +2,13 @@
 //!
 //! Captures audio from desktop (monitor) or application sources.
 
+use crate::capture::PipeWireConnection;
 use crate::error::{Error, Result};
 use super::types::{AudioFrame, ChannelLayout, SampleFormat};
 
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 
 /// Audio source type
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -21,6 +23,89 @@ pub enum AudioSource {
     DefaultInput,
     /// Default output device (speakers/headphones monitor)
     DefaultOutput,
+    /// Specific device by PipeWire node name (`node.name`) or description
+    Device(String),
+}
+
+impl std::fmt::Display for AudioSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AudioSource::Desktop => write!(f, "desktop"),
+            AudioSource::Application(name) => write!(f, "application '{}'", name),
+            AudioSource::NodeId(id) => write!(f, "node {}", id),
+            AudioSource::DefaultInput => write!(f, "default input"),
+            AudioSource::DefaultOutput => write!(f, "default output"),
+            AudioSource::Device(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Kind of PipeWire audio node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioDeviceKind {
+    /// Capture device (microphone, line in) - `Audio/Source`
+    Input,
+    /// Playback device, captured via its monitor - `Audio/Sink`
+    Output,
+    /// Application playback stream - `Stream/Output/Audio`
+    Application,
+}
+
+impl AudioDeviceKind {
+    fn from_media_class(class: &str) -> Option<Self> {
+        match class {
+            "Audio/Source" | "Audio/Source/Virtual" | "Audio/Duplex" => Some(Self::Input),
+            "Audio/Sink" => Some(Self::Output),
+            "Stream/Output/Audio" => Some(Self::Application),
+            _ => None,
+        }
+    }
+}
+
+/// An audio node available for capture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioDeviceInfo {
+    /// PipeWire node (global) ID
+    pub node_id: u32,
+    /// Stable node name (`node.name`)
+    pub name: String,
+    /// Human-readable description (device or application name)
+    pub description: Option<String>,
+    pub kind: AudioDeviceKind,
+}
+
+impl AudioDeviceInfo {
+    /// Source selecting this device by name (stable across restarts,
+    /// unlike node IDs)
+    pub fn source(&self) -> AudioSource {
+        AudioSource::Device(self.name.clone())
+    }
+
+    fn matches(&self, source: &AudioSource) -> bool {
+        match source {
+            AudioSource::NodeId(id) => self.node_id == *id,
+            AudioSource::Device(name) => {
+                self.name == *name || self.description.as_deref() == Some(name.as_str())
+            }
+            AudioSource::Application(app) => {
+                self.kind == AudioDeviceKind::Application
+                    && self
+                        .description
+                        .as_deref()
+                        .is_some_and(|d| d.eq_ignore_ascii_case(app))
+            }
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for AudioDeviceInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.description {
+            Some(description) => write!(f, "{} ({})", self.name, description),
+            None => write!(f, "{}", self.name),
+        }
+    }
 }
 
 impl Default for AudioSource {
@@ -42,6 +127,10 @@ pub struct AudioCaptureConfig {
     pub format: SampleFormat,
     /// Buffer size in samples (default: 1024)
     pub buffer_size: u32,
+    /// Linear gain applied to captured samples (1.0 = unchanged, 0.0 = mute)
+    pub gain: f32,
+    /// Time PTS are measured from (None = the first buffer is PTS 0)
+    pub epoch: Option<Instant>,
 }
 
 impl Default for AudioCaptureConfig {
@@ -52,6 +141,8 @@ impl Default for AudioCaptureConfig {
             channels: ChannelLayout::Stereo,
             format: SampleFormat::F32,
             buffer_size: 1024,
+            gain: 1.0,
+            epoch: None,
         }
     }
 }
@@ -81,6 +172,10 @@ pub trait AudioCapture: Send + Sync {
 /// PipeWire-based audio capture
 pub struct PipeWireAudioCapture {
     config: AudioCaptureConfig,
+    /// Resolved node for device/application/node-id sources
+    target: Option<AudioDeviceInfo>,
+    /// Live gain (f32 bits), read by the capture thread for every buffer
+    gain: Arc<AtomicU32>,
     running: Arc<AtomicBool>,
     frame_rx: Option<crossbeam_channel::Receiver<AudioFrame>>,
     _thread_handle: Option<std::thread::JoinHandle<()>>,
@@ -88,9 +183,26 @@ pub struct PipeWireAudioCapture {
 
 impl PipeWireAudioCapture {
     /// Create new PipeWire audio capture
+    ///
+    /// Specific sources (device, application, node ID) are resolved against
+    /// the current PipeWire graph; `Error::AudioSourceNotFound` lists what
+    /// is available if the requested one doesn't exist.
     pub fn new(config: AudioCaptureConfig) -> Result<Self> {
+        let target = resolve_source(&config.source)?;
+        if let Some(device) = &target {
+            tracing::info!(
+                "Audio source resolved to node {}: {}",
+                device.node_id,
+                device
+            );
+        }
+
+        let gain = Arc::new(AtomicU32::new(config.gain.to_bits()));
+
         Ok(Self {
             config,
+            target,
+            gain,
             running: Arc::new(AtomicBool::new(false)),
             frame_rx: None,
             _thread_handle: None,
@@ -98,6 +210,18 @@ impl PipeWireAudioCapture {
     }
 }
 
+impl PipeWireAudioCapture {
+    /// Change the source gain while capturing (0.0 mutes without stopping)
+    pub fn set_gain(&self, gain: f32) {
+        self.gain.store(gain.max(0.0).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Current source gain (linear)
+    pub fn gain(&self) -> f32 {
+        f32::from_bits(self.gain.load(Ordering::Relaxed))
+    }
+}
+
 #[async_trait::async_trait]
 impl AudioCapture for PipeWireAudioCapture {
     async fn start(&mut self) -> Result<()> {
@@ -111,11 +235,13 @@ impl AudioCapture for PipeWireAudioCapture {
         self.frame_rx = Some(frame_rx);
 
         let config = self.config.clone();
+        let target = self.target.clone();
+        let gain = self.gain.clone();
         let running = self.running.clone();
 
         // Spawn PipeWire capture thread
         let handle = std::thread::spawn(move || {
-            if let Err(e) = run_pipewire_capture(config, running.clone(), frame_tx) {
+            if let Err(e) = run_pipewire_capture(config, target, gain, running.clone(), frame_tx) {
                 tracing::error!("PipeWire audio capture error: {}", e);
                 running.store(false, Ordering::SeqCst);
             }
@@ -163,31 +289,32 @@ impl AudioCapture for PipeWireAudioCapture {
 /// Run PipeWire capture loop
 fn run_pipewire_capture(
     config: AudioCaptureConfig,
+    target: Option<AudioDeviceInfo>,
+    gain: Arc<AtomicU32>,
     running: Arc<AtomicBool>,
     frame_tx: crossbeam_channel::Sender<AudioFrame>,
 ) -> Result<()> {
     use pipewire as pw;
 
-    // Initialize PipeWire
-    pw::init();
-
-    let mainloop = pw::main_loop::MainLoop::new(None)
-        .map_err(|e| Error::PipeWire(format!("Failed to create mainloop: {}", e)))?;
+    let pipewire = PipeWireConnection::new()?;
+    let (mainloop, core) = (&pipewire.mainloop, &pipewire.core);
 
-    let context = pw::context::Context::new(&mainloop)
-        .map_err(|e| Error::PipeWire(format!("Failed to create context: {}", e)))?;
-
-    let core = context
-        .connect(None)
-        .map_err(|e| Error::PipeWire(format!("Failed to connect: {}", e)))?;
+    // Playback devices are captured through their monitor ports
+    let capture_sink = match &target {
+        Some(device) => device.kind == AudioDeviceKind::Output,
+        None => !matches!(config.source, AudioSource::DefaultInput),
+    };
 
     // Create stream properties
-    let props = pw::properties::properties! {
+    let mut props = pw::properties::properties! {
         *pw::keys::MEDIA_TYPE => "Audio",
         *pw::keys::MEDIA_CATEGORY => "Capture",
         *pw::keys::MEDIA_ROLE => "Music",
-        *pw::keys::STREAM_CAPTURE_SINK => "true",
+        *pw::keys::STREAM_CAPTURE_SINK => if capture_sink { "true" } else { "false" },
     };
+    if let Some(device) = &target {
+        props.insert("target.object", device.name.as_str());
+    }
 
     // Create stream
     let stream = pw::stream::Stream::new(&core, "ghoststream-audio", props)
@@ -197,7 +324,11 @@ fn run_pipewire_capture(
     let config_clone = config.clone();
     let running_clone = running.clone();
     let frame_tx_clone = frame_tx.clone();
-    let mut pts: i64 = 0;
+    // PTS follow the running sample count, so the audio clock stays
+    // regular whatever buffer sizes PipeWire delivers; only the first
+    // buffer's position comes from the wall clock
+    let rate = config.sample_rate.max(1) as i64;
+    let mut next_sample: Option<i64> = None;
 
     let _listener = stream
         .add_local_listener_with_user_data(())
@@ -231,9 +362,15 @@ fn run_pipewire_capture(
                             config_clone.format,
                             config_clone.sample_rate,
                         );
-                        frame.pts = pts;
+                        let position = *next_sample.get_or_insert_with(|| {
+                            config_clone.epoch.map_or(0, |epoch| {
+                                epoch.elapsed().as_micros() as i64 * rate / 1_000_000
+                            })
+                        });
+                        next_sample = Some(position + samples as i64);
+                        frame.pts = position * 1_000_000 / rate;
                         frame.duration = frame.calculated_duration_us();
-                        pts += frame.duration;
+                        frame.apply_gain(f32::from_bits(gain.load(Ordering::Relaxed)));
 
                         let _ = frame_tx_clone.try_send(frame);
                     }
@@ -304,7 +441,12 @@ fn run_pipewire_capture(
         | pw::stream::StreamFlags::RT_PROCESS;
 
     stream
-        .connect(libspa::utils::Direction::Input, None, flags, &mut [pod])
+        .connect(
+            libspa::utils::Direction::Input,
+            target.as_ref().map(|d| d.node_id),
+            flags,
+            &mut [pod],
+        )
         .map_err(|e| Error::PipeWire(format!("Failed to connect stream: {}", e)))?;
 
     tracing::info!("PipeWire audio stream connected");
@@ -324,10 +466,114 @@ fn run_pipewire_capture(
 }
 
 /// List available audio sources
+///
+/// The generic sources first, followed by every device/application node
+/// currently present (empty if PipeWire can't be reached).
 pub fn list_sources() -> Vec<AudioSource> {
-    vec![
+    let mut sources = vec![
         AudioSource::Desktop,
         AudioSource::DefaultInput,
         AudioSource::DefaultOutput,
-    ]
+    ];
+    match list_devices() {
+        Ok(devices) => sources.extend(devices.iter().map(AudioDeviceInfo::source)),
+        Err(e) => tracing::warn!("Failed to enumerate audio devices: {}", e),
+    }
+    sources
+}
+
+/// Enumerate audio nodes in the PipeWire graph
+pub fn list_devices() -> Result<Vec<AudioDeviceInfo>> {
+    use pipewire as pw;
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    let pipewire = PipeWireConnection::new()?;
+    let (mainloop, core) = (&pipewire.mainloop, &pipewire.core);
+
+    let registry = core
+        .get_registry()
+        .map_err(|e| Error::PipeWire(format!("Failed to get registry: {}", e)))?;
+
+    let devices = Rc::new(RefCell::new(Vec::new()));
+    let done = Rc::new(Cell::new(false));
+
+    let devices_clone = devices.clone();
+    let _registry_listener = registry
+        .add_listener_local()
+        .global(move |global| {
+            if global.type_ != pw::types::ObjectType::Node {
+                return;
+            }
+            let Some(props) = global.props else { return };
+            let Some(kind) = props
+                .get(*pw::keys::MEDIA_CLASS)
+                .and_then(AudioDeviceKind::from_media_class)
+            else {
+                return;
+            };
+
+            let description = props
+                .get(*pw::keys::NODE_DESCRIPTION)
+                .or_else(|| props.get(*pw::keys::APP_NAME))
+                .map(String::from);
+
+            devices_clone.borrow_mut().push(AudioDeviceInfo {
+                node_id: global.id,
+                name: props
+                    .get(*pw::keys::NODE_NAME)
+                    .map(String::from)
+                    .unwrap_or_else(|| format!("node-{}", global.id)),
+                description,
+                kind,
+            });
+        })
+        .register();
+
+    // Round-trip so every existing global has been announced
+    let pending = core
+        .sync(0)
+        .map_err(|e| Error::PipeWire(format!("Failed to sync: {}", e)))?;
+
+    let done_clone = done.clone();
+    let _core_listener = core
+        .add_listener_local()
+        .done(move |id, seq| {
+            if id == pw::core::PW_ID_CORE && seq == pending {
+                done_clone.set(true);
+            }
+        })
+        .register();
+
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(2);
+    while !done.get() {
+        if std::time::Instant::now() >= deadline {
+            return Err(Error::Timeout("PipeWire registry enumeration".into()));
+        }
+        mainloop
+            .loop_()
+            .iterate(std::time::Duration::from_millis(10));
+    }
+
+    let devices = devices.borrow().clone();
+    Ok(devices)
+}
+
+/// Resolve a specific source to a node; generic sources resolve to `None`
+fn resolve_source(source: &AudioSource) -> Result<Option<AudioDeviceInfo>> {
+    if matches!(
+        source,
+        AudioSource::Desktop | AudioSource::DefaultInput | AudioSource::DefaultOutput
+    ) {
+        return Ok(None);
+    }
+
+    let devices = list_devices()?;
+    match devices.iter().find(|d| d.matches(source)) {
+        Some(device) => Ok(Some(device.clone())),
+        None => Err(Error::AudioSourceNotFound {
+            requested: source.to_string(),
+            available: devices.iter().map(|d| d.to_string()).collect(),
+        }),
+    }
 }
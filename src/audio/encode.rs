@@ -63,6 +63,140 @@ impl AudioCodec {
     }
 }
 
+/// FFmpeg encoder used for `AudioCodec::Aac`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AacEncoder {
+    /// FFmpeg's built-in `aac`: always available, AAC-LC only
+    #[default]
+    Native,
+    /// Fraunhofer `libfdk_aac`: better at low bitrates and the only one
+    /// with the HE-AAC and ELD profiles; needs an FFmpeg built with it
+    LibFdk,
+}
+
+impl AacEncoder {
+    /// FFmpeg encoder name
+    pub fn encoder_name(&self) -> &'static str {
+        match self {
+            AacEncoder::Native => "aac",
+            AacEncoder::LibFdk => "libfdk_aac",
+        }
+    }
+}
+
+/// AAC profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AacProfile {
+    /// Low Complexity, the common profile for 96 kbps and up
+    #[default]
+    Lc,
+    /// HE-AAC (SBR): much better below ~64 kbps; libfdk_aac only
+    HeAac,
+    /// HE-AAC v2 (SBR + parametric stereo): for ~32 kbps stereo;
+    /// libfdk_aac only
+    HeAacV2,
+    /// Enhanced Low Delay; libfdk_aac only (see
+    /// `AudioEncoderConfig::low_latency`)
+    Eld,
+}
+
+impl AacProfile {
+    /// FFmpeg `profile` option value
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AacProfile::Lc => "aac_low",
+            AacProfile::HeAac => "aac_he",
+            AacProfile::HeAacV2 => "aac_he_v2",
+            AacProfile::Eld => "aac_eld",
+        }
+    }
+
+    /// Display name
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            AacProfile::Lc => "AAC-LC",
+            AacProfile::HeAac => "HE-AAC",
+            AacProfile::HeAacV2 => "HE-AACv2",
+            AacProfile::Eld => "AAC-ELD",
+        }
+    }
+}
+
+/// Opus `application` mode: what the encoder optimizes for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OpusApplication {
+    /// Speech intelligibility (voice chat)
+    Voip,
+    /// Faithful music and general audio
+    #[default]
+    Audio,
+    /// Lowest algorithmic delay, disables speech-optimized modes
+    LowDelay,
+}
+
+impl OpusApplication {
+    /// libopus `application` option value
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OpusApplication::Voip => "voip",
+            OpusApplication::Audio => "audio",
+            OpusApplication::LowDelay => "lowdelay",
+        }
+    }
+}
+
+/// libopus encoder options (ignored for other codecs)
+///
+/// The default matches libopus and suits recording: `Audio` mode without
+/// FEC. For streaming over lossy links use `streaming()`, for voice chat
+/// `voice()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OpusOptions {
+    pub application: OpusApplication,
+    /// In-band forward error correction; only used when `packet_loss` is
+    /// above zero
+    pub fec: bool,
+    /// Discontinuous transmission: send almost nothing during silence
+    pub dtx: bool,
+    /// Expected packet loss in percent (0-100), sizes the FEC data
+    pub packet_loss: u8,
+}
+
+impl OpusOptions {
+    /// Music/game audio over a network: FEC for 5% loss
+    pub fn streaming() -> Self {
+        Self {
+            application: OpusApplication::Audio,
+            fec: true,
+            dtx: false,
+            packet_loss: 5,
+        }
+    }
+
+    /// Voice chat: VOIP mode, FEC for 10% loss, DTX during silence
+    pub fn voice() -> Self {
+        Self {
+            application: OpusApplication::Voip,
+            fec: true,
+            dtx: true,
+            packet_loss: 10,
+        }
+    }
+
+    /// libopus private options
+    fn to_dictionary(self) -> ffmpeg::Dictionary<'static> {
+        let mut opts = ffmpeg::Dictionary::new();
+        opts.set("application", self.application.as_str());
+        opts.set("fec", if self.fec { "1" } else { "0" });
+        opts.set("dtx", if self.dtx { "1" } else { "0" });
+        opts.set("packet_loss", &self.packet_loss.min(100).to_string());
+        opts
+    }
+}
+
+/// Frame durations libopus accepts (ms)
+const OPUS_FRAME_DURATIONS: [f32; 6] = [2.5, 5.0, 10.0, 20.0, 40.0, 60.0];
+
 /// Audio encoder configuration
 #[derive(Debug, Clone)]
 pub struct AudioEncoderConfig {
@@ -76,6 +210,24 @@ pub struct AudioEncoderConfig {
     pub bitrate: u32,
     /// Input sample format
     pub input_format: SampleFormat,
+    /// Opus-specific options (only used with `AudioCodec::Opus`)
+    pub opus: OpusOptions,
+    /// Minimize encoder delay for interactive use: Opus switches to 10ms
+    /// frames and `LowDelay` mode (2.5ms instead of 6.5ms lookahead), AAC
+    /// to the AAC-ELD profile when FFmpeg has libfdk_aac (plain AAC-LC
+    /// otherwise). Costs some quality per bit.
+    pub low_latency: bool,
+    /// Opus frame duration in ms (2.5, 5, 10, 20, 40 or 60; None = 20, or
+    /// 10 in low-latency mode). Shorter frames cut latency and raise
+    /// overhead. Ignored by the other codecs, whose frame size is fixed;
+    /// other values fail `init` (see `check_frame_duration`).
+    pub frame_duration_ms: Option<f32>,
+    /// Encoder for `AudioCodec::Aac`; falls back to the native encoder
+    /// when FFmpeg lacks libfdk_aac
+    pub aac_encoder: AacEncoder,
+    /// AAC profile; the profiles other than LC select libfdk_aac and fall
+    /// back to AAC-LC without it. Overridden by `low_latency`.
+    pub aac_profile: AacProfile,
 }
 
 impl Default for AudioEncoderConfig {
@@ -86,6 +238,11 @@ impl Default for AudioEncoderConfig {
             channels: ChannelLayout::Stereo,
             bitrate: 192_000,
             input_format: SampleFormat::F32,
+            opus: OpusOptions::default(),
+            low_latency: false,
+            frame_duration_ms: None,
+            aac_encoder: AacEncoder::Native,
+            aac_profile: AacProfile::Lc,
         }
     }
 }
@@ -113,6 +270,78 @@ impl AudioEncoderConfig {
         self.bitrate = bitrate;
         self
     }
+
+    pub fn with_opus_options(mut self, opus: OpusOptions) -> Self {
+        self.opus = opus;
+        self
+    }
+
+    /// Trade quality for encoder delay (see `low_latency`)
+    pub fn with_low_latency(mut self, enabled: bool) -> Self {
+        self.low_latency = enabled;
+        self
+    }
+
+    /// Set the Opus frame duration in ms
+    pub fn with_frame_duration_ms(mut self, ms: f32) -> Self {
+        self.frame_duration_ms = Some(ms);
+        self
+    }
+
+    /// Select the AAC encoder
+    pub fn with_aac_encoder(mut self, encoder: AacEncoder) -> Self {
+        self.aac_encoder = encoder;
+        self
+    }
+
+    /// Select the AAC profile (e.g. `AacProfile::HeAac` for low-bitrate
+    /// streams)
+    pub fn with_aac_profile(mut self, profile: AacProfile) -> Self {
+        self.aac_profile = profile;
+        self
+    }
+
+    /// Reject an Opus frame duration libopus doesn't support
+    pub fn check_frame_duration(&self) -> Result<()> {
+        match self.frame_duration_ms {
+            Some(ms) if self.codec == AudioCodec::Opus && !OPUS_FRAME_DURATIONS.contains(&ms) => {
+                Err(Error::AudioEncoder(format!(
+                    "Opus frame duration must be one of {:?} ms, got {}",
+                    OPUS_FRAME_DURATIONS, ms
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// AAC encoder and profile to ask FFmpeg for
+    fn aac_selection(&self) -> (AacEncoder, AacProfile) {
+        let profile = if self.low_latency {
+            AacProfile::Eld
+        } else {
+            self.aac_profile
+        };
+        let encoder = if profile == AacProfile::Lc {
+            self.aac_encoder
+        } else {
+            AacEncoder::LibFdk
+        };
+        (encoder, profile)
+    }
+
+    /// Opus options including the frame duration and low-latency overrides
+    fn opus_dictionary(&self) -> ffmpeg::Dictionary<'static> {
+        let mut opus = self.opus;
+        if self.low_latency {
+            opus.application = OpusApplication::LowDelay;
+        }
+        let mut opts = opus.to_dictionary();
+        let frame_ms = self.frame_duration_ms.or(self.low_latency.then_some(10.0));
+        if let Some(ms) = frame_ms {
+            opts.set("frame_duration", &ms.to_string());
+        }
+        opts
+    }
 }
 
 /// Trait for audio encoders
@@ -121,7 +350,10 @@ pub trait AudioEncoder: Send {
     fn init(&mut self) -> Result<()>;
 
     /// Encode audio frame
-    fn encode(&mut self, frame: &AudioFrame) -> Result<Option<AudioPacket>>;
+    ///
+    /// Returns every packet the encoder has ready, which may be none (input
+    /// buffered until a full codec frame is available) or several.
+    fn encode(&mut self, frame: &AudioFrame) -> Result<Vec<AudioPacket>>;
 
     /// Flush remaining frames
     fn flush(&mut self) -> Result<Vec<AudioPacket>>;
@@ -171,10 +403,32 @@ impl AudioEncoder for FfmpegAudioEncoder {
             return Ok(());
         }
 
+        self.config.check_frame_duration()?;
         ffmpeg::init().map_err(|e| Error::Ffmpeg(format!("FFmpeg init failed: {}", e)))?;
 
-        // Find encoder
-        let codec = ffmpeg::encoder::find_by_name(self.config.codec.encoder_name())
+        // Find encoder; libfdk_aac by name, falling back to the native
+        // AAC-LC encoder when FFmpeg was built without it
+        let aac = (self.config.codec == AudioCodec::Aac).then(|| self.config.aac_selection());
+        let fdk = match aac {
+            Some((AacEncoder::LibFdk, profile)) => {
+                let fdk = ffmpeg::encoder::find_by_name(AacEncoder::LibFdk.encoder_name());
+                if fdk.is_none() {
+                    tracing::warn!(
+                        "{} needs FFmpeg with libfdk_aac, using the native AAC-LC encoder",
+                        profile.display_name()
+                    );
+                }
+                fdk
+            }
+            _ => None,
+        };
+        let aac_profile = match aac {
+            Some((_, profile)) if fdk.is_some() => profile,
+            Some(_) => AacProfile::Lc,
+            None => AacProfile::default(),
+        };
+        let codec = fdk
+            .or_else(|| ffmpeg::encoder::find_by_name(self.config.codec.encoder_name()))
             .or_else(|| ffmpeg::encoder::find(self.config.codec.codec_id()))
             .ok_or_else(|| Error::CodecNotSupported(
                 format!("Audio encoder {} not found", self.config.codec.encoder_name())
@@ -205,9 +459,17 @@ impl AudioEncoder for FfmpegAudioEncoder {
         }
 
         // Open encoder
-        let encoder = encoder
-            .open()
-            .map_err(|e| Error::Ffmpeg(format!("Failed to open audio encoder: {}", e)))?;
+        let opened = if self.config.codec == AudioCodec::Opus {
+            encoder.open_with(self.config.opus_dictionary())
+        } else if aac_profile != AacProfile::Lc {
+            let mut opts = ffmpeg::Dictionary::new();
+            opts.set("profile", aac_profile.as_str());
+            encoder.open_with(opts)
+        } else {
+            encoder.open()
+        };
+        let encoder =
+            opened.map_err(|e| Error::Ffmpeg(format!("Failed to open audio encoder: {}", e)))?;
 
         // Get frame size from encoder
         self.frame_size = unsafe { (*encoder.as_ptr()).frame_size as u32 };
@@ -216,8 +478,13 @@ impl AudioEncoder for FfmpegAudioEncoder {
         }
 
         tracing::info!(
-            "Audio encoder initialized: {} @ {}Hz, {} channels, {} kbps, frame_size={}",
-            self.config.codec.display_name(),
+            "Audio encoder initialized: {} ({}) @ {}Hz, {} channels, {} kbps, frame_size={}",
+            if aac.is_some() {
+                aac_profile.display_name()
+            } else {
+                self.config.codec.display_name()
+            },
+            codec.name(),
             self.config.sample_rate,
             self.config.channels.channels(),
             self.config.bitrate / 1000,
@@ -229,7 +496,7 @@ impl AudioEncoder for FfmpegAudioEncoder {
         Ok(())
     }
 
-    fn encode(&mut self, frame: &AudioFrame) -> Result<Option<AudioPacket>> {
+    fn encode(&mut self, frame: &AudioFrame) -> Result<Vec<AudioPacket>> {
         if !self.initialized {
             self.init()?;
         }
@@ -243,9 +510,13 @@ impl AudioEncoder for FfmpegAudioEncoder {
             ffmpeg::channel_layout::ChannelLayout::STEREO,
         );
 
+        // Frame PTS (microseconds) back to samples; frames without
+        // timestamps just continue from the previous one
+        let rate = self.config.sample_rate as i64;
+        let pts = ((frame.pts * rate + 500_000) / 1_000_000).max(self.pts);
         ff_frame.set_rate(self.config.sample_rate);
-        ff_frame.set_pts(Some(self.pts));
-        self.pts += frame.samples as i64;
+        ff_frame.set_pts(Some(pts));
+        self.pts = pts + frame.samples as i64;
 
         // Copy audio data - basic conversion
         let plane = ff_frame.data_mut(0);
@@ -257,24 +528,29 @@ impl AudioEncoder for FfmpegAudioEncoder {
             .send_frame(&ff_frame)
             .map_err(|e| Error::Ffmpeg(format!("Send frame failed: {}", e)))?;
 
-        // Receive encoded packet
+        // Drain every packet the encoder has ready
+        let mut packets = Vec::new();
         let mut packet = ffmpeg::Packet::empty();
-        match encoder.receive_packet(&mut packet) {
-            Ok(()) => {
-                self.stats.frames_encoded += 1;
-                self.stats.bytes_output += packet.size() as u64;
-
-                let audio_packet = AudioPacket {
-                    data: packet.data().unwrap_or(&[]).to_vec(),
-                    pts: packet.pts().unwrap_or(0),
-                    dts: packet.dts().unwrap_or(0),
-                    duration: packet.duration(),
-                };
-                Ok(Some(audio_packet))
+        loop {
+            match encoder.receive_packet(&mut packet) {
+                Ok(()) => {
+                    self.stats.frames_encoded += 1;
+                    self.stats.bytes_output += packet.size() as u64;
+
+                    packets.push(AudioPacket {
+                        data: packet.data().unwrap_or(&[]).to_vec(),
+                        pts: packet.pts().unwrap_or(0),
+                        dts: packet.dts().unwrap_or(0),
+                        duration: packet.duration(),
+                    });
+                }
+                Err(ffmpeg::Error::Other { errno }) if errno == ffmpeg::error::EAGAIN => break,
+                Err(ffmpeg::Error::Eof) => break,
+                Err(e) => return Err(Error::Ffmpeg(format!("Receive packet failed: {}", e))),
             }
-            Err(ffmpeg::Error::Other { errno }) if errno == ffmpeg::error::EAGAIN => Ok(None),
-            Err(e) => Err(Error::Ffmpeg(format!("Receive packet failed: {}", e))),
         }
+
+        Ok(packets)
     }
 
     fn flush(&mut self) -> Result<Vec<AudioPacket>> {
@@ -8,12 +8,18 @@ mod capture;
 mod encode;
 mod types;
 
-pub use capture::{AudioCapture, AudioCaptureConfig, AudioSource, PipeWireAudioCapture};
+pub use capture::{
+    AudioCapture, AudioCaptureConfig, AudioDeviceInfo, AudioDeviceKind, AudioSource,
+    PipeWireAudioCapture,
+};
 pub use encode::{
-    available_codecs, is_codec_available, AudioCodec, AudioEncoder, AudioEncoderConfig,
-    FfmpegAudioEncoder,
+    available_codecs, is_codec_available, AacEncoder, AacProfile, AudioCodec, AudioEncoder,
+    AudioEncoderConfig, FfmpegAudioEncoder, OpusApplication, OpusOptions,
+};
+pub use types::{
+    db_to_linear, linear_to_db, AudioFrame, AudioLevels, AudioPacket, AudioParams, ChannelLayout,
+    SampleFormat,
 };
-pub use types::{AudioFrame, AudioPacket, AudioParams, ChannelLayout, SampleFormat};
 
 use crate::error::Result;
 
@@ -43,3 +49,8 @@ pub fn is_audio_available() -> bool {
 pub fn list_audio_sources() -> Vec<AudioSource> {
     capture::list_sources()
 }
+
+/// Get audio devices and application streams with their PipeWire identity
+pub fn list_audio_devices() -> Result<Vec<AudioDeviceInfo>> {
+    capture::list_devices()
+}
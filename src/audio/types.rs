@@ -142,6 +142,113 @@ impl AudioFrame {
     pub fn size_bytes(&self) -> usize {
         self.data.len()
     }
+
+    /// Scale all samples by a linear gain (0.0 = mute), clipping integer formats
+    pub fn apply_gain(&mut self, gain: f32) {
+        if gain == 1.0 {
+            return;
+        }
+        match self.format {
+            SampleFormat::F32 | SampleFormat::F32P => {
+                for chunk in self.data.chunks_exact_mut(4) {
+                    let sample = f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                    chunk.copy_from_slice(&(sample * gain).to_le_bytes());
+                }
+            }
+            SampleFormat::S16 | SampleFormat::S16P => {
+                for chunk in self.data.chunks_exact_mut(2) {
+                    let sample = i16::from_le_bytes([chunk[0], chunk[1]]) as f32 * gain;
+                    let clipped = sample.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+                    chunk.copy_from_slice(&clipped.to_le_bytes());
+                }
+            }
+            SampleFormat::S32 => {
+                for chunk in self.data.chunks_exact_mut(4) {
+                    let sample = i32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                    let scaled = (sample as f64 * gain as f64).round();
+                    let clipped = scaled.clamp(i32::MIN as f64, i32::MAX as f64) as i32;
+                    chunk.copy_from_slice(&clipped.to_le_bytes());
+                }
+            }
+        }
+    }
+
+    /// Peak and RMS level across all channels
+    pub fn levels(&self) -> AudioLevels {
+        let mut peak = 0.0f32;
+        let mut sum_squares = 0.0f64;
+        let mut count = 0usize;
+
+        let mut accumulate = |sample: f32| {
+            let magnitude = sample.abs();
+            peak = peak.max(magnitude);
+            sum_squares += (sample as f64) * (sample as f64);
+            count += 1;
+        };
+
+        match self.format {
+            SampleFormat::F32 | SampleFormat::F32P => {
+                for chunk in self.data.chunks_exact(4) {
+                    accumulate(f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
+                }
+            }
+            SampleFormat::S16 | SampleFormat::S16P => {
+                for chunk in self.data.chunks_exact(2) {
+                    accumulate(i16::from_le_bytes([chunk[0], chunk[1]]) as f32 / 32768.0);
+                }
+            }
+            SampleFormat::S32 => {
+                for chunk in self.data.chunks_exact(4) {
+                    let sample = i32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                    accumulate((sample as f64 / 2_147_483_648.0) as f32);
+                }
+            }
+        }
+
+        AudioLevels {
+            peak,
+            rms: if count > 0 {
+                (sum_squares / count as f64).sqrt() as f32
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+/// Audio level meter reading (linear, 1.0 = full scale)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct AudioLevels {
+    /// Highest absolute sample value
+    pub peak: f32,
+    /// Root-mean-square level
+    pub rms: f32,
+}
+
+impl AudioLevels {
+    /// Peak level in dBFS
+    pub fn peak_db(&self) -> f32 {
+        linear_to_db(self.peak)
+    }
+
+    /// RMS level in dBFS
+    pub fn rms_db(&self) -> f32 {
+        linear_to_db(self.rms)
+    }
+}
+
+/// Convert decibels to a linear gain factor
+pub fn db_to_linear(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Convert a linear gain/level to decibels (`-inf` for silence)
+pub fn linear_to_db(linear: f32) -> f32 {
+    if linear <= 0.0 {
+        f32::NEG_INFINITY
+    } else {
+        20.0 * linear.log10()
+    }
 }
 
 /// Audio codec parameters for muxing
@@ -201,3 +308,28 @@ impl AudioPacket {
         self.data.len()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gain_and_levels() {
+        let samples: Vec<u8> = [0.5f32, -0.25, 0.5, -0.25]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let mut frame = AudioFrame::from_data(samples, 2, 2, SampleFormat::F32, 48000);
+
+        let levels = frame.levels();
+        assert_eq!(levels.peak, 0.5);
+        assert!((levels.peak_db() + 6.02).abs() < 0.01);
+
+        frame.apply_gain(db_to_linear(6.0206));
+        assert!((frame.levels().peak - 1.0).abs() < 1e-3);
+
+        frame.apply_gain(0.0);
+        assert_eq!(frame.levels(), AudioLevels::default());
+        assert_eq!(AudioLevels::default().rms_db(), f32::NEG_INFINITY);
+    }
+}
@@ -12,14 +12,21 @@ use crate::config::CaptureConfig;
 use crate::error::{Error, Result};
 use crate::types::{Frame, FrameFormat, Framerate, Resolution};
 
+use super::portal::{buffer_datas, meta_param, read_header_pts, wall_clock_us, BufferClock};
+use super::PipeWireConnection;
+
 use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 /// DMA-BUF buffer information
+///
+/// Multi-planar formats (NV12, P010) come either as one fd with a plane
+/// per offset or as a separate fd per plane; `fds`, `offsets` and
+/// `strides` describe each plane either way.
 #[derive(Debug, Clone)]
 pub struct DmaBufInfo {
-    /// File descriptor for the buffer
+    /// File descriptor of the first plane
     pub fd: RawFd,
     /// Buffer width
     pub width: u32,
@@ -33,6 +40,8 @@ pub struct DmaBufInfo {
     pub modifier: u64,
     /// Number of planes
     pub num_planes: u32,
+    /// Plane file descriptors (-1 past `num_planes`; planes may share one)
+    pub fds: [RawFd; 4],
     /// Plane offsets
     pub offsets: [u32; 4],
     /// Plane strides
@@ -45,6 +54,91 @@ impl DmaBufInfo {
         self.modifier == 0 || self.modifier == DRM_FORMAT_MOD_LINEAR
     }
 
+    /// Planes the DRM format consists of
+    pub fn expected_planes(&self) -> u32 {
+        match self.format {
+            DRM_FORMAT_NV12 | DRM_FORMAT_P010 => 2,
+            _ => 1,
+        }
+    }
+
+    /// Fill in planes a linear single-fd buffer leaves implicit: the
+    /// chroma plane of NV12/P010 directly follows the luma rows
+    ///
+    /// Fails when planes are still missing, e.g. on a tiled buffer whose
+    /// layout can't be derived.
+    pub fn complete_planes(&mut self) -> Result<()> {
+        let expected = self.expected_planes();
+        if self.num_planes == 1 && expected == 2 && self.is_linear() && self.height > 0 {
+            self.fds[1] = self.fds[0];
+            self.offsets[1] = self.offsets[0] + self.strides[0] * self.height;
+            self.strides[1] = self.strides[0];
+            self.num_planes = 2;
+        }
+        if self.num_planes < expected {
+            return Err(Error::Pipeline(format!(
+                "DMA-BUF has {} of {} planes",
+                self.num_planes, expected
+            )));
+        }
+        Ok(())
+    }
+
+    /// Attribute list for `eglCreateImage(..., EGL_LINUX_DMA_BUF_EXT, ...)`
+    /// (EGL_EXT_image_dma_buf_import), covering every plane and the
+    /// modifier, terminated by `EGL_NONE`
+    pub fn egl_attributes(&self) -> Vec<isize> {
+        // EGL_DMA_BUF_PLANE{0,1,2,3}_{FD,OFFSET,PITCH}_EXT
+        const PLANE_ATTRS: [[isize; 3]; 4] = [
+            [0x3272, 0x3273, 0x3274],
+            [0x3275, 0x3276, 0x3277],
+            [0x3278, 0x3279, 0x327A],
+            [0x3440, 0x3441, 0x3442],
+        ];
+        // EGL_DMA_BUF_PLANE{0,1,2,3}_MODIFIER_{LO,HI}_EXT
+        const MODIFIER_ATTRS: [[isize; 2]; 4] = [
+            [0x3443, 0x3444],
+            [0x3445, 0x3446],
+            [0x3447, 0x3448],
+            [0x3449, 0x344A],
+        ];
+        const EGL_WIDTH: isize = 0x3057;
+        const EGL_HEIGHT: isize = 0x3056;
+        const EGL_LINUX_DRM_FOURCC_EXT: isize = 0x3271;
+        const EGL_NONE: isize = 0x3038;
+
+        let mut attrs = vec![
+            EGL_WIDTH,
+            self.width as isize,
+            EGL_HEIGHT,
+            self.height as isize,
+            EGL_LINUX_DRM_FOURCC_EXT,
+            self.format as isize,
+        ];
+        for plane in 0..(self.num_planes as usize).min(4) {
+            let [fd, offset, pitch] = PLANE_ATTRS[plane];
+            attrs.extend([
+                fd,
+                self.fds[plane] as isize,
+                offset,
+                self.offsets[plane] as isize,
+                pitch,
+                self.strides[plane] as isize,
+            ]);
+            if self.modifier != DRM_FORMAT_MOD_INVALID {
+                let [lo, hi] = MODIFIER_ATTRS[plane];
+                attrs.extend([
+                    lo,
+                    (self.modifier & 0xffff_ffff) as isize,
+                    hi,
+                    (self.modifier >> 32) as isize,
+                ]);
+            }
+        }
+        attrs.push(EGL_NONE);
+        attrs
+    }
+
     /// Get the equivalent FrameFormat
     pub fn frame_format(&self) -> Option<FrameFormat> {
         match self.format {
@@ -59,6 +153,7 @@ impl DmaBufInfo {
 
 // DRM format constants
 const DRM_FORMAT_MOD_LINEAR: u64 = 0;
+const DRM_FORMAT_MOD_INVALID: u64 = 0x00ff_ffff_ffff_ffff;
 const DRM_FORMAT_NV12: u32 = fourcc(b"NV12");
 const DRM_FORMAT_P010: u32 = fourcc(b"P010");
 const DRM_FORMAT_ARGB8888: u32 = fourcc(b"AR24");
@@ -70,12 +165,13 @@ const fn fourcc(code: &[u8; 4]) -> u32 {
     (code[0] as u32) | ((code[1] as u32) << 8) | ((code[2] as u32) << 16) | ((code[3] as u32) << 24)
 }
 
-/// DMA-BUF frame with owned file descriptor
+/// DMA-BUF frame with owned file descriptors
 pub struct DmaBufFrame {
     /// Buffer information
     pub info: DmaBufInfo,
-    /// Owned file descriptor (closes on drop)
-    fd: Option<OwnedFd>,
+    /// Owned plane descriptors, one per distinct fd in `info.fds` (close
+    /// on drop)
+    fds: Vec<OwnedFd>,
     /// Presentation timestamp
     pub pts: i64,
     /// Duration
@@ -83,25 +179,31 @@ pub struct DmaBufFrame {
 }
 
 impl DmaBufFrame {
-    /// Create a new DMA-BUF frame
+    /// Create a new DMA-BUF frame, taking ownership of the plane
+    /// descriptors in `info`
     pub fn new(info: DmaBufInfo, pts: i64) -> Self {
-        let fd = unsafe { Some(OwnedFd::from_raw_fd(info.fd)) };
+        let mut fds: Vec<OwnedFd> = Vec::new();
+        for &fd in &info.fds[..(info.num_planes as usize).min(4)] {
+            if fd >= 0 && !fds.iter().any(|owned| owned.as_raw_fd() == fd) {
+                fds.push(unsafe { OwnedFd::from_raw_fd(fd) });
+            }
+        }
         Self {
             info,
-            fd,
+            fds,
             pts,
             duration: 0,
         }
     }
 
-    /// Get the raw file descriptor
+    /// Get the raw file descriptor of the first plane
     pub fn fd(&self) -> RawFd {
-        self.fd.as_ref().map(|f| f.as_raw_fd()).unwrap_or(-1)
+        self.fds.first().map(|f| f.as_raw_fd()).unwrap_or(-1)
     }
 
-    /// Take ownership of the file descriptor
-    pub fn take_fd(&mut self) -> Option<OwnedFd> {
-        self.fd.take()
+    /// Take ownership of the plane descriptors (one per distinct fd)
+    pub fn take_fds(&mut self) -> Vec<OwnedFd> {
+        std::mem::take(&mut self.fds)
     }
 
     /// Convert to a Frame (zero-copy reference)
@@ -116,6 +218,10 @@ impl DmaBufFrame {
             duration: self.duration,
             is_keyframe: false,
             dmabuf_fd: Some(self.fd()),
+            cursor: None,
+            color_space: None,
+            color_range: None,
+            primaries: None,
         }
     }
 }
@@ -246,6 +352,101 @@ impl super::Capture for DmaBufCapture {
     }
 }
 
+/// Stream state of the DMA-BUF capture loop
+struct DmaBufState {
+    format: pipewire::spa::param::video::VideoInfoRaw,
+    clock: BufferClock,
+}
+
+/// DRM fourcc for a negotiated PipeWire video format
+fn drm_format(format: pipewire::spa::param::video::VideoFormat) -> Option<u32> {
+    use pipewire::spa::param::video::VideoFormat;
+    match format {
+        VideoFormat::NV12 => Some(DRM_FORMAT_NV12),
+        VideoFormat::BGRx => Some(DRM_FORMAT_XRGB8888),
+        VideoFormat::BGRA => Some(DRM_FORMAT_ARGB8888),
+        VideoFormat::RGBx => Some(DRM_FORMAT_XBGR8888),
+        VideoFormat::RGBA => Some(DRM_FORMAT_ABGR8888),
+        _ => None,
+    }
+}
+
+/// Describe a dequeued buffer's planes, duplicating their descriptors
+///
+/// Compositors hand out multi-planar buffers either as one fd per plane or
+/// as several planes sharing an fd at different offsets. PipeWire keeps
+/// ownership of its descriptors and reuses the buffer, so every distinct fd
+/// is duplicated for the frame to own. Returns None for buffers that aren't
+/// DMA-BUFs or whose format or planes can't be described.
+fn dmabuf_info(
+    format: &pipewire::spa::param::video::VideoInfoRaw,
+    datas: &mut [pipewire::spa::buffer::Data],
+) -> Option<DmaBufInfo> {
+    let drm = drm_format(format.format())?;
+    let size = format.size();
+
+    let mut info = DmaBufInfo {
+        fd: -1,
+        width: size.width,
+        height: size.height,
+        stride: 0,
+        format: drm,
+        modifier: format.modifier(),
+        num_planes: 0,
+        fds: [-1; 4],
+        offsets: [0; 4],
+        strides: [0; 4],
+    };
+    // PipeWire fd -> our duplicate
+    let mut duplicated: Vec<(i64, RawFd)> = Vec::new();
+    let close_all = |duplicated: &[(i64, RawFd)]| {
+        for &(_, fd) in duplicated {
+            unsafe { libc::close(fd) };
+        }
+    };
+
+    for data in datas.iter_mut().take(4) {
+        let source = data.as_raw().fd;
+        if data.type_() != pipewire::spa::buffer::DataType::DmaBuf || source < 0 {
+            break;
+        }
+        let fd = match duplicated.iter().find(|(fd, _)| *fd == source) {
+            Some(&(_, fd)) => fd,
+            None => {
+                let fd = unsafe { libc::fcntl(source as RawFd, libc::F_DUPFD_CLOEXEC, 0) };
+                if fd < 0 {
+                    tracing::warn!(
+                        "Failed to duplicate DMA-BUF fd: {}",
+                        std::io::Error::last_os_error()
+                    );
+                    close_all(&duplicated);
+                    return None;
+                }
+                duplicated.push((source, fd));
+                fd
+            }
+        };
+        let chunk = data.chunk();
+        let plane = info.num_planes as usize;
+        info.fds[plane] = fd;
+        info.offsets[plane] = chunk.offset();
+        info.strides[plane] = chunk.stride().max(0) as u32;
+        info.num_planes += 1;
+    }
+    if info.num_planes == 0 {
+        return None;
+    }
+
+    info.fd = info.fds[0];
+    info.stride = info.strides[0];
+    if let Err(e) = info.complete_planes() {
+        tracing::warn!("Skipping DMA-BUF frame: {}", e);
+        close_all(&duplicated);
+        return None;
+    }
+    Some(info)
+}
+
 /// Run PipeWire DMA-BUF capture loop
 fn run_dmabuf_capture(
     config: CaptureConfig,
@@ -254,81 +455,110 @@ fn run_dmabuf_capture(
 ) -> Result<()> {
     use pipewire as pw;
 
-    // Initialize PipeWire
-    pw::init();
-
-    let mainloop = pw::main_loop::MainLoop::new(None)
-        .map_err(|e| Error::PipeWire(format!("Failed to create mainloop: {}", e)))?;
-
-    let context = pw::context::Context::new(&mainloop)
-        .map_err(|e| Error::PipeWire(format!("Failed to create context: {}", e)))?;
-
-    let core = context
-        .connect(None)
-        .map_err(|e| Error::PipeWire(format!("Failed to connect: {}", e)))?;
+    let pipewire = PipeWireConnection::new()?;
+    let (mainloop, core) = (&pipewire.mainloop, &pipewire.core);
 
     // Create stream properties requesting DMA-BUF
+    let (stream_name, app_name) = config.stream_identity("ghoststream-dmabuf");
     let props = pw::properties::properties! {
         *pw::keys::MEDIA_TYPE => "Video",
         *pw::keys::MEDIA_CATEGORY => "Capture",
         *pw::keys::MEDIA_ROLE => "Screen",
+        *pw::keys::NODE_NAME => stream_name.as_str(),
+        *pw::keys::APP_NAME => app_name.as_str(),
     };
 
     // Create stream
-    let stream = pw::stream::Stream::new(&core, "ghoststream-dmabuf", props)
+    let stream = pw::stream::Stream::new(&core, &stream_name, props)
         .map_err(|e| Error::PipeWire(format!("Failed to create stream: {}", e)))?;
 
     // Set up stream listener for DMA-BUF buffers
     let running_clone = running.clone();
     let frame_tx_clone = frame_tx.clone();
-    let mut frame_count: u64 = 0;
     let frame_duration = config.framerate.frame_duration_us();
+    let state = DmaBufState {
+        format: Default::default(),
+        clock: BufferClock::new(),
+    };
 
     let _listener = stream
-        .add_local_listener_with_user_data(())
-        .process(move |stream, _| {
-            if !running_clone.load(Ordering::SeqCst) {
+        .add_local_listener_with_user_data(state)
+        .param_changed(|stream, state, id, param| {
+            let Some(param) = param else { return };
+            if id != pw::spa::param::ParamType::Format.as_raw() {
                 return;
             }
-
-            if let Some(mut buffer) = stream.dequeue_buffer() {
-                let datas = buffer.datas_mut();
-                if datas.is_empty() {
-                    return;
+            let (media_type, media_subtype) =
+                match pw::spa::param::format_utils::parse_format(param) {
+                    Ok(v) => v,
+                    Err(_) => return,
+                };
+            if media_type != pw::spa::param::format::MediaType::Video
+                || media_subtype != pw::spa::param::format::MediaSubtype::Raw
+            {
+                return;
+            }
+            if let Err(e) = state.format.parse(param) {
+                tracing::warn!("Failed to parse DMA-BUF video format: {:?}", e);
+                return;
+            }
+            tracing::info!(
+                "DMA-BUF format negotiated: {:?} {}x{}, modifier {:#x}",
+                state.format.format(),
+                state.format.size().width,
+                state.format.size().height,
+                state.format.modifier()
+            );
+
+            // Ask the producer to timestamp each buffer
+            let header_size = std::mem::size_of::<pw::spa::sys::spa_meta_header>() as i32;
+            match meta_param(pw::spa::sys::SPA_META_Header, header_size, header_size) {
+                Ok(bytes) => {
+                    if let Some(pod) = pw::spa::pod::Pod::from_bytes(&bytes) {
+                        if let Err(e) = stream.update_params(&mut [pod]) {
+                            tracing::warn!("Failed to request buffer metadata: {:?}", e);
+                        }
+                    }
                 }
+                Err(e) => tracing::warn!("{}", e),
+            }
+        })
+        .process(move |stream, state| {
+            if !running_clone.load(Ordering::SeqCst) {
+                return;
+            }
 
-                let data = &mut datas[0];
-
-                // Check for DMA-BUF
-                let raw_fd = data.as_raw().fd;
-                if raw_fd >= 0 {
-                    let fd = raw_fd as i32;
-                    // This is a DMA-BUF!
-                    let chunk = data.chunk();
-
-                    let info = DmaBufInfo {
-                        fd,
-                        width: chunk.size() as u32, // Will be set properly from format
-                        height: 0,
-                        stride: chunk.stride() as u32,
-                        format: DRM_FORMAT_NV12, // Default, should come from negotiation
-                        modifier: DRM_FORMAT_MOD_LINEAR,
-                        num_planes: 1,
-                        offsets: [chunk.offset(), 0, 0, 0],
-                        strides: [chunk.stride() as u32, 0, 0, 0],
-                    };
-
-                    let pts = (frame_count as i64) * frame_duration;
-                    let mut dmabuf_frame = DmaBufFrame::new(info, pts);
-                    dmabuf_frame.duration = frame_duration;
-
-                    let _ = frame_tx_clone.try_send(dmabuf_frame);
-                    frame_count += 1;
-                } else {
-                    // Fall back to copying if not DMA-BUF
-                    tracing::debug!("Non-DMA-BUF buffer received, falling back to copy");
-                }
+            let raw = unsafe { stream.dequeue_raw_buffer() };
+            if raw.is_null() {
+                return;
             }
+            let spa_buffer = unsafe { (*raw).buffer };
+            let (info, buffer_pts) = if spa_buffer.is_null() {
+                (None, None)
+            } else {
+                let datas = unsafe { buffer_datas(spa_buffer) };
+                (dmabuf_info(&state.format, datas), unsafe {
+                    read_header_pts(spa_buffer)
+                })
+            };
+            // The descriptors were duplicated; PipeWire can reuse the buffer
+            unsafe { stream.queue_raw_buffer(raw) };
+
+            let Some(info) = info else {
+                // Fall back to copying if not DMA-BUF
+                tracing::debug!("Non-DMA-BUF buffer received, falling back to copy");
+                return;
+            };
+
+            // Compositor presentation time, as for copied frames; arrival
+            // time without one
+            let pts = buffer_pts
+                .and_then(|ns| state.clock.frame_pts(ns))
+                .unwrap_or_else(wall_clock_us);
+            let mut dmabuf_frame = DmaBufFrame::new(info, pts);
+            dmabuf_frame.duration = frame_duration;
+
+            let _ = frame_tx_clone.try_send(dmabuf_frame);
         })
         .register()
         .map_err(|e| Error::PipeWire(format!("Failed to register listener: {}", e)))?;
@@ -419,11 +649,22 @@ impl DmaBufImporter {
     }
 
     /// Import a DMA-BUF for GPU access
-    pub fn import(&self, _dmabuf: &DmaBufInfo) -> Result<()> {
+    ///
+    /// Every plane of the format has to be present, whether the planes
+    /// share an fd or not.
+    pub fn import(&self, dmabuf: &DmaBufInfo) -> Result<()> {
+        if dmabuf.num_planes < dmabuf.expected_planes() {
+            return Err(Error::Pipeline(format!(
+                "DMA-BUF has {} of {} planes",
+                dmabuf.num_planes,
+                dmabuf.expected_planes()
+            )));
+        }
         // This would use EGL_EXT_image_dma_buf_import to create an EGL image
         // that can be used with CUDA/OpenGL for zero-copy encoding
         //
-        // eglCreateImage(display, EGL_NO_CONTEXT, EGL_LINUX_DMA_BUF_EXT, NULL, attribs)
+        // eglCreateImage(display, EGL_NO_CONTEXT, EGL_LINUX_DMA_BUF_EXT, NULL,
+        //                dmabuf.egl_attributes())
         //
         // For NVENC, we'd then use cuGraphicsEGLRegisterImage to make it available to CUDA
         Ok(())
@@ -435,3 +676,63 @@ impl Default for DmaBufImporter {
         Self {}
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nv12_info(num_planes: u32) -> DmaBufInfo {
+        DmaBufInfo {
+            fd: 7,
+            width: 1920,
+            height: 1080,
+            stride: 2048,
+            format: DRM_FORMAT_NV12,
+            modifier: DRM_FORMAT_MOD_LINEAR,
+            num_planes,
+            fds: [7, -1, -1, -1],
+            offsets: [0; 4],
+            strides: [2048, 0, 0, 0],
+        }
+    }
+
+    #[test]
+    fn test_complete_planes() {
+        // Single fd: chroma follows the luma rows
+        let mut info = nv12_info(1);
+        info.complete_planes().unwrap();
+        assert_eq!(info.num_planes, 2);
+        assert_eq!(info.fds[1], 7);
+        assert_eq!(info.offsets[1], 2048 * 1080);
+        assert_eq!(info.strides[1], 2048);
+
+        // Tiled single plane: chroma location unknown
+        let mut tiled = nv12_info(1);
+        tiled.modifier = 0x0200_0000_0000_0001;
+        assert!(tiled.complete_planes().is_err());
+
+        // Separate fd per plane is kept as delivered
+        let mut split = nv12_info(2);
+        split.fds[1] = 8;
+        split.strides[1] = 2048;
+        split.complete_planes().unwrap();
+        assert_eq!(split.fds[..2], [7, 8]);
+        assert_eq!(split.offsets[1], 0);
+    }
+
+    #[test]
+    fn test_egl_attributes() {
+        let mut info = nv12_info(2);
+        info.fds[1] = 8;
+        info.strides[1] = 2048;
+
+        let attrs = info.egl_attributes();
+        assert_eq!(attrs.last(), Some(&0x3038));
+        // Plane 1 fd, then offset and pitch
+        let plane1 = attrs.iter().position(|&a| a == 0x3275).unwrap();
+        assert_eq!(attrs[plane1 + 1], 8);
+        assert_eq!(attrs[plane1 + 5], 2048);
+        // Linear modifier passed explicitly for both planes
+        assert!(attrs.contains(&0x3443) && attrs.contains(&0x3445));
+    }
+}
@@ -0,0 +1,116 @@
+//! External frame input
+//!
+//! A `Capture` fed by the application instead of the screen: frames pushed
+//! through `Pipeline::push_frame` (or an `ExternalFrameSender`) arrive here
+//! and flow through processing, encoding and output like captured frames.
+
+use crate::error::{Error, Result};
+use crate::types::{Frame, Framerate, Resolution};
+
+use super::Capture;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Sending half for an `ExternalCapture`
+///
+/// Cheap to clone and usable from any thread. The channel is bounded, so a
+/// producer faster than the encoder is slowed down (or told the frame was
+/// not accepted, with `try_send`).
+#[derive(Debug, Clone)]
+pub struct ExternalFrameSender {
+    tx: mpsc::Sender<Frame>,
+}
+
+impl ExternalFrameSender {
+    /// Push a frame, waiting for room in the channel
+    pub async fn send(&self, frame: Frame) -> Result<()> {
+        self.tx.send(frame).await.map_err(|_| Error::CaptureEnded)
+    }
+
+    /// Push a frame from a non-async thread, blocking until there is room
+    ///
+    /// Must not be called from within an async runtime.
+    pub fn blocking_send(&self, frame: Frame) -> Result<()> {
+        self.tx
+            .blocking_send(frame)
+            .map_err(|_| Error::CaptureEnded)
+    }
+
+    /// Push a frame without waiting; returns `false` if the channel is full
+    /// and the frame was dropped
+    pub fn try_send(&self, frame: Frame) -> Result<bool> {
+        match self.tx.try_send(frame) {
+            Ok(()) => Ok(true),
+            Err(mpsc::error::TrySendError::Full(_)) => Ok(false),
+            Err(mpsc::error::TrySendError::Closed(_)) => Err(Error::CaptureEnded),
+        }
+    }
+}
+
+/// Capture source that yields frames pushed by the application
+pub struct ExternalCapture {
+    frame_rx: mpsc::Receiver<Frame>,
+    active: Arc<AtomicBool>,
+    resolution: Option<Resolution>,
+    framerate: Option<Framerate>,
+}
+
+impl ExternalCapture {
+    /// Create an external input and its sender; `capacity` frames may be
+    /// queued before senders are backpressured
+    pub fn new(capacity: usize) -> (Self, ExternalFrameSender) {
+        let (tx, rx) = mpsc::channel(capacity.max(1));
+        let capture = Self {
+            frame_rx: rx,
+            active: Arc::new(AtomicBool::new(false)),
+            resolution: None,
+            framerate: None,
+        };
+        (capture, ExternalFrameSender { tx })
+    }
+
+    /// Framerate reported to the pipeline (external frames carry their own PTS)
+    pub fn with_framerate(mut self, framerate: Framerate) -> Self {
+        self.framerate = Some(framerate);
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl Capture for ExternalCapture {
+    async fn start(&mut self) -> Result<()> {
+        self.active.store(true, Ordering::SeqCst);
+        tracing::info!("External frame input started");
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        self.active.store(false, Ordering::SeqCst);
+        self.frame_rx.close();
+        Ok(())
+    }
+
+    async fn next_frame(&mut self) -> Result<Frame> {
+        if !self.active.load(Ordering::SeqCst) {
+            return Err(Error::CaptureNotStarted);
+        }
+
+        let frame = self.frame_rx.recv().await.ok_or(Error::CaptureEnded)?;
+        self.resolution = Some(frame.resolution());
+        Ok(frame)
+    }
+
+    fn is_active(&self) -> bool {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    fn resolution(&self) -> Option<Resolution> {
+        self.resolution
+    }
+
+    fn framerate(&self) -> Option<Framerate> {
+        self.framerate
+    }
+}
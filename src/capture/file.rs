@@ -0,0 +1,363 @@
+//! Media file input
+//!
+//! Demuxes and decodes the video stream of an existing file with FFmpeg
+//! and yields BGRA `Frame`s, so a file can be transcoded through the
+//! normal processing/encoding/output path. Decoding runs on its own
+//! thread (FFmpeg contexts are not `Send`) and is paced only by the
+//! bounded frame channel, i.e. as fast as the encoder keeps up. NVDEC or
+//! VAAPI is used for decoding when available (see `DecoderBackend`).
+
+use crate::decode::{self, DecoderBackend, VideoDecoder};
+use crate::error::{Error, Result};
+use crate::types::{Frame, FrameFormat, Framerate, Resolution};
+
+use super::Capture;
+
+use ffmpeg_next as ffmpeg;
+use ffmpeg_next::format::Pixel;
+use ffmpeg_next::software::scaling::{Context as SwsContext, Flags as SwsFlags};
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Capture source that decodes frames from a media file
+pub struct FileInput {
+    path: PathBuf,
+    start: Option<Duration>,
+    end: Option<Duration>,
+    decoder_backend: DecoderBackend,
+    active: Arc<AtomicBool>,
+    resolution: Option<Resolution>,
+    framerate: Option<Framerate>,
+    frame_rx: Option<mpsc::Receiver<Frame>>,
+    decode_thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl FileInput {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            start: None,
+            end: None,
+            decoder_backend: DecoderBackend::Auto,
+            active: Arc::new(AtomicBool::new(false)),
+            resolution: None,
+            framerate: None,
+            frame_rx: None,
+            decode_thread: None,
+        }
+    }
+
+    /// Seek to this position before decoding (clip start)
+    pub fn with_start(mut self, start: Duration) -> Self {
+        self.start = Some(start);
+        self
+    }
+
+    /// Stop once frames reach this position (clip end)
+    pub fn with_end(mut self, end: Duration) -> Self {
+        self.end = Some(end);
+        self
+    }
+
+    /// Select the decoder backend (default: hardware when available)
+    pub fn with_decoder_backend(mut self, backend: DecoderBackend) -> Self {
+        self.decoder_backend = backend;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl Capture for FileInput {
+    async fn start(&mut self) -> Result<()> {
+        if self.active.load(Ordering::SeqCst) {
+            return Err(Error::Pipeline("File input already active".into()));
+        }
+
+        if !self.path.exists() {
+            return Err(Error::Config(format!(
+                "Input file {} does not exist",
+                self.path.display()
+            )));
+        }
+
+        let (frame_tx, frame_rx) = mpsc::channel::<Frame>(4);
+        let (info_tx, info_rx) = tokio::sync::oneshot::channel();
+        let path = self.path.clone();
+        let (start, end) = (self.start, self.end);
+        let backend = self.decoder_backend;
+        let active = self.active.clone();
+        active.store(true, Ordering::SeqCst);
+
+        let handle = std::thread::spawn(move || {
+            let decoder = DecoderSettings {
+                path,
+                start,
+                end,
+                backend,
+            };
+            if let Err(e) = run_decoder(decoder, active.clone(), frame_tx, info_tx) {
+                tracing::error!("File input error: {}", e);
+            }
+            active.store(false, Ordering::SeqCst);
+        });
+
+        // Wait for the decoder to open the file so errors surface here
+        match info_rx.await {
+            Ok(Ok((resolution, framerate))) => {
+                self.resolution = Some(resolution);
+                self.framerate = framerate;
+            }
+            Ok(Err(e)) => {
+                let _ = handle.join();
+                return Err(e);
+            }
+            Err(_) => {
+                let _ = handle.join();
+                return Err(Error::Internal("File decoder exited during startup".into()));
+            }
+        }
+
+        self.frame_rx = Some(frame_rx);
+        self.decode_thread = Some(handle);
+        tracing::info!("File input started: {}", self.path.display());
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        self.active.store(false, Ordering::SeqCst);
+        // Unblock the decoder if it is waiting on a full channel
+        if let Some(rx) = self.frame_rx.as_mut() {
+            rx.close();
+        }
+        if let Some(handle) = self.decode_thread.take() {
+            let _ = tokio::task::spawn_blocking(move || handle.join()).await;
+        }
+        Ok(())
+    }
+
+    async fn next_frame(&mut self) -> Result<Frame> {
+        let rx = self.frame_rx.as_mut().ok_or(Error::CaptureNotStarted)?;
+        rx.recv().await.ok_or(Error::CaptureEnded)
+    }
+
+    fn is_active(&self) -> bool {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    fn resolution(&self) -> Option<Resolution> {
+        self.resolution
+    }
+
+    fn framerate(&self) -> Option<Framerate> {
+        self.framerate
+    }
+}
+
+type StreamInfo = (Resolution, Option<Framerate>);
+
+/// What to decode and how
+struct DecoderSettings {
+    path: PathBuf,
+    start: Option<Duration>,
+    end: Option<Duration>,
+    backend: DecoderBackend,
+}
+
+/// Demux + decode loop (runs on the decode thread)
+fn run_decoder(
+    settings: DecoderSettings,
+    active: Arc<AtomicBool>,
+    frame_tx: mpsc::Sender<Frame>,
+    info_tx: tokio::sync::oneshot::Sender<Result<StreamInfo>>,
+) -> Result<()> {
+    let DecoderSettings {
+        path,
+        start,
+        end,
+        backend,
+    } = settings;
+    let opened = open_input(&path, start, backend);
+    let (mut ictx, stream_index, time_base, mut decoder) = match opened {
+        Ok(v) => v,
+        Err(e) => {
+            let _ = info_tx.send(Err(e));
+            return Ok(());
+        }
+    };
+
+    let resolution = Resolution::new(decoder.width(), decoder.height());
+    let framerate = ictx
+        .stream(stream_index)
+        .map(|s| s.avg_frame_rate())
+        .filter(|r| r.numerator() > 0 && r.denominator() > 0)
+        .map(|r| Framerate::new(r.numerator() as u32, r.denominator() as u32));
+    let _ = info_tx.send(Ok((resolution, framerate)));
+
+    let start_us = start.map(|d| d.as_micros() as i64).unwrap_or(0);
+    let end_us = end.map(|d| d.as_micros() as i64);
+    let mut state = DecodeState {
+        scaler: None,
+        time_base,
+        start_us,
+        end_us,
+        frame_tx,
+        frames: 0,
+    };
+
+    for (stream, packet) in ictx.packets() {
+        if !active.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        if stream.index() != stream_index {
+            continue;
+        }
+        if let Err(e) = decoder.send_packet(&packet) {
+            tracing::warn!("{}", e);
+            continue;
+        }
+        if !state.receive_frames(&mut decoder)? {
+            tracing::info!("File input reached clip end after {} frames", state.frames);
+            return Ok(());
+        }
+    }
+
+    // Drain frames buffered in the decoder
+    let _ = decoder.send_eof();
+    state.receive_frames(&mut decoder)?;
+    tracing::info!("File input finished: {} frames", state.frames);
+    Ok(())
+}
+
+type OpenedInput = (
+    ffmpeg::format::context::Input,
+    usize,
+    ffmpeg::Rational,
+    VideoDecoder,
+);
+
+fn open_input(
+    path: &std::path::Path,
+    start: Option<Duration>,
+    backend: DecoderBackend,
+) -> Result<OpenedInput> {
+    ffmpeg::init().map_err(|e| Error::Ffmpeg(e.to_string()))?;
+
+    let mut ictx = ffmpeg::format::input(&path)
+        .map_err(|e| Error::Ffmpeg(format!("Failed to open {}: {}", path.display(), e)))?;
+
+    let stream = ictx
+        .streams()
+        .best(ffmpeg::media::Type::Video)
+        .ok_or_else(|| Error::Config(format!("{} has no video stream", path.display())))?;
+    let stream_index = stream.index();
+    let time_base = stream.time_base();
+
+    let decoder = decode::create_decoder(stream.parameters(), backend)?;
+
+    // Seek (in AV_TIME_BASE = microseconds) to the keyframe at or before
+    // the start; frames before the start are discarded after decoding
+    if let Some(start) = start {
+        let ts = start.as_micros() as i64;
+        ictx.seek(ts, ..ts)
+            .map_err(|e| Error::Ffmpeg(format!("Failed to seek to {:?}: {}", start, e)))?;
+    }
+
+    Ok((ictx, stream_index, time_base, decoder))
+}
+
+struct DecodeState {
+    scaler: Option<SwsContext>,
+    time_base: ffmpeg::Rational,
+    start_us: i64,
+    end_us: Option<i64>,
+    frame_tx: mpsc::Sender<Frame>,
+    frames: u64,
+}
+
+impl DecodeState {
+    /// Pull all decoded frames; returns false once the clip end is reached
+    /// or the receiver has gone away
+    fn receive_frames(&mut self, decoder: &mut VideoDecoder) -> Result<bool> {
+        let mut decoded = ffmpeg::frame::Video::empty();
+        while decoder.receive_frame(&mut decoded)? {
+            let pts = decoded.timestamp().or(decoded.pts()).unwrap_or(0);
+            let pts_us = pts * 1_000_000 * self.time_base.numerator() as i64
+                / self.time_base.denominator().max(1) as i64;
+
+            if pts_us < self.start_us {
+                continue;
+            }
+            if self.end_us.is_some_and(|end| pts_us >= end) {
+                return Ok(false);
+            }
+
+            let mut frame = self.to_bgra(decoder.download(&decoded)?)?;
+            // Clip-relative timestamps so output starts at zero
+            frame.pts = pts_us - self.start_us;
+            frame.is_keyframe = decoded.is_key();
+
+            if self.frame_tx.blocking_send(frame).is_err() {
+                return Ok(false);
+            }
+            self.frames += 1;
+        }
+        Ok(true)
+    }
+
+    fn to_bgra(&mut self, decoded: &ffmpeg::frame::Video) -> Result<Frame> {
+        let (width, height) = (decoded.width(), decoded.height());
+
+        // (Re)create the converter on the first frame or a format change
+        let stale = self.scaler.as_ref().is_none_or(|s| {
+            s.input().format != decoded.format()
+                || s.input().width != width
+                || s.input().height != height
+        });
+        if stale {
+            self.scaler = None;
+        }
+        let scaler = match &mut self.scaler {
+            Some(scaler) => scaler,
+            slot @ None => slot.insert(
+                SwsContext::get(
+                    decoded.format(),
+                    width,
+                    height,
+                    Pixel::BGRA,
+                    width,
+                    height,
+                    SwsFlags::BILINEAR,
+                )
+                .map_err(|e| {
+                    Error::ColorspaceConversion(format!("Failed to create scaler: {}", e))
+                })?,
+            ),
+        };
+
+        let mut bgra = ffmpeg::frame::Video::new(Pixel::BGRA, width, height);
+        scaler
+            .run(decoded, &mut bgra)
+            .map_err(|e| Error::ColorspaceConversion(format!("Conversion failed: {}", e)))?;
+
+        // Copy out row by row (FFmpeg frames are padded)
+        let row = (width * 4) as usize;
+        let stride = bgra.stride(0);
+        let plane = bgra.data(0);
+        let mut data = vec![0u8; row * height as usize];
+        for y in 0..height as usize {
+            data[y * row..(y + 1) * row].copy_from_slice(&plane[y * stride..y * stride + row]);
+        }
+
+        Ok(Frame::from_data(
+            data,
+            width,
+            height,
+            width * 4,
+            FrameFormat::Bgra,
+        ))
+    }
+}
@@ -4,14 +4,32 @@
 //! - xdg-desktop-portal (recommended for Wayland)
 //! - PipeWire direct capture
 //! - DMA-BUF zero-copy (wlroots, KDE, GNOME)
+//! - X11 (XShm/XComposite) where no portal is available
+//! - V4L2 webcams and capture cards
+//! - External frames pushed by the application
+//! - Media files (decoded with FFmpeg, for transcoding)
+//! - Synthetic test frames (no hardware or display needed)
 
 mod dmabuf;
+mod external;
+mod file;
 mod portal;
+mod pw_connection;
 mod stream;
+mod synthetic;
+mod v4l2;
+mod x11;
 
 pub use dmabuf::{DmaBufCapture, DmaBufFrame, DmaBufInfo, DmaBufImporter};
+pub use external::{ExternalCapture, ExternalFrameSender};
+pub use file::FileInput;
 pub use portal::PortalCapture;
+pub use pw_connection::live_pipewire_connections;
+pub(crate) use pw_connection::PipeWireConnection;
 pub use stream::CaptureStream;
+pub use synthetic::TestCapture;
+pub use v4l2::{list_v4l2_devices, V4l2Capture, V4l2Device, V4l2PixelFormat};
+pub use x11::X11Capture;
 
 use crate::config::{CaptureBackend, CaptureConfig};
 use crate::error::Result;
@@ -37,6 +55,11 @@ pub trait Capture: Send + Sync {
 
     /// Get current framerate
     fn framerate(&self) -> Option<crate::types::Framerate>;
+
+    /// Frames skipped because the source delivered an incomplete buffer
+    fn partial_frames(&self) -> u64 {
+        0
+    }
 }
 
 /// Create a capture source based on configuration
@@ -70,6 +93,11 @@ pub async fn create_capture(config: CaptureConfig) -> Result<Box<dyn Capture>> {
                 Ok(Box::new(capture))
             }
         }
+        CaptureBackend::X11 => {
+            let capture = X11Capture::new(config)?;
+            Ok(Box::new(capture))
+        }
+        CaptureBackend::V4l2 => Ok(Box::new(V4l2Capture::new(config))),
     }
 }
 
@@ -92,6 +120,13 @@ fn detect_best_backend(config: &CaptureConfig) -> CaptureBackend {
         return CaptureBackend::Portal;
     }
 
+    // Native capture on X11, where a portal backend often isn't installed
+    let is_x11 = std::env::var("XDG_SESSION_TYPE").is_ok_and(|s| s == "x11");
+    if is_x11 && X11Capture::is_available() {
+        tracing::info!("Auto-selecting X11 capture");
+        return CaptureBackend::X11;
+    }
+
     // Default to portal (works on X11 too via xdg-desktop-portal-gtk)
     CaptureBackend::Portal
 }
@@ -3,11 +3,12 @@
 //! Uses the Portal API for secure screen sharing on Wayland.
 //! PipeWire receives the actual video frames.
 
-use crate::config::CaptureConfig;
+use crate::config::{CaptureConfig, CursorMode};
 use crate::error::{Error, Result};
-use crate::types::{Frame, FrameFormat, Framerate, Resolution};
+use crate::processing::{ColorMatrix, ColorPrimaries, ColorRange};
+use crate::types::{CursorInfo, Frame, FrameFormat, Framerate, Resolution};
 
-use super::Capture;
+use super::{Capture, PipeWireConnection};
 
 use pipewire as pw;
 use pw::spa::param::video::VideoFormat;
@@ -26,6 +27,7 @@ pub struct PortalCapture {
     frame_rx: Option<mpsc::Receiver<Frame>>,
     pipewire_thread: Option<std::thread::JoinHandle<()>>,
     frame_count: Arc<AtomicU64>,
+    partial_frames: Arc<AtomicU64>,
     node_id: Option<u32>,
 }
 
@@ -40,13 +42,14 @@ impl PortalCapture {
             frame_rx: None,
             pipewire_thread: None,
             frame_count: Arc::new(AtomicU64::new(0)),
+            partial_frames: Arc::new(AtomicU64::new(0)),
             node_id: None,
         })
     }
 
     /// Request screen capture permission from user via portal
     async fn request_permission(&mut self) -> Result<u32> {
-        use ashpd::desktop::screencast::{CursorMode, Screencast, SourceType};
+        use ashpd::desktop::screencast::{CursorMode as PortalCursorMode, Screencast, SourceType};
         use ashpd::desktop::PersistMode;
 
         tracing::info!("Requesting screen capture permission via portal");
@@ -61,11 +64,17 @@ impl PortalCapture {
             .await
             .map_err(|e| Error::Portal(format!("Failed to create session: {}", e)))?;
 
+        let cursor_mode = match (self.config.show_cursor, self.config.cursor_mode) {
+            (false, _) | (_, CursorMode::Hidden) => PortalCursorMode::Hidden,
+            (true, CursorMode::Embedded) => PortalCursorMode::Embedded,
+            (true, CursorMode::Metadata) => PortalCursorMode::Metadata,
+        };
+
         // Select sources - allow both monitors and windows
         proxy
             .select_sources(
                 &session,
-                CursorMode::Embedded, // Include cursor in capture
+                cursor_mode,
                 SourceType::Monitor | SourceType::Window,
                 false, // multiple selection
                 None,  // restore_token
@@ -109,8 +118,12 @@ impl PortalCapture {
         let (frame_tx, frame_rx) = mpsc::channel::<Frame>(4);
         let active = self.active.clone();
         let frame_count = self.frame_count.clone();
+        let partial_frames = self.partial_frames.clone();
         let target_resolution = self.resolution;
         let target_fps = self.config.framerate.fps();
+        let cursor_metadata =
+            self.config.show_cursor && self.config.cursor_mode == CursorMode::Metadata;
+        let identity = self.config.stream_identity("ghoststream-capture");
 
         // PipeWire needs to run on its own thread with a MainLoop
         let handle = std::thread::spawn(move || {
@@ -119,8 +132,11 @@ impl PortalCapture {
                 frame_tx,
                 active,
                 frame_count,
+                partial_frames,
                 target_resolution,
                 target_fps,
+                cursor_metadata,
+                identity,
             ) {
                 tracing::error!("PipeWire capture error: {}", e);
             }
@@ -170,18 +186,16 @@ impl Capture for PortalCapture {
 
     async fn next_frame(&mut self) -> Result<Frame> {
         if !self.active.load(Ordering::SeqCst) {
-            return Err(Error::PipelineNotStarted);
+            return Err(Error::CaptureNotStarted);
         }
 
-        let rx = self
-            .frame_rx
-            .as_mut()
-            .ok_or_else(|| Error::Pipeline("Frame receiver not initialized".into()))?;
+        let rx = self.frame_rx.as_mut().ok_or(Error::CaptureNotStarted)?;
 
         // Wait for next frame with timeout
         match tokio::time::timeout(std::time::Duration::from_millis(100), rx.recv()).await {
             Ok(Some(frame)) => Ok(frame),
-            Ok(None) => Err(Error::Pipeline("Frame channel closed".into())),
+            // The PipeWire thread exited (e.g. screen sharing was stopped)
+            Ok(None) => Err(Error::CaptureEnded),
             Err(_) => {
                 // Timeout - return an empty frame to keep pipeline moving
                 let resolution = self.resolution.unwrap_or(Resolution::FHD_1080P);
@@ -206,6 +220,10 @@ impl Capture for PortalCapture {
     fn framerate(&self) -> Option<Framerate> {
         self.framerate
     }
+
+    fn partial_frames(&self) -> u64 {
+        self.partial_frames.load(Ordering::Relaxed)
+    }
 }
 
 impl Drop for PortalCapture {
@@ -225,7 +243,76 @@ impl Drop for PortalCapture {
 struct CaptureState {
     frame_tx: mpsc::Sender<Frame>,
     frame_count: Arc<AtomicU64>,
+    /// Buffers skipped for holding less than a full frame
+    partial_frames: Arc<AtomicU64>,
     format: pw::spa::param::video::VideoInfoRaw,
+    /// Request and read SPA_META_Cursor on buffers
+    cursor_metadata: bool,
+    /// Last valid cursor position (metadata is only sent on change)
+    last_cursor: Option<CursorInfo>,
+    clock: BufferClock,
+}
+
+/// Frames further than this from the wall clock after conversion are
+/// stamped on a clock other than CLOCK_MONOTONIC
+const MAX_BUFFER_CLOCK_SKEW_US: i64 = 1_000_000;
+
+/// Maps buffer timestamps onto the wall-clock microseconds frames carry
+///
+/// Compositors stamp buffers (SPA_META_Header) with the CLOCK_MONOTONIC
+/// time the frame was rendered, which is closer to when it was shown than
+/// the time the buffer reaches us. The offset to the wall clock is taken
+/// once, so converted stamps keep the compositor's spacing.
+pub(super) struct BufferClock {
+    /// Wall clock minus CLOCK_MONOTONIC (µs)
+    offset_us: i64,
+    /// Whether buffer stamps turned out unusable (warned once)
+    unusable: bool,
+}
+
+impl BufferClock {
+    pub(super) fn new() -> Self {
+        Self {
+            offset_us: wall_clock_us() - monotonic_us(),
+            unusable: false,
+        }
+    }
+
+    /// Frame PTS (µs) for a buffer stamped `buffer_ns`, None if the stamp
+    /// can't be trusted
+    pub(super) fn frame_pts(&mut self, buffer_ns: i64) -> Option<i64> {
+        if self.unusable || buffer_ns <= 0 {
+            return None;
+        }
+        let pts = buffer_ns / 1000 + self.offset_us;
+        let skew = pts - wall_clock_us();
+        if skew.abs() > MAX_BUFFER_CLOCK_SKEW_US {
+            tracing::warn!(
+                "PipeWire buffer timestamps are {}ms off the monotonic clock, \
+                 using arrival time instead",
+                skew / 1000
+            );
+            self.unusable = true;
+            return None;
+        }
+        Some(pts)
+    }
+}
+
+pub(super) fn wall_clock_us() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_micros() as i64
+}
+
+fn monotonic_us() -> i64 {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    now.tv_sec * 1_000_000 + now.tv_nsec / 1000
 }
 
 /// Run PipeWire capture loop - based on pipewire-rs streams.rs example
@@ -234,32 +321,27 @@ fn run_pipewire_capture(
     frame_tx: mpsc::Sender<Frame>,
     active: Arc<AtomicBool>,
     frame_count: Arc<AtomicU64>,
+    partial_frames: Arc<AtomicU64>,
     target_resolution: Option<Resolution>,
     target_fps: u32,
+    cursor_metadata: bool,
+    (stream_name, app_name): (String, String),
 ) -> Result<()> {
     tracing::info!("Starting PipeWire capture for node {}", node_id);
 
-    // Initialize PipeWire
-    pw::init();
-
-    let mainloop = pw::main_loop::MainLoop::new(None)
-        .map_err(|e| Error::PipeWire(format!("Failed to create main loop: {:?}", e)))?;
-
-    let context = pw::context::Context::new(&mainloop)
-        .map_err(|e| Error::PipeWire(format!("Failed to create context: {:?}", e)))?;
-
-    let core = context
-        .connect(None)
-        .map_err(|e| Error::PipeWire(format!("Failed to connect to PipeWire: {:?}", e)))?;
+    let pipewire = PipeWireConnection::new()?;
+    let (mainloop, core) = (&pipewire.mainloop, &pipewire.core);
 
     // Create stream with properties
     let stream = pw::stream::Stream::new(
         &core,
-        "ghoststream-capture",
+        &stream_name,
         pw::properties::properties! {
             *pw::keys::MEDIA_TYPE => "Video",
             *pw::keys::MEDIA_CATEGORY => "Capture",
             *pw::keys::MEDIA_ROLE => "Screen",
+            *pw::keys::NODE_NAME => stream_name.as_str(),
+            *pw::keys::APP_NAME => app_name.as_str(),
         },
     )
     .map_err(|e| Error::PipeWire(format!("Failed to create stream: {:?}", e)))?;
@@ -268,7 +350,11 @@ fn run_pipewire_capture(
     let state = CaptureState {
         frame_tx,
         frame_count,
+        partial_frames,
         format: Default::default(),
+        cursor_metadata,
+        last_cursor: None,
+        clock: BufferClock::new(),
     };
 
     // Clone for use in main loop check
@@ -288,7 +374,7 @@ fn run_pipewire_capture(
                 }
             }
         })
-        .param_changed(|_, state, id, param| {
+        .param_changed(|stream, state, id, param| {
             // Only handle Format params
             let Some(param) = param else { return };
             if id != pw::spa::param::ParamType::Format.as_raw() {
@@ -310,11 +396,27 @@ fn run_pipewire_capture(
             }
 
             // Parse video format info
+            let previous_size = state.format.size();
             if let Err(e) = state.format.parse(param) {
                 tracing::warn!("Failed to parse video format: {:?}", e);
                 return;
             }
 
+            // Renegotiation after a display mode change delivers a new size;
+            // the pipeline handles it per CaptureConfig::on_resolution_change
+            let size = state.format.size();
+            if previous_size.width != 0
+                && (previous_size.width != size.width || previous_size.height != size.height)
+            {
+                tracing::warn!(
+                    "Capture resolution changed mid-stream: {}x{} -> {}x{}",
+                    previous_size.width,
+                    previous_size.height,
+                    size.width,
+                    size.height
+                );
+            }
+
             tracing::info!(
                 "Video format negotiated: {:?} {}x{} @ {}/{}fps",
                 state.format.format(),
@@ -323,67 +425,75 @@ fn run_pipewire_capture(
                 state.format.framerate().num,
                 state.format.framerate().denom,
             );
+
+            // Ask the producer to attach timestamps, and cursor metadata if
+            // wanted, to each buffer
+            let header_size = std::mem::size_of::<pw::spa::sys::spa_meta_header>() as i32;
+            let mut metas = vec![meta_param(
+                pw::spa::sys::SPA_META_Header,
+                header_size,
+                header_size,
+            )];
+            if state.cursor_metadata {
+                metas.push(cursor_meta_param());
+            }
+            match metas.into_iter().collect::<Result<Vec<_>>>() {
+                Ok(params) => {
+                    let mut pods: Vec<&Pod> = params
+                        .iter()
+                        .filter_map(|bytes| Pod::from_bytes(bytes))
+                        .collect();
+                    if let Err(e) = stream.update_params(&mut pods) {
+                        tracing::warn!("Failed to request buffer metadata: {:?}", e);
+                    }
+                }
+                Err(e) => tracing::warn!("{}", e),
+            }
         })
         .process(|stream, state| {
-            // Dequeue buffer from stream
-            let Some(mut buffer) = stream.dequeue_buffer() else {
-                return;
-            };
-
-            let datas = buffer.datas_mut();
-            if datas.is_empty() {
+            // Dequeue the raw buffer so buffer metadata (cursor) is reachable
+            let raw = unsafe { stream.dequeue_raw_buffer() };
+            if raw.is_null() {
                 return;
             }
+            let spa_buffer = unsafe { (*raw).buffer };
 
-            let data = &mut datas[0];
-            let chunk = data.chunk();
-            let size = chunk.size() as usize;
-            let offset = chunk.offset() as usize;
-
-            if size == 0 {
-                return;
+            if state.cursor_metadata && !spa_buffer.is_null() {
+                if let Some(cursor) = unsafe { read_cursor_meta(spa_buffer) } {
+                    state.last_cursor = Some(cursor);
+                }
             }
-
-            // Get the actual frame data
-            let Some(slice) = data.data() else {
-                return;
+            let buffer_pts = if spa_buffer.is_null() {
+                None
+            } else {
+                unsafe { read_header_pts(spa_buffer) }
             };
 
-            // Determine resolution from negotiated format
-            let width = state.format.size().width;
-            let height = state.format.size().height;
-
-            // Map PipeWire format to our format
-            let frame_format = match state.format.format() {
-                VideoFormat::BGRx | VideoFormat::BGRA => FrameFormat::Bgra,
-                VideoFormat::RGBx | VideoFormat::RGBA => FrameFormat::Rgba,
-                VideoFormat::RGB => FrameFormat::Rgb24,
-                VideoFormat::NV12 => FrameFormat::Nv12,
-                VideoFormat::I420 => FrameFormat::Yuv420p,
-                _ => {
-                    tracing::warn!("Unsupported video format: {:?}", state.format.format());
-                    FrameFormat::Bgra // Fallback
-                }
+            let frame = if spa_buffer.is_null() {
+                None
+            } else {
+                let datas = unsafe { buffer_datas(spa_buffer) };
+                frame_from_datas(&state.format, datas, &state.partial_frames)
             };
 
-            // Create frame and copy data
-            let mut frame = Frame::new(width, height, frame_format);
+            unsafe { stream.queue_raw_buffer(raw) };
 
-            // Calculate expected size based on format
-            let expected_size = frame.data.len();
-            let copy_size = size
-                .min(expected_size)
-                .min(slice.len().saturating_sub(offset));
+            let Some(mut frame) = frame else {
+                return;
+            };
 
-            if copy_size > 0 && offset < slice.len() {
-                frame.data[..copy_size].copy_from_slice(&slice[offset..offset + copy_size]);
+            // Compositor presentation time; arrival time without one
+            if let Some(pts) = buffer_pts.and_then(|ns| state.clock.frame_pts(ns)) {
+                frame.pts = pts;
             }
 
-            // Set timestamp
-            frame.pts = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_micros() as i64;
+            if let Some(mut cursor) = state.last_cursor {
+                cursor.visible &= cursor.x >= 0
+                    && cursor.y >= 0
+                    && (cursor.x as u32) < frame.width
+                    && (cursor.y as u32) < frame.height;
+                frame.cursor = Some(cursor);
+            }
 
             state.frame_count.fetch_add(1, Ordering::Relaxed);
 
@@ -494,3 +604,234 @@ fn run_pipewire_capture(
     tracing::info!("PipeWire capture loop ended");
     Ok(())
 }
+
+/// Carry the negotiated colorimetry (where the producer sent any) into
+/// the frame so conversion and encoding use the source's matrix and range
+fn set_colorimetry(frame: &mut Frame, format: &pw::spa::param::video::VideoInfoRaw) {
+    use pw::spa::sys as spa_sys;
+
+    frame.color_space = match format.color_matrix() {
+        spa_sys::SPA_VIDEO_COLOR_MATRIX_BT709 => Some(ColorMatrix::Bt709),
+        spa_sys::SPA_VIDEO_COLOR_MATRIX_BT601 => Some(ColorMatrix::Bt601),
+        spa_sys::SPA_VIDEO_COLOR_MATRIX_BT2020 => Some(ColorMatrix::Bt2020Ncl),
+        _ => None,
+    };
+    frame.color_range = match format.color_range() {
+        spa_sys::SPA_VIDEO_COLOR_RANGE_0_255 => Some(ColorRange::Full),
+        spa_sys::SPA_VIDEO_COLOR_RANGE_16_235 => Some(ColorRange::Limited),
+        _ => None,
+    };
+    frame.primaries = match format.color_primaries() {
+        spa_sys::SPA_VIDEO_COLOR_PRIMARIES_BT709 => Some(ColorPrimaries::Bt709),
+        spa_sys::SPA_VIDEO_COLOR_PRIMARIES_SMPTE170M
+        | spa_sys::SPA_VIDEO_COLOR_PRIMARIES_BT470BG => Some(ColorPrimaries::Bt601),
+        spa_sys::SPA_VIDEO_COLOR_PRIMARIES_BT2020 => Some(ColorPrimaries::Bt2020),
+        spa_sys::SPA_VIDEO_COLOR_PRIMARIES_SMPTEEG432 => Some(ColorPrimaries::DciP3),
+        _ => None,
+    };
+}
+
+/// Build the SPA_PARAM_Meta pod requesting cursor metadata (with room for
+/// a 64x64 RGBA bitmap, which we don't use but producers may insist on)
+fn cursor_meta_param() -> Result<Vec<u8>> {
+    use pw::spa::sys as spa_sys;
+
+    let min_size = std::mem::size_of::<spa_sys::spa_meta_cursor>() as i32;
+    let max_size = min_size + std::mem::size_of::<spa_sys::spa_meta_bitmap>() as i32 + 64 * 64 * 4;
+    meta_param(spa_sys::SPA_META_Cursor, min_size, max_size)
+}
+
+/// Build a SPA_PARAM_Meta pod requesting metadata of `meta_type`
+pub(super) fn meta_param(meta_type: u32, min_size: i32, max_size: i32) -> Result<Vec<u8>> {
+    use pw::spa::pod::{ChoiceValue, Property, Value};
+    use pw::spa::sys as spa_sys;
+    use pw::spa::utils::{Choice, ChoiceEnum, ChoiceFlags, Id};
+
+    let obj = pw::spa::pod::object!(
+        pw::spa::utils::SpaTypes::ObjectParamMeta,
+        pw::spa::param::ParamType::Meta,
+        Property::new(spa_sys::SPA_PARAM_META_type, Value::Id(Id(meta_type))),
+        Property::new(
+            spa_sys::SPA_PARAM_META_size,
+            Value::Choice(ChoiceValue::Int(Choice(
+                ChoiceFlags::empty(),
+                ChoiceEnum::Range {
+                    default: max_size,
+                    min: min_size,
+                    max: max_size,
+                },
+            ))),
+        ),
+    );
+
+    let values: Vec<u8> = pw::spa::pod::serialize::PodSerializer::serialize(
+        std::io::Cursor::new(Vec::new()),
+        &pw::spa::pod::Value::Object(obj),
+    )
+    .map_err(|e| Error::PipeWire(format!("Failed to serialize meta params: {:?}", e)))?
+    .0
+    .into_inner();
+
+    Ok(values)
+}
+
+/// Read SPA_META_Cursor from a buffer, if present and valid
+///
+/// # Safety
+/// `buffer` must point to a valid `spa_buffer` owned by a dequeued `pw_buffer`.
+unsafe fn read_cursor_meta(buffer: *mut pw::spa::sys::spa_buffer) -> Option<CursorInfo> {
+    use pw::spa::sys as spa_sys;
+
+    let meta = spa_sys::spa_buffer_find_meta_data(
+        buffer,
+        spa_sys::SPA_META_Cursor,
+        std::mem::size_of::<spa_sys::spa_meta_cursor>(),
+    ) as *const spa_sys::spa_meta_cursor;
+    if meta.is_null() {
+        return None;
+    }
+
+    // id == 0 means "no cursor update in this buffer"
+    let cursor = &*meta;
+    if cursor.id == 0 {
+        return None;
+    }
+
+    Some(CursorInfo {
+        x: cursor.position.x,
+        y: cursor.position.y,
+        hotspot_x: cursor.hotspot.x,
+        hotspot_y: cursor.hotspot.y,
+        visible: true,
+        buttons: 0,
+    })
+}
+
+/// Presentation time (ns) from SPA_META_Header, if the producer set one
+///
+/// # Safety
+/// `buffer` must point to a valid `spa_buffer` owned by a dequeued `pw_buffer`.
+pub(super) unsafe fn read_header_pts(buffer: *mut pw::spa::sys::spa_buffer) -> Option<i64> {
+    use pw::spa::sys as spa_sys;
+
+    let header = spa_sys::spa_buffer_find_meta_data(
+        buffer,
+        spa_sys::SPA_META_Header,
+        std::mem::size_of::<spa_sys::spa_meta_header>(),
+    ) as *const spa_sys::spa_meta_header;
+    if header.is_null() {
+        return None;
+    }
+    let header = &*header;
+    if header.flags & spa_sys::SPA_META_HEADER_FLAG_CORRUPTED != 0 {
+        return None;
+    }
+    Some(header.pts)
+}
+
+/// View the data planes of a raw `spa_buffer`
+///
+/// # Safety
+/// `buffer` must point to a valid `spa_buffer` that outlives the returned slice.
+pub(super) unsafe fn buffer_datas<'a>(
+    buffer: *mut pw::spa::sys::spa_buffer,
+) -> &'a mut [pw::spa::buffer::Data] {
+    if (*buffer).n_datas == 0 || (*buffer).datas.is_null() {
+        return &mut [];
+    }
+    std::slice::from_raw_parts_mut(
+        (*buffer).datas as *mut pw::spa::buffer::Data,
+        (*buffer).n_datas as usize,
+    )
+}
+
+/// Copy the first data plane of a buffer into a `Frame`
+///
+/// Buffers holding less than a full frame for the negotiated format (seen
+/// under load) are skipped and counted in `partial_frames` rather than
+/// encoded with a blank or stale tail.
+fn frame_from_datas(
+    format: &pw::spa::param::video::VideoInfoRaw,
+    datas: &mut [pw::spa::buffer::Data],
+    partial_frames: &AtomicU64,
+) -> Option<Frame> {
+    if datas.is_empty() {
+        return None;
+    }
+
+    let data = &mut datas[0];
+    let chunk = data.chunk();
+    let size = chunk.size() as usize;
+    let offset = chunk.offset() as usize;
+
+    if size == 0 {
+        return None;
+    }
+
+    // Get the actual frame data
+    let slice = data.data()?;
+
+    // Determine resolution from negotiated format
+    let width = format.size().width;
+    let height = format.size().height;
+
+    // Map PipeWire format to our format
+    let frame_format = match format.format() {
+        VideoFormat::BGRx | VideoFormat::BGRA => FrameFormat::Bgra,
+        VideoFormat::RGBx | VideoFormat::RGBA => FrameFormat::Rgba,
+        VideoFormat::RGB => FrameFormat::Rgb24,
+        VideoFormat::NV12 => FrameFormat::Nv12,
+        VideoFormat::I420 => FrameFormat::Yuv420p,
+        _ => {
+            tracing::warn!("Unsupported video format: {:?}", format.format());
+            FrameFormat::Bgra // Fallback
+        }
+    };
+
+    // Create frame and copy data
+    let mut frame = Frame::new(width, height, frame_format);
+
+    // Calculate expected size based on format
+    let expected_size = frame.data.len();
+    let available = size.min(slice.len().saturating_sub(offset));
+    if available < expected_size {
+        let skipped = partial_frames.fetch_add(1, Ordering::Relaxed) + 1;
+        if skipped == 1 || skipped % 100 == 0 {
+            tracing::warn!(
+                "Skipping partial PipeWire buffer ({} of {} bytes, {} skipped so far)",
+                available,
+                expected_size,
+                skipped
+            );
+        }
+        return None;
+    }
+
+    let src = &slice[offset..offset + expected_size];
+    frame.data.copy_from_slice(src);
+    set_colorimetry(&mut frame, format);
+
+    // Arrival time; replaced by the buffer's own stamp where it has one
+    frame.pts = wall_clock_us();
+
+    Some(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_clock() {
+        let mut clock = BufferClock::new();
+        assert_eq!(clock.frame_pts(0), None);
+
+        // A monotonic stamp lands on the wall clock
+        let pts = clock.frame_pts(monotonic_us() * 1000).unwrap();
+        assert!((pts - wall_clock_us()).abs() < 100_000);
+
+        // Stamps on another clock disable conversion for good
+        assert_eq!(clock.frame_pts(wall_clock_us() * 1000), None);
+        assert_eq!(clock.frame_pts(monotonic_us() * 1000), None);
+    }
+}
@@ -0,0 +1,114 @@
+//! Shared PipeWire connection setup
+//!
+//! `pw::init()` is process-global and only runs once; it is never undone,
+//! because `pw_deinit` may only be called once every PipeWire object in the
+//! process is gone and PipeWire cannot be initialized again afterwards.
+//! What does need releasing per session is the main loop, context and core:
+//! every capture, camera and audio thread opens them through
+//! `PipeWireConnection`, which tears them down in order when dropped, so
+//! starting and stopping capture repeatedly doesn't accumulate sockets or
+//! event fds.
+
+use crate::error::{Error, Result};
+
+use pipewire as pw;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Connections currently open in this process
+static LIVE_CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of PipeWire connections currently open
+///
+/// Each running capture, virtual camera or audio stream holds one; the count
+/// should drop back to zero once they are all stopped.
+pub fn live_pipewire_connections() -> usize {
+    LIVE_CONNECTIONS.load(Ordering::Relaxed)
+}
+
+/// Main loop, context and core for one PipeWire session
+///
+/// Streams and listeners created from `core` must be dropped first, which
+/// holds when they are locals declared after the connection. Fields drop in
+/// declaration order: core, then context, then the main loop.
+pub(crate) struct PipeWireConnection {
+    pub core: pw::core::Core,
+    _context: pw::context::Context,
+    pub mainloop: pw::main_loop::MainLoop,
+}
+
+impl PipeWireConnection {
+    /// Initialize PipeWire (once per process) and connect to the daemon
+    pub fn new() -> Result<Self> {
+        pw::init();
+
+        let mainloop = pw::main_loop::MainLoop::new(None)
+            .map_err(|e| Error::PipeWire(format!("Failed to create main loop: {}", e)))?;
+
+        let context = pw::context::Context::new(&mainloop)
+            .map_err(|e| Error::PipeWire(format!("Failed to create context: {}", e)))?;
+
+        let core = context
+            .connect(None)
+            .map_err(|e| Error::PipeWire(format!("Failed to connect to PipeWire: {}", e)))?;
+
+        LIVE_CONNECTIONS.fetch_add(1, Ordering::Relaxed);
+        Ok(Self {
+            core,
+            _context: context,
+            mainloop,
+        })
+    }
+}
+
+impl Drop for PipeWireConnection {
+    fn drop(&mut self) {
+        LIVE_CONNECTIONS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_fds() -> usize {
+        std::fs::read_dir("/proc/self/fd").map_or(0, |dir| dir.count())
+    }
+
+    /// Open and drop connections, checking the process's fd count
+    ///
+    /// Counting fds is only exact with nothing else running, so this runs
+    /// alone in a child process (see `test_repeated_connections_release_fds`).
+    #[test]
+    #[ignore]
+    fn repeated_connections_child() {
+        // Without a daemon the connect fails after the loop and context
+        // were created, which exercises the same teardown. The first call
+        // also covers PipeWire's one-time setup.
+        drop(PipeWireConnection::new());
+        let before = open_fds();
+
+        for _ in 0..50 {
+            drop(PipeWireConnection::new());
+        }
+
+        assert_eq!(open_fds(), before);
+        assert_eq!(live_pipewire_connections(), 0);
+    }
+
+    #[test]
+    fn test_repeated_connections_release_fds() {
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--ignored",
+                "--exact",
+                "--test-threads=1",
+                "capture::pw_connection::tests::repeated_connections_child",
+            ])
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{}", stdout);
+        // A renamed child would match nothing and pass silently
+        assert!(stdout.contains("1 passed"), "{}", stdout);
+    }
+}
@@ -0,0 +1,124 @@
+//! Synthetic test input
+//!
+//! A `Capture` that generates a fixed number of patterned frames at a fixed
+//! rate, so pipelines can be exercised without a compositor, portal or
+//! media file (CI, integration tests, benchmarks).
+
+use crate::error::{Error, Result};
+use crate::types::{Frame, FrameFormat, Framerate, Resolution};
+
+use super::Capture;
+
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Capture source producing `frame_count` generated BGRA frames
+///
+/// Frames carry a moving gradient so consecutive frames differ, and PTS
+/// advances by exactly one frame duration (microseconds, starting at 0).
+/// After the last frame `next_frame` returns `Error::CaptureEnded`, which
+/// ends the pipeline like the end of a file input.
+pub struct TestCapture {
+    resolution: Resolution,
+    framerate: Framerate,
+    frame_count: u64,
+    paced: bool,
+    emitted: u64,
+    started_at: Option<Instant>,
+}
+
+impl TestCapture {
+    /// Generate `frame_count` frames of `resolution` at `framerate`
+    pub fn new(resolution: Resolution, framerate: Framerate, frame_count: u64) -> Self {
+        Self {
+            resolution,
+            framerate,
+            frame_count,
+            paced: true,
+            emitted: 0,
+            started_at: None,
+        }
+    }
+
+    /// Deliver frames in real time (default) or as fast as they are consumed
+    pub fn with_pacing(mut self, paced: bool) -> Self {
+        self.paced = paced;
+        self
+    }
+
+    /// Frames generated so far
+    pub fn frames_emitted(&self) -> u64 {
+        self.emitted
+    }
+
+    fn render(&self, index: u64) -> Frame {
+        let Resolution { width, height } = self.resolution;
+        let stride = width * 4;
+        let mut data = vec![0u8; (stride * height) as usize];
+
+        let shift = (index * 4) as u32;
+        for (y, row) in data.chunks_exact_mut(stride as usize).enumerate() {
+            let g = (y as u32 * 255 / height.max(1)) as u8;
+            for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                let b = ((x as u32 + shift) % 256) as u8;
+                pixel.copy_from_slice(&[b, g, index as u8, 0xff]);
+            }
+        }
+
+        let duration = self.framerate.frame_duration_us();
+        let mut frame = Frame::from_data(data, width, height, stride, FrameFormat::Bgra);
+        frame.pts = index as i64 * duration;
+        frame.duration = duration;
+        frame
+    }
+}
+
+#[async_trait::async_trait]
+impl Capture for TestCapture {
+    async fn start(&mut self) -> Result<()> {
+        self.emitted = 0;
+        self.started_at = Some(Instant::now());
+        tracing::info!(
+            "Test capture started ({} frames, {} @ {})",
+            self.frame_count,
+            self.resolution,
+            self.framerate
+        );
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        self.started_at = None;
+        Ok(())
+    }
+
+    async fn next_frame(&mut self) -> Result<Frame> {
+        let Some(started_at) = self.started_at else {
+            return Err(Error::CaptureNotStarted);
+        };
+        if self.emitted >= self.frame_count {
+            return Err(Error::CaptureEnded);
+        }
+
+        if self.paced {
+            let offset = self.emitted as i64 * self.framerate.frame_duration_us();
+            tokio::time::sleep_until(started_at + Duration::from_micros(offset as u64)).await;
+        }
+
+        let frame = self.render(self.emitted);
+        self.emitted += 1;
+        Ok(frame)
+    }
+
+    fn is_active(&self) -> bool {
+        self.started_at.is_some()
+    }
+
+    fn resolution(&self) -> Option<Resolution> {
+        Some(self.resolution)
+    }
+
+    fn framerate(&self) -> Option<Framerate> {
+        Some(self.framerate)
+    }
+}
@@ -0,0 +1,857 @@
+//! V4L2 webcam capture
+//!
+//! `V4l2Capture` reads a Video4Linux2 device (`/dev/video*`: USB webcams,
+//! HDMI capture cards) with memory-mapped streaming I/O. Of the formats
+//! the device offers, NV12 is taken first, then YUYV (repacked to planar
+//! 4:2:2) and then MJPEG, which is decoded with FFmpeg. A raw format is
+//! only passed over when it can't deliver the requested size: most USB
+//! cameras reach their larger modes only compressed.
+//!
+//! Selected with `CaptureBackend::V4l2`, never picked automatically; the
+//! device is `CaptureConfig::v4l2_device`. `list_v4l2_devices` finds the
+//! capture devices of the system.
+
+use crate::config::CaptureConfig;
+use crate::error::{Error, Result};
+use crate::processing::ColorRange;
+use crate::types::{Frame, FrameFormat, Framerate, Resolution};
+
+use super::portal::wall_clock_us;
+use super::Capture;
+
+use ffmpeg_next as ffmpeg;
+use ffmpeg_next::format::Pixel;
+use std::ffi::{c_int, c_ulong, c_void};
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use tokio::io::unix::AsyncFd;
+
+/// Device opened when `CaptureConfig::v4l2_device` is not set
+const DEFAULT_DEVICE: &str = "/dev/video0";
+
+/// Buffers queued with the driver
+const BUFFER_COUNT: u32 = 4;
+
+const BUF_TYPE_VIDEO_CAPTURE: u32 = 1;
+const MEMORY_MMAP: u32 = 1;
+const FIELD_NONE: u32 = 1;
+const CAP_VIDEO_CAPTURE: u32 = 0x0000_0001;
+const CAP_STREAMING: u32 = 0x0400_0000;
+const CAP_DEVICE_CAPS: u32 = 0x8000_0000;
+const BUF_FLAG_ERROR: u32 = 0x0040;
+
+// V4L2 structures, laid out as in linux/videodev2.h
+
+#[repr(C)]
+#[allow(dead_code)]
+struct Capability {
+    driver: [u8; 16],
+    card: [u8; 32],
+    bus_info: [u8; 32],
+    version: u32,
+    capabilities: u32,
+    device_caps: u32,
+    reserved: [u32; 3],
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+struct FmtDesc {
+    index: u32,
+    kind: u32,
+    flags: u32,
+    description: [u8; 32],
+    pixelformat: u32,
+    mbus_code: u32,
+    reserved: [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+struct PixFormat {
+    width: u32,
+    height: u32,
+    pixelformat: u32,
+    field: u32,
+    bytesperline: u32,
+    sizeimage: u32,
+    colorspace: u32,
+    private: u32,
+    flags: u32,
+    ycbcr_enc: u32,
+    quantization: u32,
+    xfer_func: u32,
+}
+
+/// The kernel's format union; the pointer gives it the same alignment
+#[repr(C)]
+#[allow(dead_code)]
+union FormatData {
+    pix: PixFormat,
+    raw: [u8; 200],
+    align: *mut c_void,
+}
+
+#[repr(C)]
+struct Format {
+    kind: u32,
+    fmt: FormatData,
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+struct RequestBuffers {
+    count: u32,
+    kind: u32,
+    memory: u32,
+    capabilities: u32,
+    flags: u8,
+    reserved: [u8; 3],
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+struct Timecode {
+    kind: u32,
+    flags: u32,
+    frames: u8,
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    userbits: [u8; 4],
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+union BufferLocation {
+    offset: u32,
+    userptr: c_ulong,
+    planes: *mut c_void,
+    fd: i32,
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+struct Buffer {
+    index: u32,
+    kind: u32,
+    bytesused: u32,
+    flags: u32,
+    field: u32,
+    timestamp: libc::timeval,
+    timecode: Timecode,
+    sequence: u32,
+    memory: u32,
+    m: BufferLocation,
+    length: u32,
+    reserved2: u32,
+    request_fd: i32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Fract {
+    numerator: u32,
+    denominator: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+struct CaptureParm {
+    capability: u32,
+    capturemode: u32,
+    timeperframe: Fract,
+    extendedmode: u32,
+    readbuffers: u32,
+    reserved: [u32; 4],
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+union StreamParmData {
+    capture: CaptureParm,
+    raw: [u8; 200],
+}
+
+#[repr(C)]
+struct StreamParm {
+    kind: u32,
+    parm: StreamParmData,
+}
+
+const fn ioc(dir: u32, nr: u32, size: usize) -> c_ulong {
+    ((dir << 30) | ((size as u32) << 16) | ((b'V' as u32) << 8) | nr) as c_ulong
+}
+
+const IOC_WRITE: u32 = 1;
+const IOC_READ: u32 = 2;
+const IOC_RW: u32 = IOC_READ | IOC_WRITE;
+
+const VIDIOC_QUERYCAP: c_ulong = ioc(IOC_READ, 0, std::mem::size_of::<Capability>());
+const VIDIOC_ENUM_FMT: c_ulong = ioc(IOC_RW, 2, std::mem::size_of::<FmtDesc>());
+const VIDIOC_G_FMT: c_ulong = ioc(IOC_RW, 4, std::mem::size_of::<Format>());
+const VIDIOC_S_FMT: c_ulong = ioc(IOC_RW, 5, std::mem::size_of::<Format>());
+const VIDIOC_REQBUFS: c_ulong = ioc(IOC_RW, 8, std::mem::size_of::<RequestBuffers>());
+const VIDIOC_QUERYBUF: c_ulong = ioc(IOC_RW, 9, std::mem::size_of::<Buffer>());
+const VIDIOC_QBUF: c_ulong = ioc(IOC_RW, 15, std::mem::size_of::<Buffer>());
+const VIDIOC_DQBUF: c_ulong = ioc(IOC_RW, 17, std::mem::size_of::<Buffer>());
+const VIDIOC_STREAMON: c_ulong = ioc(IOC_WRITE, 18, std::mem::size_of::<c_int>());
+const VIDIOC_STREAMOFF: c_ulong = ioc(IOC_WRITE, 19, std::mem::size_of::<c_int>());
+const VIDIOC_S_PARM: c_ulong = ioc(IOC_RW, 22, std::mem::size_of::<StreamParm>());
+const VIDIOC_TRY_FMT: c_ulong = ioc(IOC_RW, 64, std::mem::size_of::<Format>());
+
+/// ioctl on a V4L2 device, retried when interrupted
+fn xioctl<T>(fd: RawFd, request: c_ulong, arg: &mut T) -> std::io::Result<()> {
+    loop {
+        if unsafe { libc::ioctl(fd, request as _, arg as *mut T) } != -1 {
+            return Ok(());
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// All-zero V4L2 structure, how the API expects them to be initialized
+fn zeroed<T>() -> T {
+    // Only used for the plain-data structures above
+    unsafe { std::mem::zeroed() }
+}
+
+/// NUL-terminated string field of a V4L2 structure
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+/// Pixel formats `V4l2Capture` can read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum V4l2PixelFormat {
+    /// 4:2:0, Y plane + interleaved UV
+    Nv12,
+    /// Packed 4:2:2 (Y0 U Y1 V)
+    Yuyv,
+    /// Motion JPEG, decoded on the CPU
+    Mjpeg,
+}
+
+impl V4l2PixelFormat {
+    /// Negotiation order
+    const PREFERENCE: [Self; 3] = [Self::Nv12, Self::Yuyv, Self::Mjpeg];
+
+    fn fourcc(self) -> u32 {
+        u32::from_le_bytes(match self {
+            Self::Nv12 => *b"NV12",
+            Self::Yuyv => *b"YUYV",
+            Self::Mjpeg => *b"MJPG",
+        })
+    }
+
+    fn from_fourcc(fourcc: u32) -> Option<Self> {
+        Self::PREFERENCE
+            .into_iter()
+            .find(|format| format.fourcc() == fourcc)
+    }
+}
+
+/// A V4L2 video capture device
+#[derive(Debug, Clone)]
+pub struct V4l2Device {
+    /// Device node, e.g. `/dev/video0`
+    pub path: PathBuf,
+    /// Card name reported by the driver
+    pub name: String,
+    pub driver: String,
+    /// Where the device is attached (e.g. `usb-0000:00:14.0-2`)
+    pub bus_info: String,
+    /// Formats it offers that `V4l2Capture` can read
+    pub formats: Vec<V4l2PixelFormat>,
+}
+
+/// Video capture devices of the system, in device node order
+///
+/// Nodes a camera exposes for metadata only are left out.
+pub fn list_v4l2_devices() -> Vec<V4l2Device> {
+    let Ok(entries) = std::fs::read_dir("/dev") else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("video"))
+        .map(|entry| entry.path())
+        .collect();
+    paths.sort_by_key(|path| {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        name.trim_start_matches("video")
+            .parse::<u32>()
+            .unwrap_or(u32::MAX)
+    });
+
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let fd = open_device(&path).ok()?;
+            let caps = query_capability(fd.as_raw_fd()).ok()?;
+            if device_caps(&caps) & CAP_VIDEO_CAPTURE == 0 {
+                return None;
+            }
+            Some(V4l2Device {
+                name: c_string(&caps.card),
+                driver: c_string(&caps.driver),
+                bus_info: c_string(&caps.bus_info),
+                formats: supported_formats(fd.as_raw_fd()),
+                path,
+            })
+        })
+        .collect()
+}
+
+fn open_device(path: &Path) -> Result<OwnedFd> {
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
+        .open(path)
+        .map_err(|e| Error::V4l2(format!("Cannot open {}: {}", path.display(), e)))?;
+    Ok(file.into())
+}
+
+fn query_capability(fd: RawFd) -> Result<Capability> {
+    let mut caps: Capability = zeroed();
+    xioctl(fd, VIDIOC_QUERYCAP, &mut caps)
+        .map_err(|e| Error::V4l2(format!("Not a V4L2 device: {}", e)))?;
+    Ok(caps)
+}
+
+/// Capabilities of the opened node rather than the whole device
+fn device_caps(caps: &Capability) -> u32 {
+    if caps.capabilities & CAP_DEVICE_CAPS != 0 {
+        caps.device_caps
+    } else {
+        caps.capabilities
+    }
+}
+
+/// Readable formats the device offers, in the driver's order
+fn supported_formats(fd: RawFd) -> Vec<V4l2PixelFormat> {
+    let mut formats = Vec::new();
+    for index in 0.. {
+        let mut desc: FmtDesc = zeroed();
+        desc.index = index;
+        desc.kind = BUF_TYPE_VIDEO_CAPTURE;
+        if xioctl(fd, VIDIOC_ENUM_FMT, &mut desc).is_err() {
+            break;
+        }
+        if let Some(format) = V4l2PixelFormat::from_fourcc(desc.pixelformat) {
+            formats.push(format);
+        }
+    }
+    formats
+}
+
+fn capture_format(format: V4l2PixelFormat, size: Resolution) -> Format {
+    let mut request: Format = zeroed();
+    request.kind = BUF_TYPE_VIDEO_CAPTURE;
+    request.fmt.pix = PixFormat {
+        width: size.width,
+        height: size.height,
+        pixelformat: format.fourcc(),
+        field: FIELD_NONE,
+        ..unsafe { request.fmt.pix }
+    };
+    request
+}
+
+/// Webcam / capture card input
+pub struct V4l2Capture {
+    config: CaptureConfig,
+    device: PathBuf,
+    session: Option<V4l2Session>,
+    partial_frames: u64,
+}
+
+impl V4l2Capture {
+    /// Create a capture for `config`; the device is opened by `start`
+    pub fn new(config: CaptureConfig) -> Self {
+        let device = config
+            .v4l2_device
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_DEVICE));
+        Self {
+            config,
+            device,
+            session: None,
+            partial_frames: 0,
+        }
+    }
+
+    /// Pixel format negotiated with the device (None before `start`)
+    pub fn pixel_format(&self) -> Option<V4l2PixelFormat> {
+        self.session.as_ref().map(|s| s.format)
+    }
+}
+
+#[async_trait::async_trait]
+impl Capture for V4l2Capture {
+    async fn start(&mut self) -> Result<()> {
+        if self.session.is_some() {
+            return Ok(());
+        }
+        let session = V4l2Session::open(&self.device, &self.config)?;
+        tracing::info!(
+            "V4L2 capture started ({}, {:?} {} @ {})",
+            self.device.display(),
+            session.format,
+            session.size,
+            session.framerate
+        );
+        self.session = Some(session);
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        self.session = None;
+        Ok(())
+    }
+
+    async fn next_frame(&mut self) -> Result<Frame> {
+        let Some(session) = self.session.as_mut() else {
+            return Err(Error::CaptureNotStarted);
+        };
+
+        loop {
+            let buffer = loop {
+                let mut guard = session
+                    .fd
+                    .readable()
+                    .await
+                    .map_err(|e| Error::V4l2(format!("Device poll failed: {}", e)))?;
+                if let Ok(result) = guard.try_io(|fd| dequeue(fd.as_raw_fd())) {
+                    break result.map_err(|e| Error::V4l2(format!("Dequeue failed: {}", e)))?;
+                }
+            };
+
+            // Copy the frame out, then give the buffer straight back
+            let corrupt = buffer.flags & BUF_FLAG_ERROR != 0 || buffer.bytesused == 0;
+            let frame = (!corrupt).then(|| session.frame(&buffer));
+            session.queue(buffer)?;
+            match frame {
+                Some(frame) => {
+                    let mut frame = frame?;
+                    frame.pts = wall_clock_us();
+                    frame.duration = session.framerate.frame_duration_us();
+                    return Ok(frame);
+                }
+                None => {
+                    self.partial_frames += 1;
+                    tracing::trace!("Skipping corrupt V4L2 buffer");
+                }
+            }
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.session.is_some()
+    }
+
+    fn resolution(&self) -> Option<Resolution> {
+        self.session.as_ref().map(|s| s.size)
+    }
+
+    fn framerate(&self) -> Option<Framerate> {
+        self.session.as_ref().map(|s| s.framerate)
+    }
+
+    fn partial_frames(&self) -> u64 {
+        self.partial_frames
+    }
+}
+
+fn dequeue(fd: RawFd) -> std::io::Result<Buffer> {
+    let mut buffer: Buffer = zeroed();
+    buffer.kind = BUF_TYPE_VIDEO_CAPTURE;
+    buffer.memory = MEMORY_MMAP;
+    xioctl(fd, VIDIOC_DQBUF, &mut buffer)?;
+    Ok(buffer)
+}
+
+/// A driver buffer mapped into our address space
+struct Mapping {
+    ptr: *mut c_void,
+    len: usize,
+}
+
+/// An open, streaming device
+struct V4l2Session {
+    fd: AsyncFd<OwnedFd>,
+    buffers: Vec<Mapping>,
+    streaming: bool,
+    format: V4l2PixelFormat,
+    size: Resolution,
+    bytes_per_line: usize,
+    framerate: Framerate,
+    decoder: Option<MjpegDecoder>,
+}
+
+// The mappings and the decoder are only ever used through
+// `&mut V4l2Capture`, one call at a time
+unsafe impl Send for V4l2Session {}
+unsafe impl Sync for V4l2Session {}
+
+impl V4l2Session {
+    fn open(path: &Path, config: &CaptureConfig) -> Result<Self> {
+        let fd = open_device(path)?;
+        let raw = fd.as_raw_fd();
+        let caps = query_capability(raw)?;
+        let caps = device_caps(&caps);
+        if caps & CAP_VIDEO_CAPTURE == 0 || caps & CAP_STREAMING == 0 {
+            return Err(Error::V4l2(format!(
+                "{} is not a streaming video capture device",
+                path.display()
+            )));
+        }
+
+        let (format, pix) = negotiate_format(raw, config.v4l2_resolution)?;
+        let framerate = set_framerate(raw, config.framerate);
+        let decoder = match format {
+            V4l2PixelFormat::Mjpeg => Some(MjpegDecoder::new()?),
+            _ => None,
+        };
+
+        let mut session = Self {
+            fd: AsyncFd::new(fd)
+                .map_err(|e| Error::V4l2(format!("Cannot poll {}: {}", path.display(), e)))?,
+            buffers: Vec::new(),
+            streaming: false,
+            format,
+            size: Resolution::new(pix.width, pix.height),
+            bytes_per_line: pix.bytesperline as usize,
+            framerate,
+            decoder,
+        };
+        session.map_buffers()?;
+
+        let mut kind = BUF_TYPE_VIDEO_CAPTURE as c_int;
+        xioctl(raw, VIDIOC_STREAMON, &mut kind)
+            .map_err(|e| Error::V4l2(format!("Failed to start streaming: {}", e)))?;
+        session.streaming = true;
+        Ok(session)
+    }
+
+    /// Request, map and queue the driver's buffers
+    fn map_buffers(&mut self) -> Result<()> {
+        let fd = self.fd.as_raw_fd();
+        let mut request: RequestBuffers = zeroed();
+        request.count = BUFFER_COUNT;
+        request.kind = BUF_TYPE_VIDEO_CAPTURE;
+        request.memory = MEMORY_MMAP;
+        xioctl(fd, VIDIOC_REQBUFS, &mut request)
+            .map_err(|e| Error::V4l2(format!("Failed to request buffers: {}", e)))?;
+        if request.count < 2 {
+            return Err(Error::V4l2("Device granted too few buffers".into()));
+        }
+
+        for index in 0..request.count {
+            let mut buffer: Buffer = zeroed();
+            buffer.index = index;
+            buffer.kind = BUF_TYPE_VIDEO_CAPTURE;
+            buffer.memory = MEMORY_MMAP;
+            xioctl(fd, VIDIOC_QUERYBUF, &mut buffer)
+                .map_err(|e| Error::V4l2(format!("Failed to query buffer: {}", e)))?;
+
+            let len = buffer.length as usize;
+            let ptr = unsafe {
+                libc::mmap(
+                    std::ptr::null_mut(),
+                    len,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_SHARED,
+                    fd,
+                    buffer.m.offset as libc::off_t,
+                )
+            };
+            if ptr == libc::MAP_FAILED {
+                return Err(Error::V4l2(format!(
+                    "Failed to map buffer: {}",
+                    std::io::Error::last_os_error()
+                )));
+            }
+            self.buffers.push(Mapping { ptr, len });
+            self.queue(buffer)?;
+        }
+        Ok(())
+    }
+
+    /// Hand a buffer (back) to the driver
+    fn queue(&self, mut buffer: Buffer) -> Result<()> {
+        xioctl(self.fd.as_raw_fd(), VIDIOC_QBUF, &mut buffer)
+            .map_err(|e| Error::V4l2(format!("Failed to queue buffer: {}", e)))
+    }
+
+    /// Frame from the contents of a dequeued buffer
+    fn frame(&mut self, buffer: &Buffer) -> Result<Frame> {
+        let mapping = self
+            .buffers
+            .get(buffer.index as usize)
+            .ok_or_else(|| Error::V4l2(format!("Unknown buffer {}", buffer.index)))?;
+        let len = (buffer.bytesused as usize).min(mapping.len);
+        let data = unsafe { std::slice::from_raw_parts(mapping.ptr as *const u8, len) };
+
+        let (width, height) = (self.size.width, self.size.height);
+        let short = || Error::V4l2(format!("Short {:?} buffer ({} bytes)", self.format, len));
+        let (data, format) = match self.format {
+            V4l2PixelFormat::Nv12 => {
+                let rows = height + height.div_ceil(2);
+                let data = pack_rows(data, width as usize, self.bytes_per_line, rows as usize);
+                (data.ok_or_else(short)?, FrameFormat::Nv12)
+            }
+            V4l2PixelFormat::Yuyv => {
+                let (width, height) = (width as usize, height as usize);
+                let data = yuyv_to_yuv422p(data, width, height, self.bytes_per_line);
+                (data.ok_or_else(short)?, FrameFormat::Yuv422p)
+            }
+            V4l2PixelFormat::Mjpeg => {
+                return match self.decoder.as_mut() {
+                    Some(decoder) => decoder.decode(data),
+                    None => Err(Error::Internal("MJPEG decoder missing".into())),
+                };
+            }
+        };
+        Ok(Frame::from_data(data, width, height, width, format))
+    }
+}
+
+impl Drop for V4l2Session {
+    fn drop(&mut self) {
+        let fd = self.fd.as_raw_fd();
+        if self.streaming {
+            let mut kind = BUF_TYPE_VIDEO_CAPTURE as c_int;
+            let _ = xioctl(fd, VIDIOC_STREAMOFF, &mut kind);
+        }
+        for mapping in self.buffers.drain(..) {
+            unsafe { libc::munmap(mapping.ptr, mapping.len) };
+        }
+    }
+}
+
+/// Pick and set the capture format
+///
+/// Without a requested size the device's current one is kept.
+fn negotiate_format(
+    fd: RawFd,
+    requested: Option<Resolution>,
+) -> Result<(V4l2PixelFormat, PixFormat)> {
+    let supported = supported_formats(fd);
+    let Some(&fallback) = V4l2PixelFormat::PREFERENCE
+        .iter()
+        .find(|format| supported.contains(format))
+    else {
+        return Err(Error::V4l2(
+            "Device offers none of NV12, YUYV or MJPEG".into(),
+        ));
+    };
+
+    let mut current: Format = zeroed();
+    current.kind = BUF_TYPE_VIDEO_CAPTURE;
+    xioctl(fd, VIDIOC_G_FMT, &mut current)
+        .map_err(|e| Error::V4l2(format!("Failed to read the capture format: {}", e)))?;
+    let current = unsafe { current.fmt.pix };
+    let size = requested.unwrap_or(Resolution::new(current.width, current.height));
+
+    // First format the driver can deliver at the requested size
+    let exact = V4l2PixelFormat::PREFERENCE
+        .into_iter()
+        .filter(|format| supported.contains(format))
+        .find(|&format| {
+            let mut probe = capture_format(format, size);
+            xioctl(fd, VIDIOC_TRY_FMT, &mut probe).is_ok() && {
+                let pix = unsafe { probe.fmt.pix };
+                pix.width == size.width && pix.height == size.height
+            }
+        });
+
+    let mut request = capture_format(exact.unwrap_or(fallback), size);
+    xioctl(fd, VIDIOC_S_FMT, &mut request)
+        .map_err(|e| Error::V4l2(format!("Failed to set the capture format: {}", e)))?;
+    let pix = unsafe { request.fmt.pix };
+    let format = V4l2PixelFormat::from_fourcc(pix.pixelformat).ok_or_else(|| {
+        Error::V4l2(format!(
+            "Driver switched to unsupported format {:?}",
+            pix.pixelformat.to_le_bytes().map(char::from)
+        ))
+    })?;
+    if pix.width != size.width || pix.height != size.height {
+        tracing::warn!(
+            "V4L2 device can't capture {}, using {}x{}",
+            size,
+            pix.width,
+            pix.height
+        );
+    }
+    Ok((format, pix))
+}
+
+/// Ask for `framerate`; returns the rate the driver settled on
+fn set_framerate(fd: RawFd, framerate: Framerate) -> Framerate {
+    let mut parm: StreamParm = zeroed();
+    parm.kind = BUF_TYPE_VIDEO_CAPTURE;
+    parm.parm.capture = CaptureParm {
+        timeperframe: Fract {
+            numerator: framerate.den,
+            denominator: framerate.num,
+        },
+        ..unsafe { parm.parm.capture }
+    };
+    if let Err(e) = xioctl(fd, VIDIOC_S_PARM, &mut parm) {
+        tracing::debug!("V4L2 device doesn't take a frame rate: {}", e);
+        return framerate;
+    }
+    let interval = unsafe { parm.parm.capture.timeperframe };
+    if interval.numerator == 0 || interval.denominator == 0 {
+        return framerate;
+    }
+    Framerate::new(interval.denominator, interval.numerator)
+}
+
+/// `rows` rows of `width` bytes out of rows `stride` apart
+fn pack_rows(data: &[u8], width: usize, stride: usize, rows: usize) -> Option<Vec<u8>> {
+    let stride = stride.max(width);
+    let mut packed = Vec::with_capacity(width * rows);
+    for row in 0..rows {
+        packed.extend_from_slice(data.get(row * stride..row * stride + width)?);
+    }
+    Some(packed)
+}
+
+/// Packed YUYV to planar 4:2:2 (tightly packed Y, U and V planes)
+fn yuyv_to_yuv422p(data: &[u8], width: usize, height: usize, stride: usize) -> Option<Vec<u8>> {
+    let stride = stride.max(width * 2);
+    let chroma_width = width.div_ceil(2);
+    let luma_size = width * height;
+    let chroma_size = chroma_width * height;
+    let mut planar = vec![0u8; luma_size + chroma_size * 2];
+    let (luma, chroma) = planar.split_at_mut(luma_size);
+    let (u_plane, v_plane) = chroma.split_at_mut(chroma_size);
+
+    for row in 0..height {
+        let line = data.get(row * stride..row * stride + chroma_width * 4)?;
+        for (col, pair) in line.chunks_exact(4).enumerate() {
+            let x = col * 2;
+            luma[row * width + x] = pair[0];
+            if x + 1 < width {
+                luma[row * width + x + 1] = pair[2];
+            }
+            u_plane[row * chroma_width + col] = pair[1];
+            v_plane[row * chroma_width + col] = pair[3];
+        }
+    }
+    Some(planar)
+}
+
+/// FFmpeg decoder for MJPEG camera frames
+struct MjpegDecoder {
+    decoder: ffmpeg::decoder::Video,
+}
+
+impl MjpegDecoder {
+    fn new() -> Result<Self> {
+        let _ = ffmpeg::init();
+        let codec = ffmpeg::decoder::find(ffmpeg::codec::Id::MJPEG)
+            .ok_or_else(|| Error::V4l2("MJPEG decoder not found".into()))?;
+        let decoder = ffmpeg::codec::context::Context::new_with_codec(codec)
+            .decoder()
+            .video()
+            .map_err(|e| Error::V4l2(format!("Failed to open MJPEG decoder: {}", e)))?;
+        Ok(Self { decoder })
+    }
+
+    fn decode(&mut self, jpeg: &[u8]) -> Result<Frame> {
+        self.decoder
+            .send_packet(&ffmpeg::Packet::copy(jpeg))
+            .map_err(|e| Error::V4l2(format!("Failed to decode MJPEG frame: {}", e)))?;
+        let mut decoded = ffmpeg::frame::Video::empty();
+        self.decoder
+            .receive_frame(&mut decoded)
+            .map_err(|e| Error::V4l2(format!("Failed to decode MJPEG frame: {}", e)))?;
+
+        let (format, jpeg_range) = match decoded.format() {
+            Pixel::YUVJ420P => (FrameFormat::Yuv420p, true),
+            Pixel::YUVJ422P => (FrameFormat::Yuv422p, true),
+            Pixel::YUVJ444P => (FrameFormat::Yuv444p, true),
+            Pixel::YUV420P => (FrameFormat::Yuv420p, false),
+            Pixel::YUV422P => (FrameFormat::Yuv422p, false),
+            Pixel::YUV444P => (FrameFormat::Yuv444p, false),
+            other => {
+                return Err(Error::V4l2(format!(
+                    "Unsupported MJPEG pixel format {:?}",
+                    other
+                )));
+            }
+        };
+        let (width, height) = (decoded.width(), decoded.height());
+        let (chroma_width, chroma_height) = format.chroma_size(width, height).unwrap_or_default();
+
+        // Copy the planes out without FFmpeg's row padding
+        let mut data = Vec::new();
+        let planes = [
+            (width, height),
+            (chroma_width, chroma_height),
+            (chroma_width, chroma_height),
+        ];
+        for (plane, (plane_width, rows)) in planes.into_iter().enumerate() {
+            let packed = pack_rows(
+                decoded.data(plane),
+                plane_width as usize,
+                decoded.stride(plane),
+                rows as usize,
+            )
+            .ok_or_else(|| Error::V4l2("Decoded MJPEG frame is truncated".into()))?;
+            data.extend_from_slice(&packed);
+        }
+
+        let mut frame = Frame::from_data(data, width, height, width, format);
+        // Camera JPEGs are full range whether or not FFmpeg says so with a
+        // YUVJ format
+        let full = jpeg_range || decoded.color_range() == ffmpeg::color::Range::JPEG;
+        frame.color_range = Some(if full {
+            ColorRange::Full
+        } else {
+            ColorRange::Limited
+        });
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_yuyv_to_planar() {
+        // 4x2 with 4 bytes of row padding
+        let row = |y: u8| [y, 1, y + 1, 2, y + 2, 3, y + 3, 4, 0, 0, 0, 0];
+        let data: Vec<u8> = [row(10), row(20)].concat();
+        let planar = yuyv_to_yuv422p(&data, 4, 2, 12).unwrap();
+        assert_eq!(
+            planar,
+            [10, 11, 12, 13, 20, 21, 22, 23, 1, 3, 1, 3, 2, 4, 2, 4]
+        );
+        assert!(yuyv_to_yuv422p(&data[..16], 4, 2, 12).is_none());
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_ioctl_numbers() {
+        // As computed by the kernel headers on 64-bit targets
+        assert_eq!(VIDIOC_QUERYCAP, 0x8068_5600);
+        assert_eq!(VIDIOC_S_FMT, 0xc0d0_5605);
+        assert_eq!(VIDIOC_REQBUFS, 0xc014_5608);
+        assert_eq!(VIDIOC_DQBUF, 0xc058_5611);
+        assert_eq!(VIDIOC_STREAMON, 0x4004_5612);
+        assert_eq!(VIDIOC_S_PARM, 0xc0cc_5616);
+    }
+}
@@ -0,0 +1,941 @@
+//! Native X11 screen capture
+//!
+//! `X11Capture` reads the screen through the MIT-SHM extension: the X
+//! server copies each frame straight into a shared memory segment instead
+//! of sending it over the socket. A single window (`CaptureConfig::x11_window`)
+//! is captured through XComposite: the window is redirected off-screen and
+//! its backing pixmap read the same way, so windows overlapping it don't
+//! end up in the capture. The cursor is drawn in with XFixes, or reported
+//! as `Frame::cursor` for `CursorMode::Metadata`.
+//!
+//! Used where no desktop portal is available (`CaptureBackend::X11`, picked
+//! automatically on X11 sessions). Xlib and its extensions are loaded at
+//! runtime, so Wayland-only systems need none of them.
+
+use crate::config::{CaptureConfig, CursorMode};
+use crate::error::{Error, Result};
+use crate::types::{CursorInfo, Frame, FrameFormat, Framerate, Resolution};
+
+use super::portal::wall_clock_us;
+use super::Capture;
+
+use std::ffi::{c_char, c_int, c_long, c_short, c_uint, c_ulong, c_ushort, c_void, CString};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::time::Instant;
+
+type Display = c_void;
+type Window = c_ulong;
+type Drawable = c_ulong;
+type Pixmap = c_ulong;
+type Bool = c_int;
+
+const ZPIXMAP: c_int = 2;
+const COMPOSITE_REDIRECT_AUTOMATIC: c_int = 0;
+const IS_VIEWABLE: c_int = 2;
+const BUTTON1_MASK: c_uint = 1 << 8;
+const BUTTON2_MASK: c_uint = 1 << 9;
+const BUTTON3_MASK: c_uint = 1 << 10;
+
+// Xlib/XShm/XFixes structures, laid out as in their C headers
+
+#[repr(C)]
+#[allow(dead_code)]
+struct XImage {
+    width: c_int,
+    height: c_int,
+    xoffset: c_int,
+    format: c_int,
+    data: *mut c_char,
+    byte_order: c_int,
+    bitmap_unit: c_int,
+    bitmap_bit_order: c_int,
+    bitmap_pad: c_int,
+    depth: c_int,
+    bytes_per_line: c_int,
+    bits_per_pixel: c_int,
+    red_mask: c_ulong,
+    green_mask: c_ulong,
+    blue_mask: c_ulong,
+    obdata: *mut c_char,
+    create_image: *mut c_void,
+    destroy_image: Option<unsafe extern "C" fn(*mut XImage) -> c_int>,
+    get_pixel: *mut c_void,
+    put_pixel: *mut c_void,
+    sub_image: *mut c_void,
+    add_pixel: *mut c_void,
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+struct XShmSegmentInfo {
+    shmseg: c_ulong,
+    shmid: c_int,
+    shmaddr: *mut c_char,
+    read_only: Bool,
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+struct XWindowAttributes {
+    x: c_int,
+    y: c_int,
+    width: c_int,
+    height: c_int,
+    border_width: c_int,
+    depth: c_int,
+    visual: *mut c_void,
+    root: Window,
+    class: c_int,
+    bit_gravity: c_int,
+    win_gravity: c_int,
+    backing_store: c_int,
+    backing_planes: c_ulong,
+    backing_pixel: c_ulong,
+    save_under: Bool,
+    colormap: c_ulong,
+    map_installed: Bool,
+    map_state: c_int,
+    all_event_masks: c_long,
+    your_event_mask: c_long,
+    do_not_propagate_mask: c_long,
+    override_redirect: Bool,
+    screen: *mut c_void,
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+struct XErrorEvent {
+    kind: c_int,
+    display: *mut Display,
+    resource_id: c_ulong,
+    serial: c_ulong,
+    error_code: u8,
+    request_code: u8,
+    minor_code: u8,
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+struct XFixesCursorImage {
+    x: c_short,
+    y: c_short,
+    width: c_ushort,
+    height: c_ushort,
+    xhot: c_ushort,
+    yhot: c_ushort,
+    cursor_serial: c_ulong,
+    /// ARGB, premultiplied, one pixel per `long`
+    pixels: *mut c_ulong,
+    atom: c_ulong,
+    name: *const c_char,
+}
+
+type ErrorHandler = unsafe extern "C" fn(*mut Display, *mut XErrorEvent) -> c_int;
+
+/// Xlib, XShm and the optional XComposite/XFixes entry points
+struct Xlib {
+    open_display: unsafe extern "C" fn(*const c_char) -> *mut Display,
+    close_display: unsafe extern "C" fn(*mut Display) -> c_int,
+    default_root_window: unsafe extern "C" fn(*mut Display) -> Window,
+    get_window_attributes:
+        unsafe extern "C" fn(*mut Display, Window, *mut XWindowAttributes) -> c_int,
+    translate_coordinates: unsafe extern "C" fn(
+        *mut Display,
+        Window,
+        Window,
+        c_int,
+        c_int,
+        *mut c_int,
+        *mut c_int,
+        *mut Window,
+    ) -> Bool,
+    query_pointer: unsafe extern "C" fn(
+        *mut Display,
+        Window,
+        *mut Window,
+        *mut Window,
+        *mut c_int,
+        *mut c_int,
+        *mut c_int,
+        *mut c_int,
+        *mut c_uint,
+    ) -> Bool,
+    sync: unsafe extern "C" fn(*mut Display, Bool) -> c_int,
+    free: unsafe extern "C" fn(*mut c_void) -> c_int,
+    free_pixmap: unsafe extern "C" fn(*mut Display, Pixmap) -> c_int,
+    set_error_handler: unsafe extern "C" fn(Option<ErrorHandler>) -> Option<ErrorHandler>,
+    shm_query_extension: unsafe extern "C" fn(*mut Display) -> Bool,
+    shm_create_image: unsafe extern "C" fn(
+        *mut Display,
+        *mut c_void,
+        c_uint,
+        c_int,
+        *mut c_char,
+        *mut XShmSegmentInfo,
+        c_uint,
+        c_uint,
+    ) -> *mut XImage,
+    shm_attach: unsafe extern "C" fn(*mut Display, *mut XShmSegmentInfo) -> Bool,
+    shm_detach: unsafe extern "C" fn(*mut Display, *mut XShmSegmentInfo) -> Bool,
+    shm_get_image:
+        unsafe extern "C" fn(*mut Display, Drawable, *mut XImage, c_int, c_int, c_ulong) -> Bool,
+    composite: Option<Composite>,
+    get_cursor_image: Option<unsafe extern "C" fn(*mut Display) -> *mut XFixesCursorImage>,
+}
+
+struct Composite {
+    query_extension: unsafe extern "C" fn(*mut Display, *mut c_int, *mut c_int) -> Bool,
+    redirect_window: unsafe extern "C" fn(*mut Display, Window, c_int),
+    unredirect_window: unsafe extern "C" fn(*mut Display, Window, c_int),
+    name_window_pixmap: unsafe extern "C" fn(*mut Display, Window) -> Pixmap,
+}
+
+/// Open a shared library that stays loaded for the life of the process
+fn open_library(name: &str) -> Result<*mut c_void> {
+    let c_name = CString::new(name).map_err(|e| Error::X11(e.to_string()))?;
+    let handle = unsafe { libc::dlopen(c_name.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+    if handle.is_null() {
+        return Err(Error::X11(format!("{} not found", name)));
+    }
+    Ok(handle)
+}
+
+/// Look up a function; `F` must be the `extern "C" fn` type matching its
+/// C declaration
+unsafe fn symbol<F: Copy>(library: *mut c_void, name: &str) -> Result<F> {
+    assert_eq!(std::mem::size_of::<F>(), std::mem::size_of::<*mut c_void>());
+    let c_name = CString::new(name).map_err(|e| Error::X11(e.to_string()))?;
+    let pointer = libc::dlsym(library, c_name.as_ptr());
+    if pointer.is_null() {
+        return Err(Error::X11(format!("Missing Xlib function {}", name)));
+    }
+    Ok(std::mem::transmute_copy::<*mut c_void, F>(&pointer))
+}
+
+impl Xlib {
+    fn load() -> Result<Self> {
+        let x11 = open_library("libX11.so.6")?;
+        let xext = open_library("libXext.so.6")?;
+        unsafe {
+            Ok(Self {
+                open_display: symbol(x11, "XOpenDisplay")?,
+                close_display: symbol(x11, "XCloseDisplay")?,
+                default_root_window: symbol(x11, "XDefaultRootWindow")?,
+                get_window_attributes: symbol(x11, "XGetWindowAttributes")?,
+                translate_coordinates: symbol(x11, "XTranslateCoordinates")?,
+                query_pointer: symbol(x11, "XQueryPointer")?,
+                sync: symbol(x11, "XSync")?,
+                free: symbol(x11, "XFree")?,
+                free_pixmap: symbol(x11, "XFreePixmap")?,
+                set_error_handler: symbol(x11, "XSetErrorHandler")?,
+                shm_query_extension: symbol(xext, "XShmQueryExtension")?,
+                shm_create_image: symbol(xext, "XShmCreateImage")?,
+                shm_attach: symbol(xext, "XShmAttach")?,
+                shm_detach: symbol(xext, "XShmDetach")?,
+                shm_get_image: symbol(xext, "XShmGetImage")?,
+                composite: Self::load_composite().ok(),
+                get_cursor_image: open_library("libXfixes.so.3")
+                    .and_then(|xfixes| symbol(xfixes, "XFixesGetCursorImage"))
+                    .ok(),
+            })
+        }
+    }
+
+    fn load_composite() -> Result<Composite> {
+        let library = open_library("libXcomposite.so.1")?;
+        unsafe {
+            Ok(Composite {
+                query_extension: symbol(library, "XCompositeQueryExtension")?,
+                redirect_window: symbol(library, "XCompositeRedirectWindow")?,
+                unredirect_window: symbol(library, "XCompositeUnredirectWindow")?,
+                name_window_pixmap: symbol(library, "XCompositeNameWindowPixmap")?,
+            })
+        }
+    }
+
+    /// The libraries, loaded on first use
+    fn get() -> Result<&'static Self> {
+        static XLIB: OnceLock<std::result::Result<Xlib, String>> = OnceLock::new();
+        XLIB.get_or_init(|| Xlib::load().map_err(|e| e.to_string()))
+            .as_ref()
+            .map_err(|e| Error::X11(e.clone()))
+    }
+}
+
+/// X protocol errors of the open sessions
+///
+/// Xlib's default handler exits the process, e.g. when a captured window
+/// is closed between two frames. While a session is open, `record_error`
+/// is installed instead: it records errors against the session's display,
+/// which checks them after each request, and passes errors on any other
+/// connection to the handler it replaced. The last session to close puts
+/// that handler back.
+struct ErrorTrap {
+    /// Handler in place before the first session opened
+    previous: Option<ErrorHandler>,
+    /// Display of each open session (by address) with the code of its last
+    /// error, 0 if none
+    displays: Vec<(usize, u8)>,
+}
+
+static ERROR_TRAP: parking_lot::Mutex<ErrorTrap> = parking_lot::const_mutex(ErrorTrap {
+    previous: None,
+    displays: Vec::new(),
+});
+
+impl ErrorTrap {
+    /// Start recording errors on `display`
+    fn register(x: &Xlib, display: *mut Display) {
+        let mut trap = ERROR_TRAP.lock();
+        if trap.displays.is_empty() {
+            trap.previous = unsafe { (x.set_error_handler)(Some(record_error)) };
+        }
+        trap.displays.push((display as usize, 0));
+    }
+
+    /// Stop recording errors on a closed `display`
+    fn unregister(x: &Xlib, display: *mut Display) {
+        let mut trap = ERROR_TRAP.lock();
+        if let Some(index) = trap.position(display) {
+            trap.displays.remove(index);
+        }
+        if trap.displays.is_empty() {
+            unsafe { (x.set_error_handler)(trap.previous.take()) };
+        }
+    }
+
+    /// Take the code of the last error on `display`, 0 if none
+    fn take(display: *mut Display) -> u8 {
+        let mut trap = ERROR_TRAP.lock();
+        match trap.position(display) {
+            Some(index) => std::mem::take(&mut trap.displays[index].1),
+            None => 0,
+        }
+    }
+
+    fn position(&self, display: *mut Display) -> Option<usize> {
+        let address = display as usize;
+        self.displays.iter().position(|&(d, _)| d == address)
+    }
+}
+
+unsafe extern "C" fn record_error(display: *mut Display, event: *mut XErrorEvent) -> c_int {
+    let previous = {
+        let mut trap = ERROR_TRAP.lock();
+        match trap.position(display) {
+            Some(index) => {
+                trap.displays[index].1 = (*event).error_code.max(1);
+                return 0;
+            }
+            None => trap.previous,
+        }
+    };
+    previous.map_or(0, |handler| handler(display, event))
+}
+
+/// Capture from an X11 display with XShm (and XComposite for windows)
+///
+/// Frames are BGRA (RGBA on servers with a reversed visual) and paced to
+/// the configured framerate. Captures the whole screen unless
+/// `CaptureConfig::x11_window` names a window. Xlib calls block until the
+/// server answers, so they run on the blocking thread pool.
+pub struct X11Capture {
+    config: CaptureConfig,
+    /// Shared with a grab still running after `next_frame` was cancelled
+    session: Option<Arc<parking_lot::Mutex<X11Session>>>,
+    /// Size of the last frame
+    size: Option<Resolution>,
+    /// When the next frame is due
+    next_due: Option<Instant>,
+}
+
+impl X11Capture {
+    /// Create a capture for `config`; the display is opened by `start`
+    pub fn new(config: CaptureConfig) -> Result<Self> {
+        Xlib::get()?;
+        Ok(Self {
+            config,
+            session: None,
+            size: None,
+            next_due: None,
+        })
+    }
+
+    /// Whether an X11 display is reachable through Xlib
+    pub fn is_available() -> bool {
+        std::env::var_os("DISPLAY").is_some() && Xlib::get().is_ok()
+    }
+
+    /// Whether window capture is possible (XComposite is installed)
+    pub fn supports_windows() -> bool {
+        Xlib::get().is_ok_and(|x| x.composite.is_some())
+    }
+}
+
+#[async_trait::async_trait]
+impl Capture for X11Capture {
+    async fn start(&mut self) -> Result<()> {
+        if self.session.is_some() {
+            return Ok(());
+        }
+        let window = self.config.x11_window;
+        let session = tokio::task::spawn_blocking(move || X11Session::open(Xlib::get()?, window))
+            .await
+            .map_err(|e| Error::Internal(format!("X11 capture task panicked: {}", e)))??;
+        tracing::info!(
+            "X11 capture started ({}, {} @ {})",
+            match self.config.x11_window {
+                Some(window) => format!("window 0x{:x}", window),
+                None => "full screen".to_string(),
+            },
+            session.size,
+            self.config.framerate
+        );
+        self.size = Some(session.size);
+        self.session = Some(Arc::new(parking_lot::Mutex::new(session)));
+        self.next_due = Some(Instant::now());
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        self.session = None;
+        self.size = None;
+        self.next_due = None;
+        Ok(())
+    }
+
+    async fn next_frame(&mut self) -> Result<Frame> {
+        let (Some(session), Some(due)) = (self.session.clone(), self.next_due) else {
+            return Err(Error::CaptureNotStarted);
+        };
+
+        tokio::time::sleep_until(due).await;
+        let interval = Duration::from_micros(self.config.framerate.frame_duration_us() as u64);
+        // A slow frame shifts the schedule instead of bursting to catch up
+        self.next_due = Some((due + interval).max(Instant::now()));
+
+        let cursor = self.config.show_cursor.then_some(self.config.cursor_mode);
+        let grab = tokio::task::spawn_blocking(move || {
+            let mut session = session.lock();
+            let mut frame = session.grab()?;
+            if let Some(mode) = cursor {
+                session.cursor(&mut frame, mode);
+            }
+            Ok::<_, Error>(frame)
+        });
+        let mut frame = grab
+            .await
+            .map_err(|e| Error::Internal(format!("X11 capture task panicked: {}", e)))??;
+        frame.duration = self.config.framerate.frame_duration_us();
+        self.size = Some(Resolution::new(frame.width, frame.height));
+        Ok(frame)
+    }
+
+    fn is_active(&self) -> bool {
+        self.session.is_some()
+    }
+
+    fn resolution(&self) -> Option<Resolution> {
+        self.size
+    }
+
+    fn framerate(&self) -> Option<Framerate> {
+        Some(self.config.framerate)
+    }
+}
+
+/// An open display and what is read from it
+struct X11Session {
+    x: &'static Xlib,
+    display: *mut Display,
+    root: Window,
+    /// Captured window (None = the root window, i.e. the whole screen)
+    window: Option<Window>,
+    /// Backing pixmap of the redirected window
+    pixmap: Pixmap,
+    size: Resolution,
+    image: Option<ShmImage>,
+}
+
+// The display connection is only ever used behind the capture's mutex, one
+// call at a time
+unsafe impl Send for X11Session {}
+
+impl X11Session {
+    fn open(x: &'static Xlib, window: Option<u64>) -> Result<Self> {
+        let display = unsafe { (x.open_display)(std::ptr::null()) };
+        if display.is_null() {
+            let name = std::env::var("DISPLAY").unwrap_or_default();
+            return Err(Error::X11(format!("Cannot open display '{}'", name)));
+        }
+        ErrorTrap::register(x, display);
+
+        let mut session = Self {
+            x,
+            display,
+            root: unsafe { (x.default_root_window)(display) },
+            window: None,
+            pixmap: 0,
+            size: Resolution::new(0, 0),
+            image: None,
+        };
+        if unsafe { (x.shm_query_extension)(display) } == 0 {
+            return Err(Error::X11(
+                "The X server lacks the MIT-SHM extension".into(),
+            ));
+        }
+
+        if let Some(window) = window {
+            let composite = x
+                .composite
+                .as_ref()
+                .ok_or_else(|| Error::X11("Window capture needs libXcomposite".into()))?;
+            let (mut event_base, mut error_base) = (0, 0);
+            if unsafe { (composite.query_extension)(display, &mut event_base, &mut error_base) }
+                == 0
+            {
+                return Err(Error::X11(
+                    "The X server lacks the Composite extension".into(),
+                ));
+            }
+            let window = window as Window;
+            session.checked(|| unsafe {
+                (composite.redirect_window)(display, window, COMPOSITE_REDIRECT_AUTOMATIC)
+            })?;
+            session.window = Some(window);
+        }
+        Ok(session)
+    }
+
+    /// Run X requests and fail if the server reported an error for them
+    fn checked<T>(&self, request: impl FnOnce() -> T) -> Result<T> {
+        ErrorTrap::take(self.display);
+        let result = request();
+        unsafe { (self.x.sync)(self.display, 0) };
+        match ErrorTrap::take(self.display) {
+            0 => Ok(result),
+            code => Err(Error::X11(format!(
+                "X request failed (error code {})",
+                code
+            ))),
+        }
+    }
+
+    /// Read the current contents of the screen or window
+    fn grab(&mut self) -> Result<Frame> {
+        let target = self.window.unwrap_or(self.root);
+        let mut attributes: XWindowAttributes = unsafe { std::mem::zeroed() };
+        let status = self.checked(|| unsafe {
+            (self.x.get_window_attributes)(self.display, target, &mut attributes)
+        })?;
+        if status == 0 {
+            return Err(Error::X11("Captured window is gone".into()));
+        }
+        if self.window.is_some() && attributes.map_state != IS_VIEWABLE {
+            return Err(Error::X11("Captured window is not mapped".into()));
+        }
+        let size = Resolution::new(attributes.width as u32, attributes.height as u32);
+
+        // A resized window gets a new backing pixmap and needs a new image
+        if size != self.size || self.image.is_none() {
+            self.image = None;
+            self.release_pixmap();
+            if let (Some(window), Some(composite)) = (self.window, self.x.composite.as_ref()) {
+                self.pixmap = self
+                    .checked(|| unsafe { (composite.name_window_pixmap)(self.display, window) })?;
+            }
+            self.image = Some(ShmImage::new(self.x, self.display, &attributes)?);
+            if size != self.size && self.size.width > 0 {
+                tracing::info!("X11 capture size changed: {} -> {}", self.size, size);
+            }
+            self.size = size;
+        }
+
+        let drawable = if self.window.is_some() {
+            self.pixmap
+        } else {
+            self.root
+        };
+        let image = self.image.as_ref().expect("image created above");
+        let grabbed = self.checked(|| unsafe {
+            (self.x.shm_get_image)(self.display, drawable, image.image, 0, 0, !0)
+        })?;
+        if grabbed == 0 {
+            return Err(Error::X11("XShmGetImage failed".into()));
+        }
+        image.to_frame()
+    }
+
+    /// Draw the cursor into the frame, or attach it as metadata
+    fn cursor(&self, frame: &mut Frame, mode: CursorMode) {
+        let origin = self.origin();
+        match mode {
+            CursorMode::Hidden => {}
+            CursorMode::Metadata => {
+                let (mut root, mut child) = (0, 0);
+                let (mut root_x, mut root_y, mut win_x, mut win_y) = (0, 0, 0, 0);
+                let mut mask = 0;
+                let on_screen = unsafe {
+                    (self.x.query_pointer)(
+                        self.display,
+                        self.root,
+                        &mut root,
+                        &mut child,
+                        &mut root_x,
+                        &mut root_y,
+                        &mut win_x,
+                        &mut win_y,
+                        &mut mask,
+                    )
+                } != 0;
+                let (x, y) = (root_x - origin.0, root_y - origin.1);
+                let (hotspot_x, hotspot_y) = self
+                    .cursor_image()
+                    .map_or((0, 0), |c| (c.xhot as i32, c.yhot as i32));
+                frame.cursor = Some(CursorInfo {
+                    x,
+                    y,
+                    hotspot_x,
+                    hotspot_y,
+                    visible: on_screen
+                        && (0..frame.width as i32).contains(&x)
+                        && (0..frame.height as i32).contains(&y),
+                    buttons: pointer_buttons(mask),
+                });
+            }
+            CursorMode::Embedded => {
+                let Some(cursor) = self.cursor_image() else {
+                    return;
+                };
+                blend_cursor(
+                    frame,
+                    &cursor.pixels,
+                    (cursor.width, cursor.height),
+                    (
+                        cursor.x as i32 - cursor.xhot as i32 - origin.0,
+                        cursor.y as i32 - cursor.yhot as i32 - origin.1,
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Top-left corner of the captured area in root window coordinates
+    fn origin(&self) -> (i32, i32) {
+        let Some(window) = self.window else {
+            return (0, 0);
+        };
+        let (mut x, mut y, mut child) = (0, 0, 0);
+        unsafe {
+            (self.x.translate_coordinates)(
+                self.display,
+                window,
+                self.root,
+                0,
+                0,
+                &mut x,
+                &mut y,
+                &mut child,
+            )
+        };
+        (x, y)
+    }
+
+    /// Current cursor image from XFixes
+    fn cursor_image(&self) -> Option<CursorImage> {
+        let get = self.x.get_cursor_image?;
+        unsafe {
+            let image = get(self.display);
+            if image.is_null() {
+                return None;
+            }
+            let c = &*image;
+            let count = c.width as usize * c.height as usize;
+            let pixels = std::slice::from_raw_parts(c.pixels, count)
+                .iter()
+                .map(|&p| p as u32)
+                .collect();
+            let cursor = CursorImage {
+                x: c.x,
+                y: c.y,
+                width: c.width,
+                height: c.height,
+                xhot: c.xhot,
+                yhot: c.yhot,
+                pixels,
+            };
+            (self.x.free)(image.cast());
+            Some(cursor)
+        }
+    }
+
+    fn release_pixmap(&mut self) {
+        if self.pixmap != 0 {
+            unsafe { (self.x.free_pixmap)(self.display, self.pixmap) };
+            self.pixmap = 0;
+        }
+    }
+}
+
+impl Drop for X11Session {
+    fn drop(&mut self) {
+        self.image = None;
+        self.release_pixmap();
+        if let (Some(window), Some(composite)) = (self.window, self.x.composite.as_ref()) {
+            let _ = self.checked(|| unsafe {
+                (composite.unredirect_window)(self.display, window, COMPOSITE_REDIRECT_AUTOMATIC)
+            });
+        }
+        unsafe { (self.x.close_display)(self.display) };
+        ErrorTrap::unregister(self.x, self.display);
+    }
+}
+
+/// XFixes cursor with its pixels copied out (ARGB, premultiplied)
+struct CursorImage {
+    x: c_short,
+    y: c_short,
+    width: c_ushort,
+    height: c_ushort,
+    xhot: c_ushort,
+    yhot: c_ushort,
+    pixels: Vec<u32>,
+}
+
+/// XImage backed by a shared memory segment attached to the server
+struct ShmImage {
+    x: &'static Xlib,
+    display: *mut Display,
+    image: *mut XImage,
+    /// Xlib keeps a pointer to this, so it must not move
+    info: Box<XShmSegmentInfo>,
+}
+
+impl ShmImage {
+    fn new(
+        x: &'static Xlib,
+        display: *mut Display,
+        attributes: &XWindowAttributes,
+    ) -> Result<Self> {
+        let mut info = Box::new(XShmSegmentInfo {
+            shmseg: 0,
+            shmid: -1,
+            shmaddr: std::ptr::null_mut(),
+            read_only: 0,
+        });
+        let image = unsafe {
+            (x.shm_create_image)(
+                display,
+                attributes.visual,
+                attributes.depth as c_uint,
+                ZPIXMAP,
+                std::ptr::null_mut(),
+                &mut *info,
+                attributes.width as c_uint,
+                attributes.height as c_uint,
+            )
+        };
+        if image.is_null() {
+            return Err(Error::X11("XShmCreateImage failed".into()));
+        }
+        // From here on Drop cleans up whatever has been set up
+        let mut shm = Self {
+            x,
+            display,
+            image,
+            info,
+        };
+
+        let (bits, size) = unsafe {
+            let image = &*image;
+            (
+                image.bits_per_pixel,
+                image.bytes_per_line as usize * image.height as usize,
+            )
+        };
+        if bits != 32 {
+            return Err(Error::X11(format!(
+                "Unsupported X visual ({} bits per pixel)",
+                bits
+            )));
+        }
+
+        unsafe {
+            shm.info.shmid = libc::shmget(libc::IPC_PRIVATE, size, libc::IPC_CREAT | 0o600);
+            if shm.info.shmid < 0 {
+                return Err(Error::X11(format!(
+                    "shmget failed: {}",
+                    std::io::Error::last_os_error()
+                )));
+            }
+            let address = libc::shmat(shm.info.shmid, std::ptr::null(), 0);
+            // The segment goes away once both sides have detached
+            libc::shmctl(shm.info.shmid, libc::IPC_RMID, std::ptr::null_mut());
+            if address as isize == -1 {
+                return Err(Error::X11(format!(
+                    "shmat failed: {}",
+                    std::io::Error::last_os_error()
+                )));
+            }
+            shm.info.shmaddr = address.cast();
+            (*shm.image).data = address.cast();
+
+            // Fails asynchronously on displays that can't share memory
+            // with us (remote X, containers without the IPC namespace)
+            ErrorTrap::take(display);
+            let attached = (x.shm_attach)(display, &mut *shm.info) != 0;
+            (x.sync)(display, 0);
+            if !attached || ErrorTrap::take(display) != 0 {
+                return Err(Error::X11("XShmAttach failed".into()));
+            }
+        }
+        Ok(shm)
+    }
+
+    /// Copy the image into a frame, forcing alpha opaque
+    fn to_frame(&self) -> Result<Frame> {
+        let image = unsafe { &*self.image };
+        let format = match image.red_mask {
+            0x00ff_0000 => FrameFormat::Bgra,
+            0x0000_00ff => FrameFormat::Rgba,
+            mask => {
+                return Err(Error::X11(format!(
+                    "Unsupported X visual (red mask {:#x})",
+                    mask
+                )));
+            }
+        };
+        let stride = image.bytes_per_line as usize;
+        let len = stride * image.height as usize;
+        let mut data = unsafe { std::slice::from_raw_parts(image.data as *const u8, len) }.to_vec();
+        // Depth-24 visuals leave the padding byte undefined
+        for pixel in data.chunks_exact_mut(4) {
+            pixel[3] = 0xff;
+        }
+
+        let mut frame = Frame::from_data(
+            data,
+            image.width as u32,
+            image.height as u32,
+            stride as u32,
+            format,
+        );
+        frame.pts = wall_clock_us();
+        Ok(frame)
+    }
+}
+
+impl Drop for ShmImage {
+    fn drop(&mut self) {
+        unsafe {
+            if self.info.shmseg != 0 {
+                (self.x.shm_detach)(self.display, &mut *self.info);
+                (self.x.sync)(self.display, 0);
+            }
+            if !self.info.shmaddr.is_null() {
+                libc::shmdt(self.info.shmaddr as *const c_void);
+            }
+            // The data is the segment; only the XImage itself is freed
+            (*self.image).data = std::ptr::null_mut();
+            match (*self.image).destroy_image {
+                Some(destroy) => {
+                    destroy(self.image);
+                }
+                None => {
+                    (self.x.free)(self.image.cast());
+                }
+            }
+        }
+    }
+}
+
+/// Xlib pointer button mask to `CursorInfo::buttons`
+fn pointer_buttons(mask: c_uint) -> u32 {
+    [BUTTON1_MASK, BUTTON3_MASK, BUTTON2_MASK]
+        .iter()
+        .enumerate()
+        .filter(|(_, &button)| mask & button != 0)
+        .fold(0, |buttons, (bit, _)| buttons | 1 << bit)
+}
+
+/// Alpha-blend a premultiplied ARGB cursor with its top-left corner at
+/// (x, y) onto a packed 32-bit frame
+fn blend_cursor(frame: &mut Frame, pixels: &[u32], size: (c_ushort, c_ushort), (x, y): (i32, i32)) {
+    let (width, height) = (size.0 as i32, size.1 as i32);
+    let stride = frame.stride as usize;
+    let rgba = frame.format == FrameFormat::Rgba;
+    for row in 0..height {
+        let fy = y + row;
+        if fy < 0 || fy >= frame.height as i32 {
+            continue;
+        }
+        for column in 0..width {
+            let fx = x + column;
+            if fx < 0 || fx >= frame.width as i32 {
+                continue;
+            }
+            let argb = pixels[(row * width + column) as usize];
+            let alpha = argb >> 24;
+            if alpha == 0 {
+                continue;
+            }
+            let offset = fy as usize * stride + fx as usize * 4;
+            let Some(dst) = frame.data.get_mut(offset..offset + 4) else {
+                continue;
+            };
+            let (r, g, b) = ((argb >> 16) & 0xff, (argb >> 8) & 0xff, argb & 0xff);
+            let source = if rgba { [r, g, b] } else { [b, g, r] };
+            for (channel, value) in dst.iter_mut().zip(source) {
+                *channel = (value + *channel as u32 * (255 - alpha) / 255).min(255) as u8;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blend_cursor() {
+        let mut frame = Frame::from_data(vec![100; 4 * 4 * 4], 4, 4, 16, FrameFormat::Bgra);
+        // Opaque red, half-transparent white (premultiplied), transparent
+        let pixels = [0xffff_0000, 0x8080_8080, 0x0000_0000, 0xff00_00ff];
+        blend_cursor(&mut frame, &pixels, (2, 2), (3, -1));
+
+        // Only the bottom row of the cursor lands on the frame, and only
+        // its first pixel (at x = 3): transparent, so unchanged
+        assert_eq!(&frame.data[12..16], &[100, 100, 100, 100]);
+
+        blend_cursor(&mut frame, &pixels, (2, 2), (0, 0));
+        assert_eq!(&frame.data[0..4], &[0, 0, 255, 100]);
+        assert_eq!(&frame.data[4..8], &[177, 177, 177, 100]);
+        assert_eq!(&frame.data[20..24], &[255, 0, 0, 100]);
+    }
+
+    #[test]
+    fn test_errors_kept_per_display() {
+        // Stand-ins for two sessions' connections; never dereferenced
+        let (first, second) = (0x10 as *mut Display, 0x20 as *mut Display);
+        let displays = [(first as usize, 0), (second as usize, 0)];
+        ERROR_TRAP.lock().displays.extend(displays);
+
+        let mut event: XErrorEvent = unsafe { std::mem::zeroed() };
+        event.error_code = 3;
+        unsafe { record_error(second, &mut event) };
+        assert_eq!(ErrorTrap::take(first), 0);
+        assert_eq!(ErrorTrap::take(second), 3);
+        assert_eq!(ErrorTrap::take(second), 0);
+
+        ERROR_TRAP.lock().displays.retain(|d| !displays.contains(d));
+    }
+
+    #[test]
+    fn test_pointer_buttons() {
+        assert_eq!(pointer_buttons(0), 0);
+        assert_eq!(pointer_buttons(BUTTON1_MASK), 0b001);
+        assert_eq!(pointer_buttons(BUTTON3_MASK), 0b010);
+        assert_eq!(pointer_buttons(BUTTON1_MASK | BUTTON2_MASK), 0b101);
+    }
+}
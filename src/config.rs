@@ -1,9 +1,11 @@
 //! Configuration types for GhostStream
 
 use crate::encode::Codec;
-use crate::processing::HdrConfig;
-use crate::types::{FrameFormat, Framerate, Resolution};
+use crate::processing::{ColorMatrix, ColorPrimaries, ColorRange, HdrConfig};
+use crate::types::{FrameFormat, Framerate, Resolution, Timecode};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
 
 /// Capture configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -12,12 +14,42 @@ pub struct CaptureConfig {
     pub framerate: Framerate,
     /// Show cursor in capture
     pub show_cursor: bool,
+    /// How the cursor is delivered when shown
+    #[serde(default)]
+    pub cursor_mode: CursorMode,
     /// Capture audio (for PipeWire)
     pub capture_audio: bool,
     /// Preferred capture backend
     pub backend: CaptureBackend,
     /// Use DMA-BUF zero-copy if available
     pub prefer_dmabuf: bool,
+    /// What to do when the source resolution changes mid-stream
+    #[serde(default)]
+    pub on_resolution_change: ResolutionChangePolicy,
+    /// Rotation/flip applied to captured frames before encoding
+    #[serde(default)]
+    pub transform: Transform,
+    /// Region of the captured frames to keep (None = all of it); cut
+    /// before the transform, in capture pixels
+    #[serde(default)]
+    pub crop: Option<Crop>,
+    /// PipeWire stream/node name (None = "ghoststream-capture")
+    #[serde(default)]
+    pub stream_name: Option<String>,
+    /// Application name shown for the stream (None = "GhostStream")
+    #[serde(default)]
+    pub app_name: Option<String>,
+    /// X11 window to capture with `CaptureBackend::X11` (None = the whole
+    /// screen)
+    #[serde(default)]
+    pub x11_window: Option<u64>,
+    /// V4L2 device to capture with `CaptureBackend::V4l2` (None =
+    /// `/dev/video0`)
+    #[serde(default)]
+    pub v4l2_device: Option<PathBuf>,
+    /// Size to ask the V4L2 device for (None = its current mode)
+    #[serde(default)]
+    pub v4l2_resolution: Option<Resolution>,
 }
 
 impl Default for CaptureConfig {
@@ -25,9 +57,18 @@ impl Default for CaptureConfig {
         Self {
             framerate: Framerate::FPS_60,
             show_cursor: true,
+            cursor_mode: CursorMode::default(),
             capture_audio: false,
             backend: CaptureBackend::Auto,
             prefer_dmabuf: true,
+            on_resolution_change: ResolutionChangePolicy::default(),
+            transform: Transform::None,
+            crop: None,
+            stream_name: None,
+            app_name: None,
+            x11_window: None,
+            v4l2_device: None,
+            v4l2_resolution: None,
         }
     }
 }
@@ -38,15 +79,185 @@ impl CaptureConfig {
         self
     }
 
+    /// Capture at a fractional rate (e.g. 60000/1001)
+    pub fn with_framerate(mut self, framerate: Framerate) -> Self {
+        self.framerate = framerate;
+        self
+    }
+
     pub fn with_show_cursor(mut self, show: bool) -> Self {
         self.show_cursor = show;
         self
     }
 
+    pub fn with_cursor_mode(mut self, mode: CursorMode) -> Self {
+        self.cursor_mode = mode;
+        self
+    }
+
+    /// Capture the stream's audio along with the video (PipeWire)
+    pub fn with_capture_audio(mut self, enabled: bool) -> Self {
+        self.capture_audio = enabled;
+        self
+    }
+
     pub fn with_backend(mut self, backend: CaptureBackend) -> Self {
         self.backend = backend;
         self
     }
+
+    /// Use DMA-BUF zero-copy frames when the source offers them
+    pub fn with_prefer_dmabuf(mut self, prefer: bool) -> Self {
+        self.prefer_dmabuf = prefer;
+        self
+    }
+
+    pub fn with_resolution_change_policy(mut self, policy: ResolutionChangePolicy) -> Self {
+        self.on_resolution_change = policy;
+        self
+    }
+
+    /// Rotate or flip captured frames (portrait monitors, rotated outputs)
+    pub fn with_transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    /// Keep only a region of the captured frames (e.g. one monitor of a
+    /// spanning capture, or a game's canvas without its borders)
+    pub fn with_crop(mut self, x: u32, y: u32, width: u32, height: u32) -> Self {
+        self.crop = Some(Crop::new(x, y, width, height));
+        self
+    }
+
+    /// Name the PipeWire capture stream (as listed by pavucontrol/wireplumber)
+    pub fn with_stream_name(mut self, name: impl Into<String>) -> Self {
+        self.stream_name = Some(name.into());
+        self
+    }
+
+    /// Application the capture stream is attributed to
+    pub fn with_app_name(mut self, name: impl Into<String>) -> Self {
+        self.app_name = Some(name.into());
+        self
+    }
+
+    /// Capture a single window on X11 (as shown by `xwininfo`) instead of
+    /// the whole screen; needs XComposite
+    pub fn with_x11_window(mut self, window: u64) -> Self {
+        self.x11_window = Some(window);
+        self
+    }
+
+    /// Webcam or capture card to read with `CaptureBackend::V4l2` (see
+    /// `capture::list_v4l2_devices`)
+    pub fn with_v4l2_device(mut self, device: impl Into<PathBuf>) -> Self {
+        self.v4l2_device = Some(device.into());
+        self
+    }
+
+    /// Ask the V4L2 device for this size; the driver may pick the
+    /// nearest it supports
+    pub fn with_v4l2_resolution(mut self, width: u32, height: u32) -> Self {
+        self.v4l2_resolution = Some(Resolution::new(width, height));
+        self
+    }
+
+    /// PipeWire stream name and application name, with defaults applied
+    pub(crate) fn stream_identity(&self, default_name: &str) -> (String, String) {
+        (
+            self.stream_name
+                .clone()
+                .unwrap_or_else(|| default_name.to_string()),
+            self.app_name
+                .clone()
+                .unwrap_or_else(|| "GhostStream".to_string()),
+        )
+    }
+}
+
+/// Region of a captured frame, in pixels from its top-left corner
+///
+/// Clipped to the frame when applied; for subsampled YUV the edges are
+/// rounded down to even so chroma stays aligned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Crop {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+/// Rotation or mirroring applied to captured frames
+///
+/// Rotations are clockwise. `Rotate90`/`Rotate270` swap width and height,
+/// so a 1080x1920 portrait source becomes 1920x1080 (and vice versa).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum Transform {
+    #[default]
+    None,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    /// Mirror left-right
+    FlipH,
+    /// Mirror top-bottom
+    FlipV,
+}
+
+impl Transform {
+    /// Whether the transform exchanges width and height
+    pub fn swaps_dimensions(&self) -> bool {
+        matches!(self, Transform::Rotate90 | Transform::Rotate270)
+    }
+
+    /// Resolution of a frame after the transform
+    pub fn apply_to(&self, resolution: Resolution) -> Resolution {
+        if self.swaps_dimensions() {
+            Resolution::new(resolution.height, resolution.width)
+        } else {
+            resolution
+        }
+    }
+}
+
+/// Cursor capture mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum CursorMode {
+    /// Cursor is not captured
+    Hidden,
+    /// Compositor draws the cursor into the frames
+    #[default]
+    Embedded,
+    /// Cursor position is delivered as metadata (`Frame::cursor`) and not
+    /// drawn; pair with `processing::CursorRenderer` for a custom cursor.
+    /// Requires compositor support for PipeWire cursor metadata.
+    Metadata,
+}
+
+/// Behavior when the captured resolution changes mid-stream
+/// (e.g. the user changes their display mode while capturing)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum ResolutionChangePolicy {
+    /// Scale new frames to the resolution the encoder was opened with.
+    /// Safe for streaming since the output stream never changes.
+    #[default]
+    Rescale,
+    /// Flush and re-open the encoder at the new resolution. The new encoder
+    /// starts on a keyframe; containers keep the original stream parameters,
+    /// so this is best suited to Annex-B streams (MPEG-TS/SRT).
+    Reinit,
 }
 
 /// Capture backend selection
@@ -61,10 +272,53 @@ pub enum CaptureBackend {
     PipeWire,
     /// Wlroots DMA-BUF export (for wlroots compositors)
     WlrExport,
+    /// Native X11 capture (XShm, XComposite for windows)
+    X11,
+    /// V4L2 webcam or capture card (never auto-selected)
+    V4l2,
+}
+
+/// Frame processing configuration (between capture and encoder)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessingConfig {
+    /// Colorimetry to convert with instead of the automatic choice
+    ///
+    /// For RGB capture this replaces the BT.709 limited-range YUV the
+    /// encoder input is otherwise converted to; for YUV capture it replaces
+    /// the colorimetry the capture reported, and frames are converted from
+    /// it to BT.709 (BT.2020 for P010). Frames go through the conversion
+    /// even when the capture format already matches the encoder input.
+    /// Primaries cannot be converted by swscale and are only signalled.
+    #[serde(default)]
+    pub force_conversion: Option<(ColorPrimaries, ColorMatrix, ColorRange)>,
+    /// Peak brightness in nits that 10-bit HDR capture is tonemapped from
+    /// when the encoder takes 8-bit SDR (None = 1000, the common HDR10
+    /// mastering peak). Brighter highlights clip to white.
+    #[serde(default)]
+    pub tonemap_peak_nits: Option<u32>,
+}
+
+impl ProcessingConfig {
+    /// Override the colorimetry of colorspace conversion
+    pub fn with_force_conversion(
+        mut self,
+        primaries: ColorPrimaries,
+        matrix: ColorMatrix,
+        range: ColorRange,
+    ) -> Self {
+        self.force_conversion = Some((primaries, matrix, range));
+        self
+    }
+
+    /// Peak brightness HDR capture is tonemapped from (0 = default)
+    pub fn with_tonemap_peak(mut self, nits: u32) -> Self {
+        self.tonemap_peak_nits = Some(nits).filter(|&n| n > 0);
+        self
+    }
 }
 
 /// Encoder configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct EncoderConfig {
     /// Video codec
     pub codec: Codec,
@@ -76,6 +330,17 @@ pub struct EncoderConfig {
     pub bitrate_kbps: u32,
     /// Maximum bitrate in kbps (for VBR)
     pub max_bitrate_kbps: Option<u32>,
+    /// VBV/HRD decoder buffer in kbits (None = encoder default; software
+    /// CBR uses two seconds of bitrate). Bounds how far the bitrate can
+    /// burst above the average, for platforms and hardware decoders with
+    /// strict buffer limits.
+    #[serde(default)]
+    pub vbv_buffer_kbits: Option<u32>,
+    /// Rate the VBV buffer fills at, in kbps (None = `max_bitrate_kbps`
+    /// for VBR, else the bitrate). Either VBV setting alone implies the
+    /// other; see `vbv_buffer_kbits`.
+    #[serde(default)]
+    pub vbv_max_kbps: Option<u32>,
     /// Rate control mode
     pub rate_control: RateControl,
     /// Encoder preset (speed vs quality)
@@ -84,6 +349,22 @@ pub struct EncoderConfig {
     pub tuning: EncoderTuning,
     /// GOP size (keyframe interval in frames)
     pub gop_size: u32,
+    /// Let the encoder insert extra keyframes on scene cuts. Off gives a
+    /// fixed GOP, which streaming platforms expect, and saves the bitrate
+    /// window switches would otherwise spend on keyframes.
+    #[serde(default = "default_true")]
+    pub scene_cut: bool,
+    /// Guarantee a keyframe at least every this many frames, whatever the
+    /// encoder decides on scene cuts (None = leave it to the GOP). Bounds
+    /// how far before a requested point a replay clip or a seek starts;
+    /// forced by the pipeline, so it holds on every backend.
+    #[serde(default)]
+    pub min_keyframe_interval: Option<u32>,
+    /// When the GPU runs out of memory opening the encoder, retry at a
+    /// lower resolution and then frame rate instead of failing (see
+    /// `PipelineEvent::EncoderDownshifted`)
+    #[serde(default)]
+    pub adaptive_on_oom: bool,
     /// B-frames count
     pub b_frames: u32,
     /// Enable lookahead
@@ -96,6 +377,81 @@ pub struct EncoderConfig {
     pub level: Option<String>,
     /// HDR configuration (None for SDR)
     pub hdr: Option<HdrConfig>,
+    /// Repeat SPS/PPS/VPS in-band before every keyframe (Annex-B), so
+    /// receivers joining mid-stream can start decoding. Enabled
+    /// automatically for SRT and MPEG-TS outputs.
+    #[serde(default)]
+    pub repeat_headers: bool,
+    /// Chroma subsampling of the encoded stream
+    #[serde(default)]
+    pub chroma_format: ChromaFormat,
+    /// Force a keyframe on the first frame and drop anything the encoder
+    /// emits before it, so output starts on a decodable frame at PTS 0
+    #[serde(default = "default_true")]
+    pub start_on_keyframe: bool,
+    /// NVENC multi-pass rate control (None = driver default); see `Multipass`
+    #[serde(default)]
+    pub nvenc_multipass: Option<Multipass>,
+    /// NVENC B-frames as reference (None = driver default); see `BRefMode`
+    #[serde(default)]
+    pub b_ref_mode: Option<BRefMode>,
+    /// NVIDIA GPU to encode on (None = the default device); see
+    /// `nvenc::list_gpus`
+    #[serde(default)]
+    pub gpu_index: Option<u32>,
+    /// AV1 film grain synthesis strength (1-50, None = off): grain is
+    /// removed before encoding and re-synthesized by the decoder, which
+    /// saves the bits noisy content would otherwise spend on it. SVT-AV1
+    /// only; the hardware encoders have no equivalent and ignore it.
+    #[serde(default)]
+    pub film_grain: Option<u8>,
+    /// Upper bound on the frames the encoder holds before emitting one
+    /// (B-frames, lookahead and the hardware queue). B-frames and then
+    /// lookahead are cut to fit, with a warning; the rest of the budget
+    /// goes to the NVENC/QSV queue or x265 frame threads, and SVT-AV1
+    /// switches to its low-delay structure. None leaves buffering to the
+    /// settings.
+    #[serde(default)]
+    pub max_latency_frames: Option<u32>,
+    /// Drop frames identical to the previous one (static screens, slides)
+    /// instead of re-encoding them
+    #[serde(default)]
+    pub static_frame_optimization: bool,
+    /// Minimum rate frames are still encoded at while the screen is static
+    /// (0 = no floor)
+    #[serde(default = "default_static_frame_min_fps")]
+    pub static_frame_min_fps: u32,
+    /// How captured frames are mapped onto `framerate` when the capture
+    /// rate differs
+    #[serde(default)]
+    pub framerate_conversion: FramerateConversion,
+    /// Keep software encoder threads on one CCD / NUMA node (cores sharing
+    /// an L3 cache) on multi-CCD CPUs such as Ryzen 7950X or Threadripper
+    #[serde(default)]
+    pub numa_aware: bool,
+    /// How frame sizes the codec can't encode (odd widths from window
+    /// captures) are fitted to its alignment
+    #[serde(default)]
+    pub dimension_alignment: DimensionAlignment,
+    /// Raw FFmpeg encoder options (`rc-lookahead`, `spatial-aq`,
+    /// `svtav1-params`, ...) applied after the library's own, overriding
+    /// them. Not validated: a wrong key or value can make the encoder fail
+    /// to open or silently change rate control.
+    #[serde(default)]
+    pub extra_options: HashMap<String, String>,
+    /// SMPTE timecode of the first frame; every frame is tagged with its
+    /// own (`AV_FRAME_DATA_S12M_TIMECODE`). Set by the pipeline from
+    /// `Output::File::timecode_start`.
+    #[serde(default)]
+    pub timecode: Option<Timecode>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_static_frame_min_fps() -> u32 {
+    1
 }
 
 impl Default for EncoderConfig {
@@ -106,21 +462,66 @@ impl Default for EncoderConfig {
             framerate: Framerate::FPS_60,
             bitrate_kbps: 6000,
             max_bitrate_kbps: None,
+            vbv_buffer_kbits: None,
+            vbv_max_kbps: None,
             rate_control: RateControl::Vbr,
             preset: EncoderPreset::Medium,
             tuning: EncoderTuning::HighQuality,
             gop_size: 120, // 2 seconds at 60fps
+            scene_cut: true,
+            min_keyframe_interval: None,
+            adaptive_on_oom: false,
             b_frames: 2,
             lookahead: None,
             pixel_format: FrameFormat::Nv12,
             profile: None,
             level: None,
             hdr: None, // SDR by default
+            repeat_headers: false,
+            chroma_format: ChromaFormat::Yuv420,
+            start_on_keyframe: true,
+            nvenc_multipass: None,
+            b_ref_mode: None,
+            gpu_index: None,
+            film_grain: None,
+            max_latency_frames: None,
+            static_frame_optimization: false,
+            static_frame_min_fps: default_static_frame_min_fps(),
+            framerate_conversion: FramerateConversion::Nearest,
+            numa_aware: false,
+            dimension_alignment: DimensionAlignment::Crop,
+            extra_options: HashMap::new(),
+            timecode: None,
         }
     }
 }
 
 impl EncoderConfig {
+    /// Defaults suited to a codec
+    ///
+    /// `default()` is tuned for H.264; HEVC gets an extra B-frame, and AV1
+    /// uses constant quality, a longer GOP, no B-frames and 10-bit input
+    /// (AV1 Main profile covers 10-bit, so this costs no compatibility).
+    pub fn defaults_for(codec: Codec) -> Self {
+        let defaults = Self::default();
+        match codec {
+            Codec::H264 => defaults,
+            Codec::Hevc => Self {
+                codec,
+                b_frames: 3,
+                ..defaults
+            },
+            Codec::Av1 => Self {
+                codec,
+                rate_control: RateControl::Crf { crf: 30 },
+                gop_size: 240,
+                b_frames: 0,
+                pixel_format: FrameFormat::P010,
+                ..defaults
+            },
+        }
+    }
+
     pub fn with_codec(mut self, codec: Codec) -> Self {
         self.codec = codec;
         self
@@ -151,16 +552,310 @@ impl EncoderConfig {
         self
     }
 
+    /// Constant quality from 0 (worst) to 100 (best), the same on every
+    /// codec and backend; see `encode::quality_parameter` for the mapping
+    pub fn with_quality(mut self, quality: u8) -> Self {
+        self.rate_control = RateControl::Quality {
+            quality: quality.min(100),
+        };
+        self
+    }
+
     pub fn with_gop_size(mut self, gop: u32) -> Self {
         self.gop_size = gop;
         self
     }
 
+    /// Set the tuning; the low-latency tunings are for streaming and also
+    /// turn scene-cut keyframes off (call `with_scene_cut` after to keep them)
     pub fn with_tuning(mut self, tuning: EncoderTuning) -> Self {
         self.tuning = tuning;
+        if tuning.is_low_latency() {
+            self.scene_cut = false;
+        }
+        self
+    }
+
+    /// Allow keyframes on scene cuts in addition to the GOP interval
+    pub fn with_scene_cut(mut self, enabled: bool) -> Self {
+        self.scene_cut = enabled;
         self
     }
 
+    /// Force a keyframe at least every `frames` frames (0 = off)
+    pub fn with_min_keyframe_interval(mut self, frames: u32) -> Self {
+        self.min_keyframe_interval = (frames > 0).then_some(frames);
+        self
+    }
+
+    /// Retry at lower settings when the GPU is out of memory
+    pub fn with_adaptive_on_oom(mut self, enabled: bool) -> Self {
+        self.adaptive_on_oom = enabled;
+        self
+    }
+
+    /// Repeat codec headers before every keyframe
+    pub fn with_repeat_headers(mut self, repeat: bool) -> Self {
+        self.repeat_headers = repeat;
+        self
+    }
+
+    /// Start output on a forced keyframe with timestamps rebased to zero
+    pub fn with_start_on_keyframe(mut self, enabled: bool) -> Self {
+        self.start_on_keyframe = enabled;
+        self
+    }
+
+    /// Set NVENC multi-pass mode (ignored by other encoders)
+    pub fn with_nvenc_multipass(mut self, multipass: Multipass) -> Self {
+        self.nvenc_multipass = Some(multipass);
+        self
+    }
+
+    /// Set NVENC B-frame reference mode (ignored by other encoders)
+    pub fn with_b_ref_mode(mut self, mode: BRefMode) -> Self {
+        self.b_ref_mode = Some(mode);
+        self
+    }
+
+    /// Encode on the NVIDIA GPU with this index, e.g. a second card while
+    /// the first drives the display (ignored by other encoders)
+    pub fn with_gpu_index(mut self, index: u32) -> Self {
+        self.gpu_index = Some(index);
+        self
+    }
+
+    /// Enable AV1 film grain synthesis at this strength (capped at 50,
+    /// 0 turns it off)
+    pub fn with_film_grain(mut self, strength: u8) -> Self {
+        self.film_grain = Some(strength.min(50)).filter(|&s| s > 0);
+        self
+    }
+
+    /// Limit bitrate bursts with a VBV buffer of `kbits` (0 = encoder
+    /// default)
+    pub fn with_vbv_buffer(mut self, kbits: u32) -> Self {
+        self.vbv_buffer_kbits = Some(kbits).filter(|&k| k > 0);
+        self
+    }
+
+    /// Rate the VBV buffer fills at in kbps (0 = from the bitrate)
+    pub fn with_vbv_max_rate(mut self, kbps: u32) -> Self {
+        self.vbv_max_kbps = Some(kbps).filter(|&k| k > 0);
+        self
+    }
+
+    /// Tag frames with SMPTE timecode starting at `start`
+    pub fn with_timecode(mut self, start: Timecode) -> Self {
+        self.timecode = Some(start);
+        self
+    }
+
+    /// Bound the encoder's buffering to `frames` (see `max_latency_frames`)
+    pub fn with_max_latency_frames(mut self, frames: u32) -> Self {
+        self.max_latency_frames = Some(frames);
+        self
+    }
+
+    /// Skip encoding unchanged frames, keeping at least
+    /// `static_frame_min_fps` while the screen is static
+    pub fn with_static_frame_optimization(mut self, enabled: bool) -> Self {
+        self.static_frame_optimization = enabled;
+        self
+    }
+
+    /// Set the frame rate floor for static content
+    pub fn with_static_frame_min_fps(mut self, fps: u32) -> Self {
+        self.static_frame_min_fps = fps;
+        self
+    }
+
+    /// Set how the capture rate is converted to the output framerate
+    pub fn with_framerate_conversion(mut self, conversion: FramerateConversion) -> Self {
+        self.framerate_conversion = conversion;
+        self
+    }
+
+    /// Pin software encoder threads to a single CCD / NUMA node
+    pub fn with_numa_aware(mut self, enabled: bool) -> Self {
+        self.numa_aware = enabled;
+        self
+    }
+
+    /// Set how unaligned frame sizes are fitted to the codec
+    pub fn with_dimension_alignment(mut self, alignment: DimensionAlignment) -> Self {
+        self.dimension_alignment = alignment;
+        self
+    }
+
+    /// Pass a raw FFmpeg option to the encoder (see `extra_options`)
+    pub fn with_option(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.extra_options.insert(key.into(), value.into());
+        self
+    }
+
+    /// Encode with 4:2:2 or 4:4:4 chroma instead of 4:2:0
+    pub fn with_chroma_format(mut self, chroma: ChromaFormat) -> Self {
+        self.chroma_format = chroma;
+        self
+    }
+
+    /// Render the effective settings as `key=value` pairs
+    ///
+    /// Uses FFmpeg option names where one exists, so a recording's settings
+    /// can be compared with another's or replayed on the `ffmpeg` command
+    /// line. Preset and tuning use the names of the backend
+    /// `create_encoder` picks. Logged when the encoder starts; see also
+    /// `PipelineBuilder::embed_encoder_settings`.
+    pub fn describe(&self) -> String {
+        self.describe_for(crate::encode::EncoderBackend::Auto)
+    }
+
+    /// `describe` with preset and tuning as `backend` applies them
+    pub fn describe_for(&self, backend: crate::encode::EncoderBackend) -> String {
+        use crate::encode::{CpuPreset, EncoderBackend};
+
+        let backend = match backend {
+            EncoderBackend::Auto => EncoderBackend::auto_for(self.codec, self.chroma_format),
+            other => other,
+        };
+        let codec = match self.codec {
+            Codec::H264 => "h264",
+            Codec::Hevc => "hevc",
+            Codec::Av1 => "av1",
+        };
+        let mut parts = vec![format!("codec={}", codec)];
+        if let Some(res) = self.resolution {
+            parts.push(format!("s={}x{}", res.width, res.height));
+        }
+        parts.push(format!("r={}/{}", self.framerate.num, self.framerate.den));
+        let low_latency = self.tuning.is_low_latency();
+        match backend {
+            EncoderBackend::Qsv => {
+                parts.push(format!("preset={}", self.preset.to_qsv_preset()));
+                if low_latency {
+                    parts.push("low_power=1".into());
+                }
+            }
+            EncoderBackend::Amf => {
+                parts.push(format!("quality={}", self.preset.to_amf_quality()));
+                let usage = if low_latency {
+                    "ultralowlatency"
+                } else {
+                    "transcoding"
+                };
+                parts.push(format!("usage={}", usage));
+            }
+            // The software encoder stays on its own preset (see
+            // `CpuPreset::from_encoder_preset`)
+            EncoderBackend::Software if self.codec == Codec::Av1 => {
+                parts.push(format!(
+                    "preset={}",
+                    CpuPreset::default().to_svtav1_preset()
+                ));
+            }
+            EncoderBackend::Software => {
+                parts.push(format!("preset={}", CpuPreset::default().to_x26x_preset()));
+                // x264 always runs zerolatency
+                if low_latency || self.codec == Codec::H264 {
+                    parts.push("tune=zerolatency".into());
+                }
+            }
+            _ => {
+                parts.push(format!("preset={}", self.preset.to_nvenc_preset()));
+                parts.push(format!("tune={}", self.tuning.to_nvenc_tuning()));
+            }
+        }
+        let vbv = crate::encode::vbv_settings(self);
+        match self.rate_control {
+            RateControl::Cbr => {
+                parts.push("rc=cbr".into());
+                parts.push(format!("b={}k", self.bitrate_kbps));
+            }
+            RateControl::Vbr => {
+                parts.push("rc=vbr".into());
+                parts.push(format!("b={}k", self.bitrate_kbps));
+                if let (Some(max), None) = (self.max_bitrate_kbps, vbv) {
+                    parts.push(format!("maxrate={}k", max));
+                }
+            }
+            RateControl::Cqp { qp } => {
+                parts.push("rc=constqp".into());
+                parts.push(format!("qp={}", qp));
+            }
+            RateControl::Crf { crf } => parts.push(format!("crf={}", crf)),
+            RateControl::Quality { quality } => parts.push(format!("quality={}", quality)),
+        }
+        if let Some((buffer, max)) = vbv {
+            parts.push(format!("maxrate={}k", max));
+            parts.push(format!("bufsize={}k", buffer));
+        }
+        parts.push(format!("g={}", self.gop_size));
+        if !self.scene_cut {
+            parts.push("no-scenecut=1".into());
+        }
+        if let Some(frames) = self.min_keyframe_interval {
+            parts.push(format!("force_key_frames={}", frames));
+        }
+        parts.push(format!("bf={}", self.b_frames));
+        if let Some(la) = self.lookahead {
+            parts.push(format!("rc-lookahead={}", la));
+        }
+        if let Some(frames) = self.max_latency_frames {
+            parts.push(format!("max_latency={}", frames));
+        }
+        if let Some(multipass) = self.nvenc_multipass {
+            parts.push(format!("multipass={}", multipass.to_nvenc_multipass()));
+        }
+        if let Some(mode) = self.b_ref_mode {
+            parts.push(format!("b_ref_mode={}", mode.to_nvenc_b_ref_mode()));
+        }
+        if let Some(gpu) = self.gpu_index {
+            parts.push(format!("gpu={}", gpu));
+        }
+        if let Some(grain) = self.film_grain {
+            parts.push(format!("film-grain={}", grain));
+        }
+        parts.push(format!("pix_fmt={:?}", self.input_format()).to_lowercase());
+        parts.push(format!("chroma={}", self.chroma_format));
+        if let Some(ref profile) = self.profile {
+            parts.push(format!("profile={}", profile));
+        }
+        if let Some(ref level) = self.level {
+            parts.push(format!("level={}", level));
+        }
+        if let Some(ref hdr) = self.hdr {
+            parts.push(format!("color_trc={:?}", hdr.transfer).to_lowercase());
+            parts.push(format!("color_primaries={:?}", hdr.primaries).to_lowercase());
+        }
+        if self.repeat_headers {
+            parts.push("repeat_headers=1".into());
+        }
+        if self.static_frame_optimization {
+            parts.push(format!("static_min_fps={}", self.static_frame_min_fps));
+        }
+        if self.framerate_conversion != FramerateConversion::Nearest {
+            parts.push(format!("fps_conversion={:?}", self.framerate_conversion).to_lowercase());
+        }
+        if self.numa_aware {
+            parts.push("numa_aware=1".into());
+        }
+        let mut extra: Vec<_> = self.extra_options.iter().collect();
+        extra.sort();
+        parts.extend(extra.into_iter().map(|(k, v)| format!("{}={}", k, v)));
+        parts.join(" ")
+    }
+
+    /// Frame format the encoder expects from the processing stage
+    pub fn input_format(&self) -> FrameFormat {
+        match self.chroma_format {
+            ChromaFormat::Yuv420 if self.pixel_format.is_nvenc_native() => self.pixel_format,
+            ChromaFormat::Yuv420 => FrameFormat::Nv12,
+            ChromaFormat::Yuv422 => FrameFormat::Yuv422p,
+            ChromaFormat::Yuv444 => FrameFormat::Yuv444p,
+        }
+    }
+
     /// Enable HDR10 encoding
     pub fn with_hdr10(mut self) -> Self {
         self.hdr = Some(HdrConfig::hdr10());
@@ -195,6 +890,69 @@ impl EncoderConfig {
     }
 }
 
+/// Chroma subsampling
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum ChromaFormat {
+    /// 4:2:0 - supported by every encoder and decoder
+    #[default]
+    Yuv420,
+    /// 4:2:2 - software encoders only (x264/x265)
+    Yuv422,
+    /// 4:4:4 - full chroma for sharp text and screen content
+    /// (NVENC H.264/HEVC or x264/x265)
+    Yuv444,
+}
+
+impl ChromaFormat {
+    /// Get human-readable name
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            ChromaFormat::Yuv420 => "4:2:0",
+            ChromaFormat::Yuv422 => "4:2:2",
+            ChromaFormat::Yuv444 => "4:4:4",
+        }
+    }
+}
+
+impl std::fmt::Display for ChromaFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.display_name())
+    }
+}
+
+/// Fitting of frame sizes to the codec's dimension alignment
+///
+/// 4:2:0 encoding needs even widths and heights (4:2:2 an even width), but
+/// window captures can be any size. The encoder rounds its frame size to
+/// the alignment and adjusts the right and bottom edge; the change is
+/// logged when the encoder opens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum DimensionAlignment {
+    /// Drop the last row/column
+    #[default]
+    Crop,
+    /// Add a black row/column
+    Pad,
+    /// Pass the size to the encoder unchanged (which may refuse it)
+    Off,
+}
+
+/// Frame rate conversion between capture and output
+///
+/// See `processing::FramerateConverter`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum FramerateConversion {
+    /// Encode captured frames as they arrive
+    #[default]
+    Nearest,
+    /// Average the frames within each output frame interval and cross-fade
+    /// across intervals without one (120 -> 60 fps, 30 -> 60 fps)
+    Blend,
+    /// Motion-compensated interpolation with FFmpeg's `minterpolate`
+    /// (packed RGB capture only; expensive at high resolutions)
+    Interpolate,
+}
+
 /// Rate control mode
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub enum RateControl {
@@ -207,6 +965,9 @@ pub enum RateControl {
     Cqp { qp: u8 },
     /// Constant rate factor (quality-based)
     Crf { crf: u8 },
+    /// Codec-independent constant quality, 0 (worst) to 100 (best); mapped
+    /// to the backend's CRF/CQ/QP scale by `encode::quality_parameter`
+    Quality { quality: u8 },
 }
 
 /// Encoder preset (speed vs quality tradeoff)
@@ -236,6 +997,80 @@ impl EncoderPreset {
             EncoderPreset::Slowest => "p7",
         }
     }
+
+    /// Convert to QSV preset name
+    pub fn to_qsv_preset(&self) -> &'static str {
+        match self {
+            EncoderPreset::Fastest => "veryfast",
+            EncoderPreset::Fast => "fast",
+            EncoderPreset::Medium => "medium",
+            EncoderPreset::Slow => "slow",
+            EncoderPreset::Slowest => "veryslow",
+        }
+    }
+
+    /// Convert to AMF quality preset (AMF has three)
+    pub fn to_amf_quality(&self) -> &'static str {
+        match self {
+            EncoderPreset::Fastest | EncoderPreset::Fast => "speed",
+            EncoderPreset::Medium => "balanced",
+            EncoderPreset::Slow | EncoderPreset::Slowest => "quality",
+        }
+    }
+}
+
+/// NVENC multi-pass encoding
+///
+/// A first pass at quarter or full resolution gathers statistics so the
+/// final pass can distribute bits better, at some encoder throughput cost.
+/// Works with every preset (p1-p7) and with the `hq`, `ll` and `ull`
+/// tunings; it has no effect in lossless or constant-QP mode. `QuarterRes`
+/// is the usual choice for streaming, `FullRes` for recording.
+/// Requires driver R456 or newer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Multipass {
+    /// Single pass
+    Disabled,
+    /// First pass at quarter resolution
+    QuarterRes,
+    /// First pass at full resolution
+    FullRes,
+}
+
+impl Multipass {
+    pub fn to_nvenc_multipass(&self) -> &'static str {
+        match self {
+            Multipass::Disabled => "disabled",
+            Multipass::QuarterRes => "qres",
+            Multipass::FullRes => "fullres",
+        }
+    }
+}
+
+/// NVENC B-frames used as references
+///
+/// Lets later frames predict from B-frames, improving quality at the same
+/// bitrate. Only meaningful with `b_frames >= 2`, so it does nothing under
+/// the low-latency tunings when B-frames are disabled. Requires a Turing
+/// (RTX 20 / GTX 16) or newer GPU; H.264 supports `Middle` only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BRefMode {
+    /// B-frames are never references
+    Disabled,
+    /// Every B-frame can be a reference (HEVC/AV1)
+    Each,
+    /// Only the middle B-frame of each group is a reference
+    Middle,
+}
+
+impl BRefMode {
+    pub fn to_nvenc_b_ref_mode(&self) -> &'static str {
+        match self {
+            BRefMode::Disabled => "disabled",
+            BRefMode::Each => "each",
+            BRefMode::Middle => "middle",
+        }
+    }
 }
 
 /// Encoder tuning mode
@@ -261,6 +1096,14 @@ impl EncoderTuning {
             EncoderTuning::Lossless => "lossless",
         }
     }
+
+    /// Streaming tunings (`LowLatency`, `UltraLowLatency`)
+    pub fn is_low_latency(&self) -> bool {
+        matches!(
+            self,
+            EncoderTuning::LowLatency | EncoderTuning::UltraLowLatency
+        )
+    }
 }
 
 /// High-level presets for common use cases
@@ -288,6 +1131,63 @@ pub enum Preset {
     Hdr10_1440p60,
 }
 
+impl Preset {
+    /// Every preset, in listing order
+    pub const ALL: [Preset; 10] = [
+        Preset::Discord720p,
+        Preset::Stream1080p60,
+        Preset::Quality1440p60,
+        Preset::Gaming1440p120,
+        Preset::Ultra4K60,
+        Preset::Maximum4K120,
+        Preset::LowLatency,
+        Preset::Recording,
+        Preset::Hdr10_4K60,
+        Preset::Hdr10_1440p60,
+    ];
+
+    /// Short name, as taken by `ghoststream capture --preset`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preset::Discord720p => "discord",
+            Preset::Stream1080p60 => "stream",
+            Preset::Quality1440p60 => "quality",
+            Preset::Gaming1440p120 => "gaming",
+            Preset::Ultra4K60 => "4k",
+            Preset::Maximum4K120 => "max",
+            Preset::LowLatency => "lowlatency",
+            Preset::Recording => "recording",
+            Preset::Hdr10_4K60 => "hdr4k",
+            Preset::Hdr10_1440p60 => "hdr1440",
+        }
+    }
+
+    /// One-line summary of the settings
+    pub fn description(&self) -> &'static str {
+        match self {
+            Preset::Discord720p => "720p30, H.264, 3 Mbps, low latency",
+            Preset::Stream1080p60 => "1080p60, H.264, 6 Mbps, balanced",
+            Preset::Quality1440p60 => "1440p60, HEVC, 12 Mbps, high quality",
+            Preset::Gaming1440p120 => "1440p120, HEVC, 15 Mbps, low latency",
+            Preset::Ultra4K60 => "4K60, AV1, 25 Mbps, high quality",
+            Preset::Maximum4K120 => "4K120, AV1, 35 Mbps, RTX 40/50",
+            Preset::LowLatency => "Native res, H.264, 8 Mbps, ultra low latency",
+            Preset::Recording => "Native res, HEVC, 50 Mbps, max quality",
+            Preset::Hdr10_4K60 => "4K60, HEVC Main 10, 35 Mbps, HDR10",
+            Preset::Hdr10_1440p60 => "1440p60, HEVC Main 10, 20 Mbps, HDR10",
+        }
+    }
+
+    /// Look a preset up by short name or variant name (`Stream1080p60`),
+    /// ignoring case
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|p| p.name() == name || format!("{:?}", p).to_lowercase() == name)
+    }
+}
+
 impl From<Preset> for EncoderConfig {
     fn from(preset: Preset) -> Self {
         match preset {
@@ -299,6 +1199,7 @@ impl From<Preset> for EncoderConfig {
                 preset: EncoderPreset::Fast,
                 tuning: EncoderTuning::LowLatency,
                 gop_size: 60,
+                scene_cut: false,
                 ..Default::default()
             },
             Preset::Stream1080p60 => EncoderConfig {
@@ -309,6 +1210,7 @@ impl From<Preset> for EncoderConfig {
                 preset: EncoderPreset::Medium,
                 tuning: EncoderTuning::HighQuality,
                 gop_size: 120,
+                scene_cut: false,
                 ..Default::default()
             },
             Preset::Quality1440p60 => EncoderConfig {
@@ -329,6 +1231,7 @@ impl From<Preset> for EncoderConfig {
                 preset: EncoderPreset::Fast,
                 tuning: EncoderTuning::LowLatency,
                 gop_size: 240,
+                scene_cut: false,
                 ..Default::default()
             },
             Preset::Ultra4K60 => EncoderConfig {
@@ -359,6 +1262,7 @@ impl From<Preset> for EncoderConfig {
                 preset: EncoderPreset::Fastest,
                 tuning: EncoderTuning::UltraLowLatency,
                 gop_size: 30,
+                scene_cut: false,
                 b_frames: 0,
                 ..Default::default()
             },
@@ -0,0 +1,253 @@
+//! Video decoding module
+//!
+//! Hardware-accelerated decoding for the transcode path (file input) via
+//! NVDEC (CUDA) and VAAPI, with FFmpeg software decoding as the fallback.
+//! The rest of the pipeline works on CPU-side `Frame`s, so hardware frames
+//! are downloaded to system memory, but only on request: frames the caller
+//! skips (e.g. before a seek target) never leave the GPU.
+
+use crate::error::{Error, Result};
+
+use ffmpeg_next as ffmpeg;
+use ffmpeg_next::ffi;
+use ffmpeg_next::format::Pixel;
+
+/// Decoder backend selection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DecoderBackend {
+    /// Automatically select best available (NVDEC > VAAPI > Software)
+    #[default]
+    Auto,
+    /// Force NVIDIA NVDEC hardware decoding (CUDA hwaccel)
+    Nvdec,
+    /// Force VAAPI hardware decoding (Intel/AMD)
+    Vaapi,
+    /// Force CPU software decoding
+    Software,
+}
+
+impl DecoderBackend {
+    /// FFmpeg hardware device type for this backend
+    fn device_type(&self) -> Option<ffi::AVHWDeviceType> {
+        match self {
+            DecoderBackend::Nvdec => Some(ffi::AVHWDeviceType::AV_HWDEVICE_TYPE_CUDA),
+            DecoderBackend::Vaapi => Some(ffi::AVHWDeviceType::AV_HWDEVICE_TYPE_VAAPI),
+            DecoderBackend::Auto | DecoderBackend::Software => None,
+        }
+    }
+
+    /// Get human-readable name
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            DecoderBackend::Auto => "Auto",
+            DecoderBackend::Nvdec => "NVDEC",
+            DecoderBackend::Vaapi => "VAAPI",
+            DecoderBackend::Software => "Software",
+        }
+    }
+}
+
+impl std::fmt::Display for DecoderBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.display_name())
+    }
+}
+
+/// Video decoder with optional hardware acceleration
+pub struct VideoDecoder {
+    decoder: ffmpeg::decoder::Video,
+    backend: DecoderBackend,
+    /// Scratch frame hardware surfaces are downloaded into
+    sw_frame: ffmpeg::frame::Video,
+}
+
+impl VideoDecoder {
+    /// Backend actually in use (never `Auto`)
+    pub fn backend(&self) -> DecoderBackend {
+        self.backend
+    }
+
+    pub fn width(&self) -> u32 {
+        self.decoder.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.decoder.height()
+    }
+
+    /// Submit a compressed packet
+    pub fn send_packet(&mut self, packet: &ffmpeg::Packet) -> Result<()> {
+        self.decoder
+            .send_packet(packet)
+            .map_err(|e| Error::Ffmpeg(format!("Failed to decode packet: {}", e)))
+    }
+
+    /// Signal end of stream so buffered frames can be drained
+    pub fn send_eof(&mut self) -> Result<()> {
+        self.decoder
+            .send_eof()
+            .map_err(|e| Error::Ffmpeg(format!("Failed to flush decoder: {}", e)))
+    }
+
+    /// Receive the next decoded frame
+    ///
+    /// Frames of a hardware decoder stay on the GPU; their timestamps and
+    /// flags can be read as usual, and `download` fetches the pixels of
+    /// the ones that are used. Returns `Ok(false)` when the decoder needs
+    /// more input (or is drained).
+    pub fn receive_frame(&mut self, frame: &mut ffmpeg::frame::Video) -> Result<bool> {
+        match self.decoder.receive_frame(frame) {
+            Ok(()) => Ok(true),
+            Err(ffmpeg::Error::Other {
+                errno: ffmpeg::util::error::EAGAIN,
+            })
+            | Err(ffmpeg::Error::Eof) => Ok(false),
+            Err(e) => Err(Error::Ffmpeg(format!("Failed to decode frame: {}", e))),
+        }
+    }
+
+    /// Pixels of a frame from `receive_frame` in system memory
+    ///
+    /// Software frames are returned as they are; hardware surfaces are
+    /// downloaded (NV12/P010) into a scratch frame that is reused by the
+    /// next call.
+    pub fn download<'a>(
+        &'a mut self,
+        frame: &'a ffmpeg::frame::Video,
+    ) -> Result<&'a ffmpeg::frame::Video> {
+        if !matches!(frame.format(), Pixel::CUDA | Pixel::VAAPI) {
+            return Ok(frame);
+        }
+
+        unsafe {
+            ffi::av_frame_unref(self.sw_frame.as_mut_ptr());
+            let ret = ffi::av_hwframe_transfer_data(self.sw_frame.as_mut_ptr(), frame.as_ptr(), 0);
+            if ret < 0 {
+                return Err(Error::Ffmpeg(format!(
+                    "Failed to download hardware frame: {}",
+                    ffmpeg::Error::from(ret)
+                )));
+            }
+            ffi::av_frame_copy_props(self.sw_frame.as_mut_ptr(), frame.as_ptr());
+        }
+        Ok(&self.sw_frame)
+    }
+}
+
+/// Create a decoder for a stream
+pub fn create_decoder(
+    parameters: ffmpeg::codec::Parameters,
+    backend: DecoderBackend,
+) -> Result<VideoDecoder> {
+    ffmpeg::init().map_err(|e| Error::Ffmpeg(e.to_string()))?;
+
+    match backend {
+        DecoderBackend::Auto => {
+            // Try hardware decoders in order: NVDEC > VAAPI, fall back to software
+            for candidate in [DecoderBackend::Nvdec, DecoderBackend::Vaapi] {
+                match open_decoder(parameters.clone(), candidate) {
+                    Ok(decoder) => {
+                        tracing::info!("Using {} hardware decoder", candidate);
+                        return Ok(decoder);
+                    }
+                    Err(e) => tracing::debug!("{} decoder unavailable: {}", candidate, e),
+                }
+            }
+            tracing::info!("No hardware decoder available, using software decoder");
+            open_decoder(parameters, DecoderBackend::Software)
+        }
+        backend => open_decoder(parameters, backend),
+    }
+}
+
+/// Check if a hardware decode backend can be used on this system
+pub fn is_available(backend: DecoderBackend) -> bool {
+    let Some(device_type) = backend.device_type() else {
+        return true;
+    };
+    if ffmpeg::init().is_err() {
+        return false;
+    }
+    create_device(device_type).map(release_device).is_ok()
+}
+
+fn open_decoder(
+    parameters: ffmpeg::codec::Parameters,
+    backend: DecoderBackend,
+) -> Result<VideoDecoder> {
+    let mut context = ffmpeg::codec::context::Context::from_parameters(parameters)
+        .map_err(|e| Error::Ffmpeg(format!("Failed to read stream parameters: {}", e)))?;
+
+    if let Some(device_type) = backend.device_type() {
+        let codec = ffmpeg::decoder::find(context.id())
+            .ok_or_else(|| Error::CodecNotSupported(format!("{:?}", context.id())))?;
+        if !supports_device(codec, device_type) {
+            return Err(Error::CodecNotSupported(format!(
+                "{} cannot decode {:?}",
+                backend,
+                context.id()
+            )));
+        }
+
+        // The codec context takes ownership of the device reference; the
+        // default get_format then picks the matching hardware pixel format
+        let device = create_device(device_type)?;
+        unsafe {
+            (*context.as_mut_ptr()).hw_device_ctx = device;
+        }
+    }
+
+    let decoder = context
+        .decoder()
+        .video()
+        .map_err(|e| Error::Ffmpeg(format!("Failed to open video decoder: {}", e)))?;
+
+    Ok(VideoDecoder {
+        decoder,
+        backend,
+        sw_frame: ffmpeg::frame::Video::empty(),
+    })
+}
+
+/// Does the codec offer a hwaccel for this device type?
+fn supports_device(codec: ffmpeg::Codec, device_type: ffi::AVHWDeviceType) -> bool {
+    let mut index = 0;
+    loop {
+        let config = unsafe { ffi::avcodec_get_hw_config(codec.as_ptr(), index) };
+        if config.is_null() {
+            return false;
+        }
+        let (methods, config_type) = unsafe { ((*config).methods, (*config).device_type) };
+        if methods & ffi::AV_CODEC_HW_CONFIG_METHOD_HW_DEVICE_CTX as i32 != 0
+            && config_type == device_type
+        {
+            return true;
+        }
+        index += 1;
+    }
+}
+
+fn create_device(device_type: ffi::AVHWDeviceType) -> Result<*mut ffi::AVBufferRef> {
+    let mut device = std::ptr::null_mut();
+    let ret = unsafe {
+        ffi::av_hwdevice_ctx_create(
+            &mut device,
+            device_type,
+            std::ptr::null(),
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::Ffmpeg(format!(
+            "Failed to create {:?} device: {}",
+            device_type,
+            ffmpeg::Error::from(ret)
+        )));
+    }
+    Ok(device)
+}
+
+fn release_device(mut device: *mut ffi::AVBufferRef) {
+    unsafe { ffi::av_buffer_unref(&mut device) };
+}
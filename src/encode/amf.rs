@@ -6,7 +6,7 @@ use crate::config::EncoderConfig;
 use crate::error::{Error, Result};
 use crate::types::{CodecParams, Frame, FrameFormat, Packet, Resolution};
 
-use super::{Codec, Encoder, EncoderStats};
+use super::{packet_from_ffmpeg, Codec, Encoder, EncoderStats};
 
 use ffmpeg_next as ffmpeg;
 use ffmpeg_next::format::Pixel;
@@ -21,8 +21,12 @@ pub struct AmfEncoder {
     scaler: Option<Scaler>,
     stats: EncoderStats,
     frame_count: u64,
+    force_keyframe: bool,
     start_time: Option<Instant>,
-    input_resolution: Option<Resolution>,
+    /// Frame sizes through the scaler, set on init
+    fit: super::FrameFit,
+    /// Input size `warm_up` opened the encoder for, until the first frame
+    warmed_up: Option<(u32, u32)>,
     time_base: ffmpeg::Rational,
 }
 
@@ -47,6 +51,9 @@ impl AmfEncoder {
                 config.codec.display_name()
             )));
         }
+        super::check_chroma(&config, super::EncoderBackend::Amf)?;
+        super::check_pixel_format(&config, super::EncoderBackend::Amf)?;
+        super::check_film_grain(&config, super::EncoderBackend::Amf)?;
 
         Ok(Self {
             config,
@@ -54,8 +61,10 @@ impl AmfEncoder {
             scaler: None,
             stats: EncoderStats::default(),
             frame_count: 0,
+            force_keyframe: false,
             start_time: None,
-            input_resolution: None,
+            fit: super::FrameFit::default(),
+            warmed_up: None,
             time_base: ffmpeg::Rational::new(1, 60),
         })
     }
@@ -69,21 +78,25 @@ impl AmfEncoder {
         }
     }
 
-    /// Initialize encoder with specific input resolution
-    fn init_encoder(&mut self, input_width: u32, input_height: u32) -> Result<()> {
+    /// Initialize encoder for the first frame's resolution and colorimetry
+    fn init_encoder(&mut self, frame: &Frame) -> Result<()> {
+        let (input_width, input_height) = (frame.width, frame.height);
         let encoder_name = Self::amf_encoder_name(self.config.codec);
+        let latency = super::fit_latency_budget(&mut self.config, super::EncoderBackend::Amf);
 
         // Find the encoder
         let codec = ffmpeg::encoder::find_by_name(encoder_name).ok_or_else(|| {
             Error::EncoderInit(format!("AMF encoder {} not found", encoder_name))
         })?;
 
-        // Determine output resolution
-        let (out_width, out_height) = if let Some(res) = self.config.resolution {
-            (res.width, res.height)
-        } else {
-            (input_width, input_height)
-        };
+        // Determine output resolution, fitted to the codec's alignment
+        let fit = super::FrameFit::new(
+            (input_width, input_height),
+            self.config.resolution,
+            self.config.chroma_format,
+            self.config.dimension_alignment,
+        );
+        let (out_width, out_height) = fit.encoded;
 
         // Create encoder context
         let context = ffmpeg::codec::context::Context::new_with_codec(codec);
@@ -95,7 +108,9 @@ impl AmfEncoder {
         // Set basic parameters
         encoder.set_width(out_width);
         encoder.set_height(out_height);
-        encoder.set_format(Pixel::NV12); // AMF prefers NV12
+        // NV12, or P010 for 10-bit
+        let pixel = super::encoder_pixel_format(&self.config, super::EncoderBackend::Amf);
+        encoder.set_format(pixel);
         encoder.set_time_base(ffmpeg::Rational::new(1, 1000));
         self.time_base = ffmpeg::Rational::new(1, 1000);
 
@@ -118,11 +133,7 @@ impl AmfEncoder {
         opts.set("usage", usage);
 
         // AMF quality preset
-        let quality = match self.config.preset {
-            crate::config::EncoderPreset::Fastest | crate::config::EncoderPreset::Fast => "speed",
-            crate::config::EncoderPreset::Medium => "balanced",
-            crate::config::EncoderPreset::Slow | crate::config::EncoderPreset::Slowest => "quality",
-        };
+        let quality = self.config.preset.to_amf_quality();
         opts.set("quality", quality);
 
         // Rate control
@@ -148,6 +159,16 @@ impl AmfEncoder {
                 opts.set("qp_i", &crf.to_string());
                 opts.set("qp_p", &crf.to_string());
             }
+            crate::config::RateControl::Quality { quality } => {
+                let qp = super::quality_parameter(
+                    quality,
+                    self.config.codec,
+                    super::EncoderBackend::Amf,
+                );
+                opts.set("rc", "cqp");
+                opts.set("qp_i", &qp.to_string());
+                opts.set("qp_p", &qp.to_string());
+            }
         }
 
         // B-frames
@@ -155,23 +176,33 @@ impl AmfEncoder {
             opts.set("bf", &self.config.b_frames.to_string());
         }
 
+        // Insert SPS/PPS (and VPS) before every IDR
+        if self.config.repeat_headers && self.config.codec != Codec::Av1 {
+            opts.set("header_insertion_mode", "idr");
+        }
+
+        super::apply_vbv(&mut opts, &self.config);
+        super::apply_extra_options(&mut opts, &self.config, encoder_name);
+        super::set_colorimetry(&mut encoder, &self.config, frame);
+
         // Open encoder
         let opened = encoder
             .open_with(opts)
             .map_err(|e| Error::EncoderInit(format!("Failed to open AMF encoder: {}", e)))?;
 
         self.encoder = Some(opened);
-        self.input_resolution = Some(Resolution::new(input_width, input_height));
+        self.fit = fit;
 
-        // Create scaler if needed
-        if input_width != out_width || input_height != out_height {
+        // Create scaler if frames are resized, padded or converted
+        let input = Self::to_ffmpeg_format(self.config.input_format());
+        if fit.needs_scaler() || input != pixel {
             let scaler = Scaler::get(
-                Pixel::NV12,
-                input_width,
-                input_height,
-                Pixel::NV12,
-                out_width,
-                out_height,
+                input,
+                fit.source.0,
+                fit.source.1,
+                pixel,
+                fit.scaled.0,
+                fit.scaled.1,
                 ScalerFlags::BILINEAR,
             )
             .map_err(|e| Error::EncoderInit(format!("Failed to create scaler: {}", e)))?;
@@ -190,6 +221,8 @@ impl AmfEncoder {
             quality,
         );
 
+        super::report_latency(latency, &mut self.stats);
+
         Ok(())
     }
 
@@ -197,6 +230,7 @@ impl AmfEncoder {
         match format {
             FrameFormat::Nv12 => Pixel::NV12,
             FrameFormat::Yuv420p => Pixel::YUV420P,
+            FrameFormat::Yuv422p => Pixel::YUV422P,
             FrameFormat::Yuv444p => Pixel::YUV444P,
             FrameFormat::Bgra => Pixel::BGRA,
             FrameFormat::Rgba => Pixel::RGBA,
@@ -212,8 +246,17 @@ impl Encoder for AmfEncoder {
     }
 
     fn encode(&mut self, frame: &Frame) -> Result<Option<Packet>> {
+        // Warmed up without seeing a frame: re-open if the first one differs
+        if let Some(size) = self.warmed_up.take() {
+            if !super::fits_warm_up(&self.config, frame, size) {
+                tracing::debug!("Re-opening encoder for the first frame's size and colorimetry");
+                self.encoder = None;
+                self.scaler = None;
+            }
+        }
+
         if self.encoder.is_none() {
-            self.init_encoder(frame.width, frame.height)?;
+            self.init_encoder(frame)?;
         }
 
         let encoder = self.encoder.as_mut().unwrap();
@@ -226,33 +269,30 @@ impl Encoder for AmfEncoder {
         );
 
         // Copy frame data
-        if frame.format == FrameFormat::Nv12 {
-            let y_size = (frame.width * frame.height) as usize;
-            let uv_size = y_size / 2;
-
-            if frame.data.len() >= y_size + uv_size {
-                video_frame.data_mut(0)[..y_size].copy_from_slice(&frame.data[..y_size]);
-                video_frame.data_mut(1)[..uv_size]
-                    .copy_from_slice(&frame.data[y_size..y_size + uv_size]);
-            }
+        if matches!(frame.format, FrameFormat::Nv12 | FrameFormat::P010) {
+            super::copy_semi_planar(frame, &mut video_frame);
         } else {
             let plane_size = video_frame.data(0).len().min(frame.data.len());
             video_frame.data_mut(0)[..plane_size].copy_from_slice(&frame.data[..plane_size]);
         }
 
-        video_frame.set_pts(Some(frame.pts));
+        // Capture stamps µs; the encoder counts in its own time base
+        video_frame.set_pts(Some(super::pts_to_time_base(frame.pts, self.time_base)));
 
-        let frame_to_encode = if let Some(ref mut scaler) = self.scaler {
-            let mut scaled = ffmpeg::frame::Video::empty();
-            scaler
-                .run(&video_frame, &mut scaled)
-                .map_err(|e| Error::EncodingFailed(format!("Scaling failed: {}", e)))?;
-            scaled.set_pts(video_frame.pts());
-            scaled
-        } else {
-            video_frame
+        self.fit.crop(&mut video_frame);
+        let mut frame_to_encode = match self.scaler {
+            Some(ref mut scaler) => self.fit.scale(scaler, &video_frame)?,
+            None => video_frame,
         };
 
+        super::attach_hdr_side_data(&mut frame_to_encode, &self.config);
+
+        super::attach_timecode(&mut frame_to_encode, &self.config, frame.pts);
+
+        if std::mem::take(&mut self.force_keyframe) {
+            frame_to_encode.set_kind(ffmpeg::picture::Type::I);
+        }
+
         encoder
             .send_frame(&frame_to_encode)
             .map_err(|e| Error::EncodingFailed(format!("Failed to send frame: {}", e)))?;
@@ -278,14 +318,7 @@ impl Encoder for AmfEncoder {
                     }
                 }
 
-                Ok(Some(Packet {
-                    data: ffmpeg_packet.data().map(|d| d.to_vec()).unwrap_or_default(),
-                    pts: ffmpeg_packet.pts().unwrap_or(0),
-                    dts: ffmpeg_packet.dts().unwrap_or(0),
-                    duration: ffmpeg_packet.duration(),
-                    is_keyframe: ffmpeg_packet.is_key(),
-                    flags: 0,
-                }))
+                Ok(Some(packet_from_ffmpeg(&ffmpeg_packet, self.config.codec)))
             }
             Err(ffmpeg::Error::Other { errno }) if errno == ffmpeg::error::EAGAIN => Ok(None),
             Err(e) => Err(Error::EncodingFailed(format!(
@@ -311,14 +344,7 @@ impl Encoder for AmfEncoder {
             match encoder.receive_packet(&mut ffmpeg_packet) {
                 Ok(_) => {
                     self.stats.bytes_output += ffmpeg_packet.size() as u64;
-                    packets.push(Packet {
-                        data: ffmpeg_packet.data().map(|d| d.to_vec()).unwrap_or_default(),
-                        pts: ffmpeg_packet.pts().unwrap_or(0),
-                        dts: ffmpeg_packet.dts().unwrap_or(0),
-                        duration: ffmpeg_packet.duration(),
-                        is_keyframe: ffmpeg_packet.is_key(),
-                        flags: 0,
-                    });
+                    packets.push(packet_from_ffmpeg(&ffmpeg_packet, self.config.codec));
                 }
                 Err(ffmpeg::Error::Eof) => break,
                 Err(ffmpeg::Error::Other { errno }) if errno == ffmpeg::error::EAGAIN => continue,
@@ -355,13 +381,10 @@ impl Encoder for AmfEncoder {
             }
         };
 
-        let resolution = if let Some(res) = self.config.resolution {
-            res
-        } else if let Some(res) = self.input_resolution {
-            res
-        } else {
-            Resolution::new(encoder.width(), encoder.height())
-        };
+        let resolution = Resolution::new(encoder.width(), encoder.height());
+
+        let pixel = super::encoder_pixel_format(&self.config, super::EncoderBackend::Amf);
+        let (chroma_format, bit_depth) = super::coded_format(pixel);
 
         Some(CodecParams {
             codec: self.config.codec,
@@ -371,14 +394,33 @@ impl Encoder for AmfEncoder {
             time_base_num: self.time_base.numerator(),
             time_base_den: self.time_base.denominator(),
             bitrate: (self.config.bitrate_kbps as i64) * 1000,
+            hdr: self.config.hdr.clone(),
+            chroma_format,
+            bit_depth,
         })
     }
 
     fn reconfigure(&mut self, config: &EncoderConfig) -> Result<()> {
+        // Bitrate can change live; other settings apply on the next init
+        if let Some(encoder) = self.encoder.as_mut() {
+            super::apply_bitrate_change(encoder, &self.config, config);
+        }
         self.config = config.clone();
         tracing::info!("AMF encoder config updated: {}kbps", config.bitrate_kbps);
         Ok(())
     }
+
+    fn force_keyframe(&mut self) {
+        self.force_keyframe = true;
+    }
+
+    fn warm_up(&mut self, width: u32, height: u32) -> Result<()> {
+        if self.encoder.is_none() {
+            self.init_encoder(&super::warm_up_frame(&self.config, width, height))?;
+            self.warmed_up = Some((width, height));
+        }
+        Ok(())
+    }
 }
 
 // ============================================================================
@@ -449,7 +491,7 @@ pub fn get_capabilities() -> AmfCapabilities {
 }
 
 /// AMF capabilities
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, serde::Serialize)]
 pub struct AmfCapabilities {
     pub available: bool,
     pub h264: bool,
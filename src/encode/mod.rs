@@ -5,15 +5,19 @@
 
 pub mod amf;
 pub mod nvenc;
+pub mod pool;
 pub mod qsv;
 pub mod software;
+mod topology;
 
-use crate::config::EncoderConfig;
-use crate::error::Result;
-use crate::types::{CodecParams, Frame, Packet};
+use crate::config::{ChromaFormat, DimensionAlignment, EncoderConfig, EncoderPreset};
+use crate::error::{Error, Result};
+use crate::processing::HdrConfig;
+use crate::types::{CodecParams, Frame, FrameFormat, Framerate, Packet, Resolution};
 
 pub use amf::AmfEncoder;
 pub use nvenc::NvencEncoder;
+pub use pool::{EncoderHandle, EncoderPool};
 pub use qsv::QsvEncoder;
 pub use software::{CpuPreset, SoftwareEncoder};
 
@@ -83,6 +87,972 @@ pub trait Encoder {
 
     /// Reconfigure encoder (if supported)
     fn reconfigure(&mut self, config: &EncoderConfig) -> Result<()>;
+
+    /// Encode the next frame as a keyframe (IDR)
+    fn force_keyframe(&mut self);
+
+    /// Open the encoder for `width`x`height` input before the first frame
+    ///
+    /// Encoders otherwise open on the first `encode`, which then takes far
+    /// longer than the frames after it. The stream's colorimetry is fixed
+    /// on open, so a first frame of another size or tagged with non-default
+    /// colorimetry re-opens the encoder. Does nothing once it is open.
+    fn warm_up(&mut self, width: u32, height: u32) -> Result<()>;
+}
+
+/// Apply a runtime bitrate change to an opened FFmpeg encoder
+///
+/// libx264, QSV and NVENC (on GPUs with dynamic bitrate support) compare
+/// `bit_rate`/`rc_max_rate` on every submitted frame and reconfigure in
+/// place. libx265, SVT-AV1 and AMF keep the rate they were opened with
+/// until the next init. Constant-quality modes (CQP/CRF) ignore the change.
+pub(crate) fn apply_bitrate_change(
+    encoder: &mut ffmpeg_next::encoder::Video,
+    old: &EncoderConfig,
+    new: &EncoderConfig,
+) {
+    if old.bitrate_kbps == new.bitrate_kbps && old.max_bitrate_kbps == new.max_bitrate_kbps {
+        return;
+    }
+
+    encoder.set_bit_rate(new.bitrate_kbps as usize * 1000);
+    // An explicit VBV rate stays as configured
+    if new.vbv_max_kbps.is_some() {
+        return;
+    }
+    match new.rate_control {
+        crate::config::RateControl::Cbr => {
+            encoder.set_max_bit_rate(new.bitrate_kbps as usize * 1000);
+        }
+        _ => {
+            if let Some(max) = new.max_bitrate_kbps {
+                encoder.set_max_bit_rate(max as usize * 1000);
+            }
+        }
+    }
+}
+
+/// FFmpeg colorimetry values
+pub(crate) struct FfmpegColor {
+    pub space: ffmpeg_next::color::Space,
+    pub range: ffmpeg_next::color::Range,
+    pub primaries: ffmpeg_next::color::Primaries,
+    pub transfer: ffmpeg_next::color::TransferCharacteristic,
+}
+
+/// Map our colorimetry onto FFmpeg's enums
+pub(crate) fn ffmpeg_color(
+    matrix: crate::processing::ColorMatrix,
+    primaries: crate::processing::ColorPrimaries,
+    range: crate::processing::ColorRange,
+    transfer: crate::processing::TransferFunction,
+) -> FfmpegColor {
+    use crate::processing::{ColorMatrix, ColorPrimaries, ColorRange, TransferFunction};
+    use ffmpeg_next::color;
+
+    FfmpegColor {
+        space: match matrix {
+            ColorMatrix::Bt709 => color::Space::BT709,
+            ColorMatrix::Bt2020Ncl => color::Space::BT2020NCL,
+            ColorMatrix::Bt2020Cl => color::Space::BT2020CL,
+            ColorMatrix::Bt601 => color::Space::SMPTE170M,
+        },
+        range: match range {
+            ColorRange::Limited => color::Range::MPEG,
+            ColorRange::Full => color::Range::JPEG,
+        },
+        primaries: match primaries {
+            ColorPrimaries::Bt709 => color::Primaries::BT709,
+            ColorPrimaries::Bt2020 => color::Primaries::BT2020,
+            ColorPrimaries::DciP3 => color::Primaries::SMPTE432,
+            ColorPrimaries::Bt601 => color::Primaries::SMPTE170M,
+        },
+        transfer: match transfer {
+            TransferFunction::Sdr => color::TransferCharacteristic::BT709,
+            TransferFunction::Pq => color::TransferCharacteristic::SMPTE2084,
+            TransferFunction::Hlg => color::TransferCharacteristic::ARIB_STD_B67,
+        },
+    }
+}
+
+/// Signal the stream's colorimetry in the codec context (VUI / sequence header)
+///
+/// HDR configs dictate their own primaries, matrix and transfer; SDR
+/// streams carry whatever the first frame was converted with (BT.709
+/// limited range unless the capture reported otherwise). HDR10 configs also
+/// get their mastering display and content light level metadata.
+pub(crate) fn set_colorimetry(
+    encoder: &mut ffmpeg_next::encoder::Video,
+    config: &EncoderConfig,
+    frame: &Frame,
+) {
+    use crate::processing::{ColorRange, TransferFunction};
+
+    let color = match &config.hdr {
+        Some(hdr) => ffmpeg_color(hdr.matrix, hdr.primaries, ColorRange::Limited, hdr.transfer),
+        None => ffmpeg_color(
+            frame.color_space.unwrap_or_default(),
+            frame.primaries.unwrap_or_default(),
+            frame.color_range.unwrap_or_default(),
+            TransferFunction::Sdr,
+        ),
+    };
+
+    encoder.set_colorspace(color.space);
+    encoder.set_color_range(color.range);
+    unsafe {
+        let ctx = encoder.as_mut_ptr();
+        (*ctx).color_primaries = color.primaries.into();
+        (*ctx).color_trc = color.transfer.into();
+    }
+
+    if let Some(hdr) = &config.hdr {
+        set_hdr_metadata(encoder, hdr);
+    }
+}
+
+/// Stand-in frame for opening an encoder in `Encoder::warm_up`
+///
+/// Carries no pixels and no colorimetry, so the encoder opens with the
+/// defaults `set_colorimetry` picks for untagged frames.
+pub(crate) fn warm_up_frame(config: &EncoderConfig, width: u32, height: u32) -> Frame {
+    Frame::from_data(Vec::new(), width, height, 0, config.input_format())
+}
+
+/// Can an encoder warmed up for `size` take `frame` without re-opening?
+pub(crate) fn fits_warm_up(config: &EncoderConfig, frame: &Frame, size: (u32, u32)) -> bool {
+    let default_color = frame.color_space.unwrap_or_default() == Default::default()
+        && frame.color_range.unwrap_or_default() == Default::default()
+        && frame.primaries.unwrap_or_default() == Default::default();
+    (frame.width, frame.height) == size && (config.hdr.is_some() || default_color)
+}
+
+/// Attach HDR10 static metadata to the codec context before it is opened
+///
+/// libx265 and SVT-AV1 read it from the context's `decoded_side_data` and
+/// write it into every keyframe's SEI / metadata OBUs.
+fn set_hdr_metadata(encoder: &mut ffmpeg_next::encoder::Video, hdr: &HdrConfig) {
+    use ffmpeg_next::ffi;
+
+    unsafe {
+        let ctx = encoder.as_mut_ptr();
+        let add = |kind: ffi::AVFrameSideDataType, size: usize| {
+            let sd = ffi::av_frame_side_data_new(
+                &mut (*ctx).decoded_side_data,
+                &mut (*ctx).nb_decoded_side_data,
+                kind,
+                size,
+                ffi::AV_FRAME_SIDE_DATA_FLAG_REPLACE,
+            );
+            if sd.is_null() {
+                tracing::warn!("Failed to attach {:?} to the encoder", kind);
+                std::ptr::null_mut()
+            } else {
+                (*sd).data
+            }
+        };
+
+        if let Some(meta) = &hdr.hdr10_metadata {
+            let data = add(
+                ffi::AVFrameSideDataType::AV_FRAME_DATA_MASTERING_DISPLAY_METADATA,
+                std::mem::size_of::<ffi::AVMasteringDisplayMetadata>(),
+            ) as *mut ffi::AVMasteringDisplayMetadata;
+            if !data.is_null() {
+                data.write(meta.to_ffmpeg());
+            }
+        }
+        if let Some(light) = &hdr.content_light {
+            let data = add(
+                ffi::AVFrameSideDataType::AV_FRAME_DATA_CONTENT_LIGHT_LEVEL,
+                std::mem::size_of::<ffi::AVContentLightMetadata>(),
+            ) as *mut ffi::AVContentLightMetadata;
+            if !data.is_null() {
+                data.write(light.to_ffmpeg());
+            }
+        }
+    }
+}
+
+/// Attach HDR10 static metadata to a frame about to be encoded
+///
+/// NVENC, QSV and AMF take mastering display and light level info from
+/// frame side data rather than the codec context.
+pub(crate) fn attach_hdr_side_data(frame: &mut ffmpeg_next::frame::Video, config: &EncoderConfig) {
+    use ffmpeg_next::ffi;
+
+    let Some(hdr) = &config.hdr else {
+        return;
+    };
+    unsafe {
+        if let Some(meta) = &hdr.hdr10_metadata {
+            let data = ffi::av_mastering_display_metadata_create_side_data(frame.as_mut_ptr());
+            if !data.is_null() {
+                data.write(meta.to_ffmpeg());
+            }
+        }
+        if let Some(light) = &hdr.content_light {
+            let data = ffi::av_content_light_metadata_create_side_data(frame.as_mut_ptr());
+            if !data.is_null() {
+                data.write(light.to_ffmpeg());
+            }
+        }
+    }
+}
+
+/// Attach the frame's SMPTE timecode (`EncoderConfig::timecode`) as
+/// `AV_FRAME_DATA_S12M_TIMECODE`
+///
+/// Every frame is tagged, so the keyframes an encoder places on its own
+/// carry one too; encoders that write timecode SEI (NVENC) take it from
+/// there. Frames are numbered from the session start (PTS 0).
+pub(crate) fn attach_timecode(
+    frame: &mut ffmpeg_next::frame::Video,
+    config: &EncoderConfig,
+    pts_us: i64,
+) {
+    use ffmpeg_next::ffi;
+
+    let Some(start) = config.timecode else {
+        return;
+    };
+    let rate = config.framerate.clamped();
+    let per_second = rate.den as u128 * 1_000_000;
+    let index = (pts_us.max(0) as u128 * rate.num as u128 + per_second / 2) / per_second;
+    let timecode = start.offset(index as u64, rate);
+    unsafe {
+        // Count followed by up to three timecodes
+        let sd = ffi::av_frame_new_side_data(
+            frame.as_mut_ptr(),
+            ffi::AVFrameSideDataType::AV_FRAME_DATA_S12M_TIMECODE,
+            4 * std::mem::size_of::<u32>(),
+        );
+        if !sd.is_null() {
+            let data = (*sd).data as *mut u32;
+            let values = [1, timecode.to_smpte_12m(rate), 0, 0];
+            for (i, value) in values.into_iter().enumerate() {
+                data.add(i).write_unaligned(value);
+            }
+        }
+    }
+}
+
+/// VBV buffer size (kbit) and fill rate (kbps), if either is configured
+///
+/// x264/x265 ignore one without the other, so a missing rate comes from
+/// `max_bitrate_kbps` (VBR) or the bitrate, and a missing buffer holds two
+/// seconds at that rate.
+pub(crate) fn vbv_settings(config: &EncoderConfig) -> Option<(u32, u32)> {
+    if config.vbv_buffer_kbits.is_none() && config.vbv_max_kbps.is_none() {
+        return None;
+    }
+    let vbr_max = match config.rate_control {
+        crate::config::RateControl::Vbr => config.max_bitrate_kbps,
+        _ => None,
+    };
+    let max = config
+        .vbv_max_kbps
+        .or(vbr_max)
+        .unwrap_or(config.bitrate_kbps);
+    let buffer = config.vbv_buffer_kbits.unwrap_or(max.saturating_mul(2));
+    Some((buffer, max))
+}
+
+/// Set the VBV buffer as FFmpeg's generic `bufsize`/`maxrate`
+///
+/// Every wrapper maps them onto its encoder's HRD settings: x264/x265
+/// `vbv-bufsize`/`vbv-maxrate`, NVENC `vbvBufferSize`/`maxBitRate`, QSV
+/// `BufferSizeInKB`/`MaxKbps`, AMF's VBV buffer and SVT-AV1's `buf-sz`
+/// (relative to the bitrate) and `mbr`. Overrides the rate control's own.
+pub(crate) fn apply_vbv(opts: &mut ffmpeg_next::Dictionary, config: &EncoderConfig) {
+    if let Some((buffer, max)) = vbv_settings(config) {
+        opts.set("bufsize", &format!("{}k", buffer));
+        opts.set("maxrate", &format!("{}k", max));
+    }
+}
+
+/// Merge `EncoderConfig::extra_options` over the library's own options
+///
+/// Applied last so user values win; the final set is logged since a bad
+/// option only shows up as an encoder open failure or odd output.
+pub(crate) fn apply_extra_options(
+    opts: &mut ffmpeg_next::Dictionary,
+    config: &EncoderConfig,
+    encoder_name: &str,
+) {
+    let mut extra: Vec<_> = config.extra_options.iter().collect();
+    extra.sort();
+    for (key, value) in extra {
+        opts.set(key, value);
+    }
+
+    let merged: Vec<String> = opts.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+    tracing::info!("{} options: {}", encoder_name, merged.join(" "));
+}
+
+/// Prepend Annex-B parameter sets to a keyframe that lacks them in-band
+///
+/// Safety net for `EncoderConfig::repeat_headers` with encoders that only
+/// emit SPS/PPS/VPS once: `extradata` is the encoder's Annex-B header blob.
+/// AV1 (no NAL units) and AVCC extradata are left alone.
+pub(crate) fn insert_headers(packet: &mut Packet, codec: Codec, extradata: &[u8]) {
+    let annexb = extradata.starts_with(&[0, 0, 1]) || extradata.starts_with(&[0, 0, 0, 1]);
+    if !packet.is_keyframe() || !annexb || codec == Codec::Av1 {
+        return;
+    }
+
+    if bitstream_flags(&packet.data, codec) & Packet::FLAG_HEADERS != 0 {
+        return;
+    }
+
+    let mut data = Vec::with_capacity(extradata.len() + packet.data.len());
+    data.extend_from_slice(extradata);
+    data.extend_from_slice(&packet.data);
+    packet.data = data;
+    packet.flags |= Packet::FLAG_HEADERS;
+}
+
+/// Frame PTS (µs, as stamped at capture) in an encoder's time base,
+/// rounded to the nearest tick
+pub(crate) fn pts_to_time_base(pts_us: i64, time_base: ffmpeg_next::Rational) -> i64 {
+    // pts_us / 1e6 seconds, divided by num/den seconds per tick
+    let num = time_base.numerator() as i128 * 1_000_000;
+    let den = time_base.denominator() as i128;
+    if num <= 0 || den <= 0 {
+        return pts_us;
+    }
+    let scaled = pts_us as i128 * den;
+    let half = num / 2;
+    let ticks = if scaled >= 0 {
+        (scaled + half) / num
+    } else {
+        (scaled - half) / num
+    };
+    ticks as i64
+}
+
+/// Convert an encoder packet, filling in `Packet::FLAG_*`
+///
+/// FFmpeg's key flag misses AV1 forward keyframes and rarely reports
+/// disposable frames, so the bitstream itself is inspected as well.
+pub(crate) fn packet_from_ffmpeg(packet: &ffmpeg_next::Packet, codec: Codec) -> Packet {
+    use ffmpeg_next::packet::Ref;
+
+    let data = packet.data().map(|d| d.to_vec()).unwrap_or_default();
+    let mut flags = bitstream_flags(&data, codec);
+    if packet.is_key() {
+        flags |= Packet::FLAG_KEYFRAME;
+    }
+    // Not exposed through ffmpeg-next's packet::Flags
+    let disposable = unsafe { (*packet.as_ptr()).flags & ffmpeg_next::ffi::AV_PKT_FLAG_DISPOSABLE };
+    if disposable != 0 {
+        flags |= Packet::FLAG_DISPOSABLE;
+    }
+
+    Packet {
+        data,
+        pts: packet.pts().unwrap_or(0),
+        dts: packet.dts().unwrap_or(0),
+        duration: packet.duration(),
+        is_keyframe: flags & Packet::FLAG_KEYFRAME != 0,
+        flags,
+    }
+}
+
+/// `Packet::FLAG_*` bits read from an Annex-B or AV1 OBU bitstream
+///
+/// The first slice decides keyframe/disposable: H.264 IDR or `nal_ref_idc`
+/// 0, HEVC IRAP or sub-layer non-reference types, AV1 `KEY_FRAME` headers.
+fn bitstream_flags(data: &[u8], codec: Codec) -> u32 {
+    if codec == Codec::Av1 {
+        return av1_flags(data);
+    }
+
+    let mut flags = 0;
+    let mut slice_seen = false;
+    // NAL unit header following each start code
+    for w in data.windows(4).filter(|w| w[..3] == [0, 0, 1]) {
+        let (headers, slice) = match codec {
+            Codec::H264 => {
+                let nal_type = w[3] & 0x1f;
+                let key = nal_type == 5;
+                let disposable = nal_type == 1 && (w[3] >> 5) & 0x3 == 0;
+                (
+                    nal_type == 7,
+                    (1..=5).contains(&nal_type).then_some((key, disposable)),
+                )
+            }
+            _ => {
+                let nal_type = (w[3] >> 1) & 0x3f;
+                let key = (16..=21).contains(&nal_type);
+                let disposable = nal_type <= 14 && nal_type % 2 == 0;
+                (
+                    matches!(nal_type, 32 | 33),
+                    (nal_type <= 21).then_some((key, disposable)),
+                )
+            }
+        };
+        if headers {
+            flags |= Packet::FLAG_HEADERS;
+        }
+        if let Some((key, disposable)) = slice.filter(|_| !slice_seen) {
+            slice_seen = true;
+            if key {
+                flags |= Packet::FLAG_KEYFRAME;
+            }
+            if disposable {
+                flags |= Packet::FLAG_DISPOSABLE;
+            }
+        }
+    }
+    flags
+}
+
+/// `Packet::FLAG_*` bits for an AV1 temporal unit (low-overhead OBU format)
+fn av1_flags(data: &[u8]) -> u32 {
+    const OBU_SEQUENCE_HEADER: u8 = 1;
+    const OBU_FRAME_HEADER: u8 = 3;
+    const OBU_FRAME: u8 = 6;
+
+    let mut flags = 0;
+    let mut pos = 0;
+    while pos < data.len() {
+        let header = data[pos];
+        let obu_type = (header >> 3) & 0xf;
+        pos += 1 + ((header >> 2) & 1) as usize; // extension byte
+
+        // Without a size field the OBU runs to the end of the data
+        let mut size = data.len().saturating_sub(pos);
+        if header & 0x02 != 0 {
+            size = 0;
+            for i in 0..8 {
+                let Some(&byte) = data.get(pos) else {
+                    return flags;
+                };
+                pos += 1;
+                size |= ((byte & 0x7f) as usize) << (7 * i);
+                if byte & 0x80 == 0 {
+                    break;
+                }
+            }
+        }
+
+        match obu_type {
+            OBU_SEQUENCE_HEADER => flags |= Packet::FLAG_HEADERS,
+            OBU_FRAME_HEADER | OBU_FRAME => {
+                // show_existing_frame (1 bit), then frame_type (2 bits)
+                if let Some(&byte) = data.get(pos).filter(|_| size > 0) {
+                    if byte & 0x80 == 0 && (byte >> 5) & 0x3 == 0 {
+                        flags |= Packet::FLAG_KEYFRAME;
+                    }
+                }
+            }
+            _ => {}
+        }
+        pos = pos.saturating_add(size);
+    }
+
+    // A keyframe without a sequence header is not a usable entry point
+    if flags & Packet::FLAG_HEADERS == 0 {
+        flags &= !Packet::FLAG_KEYFRAME;
+    }
+    flags
+}
+
+/// Can this backend encode `codec` with the given chroma subsampling?
+///
+/// NVENC does 4:4:4 for H.264/HEVC (Maxwell and newer) but not for AV1;
+/// QSV and AMF are limited to 4:2:0 here. x264/x265 handle everything,
+/// SVT-AV1 is 4:2:0 only.
+pub fn supports_chroma(backend: EncoderBackend, codec: Codec, chroma: ChromaFormat) -> bool {
+    match (backend, chroma) {
+        (_, ChromaFormat::Yuv420) => true,
+        (EncoderBackend::Nvenc, ChromaFormat::Yuv444) => codec != Codec::Av1,
+        (EncoderBackend::Software, _) => codec != Codec::Av1,
+        _ => false,
+    }
+}
+
+/// Map a `RateControl::Quality` level (0-100) to the rate-control value
+/// the backend takes for `codec`
+///
+/// The curve is linear over each scale's useful range rather than its full
+/// range, whose ends are lossless or unwatchable:
+///
+/// | Scale | quality 0 | 70 | 100 |
+/// |-------|-----------|----|-----|
+/// | x264/x265 CRF, NVENC CQ, QSV ICQ, AMF QP (0-51) | 45 | 23 | 14 |
+/// | SVT-AV1 CRF (0-63) | 60 | 32 | 20 |
+/// | AV1 QSV/AMF quantizer index (0-255) | 240 | 128 | 80 |
+///
+/// Quality 70 lands on the encoders' usual defaults (x264 CRF 23), and the
+/// same level gives comparable visual quality across codecs.
+pub fn quality_parameter(quality: u8, codec: Codec, backend: EncoderBackend) -> u8 {
+    let (best, worst) = match (codec, backend) {
+        (Codec::Av1, EncoderBackend::Software) => (20, 60),
+        (Codec::Av1, EncoderBackend::Qsv | EncoderBackend::Amf) => (80, 240),
+        _ => (14, 45),
+    };
+    let span = (worst - best) as u32;
+    worst - ((span * quality.min(100) as u32 + 50) / 100) as u8
+}
+
+/// Reject chroma settings the backend, profile or HDR path cannot honor
+pub(crate) fn check_chroma(config: &EncoderConfig, backend: EncoderBackend) -> Result<()> {
+    let chroma = config.chroma_format;
+    if chroma == ChromaFormat::Yuv420 {
+        return Ok(());
+    }
+
+    if !supports_chroma(backend, config.codec, chroma) {
+        return Err(Error::CodecNotSupported(format!(
+            "{} encoding with {:?} is not supported",
+            chroma, backend
+        )));
+    }
+    if config.is_hdr() {
+        return Err(Error::InvalidEncoderConfig(format!(
+            "HDR encoding is 4:2:0 (P010) only, not {}",
+            chroma
+        )));
+    }
+    if let Some(profile) = config.profile.as_deref() {
+        let compatible = match config.codec {
+            Codec::H264 => match chroma {
+                ChromaFormat::Yuv422 => matches!(profile, "high422" | "high444" | "high444p"),
+                _ => matches!(profile, "high444" | "high444p"),
+            },
+            Codec::Hevc => profile == "rext" || profile.starts_with("main4"),
+            Codec::Av1 => false,
+        };
+        if !compatible {
+            return Err(Error::InvalidEncoderConfig(format!(
+                "Profile '{}' does not allow {} chroma",
+                profile, chroma
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Buffering an encoder was fitted to (see `EncoderConfig::max_latency_frames`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LatencyFit {
+    pub b_frames: u32,
+    pub lookahead: u32,
+    /// Frames left for the encoder's own queue (NVENC `delay`, QSV
+    /// `async_depth`, x265 frame threads beyond the first)
+    pub queue: u32,
+}
+
+impl LatencyFit {
+    pub fn frames(&self) -> u32 {
+        self.b_frames + self.lookahead + self.queue
+    }
+}
+
+/// Lookahead a backend uses when `EncoderConfig::lookahead` is unset
+fn default_lookahead(codec: Codec, backend: EncoderBackend) -> u32 {
+    match (backend, codec) {
+        // x265 is opened with rc-lookahead=20
+        (EncoderBackend::Software, Codec::Hevc) => 20,
+        // SVT-AV1 picks its own, up to 120 frames
+        (EncoderBackend::Software, Codec::Av1) => 120,
+        _ => 0,
+    }
+}
+
+/// Cut B-frames, then lookahead, to `max_latency_frames`
+///
+/// Settings that don't fit are overwritten in `config`; None without a
+/// budget.
+pub(crate) fn fit_latency_budget(
+    config: &mut EncoderConfig,
+    backend: EncoderBackend,
+) -> Option<LatencyFit> {
+    let budget = config.max_latency_frames?;
+    if config.b_frames > budget {
+        tracing::warn!(
+            "{} B-frames exceed the {}-frame latency budget, using {}",
+            config.b_frames,
+            budget,
+            budget
+        );
+        config.b_frames = budget;
+    }
+    // x265 places B-frames within its lookahead, which has to cover them:
+    // the lookahead alone is the delay
+    let x265 = backend == EncoderBackend::Software && config.codec == Codec::Hevc;
+    // SVT-AV1 has no B-frames; under a budget it uses the low-delay
+    // prediction structure, which doesn't reorder
+    let svt = backend == EncoderBackend::Software && config.codec == Codec::Av1;
+    let spare = if x265 || svt {
+        budget
+    } else {
+        budget - config.b_frames
+    };
+
+    let lookahead = config
+        .lookahead
+        .unwrap_or_else(|| default_lookahead(config.codec, backend));
+    if lookahead > spare {
+        if config.lookahead.is_some() {
+            tracing::warn!(
+                "Lookahead of {} frames exceeds the latency budget, using {}",
+                lookahead,
+                spare
+            );
+        }
+        config.lookahead = Some(spare);
+    }
+    let lookahead = lookahead.min(spare);
+    if svt {
+        return Some(LatencyFit {
+            b_frames: 0,
+            lookahead,
+            queue: 0,
+        });
+    }
+    if x265 {
+        // Every frame thread past the first holds one more frame
+        let lookahead = lookahead.max(config.b_frames);
+        config.lookahead = Some(lookahead);
+        return Some(LatencyFit {
+            b_frames: 0,
+            lookahead,
+            queue: budget - lookahead,
+        });
+    }
+
+    // Only the hardware encoders have a queue to size
+    let queued = matches!(backend, EncoderBackend::Nvenc | EncoderBackend::Qsv);
+    Some(LatencyFit {
+        b_frames: config.b_frames,
+        lookahead,
+        queue: if queued { spare - lookahead } else { 0 },
+    })
+}
+
+/// Log the buffering an encoder was opened with and record it in `stats`
+pub(crate) fn report_latency(fit: Option<LatencyFit>, stats: &mut EncoderStats) {
+    let Some(fit) = fit else {
+        return;
+    };
+    stats.latency_frames = fit.frames();
+    tracing::info!(
+        "Encoder latency: {} frames ({} B-frames, {} lookahead, {} queued)",
+        fit.frames(),
+        fit.b_frames,
+        fit.lookahead,
+        fit.queue
+    );
+}
+
+/// Reject film grain synthesis outside AV1, warn where it's ignored
+pub(crate) fn check_film_grain(config: &EncoderConfig, backend: EncoderBackend) -> Result<()> {
+    let Some(strength) = config.film_grain else {
+        return Ok(());
+    };
+    if config.codec != Codec::Av1 {
+        return Err(Error::InvalidEncoderConfig(format!(
+            "Film grain synthesis is AV1 only, not {}",
+            config.codec
+        )));
+    }
+    if strength > 50 {
+        return Err(Error::InvalidEncoderConfig(format!(
+            "Film grain strength {} is out of range (1-50)",
+            strength
+        )));
+    }
+    if backend != EncoderBackend::Software {
+        tracing::warn!(
+            "Film grain synthesis is not supported by {:?}, ignoring",
+            backend
+        );
+    }
+    Ok(())
+}
+
+/// Pixel format an encoder is opened with for `config`
+///
+/// `pixel_format: P010` selects 10-bit: P010 on the hardware encoders, the
+/// planar 10-bit format of the configured chroma for x264/x265/SVT-AV1.
+/// Anything else encodes 8-bit (NV12 on hardware, planar YUV in software).
+pub(crate) fn encoder_pixel_format(
+    config: &EncoderConfig,
+    backend: EncoderBackend,
+) -> ffmpeg_next::format::Pixel {
+    use ffmpeg_next::format::Pixel;
+
+    let ten_bit = config.pixel_format == FrameFormat::P010;
+    match (backend, config.chroma_format) {
+        (EncoderBackend::Software, ChromaFormat::Yuv420) if ten_bit => Pixel::YUV420P10LE,
+        (EncoderBackend::Software, ChromaFormat::Yuv422) if ten_bit => Pixel::YUV422P10LE,
+        (EncoderBackend::Software, ChromaFormat::Yuv444) if ten_bit => Pixel::YUV444P10LE,
+        (EncoderBackend::Software, ChromaFormat::Yuv420) => Pixel::YUV420P,
+        (EncoderBackend::Software, ChromaFormat::Yuv422) => Pixel::YUV422P,
+        (EncoderBackend::Software, ChromaFormat::Yuv444) => Pixel::YUV444P,
+        (EncoderBackend::Nvenc, ChromaFormat::Yuv444) => Pixel::YUV444P,
+        _ if ten_bit => Pixel::P010LE,
+        _ => Pixel::NV12,
+    }
+}
+
+/// Chroma subsampling and bit depth of the pictures an encoder opened
+/// with `pixel` codes (NV12 and P010 are 4:2:0)
+pub(crate) fn coded_format(pixel: ffmpeg_next::format::Pixel) -> (ChromaFormat, u8) {
+    use ffmpeg_next::format::Pixel;
+
+    match pixel {
+        Pixel::YUV422P => (ChromaFormat::Yuv422, 8),
+        Pixel::YUV422P10LE => (ChromaFormat::Yuv422, 10),
+        Pixel::YUV444P => (ChromaFormat::Yuv444, 8),
+        Pixel::YUV444P10LE => (ChromaFormat::Yuv444, 10),
+        Pixel::YUV420P10LE | Pixel::P010LE => (ChromaFormat::Yuv420, 10),
+        _ => (ChromaFormat::Yuv420, 8),
+    }
+}
+
+/// Reject pixel formats the backend, codec or profile cannot encode
+///
+/// 10-bit needs HEVC or AV1 on the hardware encoders (x264 does High 10),
+/// 4:2:0 chroma outside the software encoders, and a profile that allows
+/// it (`high10`, `main10`, ...) when one is set.
+pub(crate) fn check_pixel_format(config: &EncoderConfig, backend: EncoderBackend) -> Result<()> {
+    match config.pixel_format {
+        FrameFormat::P010 => {}
+        FrameFormat::Nv12 | FrameFormat::Yuv420p | FrameFormat::Yuv422p | FrameFormat::Yuv444p => {
+            return Ok(());
+        }
+        other => {
+            return Err(Error::InvalidEncoderConfig(format!(
+                "Encoders take YUV input, not {:?}",
+                other
+            )));
+        }
+    }
+
+    let hardware = backend != EncoderBackend::Software;
+    if hardware && config.codec == Codec::H264 {
+        return Err(Error::CodecNotSupported(format!(
+            "10-bit H.264 is not supported by {:?}; use HEVC or AV1",
+            backend
+        )));
+    }
+    if hardware && config.chroma_format != ChromaFormat::Yuv420 {
+        return Err(Error::InvalidEncoderConfig(format!(
+            "10-bit encoding with {:?} is 4:2:0 only, not {}",
+            backend, config.chroma_format
+        )));
+    }
+    if let Some(profile) = config.profile.as_deref() {
+        let compatible = match config.codec {
+            Codec::H264 => matches!(profile, "high10" | "high422" | "high444" | "high444p"),
+            Codec::Hevc => matches!(profile, "main10" | "rext"),
+            Codec::Av1 => true,
+        };
+        if !compatible {
+            return Err(Error::InvalidEncoderConfig(format!(
+                "Profile '{}' does not allow 10-bit",
+                profile
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Copy a semi-planar frame (NV12, or P010 with 16-bit samples) into an
+/// FFmpeg frame
+///
+/// `frame.data` holds the Y plane and the interleaved UV plane back to
+/// back without padding.
+pub(crate) fn copy_semi_planar(frame: &Frame, video_frame: &mut ffmpeg_next::frame::Video) {
+    let sample = match frame.format {
+        FrameFormat::P010 => 2,
+        _ => 1,
+    };
+    let uv_row = frame.width.div_ceil(2) as usize * 2 * sample;
+    let planes = [
+        (frame.width as usize * sample, frame.height as usize),
+        (uv_row, frame.height.div_ceil(2) as usize),
+    ];
+
+    let mut offset = 0;
+    for (index, (width, height)) in planes.into_iter().enumerate() {
+        let stride = video_frame.stride(index);
+        let Some(src) = frame.data.get(offset..offset + width * height) else {
+            return;
+        };
+        let dst = video_frame.data_mut(index);
+        for (row, line) in src.chunks_exact(width).enumerate() {
+            dst[row * stride..row * stride + width].copy_from_slice(line);
+        }
+        offset += width * height;
+    }
+}
+
+/// Copy a planar YUV frame (4:2:0, 4:2:2 or 4:4:4) into an FFmpeg frame
+///
+/// `frame.data` holds the Y, U and V planes back to back without padding.
+pub(crate) fn copy_planar_yuv(frame: &Frame, video_frame: &mut ffmpeg_next::frame::Video) {
+    let Some((chroma_width, chroma_height)) = frame.format.chroma_size(frame.width, frame.height)
+    else {
+        return;
+    };
+    let planes = [
+        (frame.width as usize, frame.height as usize),
+        (chroma_width as usize, chroma_height as usize),
+        (chroma_width as usize, chroma_height as usize),
+    ];
+
+    let mut offset = 0;
+    for (index, (width, height)) in planes.into_iter().enumerate() {
+        let stride = video_frame.stride(index);
+        let Some(src) = frame.data.get(offset..offset + width * height) else {
+            return;
+        };
+        let dst = video_frame.data_mut(index);
+        for (row, line) in src.chunks_exact(width).enumerate() {
+            dst[row * stride..row * stride + width].copy_from_slice(line);
+        }
+        offset += width * height;
+    }
+}
+
+/// Frame sizes along an encoder's scaler, fitted to the codec's alignment
+/// (see `DimensionAlignment`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct FrameFit {
+    /// Part of the input fed to the scaler (cropped edges left out)
+    pub source: (u32, u32),
+    /// Size the scaler produces
+    pub scaled: (u32, u32),
+    /// Size of the encoded frames (the scaled picture plus padding)
+    pub encoded: (u32, u32),
+}
+
+impl FrameFit {
+    /// Fit `input`, scaled to `output` if set, to the alignment `chroma`
+    /// needs
+    pub fn new(
+        input: (u32, u32),
+        output: Option<Resolution>,
+        chroma: ChromaFormat,
+        alignment: DimensionAlignment,
+    ) -> Self {
+        let target = output.map_or(input, |res| (res.width, res.height));
+        let (align_w, align_h) = match chroma {
+            ChromaFormat::Yuv420 => (2, 2),
+            ChromaFormat::Yuv422 => (2, 1),
+            ChromaFormat::Yuv444 => (1, 1),
+        };
+        let fit = match alignment {
+            DimensionAlignment::Off => Self {
+                source: input,
+                scaled: target,
+                encoded: target,
+            },
+            DimensionAlignment::Crop => {
+                let encoded = (
+                    (target.0 / align_w * align_w).max(align_w),
+                    (target.1 / align_h * align_h).max(align_h),
+                );
+                // Unscaled frames lose their edge; a scaler absorbs it
+                let cropped = output.is_none() && encoded.0 <= input.0 && encoded.1 <= input.1;
+                Self {
+                    source: if cropped { encoded } else { input },
+                    scaled: encoded,
+                    encoded,
+                }
+            }
+            DimensionAlignment::Pad => Self {
+                source: input,
+                scaled: target,
+                encoded: (
+                    target.0.div_ceil(align_w) * align_w,
+                    target.1.div_ceil(align_h) * align_h,
+                ),
+            },
+        };
+
+        if fit.encoded != target {
+            tracing::info!(
+                "{}x{} is not a multiple of {}x{} for {} encoding, {:?} to {}x{}",
+                target.0,
+                target.1,
+                align_w,
+                align_h,
+                chroma,
+                alignment,
+                fit.encoded.0,
+                fit.encoded.1
+            );
+        }
+        fit
+    }
+
+    /// Whether frames have to go through a scaler (cropping alone narrows
+    /// the frame instead)
+    pub fn needs_scaler(&self) -> bool {
+        self.source != self.scaled || self.scaled != self.encoded
+    }
+
+    /// Narrow `frame` to the source size, cropping the right/bottom edge
+    pub fn crop(&self, frame: &mut ffmpeg_next::frame::Video) {
+        let (width, height) = self.source;
+        let size = (frame.width(), frame.height());
+        if size != self.source && size.0 >= width && size.1 >= height {
+            frame.set_width(width);
+            frame.set_height(height);
+        }
+    }
+
+    /// Scale `frame`, padding the result to the encoded size
+    pub fn scale(
+        &self,
+        scaler: &mut ffmpeg_next::software::scaling::Context,
+        frame: &ffmpeg_next::frame::Video,
+    ) -> Result<ffmpeg_next::frame::Video> {
+        let mut scaled = if self.scaled == self.encoded {
+            ffmpeg_next::frame::Video::empty()
+        } else {
+            let (width, height) = self.encoded;
+            let mut padded = ffmpeg_next::frame::Video::new(scaler.output().format, width, height);
+            fill_black(&mut padded);
+            // The scaler writes the top-left part of the frame
+            padded.set_width(self.scaled.0);
+            padded.set_height(self.scaled.1);
+            padded
+        };
+        scaler
+            .run(frame, &mut scaled)
+            .map_err(|e| Error::EncodingFailed(format!("Scaling failed: {}", e)))?;
+        scaled.set_width(self.encoded.0);
+        scaled.set_height(self.encoded.1);
+        scaled.set_pts(frame.pts());
+        Ok(scaled)
+    }
+}
+
+/// Fill a frame in one of the encoders' YUV formats with limited-range black
+fn fill_black(frame: &mut ffmpeg_next::frame::Video) {
+    use ffmpeg_next::format::Pixel;
+
+    // Little-endian samples of black luma and neutral chroma
+    let (luma, chroma): (&[u8], &[u8]) = match frame.format() {
+        // 64 and 512 in the high 10 bits
+        Pixel::P010LE => (&[0x00, 0x10], &[0x00, 0x80]),
+        Pixel::YUV420P10LE | Pixel::YUV422P10LE | Pixel::YUV444P10LE => {
+            (&[0x40, 0x00], &[0x00, 0x02])
+        }
+        _ => (&[16], &[128]),
+    };
+    for plane in 0..frame.planes() {
+        let black = if plane == 0 { luma } else { chroma };
+        for (i, byte) in frame.data_mut(plane).iter_mut().enumerate() {
+            *byte = black[i % black.len()];
+        }
+    }
 }
 
 /// Encoder backend selection
@@ -101,11 +1071,131 @@ pub enum EncoderBackend {
     Software,
 }
 
+/// How one `EncoderPreset` behaves on a backend, for preset pickers
+///
+/// Ratings run from 1 to 5 and are relative within a backend: a 5-speed
+/// NVENC preset and a 5-speed x264 preset are both that backend's fastest,
+/// not equally fast.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct PresetDescriptor {
+    /// Setting to pass to `EncoderConfig::with_preset`
+    pub preset: EncoderPreset,
+    /// The backend's own name for it (`p4`, `veryfast`, `balanced`, ...)
+    pub name: &'static str,
+    /// Relative encoding speed (5 = fastest)
+    pub speed: u8,
+    /// Relative quality at the same bitrate (5 = best)
+    pub quality: u8,
+}
+
+impl EncoderBackend {
+    /// Presets of this backend for `codec`, fastest first
+    ///
+    /// `Auto` describes the backend `create_encoder` would pick for the
+    /// codec on this machine. Ratings come from a static table, not a
+    /// benchmark. Apply a choice with `EncoderConfig::with_preset`, or
+    /// `Pipeline::reconfigure_encoder` between sessions; the software
+    /// encoder stays on `medium` unless opted in through
+    /// `SoftwareEncoder::with_preset`.
+    pub fn presets_for(&self, codec: Codec) -> Vec<PresetDescriptor> {
+        let backend = match self {
+            EncoderBackend::Auto => Self::auto_for(codec, ChromaFormat::Yuv420),
+            other => *other,
+        };
+        const PRESETS: [EncoderPreset; 5] = [
+            EncoderPreset::Fastest,
+            EncoderPreset::Fast,
+            EncoderPreset::Medium,
+            EncoderPreset::Slow,
+            EncoderPreset::Slowest,
+        ];
+        PRESETS
+            .into_iter()
+            .map(|preset| {
+                let (speed, quality) = match (backend, preset) {
+                    // AMF has three quality presets, so pairs share ratings
+                    (EncoderBackend::Amf, EncoderPreset::Fastest | EncoderPreset::Fast) => (5, 2),
+                    (EncoderBackend::Amf, EncoderPreset::Medium) => (3, 3),
+                    (EncoderBackend::Amf, _) => (2, 4),
+                    (_, EncoderPreset::Fastest) => (5, 2),
+                    (_, EncoderPreset::Fast) => (4, 3),
+                    (_, EncoderPreset::Medium) => (3, 3),
+                    (_, EncoderPreset::Slow) => (2, 4),
+                    (_, EncoderPreset::Slowest) => (1, 5),
+                };
+                let cpu = CpuPreset::from_encoder_preset(preset);
+                let name = match backend {
+                    EncoderBackend::Qsv => preset.to_qsv_preset(),
+                    EncoderBackend::Amf => preset.to_amf_quality(),
+                    EncoderBackend::Software if codec == Codec::Av1 => cpu.to_svtav1_preset(),
+                    EncoderBackend::Software => cpu.to_x26x_preset(),
+                    _ => preset.to_nvenc_preset(),
+                };
+                PresetDescriptor {
+                    preset,
+                    name,
+                    speed,
+                    quality,
+                }
+            })
+            .collect()
+    }
+
+    /// Backend `create_encoder` picks for `codec` with `chroma`
+    pub(crate) fn auto_for(codec: Codec, chroma: ChromaFormat) -> Self {
+        let usable = |backend| supports_chroma(backend, codec, chroma);
+        if nvenc::is_available() && nvenc::supports_codec(codec) && usable(EncoderBackend::Nvenc) {
+            EncoderBackend::Nvenc
+        } else if qsv::is_available() && qsv::supports_codec(codec) && usable(EncoderBackend::Qsv) {
+            EncoderBackend::Qsv
+        } else if amf::is_available() && amf::supports_codec(codec) && usable(EncoderBackend::Amf) {
+            EncoderBackend::Amf
+        } else {
+            EncoderBackend::Software
+        }
+    }
+}
+
+/// Next lower settings to retry an encoder with after the GPU ran out of
+/// memory (see `EncoderConfig::adaptive_on_oom`)
+///
+/// Steps the height down to 1440p, 1080p and 720p keeping the aspect
+/// ratio, then halves the frame rate down to 30 fps; None at 720p and
+/// 30 fps or below.
+pub fn oom_downshift(
+    resolution: Resolution,
+    framerate: Framerate,
+) -> Option<(Resolution, Framerate)> {
+    const HEIGHTS: [u32; 3] = [1440, 1080, 720];
+    if let Some(height) = HEIGHTS.into_iter().find(|&h| h < resolution.height) {
+        let width = resolution.width as u64 * height as u64 / resolution.height as u64;
+        let width = (width as u32 & !1).max(2);
+        return Some((Resolution::new(width, height), framerate));
+    }
+    let fps = framerate.fps();
+    let halved = Framerate::new((fps / 2).max(30), 1);
+    (fps > 30).then_some((resolution, halved))
+}
+
 /// Create an encoder based on configuration
 pub fn create_encoder(config: EncoderConfig) -> Result<Box<dyn Encoder>> {
     create_encoder_with_backend(config, EncoderBackend::Auto)
 }
 
+/// Encode a single frame with a fresh encoder and return all its packets
+///
+/// Creates the encoder `create_encoder` would pick, encodes `frame`,
+/// flushes and drops it again. Handy for tests and for previewing the
+/// size or quality of a configuration on a sample frame; the first packet
+/// is a keyframe carrying the stream headers.
+pub fn encode_single_frame(config: EncoderConfig, frame: &Frame) -> Result<Vec<Packet>> {
+    let mut encoder = create_encoder(config)?;
+    encoder.init()?;
+    let mut packets: Vec<Packet> = encoder.encode(frame)?.into_iter().collect();
+    packets.extend(encoder.flush()?);
+    Ok(packets)
+}
+
 /// Create an encoder with specific backend
 pub fn create_encoder_with_backend(
     config: EncoderConfig,
@@ -114,15 +1204,24 @@ pub fn create_encoder_with_backend(
     match backend {
         EncoderBackend::Auto => {
             // Try hardware encoders in order: NVENC > QSV > AMF, fall back to software
-            if nvenc::is_available() && nvenc::supports_codec(config.codec) {
+            if nvenc::is_available()
+                && nvenc::supports_codec(config.codec)
+                && supports_chroma(EncoderBackend::Nvenc, config.codec, config.chroma_format)
+            {
                 tracing::info!("Using NVENC hardware encoder");
                 let encoder = NvencEncoder::new(config)?;
                 Ok(Box::new(encoder))
-            } else if qsv::is_available() && qsv::supports_codec(config.codec) {
+            } else if qsv::is_available()
+                && qsv::supports_codec(config.codec)
+                && supports_chroma(EncoderBackend::Qsv, config.codec, config.chroma_format)
+            {
                 tracing::info!("Using Intel QSV hardware encoder");
                 let encoder = QsvEncoder::new(config)?;
                 Ok(Box::new(encoder))
-            } else if amf::is_available() && amf::supports_codec(config.codec) {
+            } else if amf::is_available()
+                && amf::supports_codec(config.codec)
+                && supports_chroma(EncoderBackend::Amf, config.codec, config.chroma_format)
+            {
                 tracing::info!("Using AMD AMF hardware encoder");
                 let encoder = AmfEncoder::new(config)?;
                 Ok(Box::new(encoder))
@@ -169,10 +1268,13 @@ pub struct EncoderStats {
     pub current_bitrate_kbps: u64,
     /// Encoder queue depth
     pub queue_depth: u32,
+    /// Frames the encoder may hold back, set when opened with
+    /// `EncoderConfig::max_latency_frames` (0 otherwise)
+    pub latency_frames: u32,
 }
 
 /// Information about available encoders
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct EncoderInfo {
     /// Is NVENC available?
     pub nvenc_available: bool,
@@ -186,6 +1288,10 @@ pub struct EncoderInfo {
     pub nvenc_av1: bool,
     /// Supports dual encoder?
     pub dual_encoder: bool,
+    /// Concurrent NVENC session limit (None = unlimited or no NVENC)
+    pub nvenc_max_sessions: Option<u32>,
+    /// NVIDIA GPUs, for `EncoderConfig::gpu_index`
+    pub nvidia_gpus: Vec<nvenc::GpuInfo>,
     /// Intel QSV info
     pub qsv: QsvEncoderInfo,
     /// AMD AMF info
@@ -197,7 +1303,7 @@ pub struct EncoderInfo {
 }
 
 /// Intel QSV encoder availability
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, serde::Serialize)]
 pub struct QsvEncoderInfo {
     /// Is QSV available?
     pub available: bool,
@@ -212,7 +1318,7 @@ pub struct QsvEncoderInfo {
 }
 
 /// AMD AMF encoder availability
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, serde::Serialize)]
 pub struct AmfEncoderInfo {
     /// Is AMF available?
     pub available: bool,
@@ -227,7 +1333,7 @@ pub struct AmfEncoderInfo {
 }
 
 /// Software encoder availability
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, serde::Serialize)]
 pub struct SoftwareEncoderInfo {
     /// x264 available for H.264
     pub x264: bool,
@@ -289,6 +1395,12 @@ pub fn get_info() -> EncoderInfo {
         driver_version: nvenc::get_driver_version(),
         nvenc_av1: nvenc::supports_codec(Codec::Av1),
         dual_encoder: nvenc::has_dual_encoder(),
+        nvenc_max_sessions: if nvenc_available {
+            nvenc::max_concurrent_sessions()
+        } else {
+            None
+        },
+        nvidia_gpus: nvenc::list_gpus(),
         qsv: qsv_info,
         amf: amf_info,
         software,
@@ -298,6 +1410,12 @@ pub fn get_info() -> EncoderInfo {
 
 // Keep old field names for compatibility
 impl EncoderInfo {
+    /// Capabilities as a pretty-printed JSON document (for GUIs and scripts)
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| Error::Internal(format!("Failed to serialize encoder info: {}", e)))
+    }
+
     /// Backward compatible: all supported codecs (NVENC + Software)
     pub fn supported_codecs(&self) -> Vec<Codec> {
         let mut codecs = self.nvenc_codecs.clone();
@@ -318,3 +1436,312 @@ impl EncoderInfo {
         self.nvenc_av1 || self.software.svtav1
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_headers_only_when_missing() {
+        let sps_pps = [0, 0, 0, 1, 0x67, 0xaa, 0, 0, 0, 1, 0x68, 0xbb];
+        let idr = vec![0, 0, 0, 1, 0x65, 0x11, 0x22];
+
+        let mut packet = Packet::new(idr.clone(), 0, 0, true);
+        insert_headers(&mut packet, Codec::H264, &sps_pps);
+        assert_eq!(&packet.data[..sps_pps.len()], &sps_pps);
+        assert_eq!(&packet.data[sps_pps.len()..], &idr[..]);
+
+        // Already carries an SPS: unchanged
+        let before = packet.data.clone();
+        insert_headers(&mut packet, Codec::H264, &sps_pps);
+        assert_eq!(packet.data, before);
+
+        // Delta frames are never touched
+        let mut delta = Packet::new(vec![0, 0, 1, 0x41, 0x33], 1, 1, false);
+        insert_headers(&mut delta, Codec::H264, &sps_pps);
+        assert_eq!(delta.data, vec![0, 0, 1, 0x41, 0x33]);
+    }
+
+    #[test]
+    fn test_encode_single_frame() {
+        if !software::has_x264() {
+            println!("x264 not available, skipping test");
+            return;
+        }
+
+        let config = EncoderConfig::default().with_resolution(64, 64);
+        let mut frame = Frame::new(64, 64, FrameFormat::Nv12);
+        frame.pts = 1_000_000;
+        let packets = encode_single_frame(config, &frame).unwrap();
+        assert_eq!(packets.len(), 1);
+        assert!(packets[0].is_keyframe());
+        assert!(!packets[0].data.is_empty());
+    }
+
+    #[test]
+    fn test_pts_to_time_base() {
+        let ms = ffmpeg_next::Rational::new(1, 1000);
+        assert_eq!(pts_to_time_base(16_667, ms), 17);
+        assert_eq!(pts_to_time_base(1_000_000, ms), 1000);
+        assert_eq!(pts_to_time_base(-1_500, ms), -2);
+
+        let mpeg = ffmpeg_next::Rational::new(1, 90_000);
+        assert_eq!(pts_to_time_base(1_000_000, mpeg), 90_000);
+        assert_eq!(pts_to_time_base(16_667, mpeg), 1500);
+
+        // Frame-rate time base: one tick per 60 fps frame
+        let fps = ffmpeg_next::Rational::new(1, 60);
+        assert_eq!(pts_to_time_base(33_333, fps), 2);
+    }
+
+    #[test]
+    fn test_oom_downshift() {
+        let ultrawide = Resolution::new(5120, 1440);
+        assert_eq!(
+            oom_downshift(ultrawide, Framerate::FPS_144),
+            Some((Resolution::new(3840, 1080), Framerate::FPS_144))
+        );
+        assert_eq!(
+            oom_downshift(Resolution::UHD_4K, Framerate::FPS_60),
+            Some((Resolution::QHD_1440P, Framerate::FPS_60))
+        );
+        assert_eq!(
+            oom_downshift(Resolution::HD_720P, Framerate::FPS_144),
+            Some((Resolution::HD_720P, Framerate::new(72, 1)))
+        );
+        assert_eq!(
+            oom_downshift(Resolution::HD_720P, Framerate::FPS_60),
+            Some((Resolution::HD_720P, Framerate::FPS_30))
+        );
+        assert_eq!(oom_downshift(Resolution::HD_720P, Framerate::FPS_30), None);
+    }
+
+    #[test]
+    fn test_frame_fit_alignment() {
+        let yuv420 = ChromaFormat::Yuv420;
+
+        // Odd window capture: crop the edge without scaling
+        let fit = FrameFit::new((1281, 721), None, yuv420, DimensionAlignment::Crop);
+        assert_eq!(fit.encoded, (1280, 720));
+        assert_eq!(fit.source, (1280, 720));
+        assert!(!fit.needs_scaler());
+
+        // Padding scales 1:1 into a larger frame
+        let fit = FrameFit::new((1281, 721), None, yuv420, DimensionAlignment::Pad);
+        assert_eq!(fit.encoded, (1282, 722));
+        assert_eq!(fit.scaled, (1281, 721));
+        assert!(fit.needs_scaler());
+
+        // An odd target size is rounded; the scaler takes the whole input
+        let odd = Some(Resolution::new(853, 480));
+        let fit = FrameFit::new((1920, 1080), odd, yuv420, DimensionAlignment::Crop);
+        assert_eq!((fit.source, fit.encoded), ((1920, 1080), (852, 480)));
+
+        // 4:4:4 and disabled alignment pass through
+        let yuv444 = ChromaFormat::Yuv444;
+        let fit = FrameFit::new((1281, 721), None, yuv444, DimensionAlignment::Crop);
+        assert_eq!(fit.encoded, (1281, 721));
+        let fit = FrameFit::new((1281, 721), None, yuv420, DimensionAlignment::Off);
+        assert_eq!(fit.encoded, (1281, 721));
+    }
+
+    #[test]
+    fn test_bitstream_flags() {
+        let idr = [0, 0, 0, 1, 0x67, 0xaa, 0, 0, 1, 0x65, 0x11];
+        assert_eq!(
+            bitstream_flags(&idr, Codec::H264),
+            Packet::FLAG_KEYFRAME | Packet::FLAG_HEADERS
+        );
+        // nal_ref_idc 0 slice
+        assert_eq!(
+            bitstream_flags(&[0, 0, 1, 0x01, 0x9a], Codec::H264),
+            Packet::FLAG_DISPOSABLE
+        );
+        assert_eq!(bitstream_flags(&[0, 0, 1, 0x41, 0x9a], Codec::H264), 0);
+
+        // HEVC TRAIL_N vs. IDR_W_RADL
+        assert_eq!(
+            bitstream_flags(&[0, 0, 1, 0x00, 0x01], Codec::Hevc),
+            Packet::FLAG_DISPOSABLE
+        );
+        assert_eq!(
+            bitstream_flags(&[0, 0, 1, 0x26, 0x01], Codec::Hevc),
+            Packet::FLAG_KEYFRAME
+        );
+
+        // AV1: temporal delimiter, sequence header, KEY_FRAME frame header
+        let av1 = [0x12, 0x00, 0x0a, 0x02, 0xaa, 0xbb, 0x32, 0x01, 0x10];
+        assert_eq!(
+            bitstream_flags(&av1, Codec::Av1),
+            Packet::FLAG_KEYFRAME | Packet::FLAG_HEADERS
+        );
+        // Inter frame (frame_type 1), no sequence header
+        assert_eq!(bitstream_flags(&[0x32, 0x01, 0x30], Codec::Av1), 0);
+    }
+
+    #[test]
+    fn test_check_chroma() {
+        let config = EncoderConfig::default().with_chroma_format(ChromaFormat::Yuv444);
+        assert!(check_chroma(&config, EncoderBackend::Nvenc).is_ok());
+        assert!(check_chroma(&config, EncoderBackend::Qsv).is_err());
+
+        // AV1 encoders here are 4:2:0 only
+        let av1 = config.clone().with_codec(Codec::Av1);
+        assert!(check_chroma(&av1, EncoderBackend::Software).is_err());
+
+        // Explicit 4:2:0-only profile conflicts
+        let mut high = config.clone();
+        high.profile = Some("high".into());
+        assert!(check_chroma(&high, EncoderBackend::Software).is_err());
+
+        assert!(check_chroma(&config.with_hdr10(), EncoderBackend::Nvenc).is_err());
+    }
+
+    #[test]
+    fn test_quality_parameter() {
+        let x264 = |q| quality_parameter(q, Codec::H264, EncoderBackend::Software);
+        assert_eq!((x264(0), x264(70), x264(100)), (45, 23, 14));
+        assert_eq!(x264(255), 14);
+        assert_eq!(
+            quality_parameter(100, Codec::Av1, EncoderBackend::Software),
+            20
+        );
+        assert_eq!(quality_parameter(0, Codec::Av1, EncoderBackend::Amf), 240);
+        assert_eq!(quality_parameter(50, Codec::Av1, EncoderBackend::Nvenc), 29);
+    }
+
+    #[test]
+    fn test_presets_for() {
+        let x264 = EncoderBackend::Software.presets_for(Codec::H264);
+        assert_eq!(x264.len(), 5);
+        assert_eq!(x264[0].name, "veryfast");
+        assert!(x264
+            .windows(2)
+            .all(|w| w[0].speed >= w[1].speed && w[0].quality <= w[1].quality));
+        let svt = EncoderBackend::Software.presets_for(Codec::Av1);
+        assert_eq!(svt[2].name, "6");
+        assert_eq!(EncoderBackend::Nvenc.presets_for(Codec::Hevc)[4].name, "p7");
+    }
+
+    #[test]
+    fn test_describe_names_backend_settings() {
+        let config = EncoderConfig::default().with_preset(EncoderPreset::Slowest);
+        let nvenc = config.describe_for(EncoderBackend::Nvenc);
+        assert!(nvenc.contains("preset=p7 tune=hq"));
+        let amf = config.describe_for(EncoderBackend::Amf);
+        assert!(amf.contains("quality=quality usage=transcoding"));
+        // The software encoder keeps its own preset
+        let x264 = config.describe_for(EncoderBackend::Software);
+        assert!(x264.contains("preset=medium tune=zerolatency"));
+        assert!(!x264.contains("p7"));
+    }
+
+    #[test]
+    fn test_pixel_format_selection() {
+        use ffmpeg_next::format::Pixel;
+
+        let sdr = EncoderConfig::default();
+        assert_eq!(
+            encoder_pixel_format(&sdr, EncoderBackend::Nvenc),
+            Pixel::NV12
+        );
+        assert_eq!(
+            encoder_pixel_format(&sdr, EncoderBackend::Software),
+            Pixel::YUV420P
+        );
+
+        let hdr = EncoderConfig::defaults_for(Codec::Hevc).with_hdr10();
+        assert!(check_pixel_format(&hdr, EncoderBackend::Nvenc).is_ok());
+        assert_eq!(
+            encoder_pixel_format(&hdr, EncoderBackend::Nvenc),
+            Pixel::P010LE
+        );
+        assert_eq!(
+            encoder_pixel_format(&hdr, EncoderBackend::Software),
+            Pixel::YUV420P10LE
+        );
+
+        // 10-bit H.264 is x264 only, and needs a 10-bit profile
+        let mut h264 = EncoderConfig::default().with_hdr10();
+        assert!(check_pixel_format(&h264, EncoderBackend::Qsv).is_err());
+        assert!(check_pixel_format(&h264, EncoderBackend::Software).is_ok());
+        h264.profile = Some("high".into());
+        assert!(check_pixel_format(&h264, EncoderBackend::Software).is_err());
+    }
+
+    #[test]
+    fn test_fit_latency_budget() {
+        let mut config = EncoderConfig::default();
+        assert_eq!(fit_latency_budget(&mut config, EncoderBackend::Nvenc), None);
+
+        // 2 B-frames + 8 lookahead into 4 frames
+        config = config.with_max_latency_frames(4);
+        config.lookahead = Some(8);
+        let fit = fit_latency_budget(&mut config, EncoderBackend::Nvenc).unwrap();
+        assert_eq!((fit.b_frames, fit.lookahead, fit.queue), (2, 2, 0));
+        assert_eq!(config.lookahead, Some(2));
+
+        // x265's default lookahead is cut, but still covers the B-frames
+        let mut hevc = EncoderConfig::defaults_for(Codec::Hevc).with_max_latency_frames(5);
+        let fit = fit_latency_budget(&mut hevc, EncoderBackend::Software).unwrap();
+        assert_eq!(fit.frames(), 5);
+        assert_eq!((hevc.b_frames, hevc.lookahead), (3, Some(5)));
+
+        // What the x265 lookahead leaves goes to frame threads
+        let mut hevc = EncoderConfig::defaults_for(Codec::Hevc).with_max_latency_frames(6);
+        hevc.lookahead = Some(2);
+        let fit = fit_latency_budget(&mut hevc, EncoderBackend::Software).unwrap();
+        assert_eq!((fit.lookahead, fit.queue), (3, 3));
+
+        // SVT-AV1's own lookahead is replaced by the budget
+        let mut av1 = EncoderConfig::defaults_for(Codec::Av1).with_max_latency_frames(8);
+        let fit = fit_latency_budget(&mut av1, EncoderBackend::Software).unwrap();
+        assert_eq!((fit.b_frames, fit.lookahead, fit.queue), (0, 8, 0));
+        assert_eq!(av1.lookahead, Some(8));
+
+        // Room to spare goes to the queue
+        let mut roomy = EncoderConfig::default().with_max_latency_frames(6);
+        let fit = fit_latency_budget(&mut roomy, EncoderBackend::Nvenc).unwrap();
+        assert_eq!((fit.b_frames, fit.lookahead, fit.queue), (2, 0, 4));
+        assert_eq!(roomy.lookahead, None);
+    }
+
+    #[test]
+    fn test_check_film_grain() {
+        let av1 = EncoderConfig::defaults_for(Codec::Av1).with_film_grain(80);
+        assert_eq!(av1.film_grain, Some(50));
+        assert!(check_film_grain(&av1, EncoderBackend::Software).is_ok());
+        // Ignored with a warning
+        assert!(check_film_grain(&av1, EncoderBackend::Nvenc).is_ok());
+
+        let h264 = EncoderConfig {
+            film_grain: Some(8),
+            ..Default::default()
+        };
+        assert!(check_film_grain(&h264, EncoderBackend::Software).is_err());
+        assert!(check_film_grain(&EncoderConfig::default(), EncoderBackend::Qsv).is_ok());
+        assert_eq!(EncoderConfig::default().with_film_grain(0).film_grain, None);
+    }
+
+    #[test]
+    fn test_vbv_settings() {
+        let config = EncoderConfig::default();
+        assert_eq!(vbv_settings(&config), None);
+
+        // The rate follows the VBR cap, the buffer holds two seconds of it
+        let mut vbr = config.clone().with_vbv_buffer(0);
+        vbr.max_bitrate_kbps = Some(9000);
+        assert_eq!(vbv_settings(&vbr), None);
+        let capped = vbr.clone().with_vbv_max_rate(6000);
+        assert_eq!(vbv_settings(&capped), Some((12000, 6000)));
+        assert_eq!(vbv_settings(&vbr.with_vbv_buffer(4500)), Some((4500, 9000)));
+
+        let cbr = EncoderConfig {
+            rate_control: crate::config::RateControl::Cbr,
+            max_bitrate_kbps: Some(9000),
+            ..config
+        };
+        let rate = cbr.bitrate_kbps;
+        assert_eq!(vbv_settings(&cbr.with_vbv_buffer(1000)), Some((1000, rate)));
+    }
+}
@@ -2,11 +2,11 @@
 //!
 //! Provides H.264, HEVC, and AV1 encoding using NVIDIA's NVENC.
 
-use crate::config::EncoderConfig;
+use crate::config::{BRefMode, ChromaFormat, EncoderConfig, Multipass};
 use crate::error::{Error, Result};
 use crate::types::{CodecParams, Frame, FrameFormat, Packet, Resolution};
 
-use super::{Codec, Encoder, EncoderStats};
+use super::{packet_from_ffmpeg, Codec, Encoder, EncoderStats};
 
 use ffmpeg_next as ffmpeg;
 use ffmpeg_next::format::Pixel;
@@ -21,8 +21,12 @@ pub struct NvencEncoder {
     scaler: Option<Scaler>,
     stats: EncoderStats,
     frame_count: u64,
+    force_keyframe: bool,
     start_time: Option<Instant>,
-    input_resolution: Option<Resolution>,
+    /// Frame sizes through the scaler, set on init
+    fit: super::FrameFit,
+    /// Input size `warm_up` opened the encoder for, until the first frame
+    warmed_up: Option<(u32, u32)>,
     time_base: ffmpeg::Rational,
 }
 
@@ -30,7 +34,7 @@ impl NvencEncoder {
     /// Create a new NVENC encoder
     pub fn new(config: EncoderConfig) -> Result<Self> {
         // Initialize FFmpeg
-        ffmpeg::init().map_err(|e| Error::FFmpeg(e.to_string()))?;
+        ffmpeg::init().map_err(|e| Error::Ffmpeg(e.to_string()))?;
 
         // Verify NVENC is available
         if !is_available() {
@@ -47,6 +51,12 @@ impl NvencEncoder {
                 config.codec.min_gpu_arch()
             )));
         }
+        super::check_chroma(&config, super::EncoderBackend::Nvenc)?;
+        super::check_pixel_format(&config, super::EncoderBackend::Nvenc)?;
+        super::check_film_grain(&config, super::EncoderBackend::Nvenc)?;
+        if let Some(index) = config.gpu_index {
+            check_gpu_index(index, &list_gpus())?;
+        }
 
         Ok(Self {
             config,
@@ -54,26 +64,32 @@ impl NvencEncoder {
             scaler: None,
             stats: EncoderStats::default(),
             frame_count: 0,
+            force_keyframe: false,
             start_time: None,
-            input_resolution: None,
+            fit: super::FrameFit::default(),
+            warmed_up: None,
             time_base: ffmpeg::Rational::new(1, 60), // Default, updated on init
         })
     }
 
-    /// Initialize encoder with specific input resolution
-    fn init_encoder(&mut self, input_width: u32, input_height: u32) -> Result<()> {
+    /// Initialize encoder for the first frame's resolution and colorimetry
+    fn init_encoder(&mut self, frame: &Frame) -> Result<()> {
+        let (input_width, input_height) = (frame.width, frame.height);
         let encoder_name = self.config.codec.nvenc_encoder_name();
+        let mut latency = super::fit_latency_budget(&mut self.config, super::EncoderBackend::Nvenc);
 
         // Find the encoder
         let codec = ffmpeg::encoder::find_by_name(encoder_name)
             .ok_or_else(|| Error::EncoderInit(format!("Encoder {} not found", encoder_name)))?;
 
-        // Determine output resolution
-        let (out_width, out_height) = if let Some(res) = self.config.resolution {
-            (res.width, res.height)
-        } else {
-            (input_width, input_height)
-        };
+        // Determine output resolution, fitted to the codec's alignment
+        let fit = super::FrameFit::new(
+            (input_width, input_height),
+            self.config.resolution,
+            self.config.chroma_format,
+            self.config.dimension_alignment,
+        );
+        let (out_width, out_height) = fit.encoded;
 
         // Create encoder context
         let context = ffmpeg::codec::context::Context::new_with_codec(codec);
@@ -85,7 +101,9 @@ impl NvencEncoder {
         // Set basic parameters
         encoder.set_width(out_width);
         encoder.set_height(out_height);
-        encoder.set_format(Pixel::NV12); // NVENC prefers NV12
+        // NV12, P010 for 10-bit; 4:4:4 needs planar YUV444P input
+        let pixel = super::encoder_pixel_format(&self.config, super::EncoderBackend::Nvenc);
+        encoder.set_format(pixel);
         encoder.set_time_base(ffmpeg::Rational::new(1, 1000)); // ms timebase
         self.time_base = ffmpeg::Rational::new(1, 1000);
 
@@ -104,6 +122,12 @@ impl NvencEncoder {
         // Build encoder options
         let mut opts = Dictionary::new();
 
+        // Device: NVENC creates its CUDA context on this GPU and uploads
+        // the frames there
+        if let Some(index) = self.config.gpu_index {
+            opts.set("gpu", &index.to_string());
+        }
+
         // Preset
         opts.set("preset", self.config.preset.to_nvenc_preset());
 
@@ -130,6 +154,14 @@ impl NvencEncoder {
             crate::config::RateControl::Crf { crf } => {
                 opts.set("cq", &crf.to_string());
             }
+            crate::config::RateControl::Quality { quality } => {
+                let cq = super::quality_parameter(
+                    quality,
+                    self.config.codec,
+                    super::EncoderBackend::Nvenc,
+                );
+                opts.set("cq", &cq.to_string());
+            }
         }
 
         // Lookahead
@@ -137,6 +169,49 @@ impl NvencEncoder {
             opts.set("rc-lookahead", &la.to_string());
         }
 
+        // Output queue within the latency budget; the low-latency tunings
+        // don't queue at all
+        if let Some(latency) = latency.as_mut() {
+            if self.config.tuning.is_low_latency() {
+                latency.queue = 0;
+            }
+            opts.set("delay", &latency.queue.to_string());
+        }
+
+        // Multi-pass and B-frame references need a recent driver / GPU
+        if let Some(multipass) = self.config.nvenc_multipass {
+            if multipass == Multipass::Disabled || supports_multipass() {
+                opts.set("multipass", multipass.to_nvenc_multipass());
+            } else {
+                tracing::warn!("NVENC multipass needs driver R456 or newer, ignoring");
+            }
+        }
+        if let Some(mode) = self.config.b_ref_mode {
+            if mode == BRefMode::Each && self.config.codec == Codec::H264 {
+                tracing::warn!("H.264 NVENC only supports middle B-frame references, ignoring");
+            } else if mode == BRefMode::Disabled || supports_b_ref_mode() {
+                opts.set("b_ref_mode", mode.to_nvenc_b_ref_mode());
+            } else {
+                tracing::warn!("B-frame references need a Turing or newer GPU, ignoring");
+            }
+        }
+
+        // Fixed GOP: no keyframes on scene cuts, and no GOP changes from
+        // the rate control
+        if !self.config.scene_cut {
+            opts.set("no-scenecut", "1");
+            opts.set("strict_gop", "1");
+        }
+
+        // 4:4:4 profiles
+        if self.config.chroma_format == ChromaFormat::Yuv444 {
+            let profile = match self.config.codec {
+                Codec::H264 => "high444p",
+                _ => "rext",
+            };
+            opts.set("profile", profile);
+        }
+
         // Low latency options
         if matches!(
             self.config.tuning,
@@ -147,23 +222,32 @@ impl NvencEncoder {
             opts.set("zerolatency", "1");
         }
 
+        // Forced keyframes must be IDRs: recordings start on one, and NVENC
+        // only writes SPS/PPS in-band on IDRs (repeat_headers)
+        opts.set("forced-idr", "1");
+
+        super::apply_vbv(&mut opts, &self.config);
+        super::apply_extra_options(&mut opts, &self.config, encoder_name);
+        super::set_colorimetry(&mut encoder, &self.config, frame);
+
         // Open encoder
         let opened = encoder
             .open_with(opts)
-            .map_err(|e| Error::EncoderInit(format!("Failed to open encoder: {}", e)))?;
+            .map_err(|e| open_error(e, self.config.gpu_index))?;
 
         self.encoder = Some(opened);
-        self.input_resolution = Some(Resolution::new(input_width, input_height));
+        self.fit = fit;
 
-        // Create scaler if input != output resolution
-        if input_width != out_width || input_height != out_height {
+        // Create scaler if frames are resized, padded or converted
+        let input = Self::to_ffmpeg_format(self.config.input_format());
+        if fit.needs_scaler() || input != pixel {
             let scaler = Scaler::get(
-                Pixel::NV12,
-                input_width,
-                input_height,
-                Pixel::NV12,
-                out_width,
-                out_height,
+                input,
+                fit.source.0,
+                fit.source.1,
+                pixel,
+                fit.scaled.0,
+                fit.scaled.1,
                 ScalerFlags::BILINEAR,
             )
             .map_err(|e| Error::EncoderInit(format!("Failed to create scaler: {}", e)))?;
@@ -173,15 +257,18 @@ impl NvencEncoder {
         self.start_time = Some(Instant::now());
 
         tracing::info!(
-            "NVENC encoder initialized: {} {}x{} @ {}kbps (preset: {}, tune: {})",
+            "NVENC encoder initialized: {} {}x{} {} @ {}kbps (preset: {}, tune: {})",
             self.config.codec,
             out_width,
             out_height,
+            self.config.chroma_format,
             self.config.bitrate_kbps,
             self.config.preset.to_nvenc_preset(),
             self.config.tuning.to_nvenc_tuning()
         );
 
+        super::report_latency(latency, &mut self.stats);
+
         Ok(())
     }
 
@@ -190,6 +277,7 @@ impl NvencEncoder {
         match format {
             FrameFormat::Nv12 => Pixel::NV12,
             FrameFormat::Yuv420p => Pixel::YUV420P,
+            FrameFormat::Yuv422p => Pixel::YUV422P,
             FrameFormat::Yuv444p => Pixel::YUV444P,
             FrameFormat::Bgra => Pixel::BGRA,
             FrameFormat::Rgba => Pixel::RGBA,
@@ -206,9 +294,18 @@ impl Encoder for NvencEncoder {
     }
 
     fn encode(&mut self, frame: &Frame) -> Result<Option<Packet>> {
+        // Warmed up without seeing a frame: re-open if the first one differs
+        if let Some(size) = self.warmed_up.take() {
+            if !super::fits_warm_up(&self.config, frame, size) {
+                tracing::debug!("Re-opening encoder for the first frame's size and colorimetry");
+                self.encoder = None;
+                self.scaler = None;
+            }
+        }
+
         // Initialize encoder on first frame
         if self.encoder.is_none() {
-            self.init_encoder(frame.width, frame.height)?;
+            self.init_encoder(frame)?;
         }
 
         let encoder = self.encoder.as_mut().unwrap();
@@ -222,16 +319,11 @@ impl Encoder for NvencEncoder {
         );
 
         // Copy frame data
-        // For NV12: Y plane is full size, UV plane is half height
-        if frame.format == FrameFormat::Nv12 {
-            let y_size = (frame.width * frame.height) as usize;
-            let uv_size = y_size / 2;
-
-            if frame.data.len() >= y_size + uv_size {
-                video_frame.data_mut(0)[..y_size].copy_from_slice(&frame.data[..y_size]);
-                video_frame.data_mut(1)[..uv_size]
-                    .copy_from_slice(&frame.data[y_size..y_size + uv_size]);
-            }
+        // For NV12/P010: Y plane is full size, UV plane is half height
+        if matches!(frame.format, FrameFormat::Nv12 | FrameFormat::P010) {
+            super::copy_semi_planar(frame, &mut video_frame);
+        } else if matches!(frame.format, FrameFormat::Yuv420p | FrameFormat::Yuv444p) {
+            super::copy_planar_yuv(frame, &mut video_frame);
         } else {
             // For other formats, copy to first plane
             let plane_size = video_frame.data(0).len().min(frame.data.len());
@@ -239,23 +331,27 @@ impl Encoder for NvencEncoder {
         }
 
         // Set PTS
-        video_frame.set_pts(Some(frame.pts));
+        // Capture stamps µs; the encoder counts in its own time base
+        video_frame.set_pts(Some(super::pts_to_time_base(frame.pts, self.time_base)));
 
         // Note: Keyframe insertion is handled by encoder GOP settings
         // frame.is_keyframe is informational for stats/logging
 
         // Scale if needed
-        let frame_to_encode = if let Some(ref mut scaler) = self.scaler {
-            let mut scaled = ffmpeg::frame::Video::empty();
-            scaler
-                .run(&video_frame, &mut scaled)
-                .map_err(|e| Error::EncodingFailed(format!("Scaling failed: {}", e)))?;
-            scaled.set_pts(video_frame.pts());
-            scaled
-        } else {
-            video_frame
+        self.fit.crop(&mut video_frame);
+        let mut frame_to_encode = match self.scaler {
+            Some(ref mut scaler) => self.fit.scale(scaler, &video_frame)?,
+            None => video_frame,
         };
 
+        super::attach_hdr_side_data(&mut frame_to_encode, &self.config);
+
+        super::attach_timecode(&mut frame_to_encode, &self.config, frame.pts);
+
+        if std::mem::take(&mut self.force_keyframe) {
+            frame_to_encode.set_kind(ffmpeg::picture::Type::I);
+        }
+
         // Send frame to encoder
         encoder
             .send_frame(&frame_to_encode)
@@ -285,14 +381,7 @@ impl Encoder for NvencEncoder {
                     }
                 }
 
-                let packet = Packet {
-                    data: ffmpeg_packet.data().map(|d| d.to_vec()).unwrap_or_default(),
-                    pts: ffmpeg_packet.pts().unwrap_or(0),
-                    dts: ffmpeg_packet.dts().unwrap_or(0),
-                    duration: ffmpeg_packet.duration(),
-                    is_keyframe: ffmpeg_packet.is_key(),
-                    flags: 0,
-                };
+                let packet = packet_from_ffmpeg(&ffmpeg_packet, self.config.codec);
 
                 Ok(Some(packet))
             }
@@ -325,14 +414,7 @@ impl Encoder for NvencEncoder {
             match encoder.receive_packet(&mut ffmpeg_packet) {
                 Ok(_) => {
                     self.stats.bytes_output += ffmpeg_packet.size() as u64;
-                    packets.push(Packet {
-                        data: ffmpeg_packet.data().map(|d| d.to_vec()).unwrap_or_default(),
-                        pts: ffmpeg_packet.pts().unwrap_or(0),
-                        dts: ffmpeg_packet.dts().unwrap_or(0),
-                        duration: ffmpeg_packet.duration(),
-                        is_keyframe: ffmpeg_packet.is_key(),
-                        flags: 0,
-                    });
+                    packets.push(packet_from_ffmpeg(&ffmpeg_packet, self.config.codec));
                 }
                 Err(ffmpeg::Error::Eof) => break,
                 Err(ffmpeg::Error::Other { errno }) if errno == ffmpeg::error::EAGAIN => continue,
@@ -372,13 +454,10 @@ impl Encoder for NvencEncoder {
         };
 
         // Get resolution
-        let resolution = if let Some(res) = self.config.resolution {
-            res
-        } else if let Some(res) = self.input_resolution {
-            res
-        } else {
-            Resolution::new(encoder.width(), encoder.height())
-        };
+        let resolution = Resolution::new(encoder.width(), encoder.height());
+
+        let pixel = super::encoder_pixel_format(&self.config, super::EncoderBackend::Nvenc);
+        let (chroma_format, bit_depth) = super::coded_format(pixel);
 
         Some(CodecParams {
             codec: self.config.codec,
@@ -388,16 +467,33 @@ impl Encoder for NvencEncoder {
             time_base_num: self.time_base.numerator(),
             time_base_den: self.time_base.denominator(),
             bitrate: (self.config.bitrate_kbps as i64) * 1000,
+            hdr: self.config.hdr.clone(),
+            chroma_format,
+            bit_depth,
         })
     }
 
     fn reconfigure(&mut self, config: &EncoderConfig) -> Result<()> {
-        // For now, just update the stored config
-        // Full reconfiguration would require re-creating the encoder
+        // Bitrate can change live; other settings apply on the next init
+        if let Some(encoder) = self.encoder.as_mut() {
+            super::apply_bitrate_change(encoder, &self.config, config);
+        }
         self.config = config.clone();
         tracing::info!("Encoder config updated: {}kbps", config.bitrate_kbps);
         Ok(())
     }
+
+    fn force_keyframe(&mut self) {
+        self.force_keyframe = true;
+    }
+
+    fn warm_up(&mut self, width: u32, height: u32) -> Result<()> {
+        if self.encoder.is_none() {
+            self.init_encoder(&super::warm_up_frame(&self.config, width, height))?;
+            self.warmed_up = Some((width, height));
+        }
+        Ok(())
+    }
 }
 
 impl Drop for NvencEncoder {
@@ -447,6 +543,33 @@ pub fn has_dual_encoder() -> bool {
     false
 }
 
+/// Check for B-frames-as-reference support (Turing and newer)
+pub fn supports_b_ref_mode() -> bool {
+    get_compute_capability().is_some_and(|cc| cc >= (7, 5))
+}
+
+/// Check for multi-pass support (SDK 10 presets, driver R456+)
+pub fn supports_multipass() -> bool {
+    driver_major_version().is_some_and(|major| major >= 456)
+}
+
+/// Concurrent NVENC session limit of the installed GPU/driver
+///
+/// GeForce cards are capped by the driver (3 sessions before R530, 5 before
+/// R550, 8 since); professional cards are unlimited (`None`).
+pub fn max_concurrent_sessions() -> Option<u32> {
+    let gpu = get_gpu_name()?;
+    if !gpu.contains("GeForce") && !gpu.contains("TITAN") {
+        return None;
+    }
+
+    Some(match driver_major_version()? {
+        550.. => 8,
+        530.. => 5,
+        _ => 3,
+    })
+}
+
 /// Get GPU name via nvidia-smi
 pub fn get_gpu_name() -> Option<String> {
     std::process::Command::new("nvidia-smi")
@@ -467,6 +590,125 @@ pub fn get_driver_version() -> Option<String> {
         .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
 }
 
+/// One NVIDIA GPU (see `list_gpus`)
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct GpuInfo {
+    /// Index for `EncoderConfig::gpu_index`
+    pub index: u32,
+    pub name: String,
+    /// PCI address, e.g. `00000000:01:00.0`
+    pub pci_bus_id: String,
+    /// Video memory in MiB (0 if unknown)
+    pub memory_mib: u64,
+}
+
+/// Enumerate NVIDIA GPUs via nvidia-smi (empty without a driver)
+///
+/// Indices follow PCI bus order, as nvidia-smi numbers them. CUDA orders
+/// devices fastest first unless `CUDA_DEVICE_ORDER=PCI_BUS_ID` is set, so
+/// set it on mixed-GPU systems for the indices to agree.
+pub fn list_gpus() -> Vec<GpuInfo> {
+    std::process::Command::new("nvidia-smi")
+        .args([
+            "--query-gpu=index,name,pci.bus_id,memory.total",
+            "--format=csv,noheader,nounits",
+        ])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| parse_gpu_list(&String::from_utf8_lossy(&o.stdout)))
+        .unwrap_or_default()
+}
+
+/// Parse `index, name, pci.bus_id, memory.total` CSV lines
+fn parse_gpu_list(csv: &str) -> Vec<GpuInfo> {
+    csv.lines()
+        .filter_map(|line| {
+            let mut fields = line.split(',').map(str::trim);
+            Some(GpuInfo {
+                index: fields.next()?.parse().ok()?,
+                name: fields.next()?.to_string(),
+                pci_bus_id: fields.next()?.to_string(),
+                memory_mib: fields.next().and_then(|m| m.parse().ok()).unwrap_or(0),
+            })
+        })
+        .collect()
+}
+
+/// Fail early on a GPU index that doesn't exist; unchecked when the GPUs
+/// can't be listed
+fn check_gpu_index(index: u32, gpus: &[GpuInfo]) -> Result<()> {
+    if gpus.is_empty() || gpus.iter().any(|gpu| gpu.index == index) {
+        return Ok(());
+    }
+    Err(Error::InvalidEncoderConfig(format!(
+        "GPU {} not found ({} NVIDIA GPUs: {})",
+        index,
+        gpus.len(),
+        gpus.iter()
+            .map(|gpu| format!("{} = {}", gpu.index, gpu.name))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )))
+}
+
+/// NVENC sessions open on a GPU, from every process
+pub fn active_sessions(gpu_index: Option<u32>) -> Option<u32> {
+    let mut command = std::process::Command::new("nvidia-smi");
+    command.args([
+        "--query-gpu=encoder.stats.sessionCount",
+        "--format=csv,noheader",
+    ]);
+    if let Some(index) = gpu_index {
+        command.arg(format!("--id={}", index));
+    }
+    let output = command.output().ok().filter(|o| o.status.success())?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Classify a failure to open the encoder
+///
+/// FFmpeg reports `NV_ENC_ERR_OUT_OF_MEMORY` as ENOMEM, both when buffers
+/// don't fit in video memory and when GeForce drivers refuse a session
+/// over their limit; the open session count tells them apart.
+fn open_error(e: ffmpeg::Error, gpu_index: Option<u32>) -> Error {
+    match e {
+        ffmpeg::Error::Other { errno } if errno == libc::ENOMEM => {
+            let limit = max_concurrent_sessions();
+            let active = active_sessions(gpu_index);
+            match (limit, active) {
+                (Some(limit), Some(active)) if active >= limit => Error::EncoderSessionLimit(
+                    format!("{} of {} NVENC sessions in use", active, limit),
+                ),
+                _ => Error::EncoderOutOfMemory(format!("Failed to open NVENC encoder: {}", e)),
+            }
+        }
+        e => Error::EncoderInit(format!("Failed to open encoder: {}", e)),
+    }
+}
+
+/// Driver branch (e.g. 570 for 570.86.16)
+fn driver_major_version() -> Option<u32> {
+    get_driver_version()?.split('.').next()?.parse().ok()
+}
+
+/// Get CUDA compute capability (e.g. (8, 9) for Ada) via nvidia-smi
+pub fn get_compute_capability() -> Option<(u32, u32)> {
+    let output = std::process::Command::new("nvidia-smi")
+        .args(["--query-gpu=compute_cap", "--format=csv,noheader"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let (major, minor) = text.lines().next()?.trim().split_once('.')?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
 /// Get detailed NVENC capabilities
 pub fn get_capabilities() -> NvencCapabilities {
     let mut caps = NvencCapabilities::default();
@@ -476,6 +718,8 @@ pub fn get_capabilities() -> NvencCapabilities {
     caps.hevc = supports_codec(Codec::Hevc);
     caps.av1 = supports_codec(Codec::Av1);
     caps.dual_encoder = has_dual_encoder();
+    caps.b_ref_mode = supports_b_ref_mode();
+    caps.multipass = supports_multipass();
     caps.gpu_name = get_gpu_name();
     caps.driver_version = get_driver_version();
 
@@ -483,13 +727,15 @@ pub fn get_capabilities() -> NvencCapabilities {
 }
 
 /// NVENC capabilities
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, serde::Serialize)]
 pub struct NvencCapabilities {
     pub available: bool,
     pub h264: bool,
     pub hevc: bool,
     pub av1: bool,
     pub dual_encoder: bool,
+    pub b_ref_mode: bool,
+    pub multipass: bool,
     pub gpu_name: Option<String>,
     pub driver_version: Option<String>,
 }
@@ -515,4 +761,22 @@ mod tests {
         let encoder = NvencEncoder::new(config);
         assert!(encoder.is_ok());
     }
+
+    #[test]
+    fn test_parse_gpu_list() {
+        let gpus = parse_gpu_list(
+            "0, NVIDIA GeForce RTX 4090, 00000000:01:00.0, 24564\n\
+             1, NVIDIA RTX A2000, 00000000:05:00.0, [N/A]\n",
+        );
+        assert_eq!(gpus.len(), 2);
+        assert_eq!(gpus[0].name, "NVIDIA GeForce RTX 4090");
+        assert_eq!(gpus[0].memory_mib, 24564);
+        assert_eq!(gpus[1].index, 1);
+        assert_eq!(gpus[1].pci_bus_id, "00000000:05:00.0");
+        assert_eq!(gpus[1].memory_mib, 0);
+
+        assert!(check_gpu_index(1, &gpus).is_ok());
+        assert!(check_gpu_index(2, &gpus).is_err());
+        assert!(check_gpu_index(2, &[]).is_ok());
+    }
 }
@@ -0,0 +1,306 @@
+//! Encoder worker pool
+//!
+//! FFmpeg encoder contexts are not `Send`: an encoder has to stay on the
+//! thread that opened it. The pipeline handles this with one dedicated
+//! thread per encoder, which is heavy for servers running many short
+//! transcodes. `EncoderPool` instead keeps a fixed set of worker threads;
+//! each encoder is opened on one worker and lives there until its handle is
+//! dropped, while async callers submit frames over a channel and await the
+//! result (much like `spawn_blocking`, but with the encoder kept between
+//! calls).
+//!
+//! Only `Send` data crosses threads: frames go in, packets, stats and codec
+//! parameters come back. `EncoderHandle` is `Send` and can be moved between
+//! tokio tasks. Jobs for encoders on the same worker run one after another,
+//! so a pool smaller than the number of open encoders trades latency for
+//! fewer threads.
+
+use crate::config::EncoderConfig;
+use crate::error::{Error, Result};
+use crate::types::{CodecParams, Frame, Packet};
+
+use super::{create_encoder_with_backend, Encoder, EncoderBackend, EncoderStats};
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::oneshot;
+
+type Reply<T> = oneshot::Sender<Result<T>>;
+
+/// Work sent to a pool thread
+enum Job {
+    Open {
+        id: u64,
+        config: EncoderConfig,
+        backend: EncoderBackend,
+        reply: Reply<()>,
+    },
+    Encode {
+        id: u64,
+        frame: Frame,
+        reply: Reply<Option<Packet>>,
+    },
+    Flush {
+        id: u64,
+        reply: Reply<Vec<Packet>>,
+    },
+    Stats {
+        id: u64,
+        reply: Reply<EncoderStats>,
+    },
+    CodecParams {
+        id: u64,
+        reply: Reply<Option<CodecParams>>,
+    },
+    ForceKeyframe {
+        id: u64,
+    },
+    Close {
+        id: u64,
+    },
+}
+
+struct Worker {
+    jobs: crossbeam_channel::Sender<Job>,
+    /// Encoders currently open on this worker
+    open: Arc<AtomicUsize>,
+}
+
+/// A fixed set of encoder threads shared by many encoders
+///
+/// Dropping the pool stops accepting new encoders; worker threads exit once
+/// every `EncoderHandle` opened from it has been dropped.
+pub struct EncoderPool {
+    workers: Vec<Worker>,
+    next_id: AtomicU64,
+}
+
+impl EncoderPool {
+    /// Start a pool with `threads` worker threads (at least one)
+    pub fn new(threads: usize) -> Result<Self> {
+        let workers = (0..threads.max(1))
+            .map(|index| {
+                let (jobs, rx) = crossbeam_channel::unbounded();
+                std::thread::Builder::new()
+                    .name(format!("ghoststream-encode-{}", index))
+                    .spawn(move || run_worker(rx))
+                    .map_err(|e| {
+                        Error::Internal(format!("Failed to spawn encoder worker: {}", e))
+                    })?;
+                Ok(Worker {
+                    jobs,
+                    open: Arc::new(AtomicUsize::new(0)),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            workers,
+            next_id: AtomicU64::new(0),
+        })
+    }
+
+    /// Number of worker threads
+    pub fn threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Open an encoder on the least busy worker, picking the backend automatically
+    pub async fn open(&self, config: EncoderConfig) -> Result<EncoderHandle> {
+        self.open_with_backend(config, EncoderBackend::Auto).await
+    }
+
+    /// Open an encoder with a specific backend
+    pub async fn open_with_backend(
+        &self,
+        config: EncoderConfig,
+        backend: EncoderBackend,
+    ) -> Result<EncoderHandle> {
+        let worker = self
+            .workers
+            .iter()
+            .min_by_key(|w| w.open.load(Ordering::Relaxed))
+            .expect("pool has at least one worker");
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        worker.open.fetch_add(1, Ordering::Relaxed);
+        let handle = EncoderHandle {
+            id,
+            jobs: worker.jobs.clone(),
+            open: worker.open.clone(),
+        };
+        handle
+            .request(|reply| Job::Open {
+                id,
+                config,
+                backend,
+                reply,
+            })
+            .await?;
+        Ok(handle)
+    }
+
+    /// Encode a batch of frames with a fresh encoder and return every packet
+    ///
+    /// Convenience for short transcodes: opens, encodes, flushes and closes.
+    pub async fn encode_all(
+        &self,
+        config: EncoderConfig,
+        frames: impl IntoIterator<Item = Frame>,
+    ) -> Result<Vec<Packet>> {
+        let encoder = self.open(config).await?;
+        let mut packets = Vec::new();
+        for frame in frames {
+            packets.extend(encoder.encode(frame).await?);
+        }
+        packets.extend(encoder.flush().await?);
+        Ok(packets)
+    }
+}
+
+/// An encoder living on an `EncoderPool` worker
+///
+/// Dropping the handle closes the encoder without flushing; call `flush`
+/// first to collect delayed packets.
+pub struct EncoderHandle {
+    id: u64,
+    jobs: crossbeam_channel::Sender<Job>,
+    open: Arc<AtomicUsize>,
+}
+
+impl EncoderHandle {
+    /// Encode a frame; `None` while the encoder is still buffering
+    pub async fn encode(&self, frame: Frame) -> Result<Option<Packet>> {
+        let id = self.id;
+        self.request(|reply| Job::Encode { id, frame, reply }).await
+    }
+
+    /// Drain packets still held by the encoder
+    pub async fn flush(&self) -> Result<Vec<Packet>> {
+        let id = self.id;
+        self.request(|reply| Job::Flush { id, reply }).await
+    }
+
+    /// Current encoder statistics
+    pub async fn stats(&self) -> Result<EncoderStats> {
+        let id = self.id;
+        self.request(|reply| Job::Stats { id, reply }).await
+    }
+
+    /// Codec parameters for muxing (available after the first frame)
+    pub async fn codec_params(&self) -> Result<Option<CodecParams>> {
+        let id = self.id;
+        self.request(|reply| Job::CodecParams { id, reply }).await
+    }
+
+    /// Encode the next frame as a keyframe
+    pub fn force_keyframe(&self) {
+        let _ = self.jobs.send(Job::ForceKeyframe { id: self.id });
+    }
+
+    async fn request<T>(&self, job: impl FnOnce(Reply<T>) -> Job) -> Result<T> {
+        let (reply, rx) = oneshot::channel();
+        self.jobs
+            .send(job(reply))
+            .map_err(|_| Error::Internal("Encoder pool worker exited".into()))?;
+        rx.await
+            .map_err(|_| Error::Internal("Encoder pool worker exited".into()))?
+    }
+}
+
+impl Drop for EncoderHandle {
+    fn drop(&mut self) {
+        let _ = self.jobs.send(Job::Close { id: self.id });
+        self.open.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Worker thread: owns its encoders and runs jobs until all senders are gone
+fn run_worker(jobs: crossbeam_channel::Receiver<Job>) {
+    let mut encoders: HashMap<u64, Box<dyn Encoder>> = HashMap::new();
+
+    while let Ok(job) = jobs.recv() {
+        match job {
+            Job::Open {
+                id,
+                config,
+                backend,
+                reply,
+            } => {
+                let opened = create_encoder_with_backend(config, backend)
+                    .and_then(|mut e| e.init().map(|_| e))
+                    .map(|encoder| {
+                        encoders.insert(id, encoder);
+                    });
+                let _ = reply.send(opened);
+            }
+            Job::Encode { id, frame, reply } => {
+                let _ = reply.send(with_encoder(&mut encoders, id, |e| e.encode(&frame)));
+            }
+            Job::Flush { id, reply } => {
+                let _ = reply.send(with_encoder(&mut encoders, id, |e| e.flush()));
+            }
+            Job::Stats { id, reply } => {
+                let _ = reply.send(with_encoder(&mut encoders, id, |e| Ok(e.stats())));
+            }
+            Job::CodecParams { id, reply } => {
+                let _ = reply.send(with_encoder(&mut encoders, id, |e| Ok(e.codec_params())));
+            }
+            Job::ForceKeyframe { id } => {
+                let _ = with_encoder(&mut encoders, id, |e| {
+                    e.force_keyframe();
+                    Ok(())
+                });
+            }
+            Job::Close { id } => {
+                encoders.remove(&id);
+            }
+        }
+    }
+}
+
+/// Run `f` on an open encoder of this worker
+fn with_encoder<T>(
+    encoders: &mut HashMap<u64, Box<dyn Encoder>>,
+    id: u64,
+    f: impl FnOnce(&mut dyn Encoder) -> Result<T>,
+) -> Result<T> {
+    match encoders.get_mut(&id) {
+        Some(encoder) => f(encoder.as_mut()),
+        None => Err(Error::Internal(format!(
+            "Pooled encoder {} is not open",
+            id
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_send<T: Send>() {}
+
+    #[test]
+    fn test_pool_handles_are_send() {
+        assert_send::<EncoderPool>();
+        assert_send::<EncoderHandle>();
+    }
+
+    #[tokio::test]
+    async fn test_unknown_encoder_is_an_error() {
+        let pool = EncoderPool::new(2).unwrap();
+        assert_eq!(pool.threads(), 2);
+
+        // A handle whose Open never ran
+        let handle = EncoderHandle {
+            id: u64::MAX,
+            jobs: pool.workers[0].jobs.clone(),
+            open: pool.workers[0].open.clone(),
+        };
+        pool.workers[0].open.fetch_add(1, Ordering::Relaxed);
+        assert!(handle.flush().await.is_err());
+        drop(handle);
+        assert_eq!(pool.workers[0].open.load(Ordering::Relaxed), 0);
+    }
+}
@@ -6,7 +6,7 @@ use crate::config::EncoderConfig;
 use crate::error::{Error, Result};
 use crate::types::{CodecParams, Frame, FrameFormat, Packet, Resolution};
 
-use super::{Codec, Encoder, EncoderStats};
+use super::{packet_from_ffmpeg, Codec, Encoder, EncoderStats};
 
 use ffmpeg_next as ffmpeg;
 use ffmpeg_next::format::Pixel;
@@ -21,8 +21,12 @@ pub struct QsvEncoder {
     scaler: Option<Scaler>,
     stats: EncoderStats,
     frame_count: u64,
+    force_keyframe: bool,
     start_time: Option<Instant>,
-    input_resolution: Option<Resolution>,
+    /// Frame sizes through the scaler, set on init
+    fit: super::FrameFit,
+    /// Input size `warm_up` opened the encoder for, until the first frame
+    warmed_up: Option<(u32, u32)>,
     time_base: ffmpeg::Rational,
 }
 
@@ -47,6 +51,9 @@ impl QsvEncoder {
                 config.codec.display_name()
             )));
         }
+        super::check_chroma(&config, super::EncoderBackend::Qsv)?;
+        super::check_pixel_format(&config, super::EncoderBackend::Qsv)?;
+        super::check_film_grain(&config, super::EncoderBackend::Qsv)?;
 
         Ok(Self {
             config,
@@ -54,8 +61,10 @@ impl QsvEncoder {
             scaler: None,
             stats: EncoderStats::default(),
             frame_count: 0,
+            force_keyframe: false,
             start_time: None,
-            input_resolution: None,
+            fit: super::FrameFit::default(),
+            warmed_up: None,
             time_base: ffmpeg::Rational::new(1, 60),
         })
     }
@@ -69,21 +78,25 @@ impl QsvEncoder {
         }
     }
 
-    /// Initialize encoder with specific input resolution
-    fn init_encoder(&mut self, input_width: u32, input_height: u32) -> Result<()> {
+    /// Initialize encoder for the first frame's resolution and colorimetry
+    fn init_encoder(&mut self, frame: &Frame) -> Result<()> {
+        let (input_width, input_height) = (frame.width, frame.height);
         let encoder_name = Self::qsv_encoder_name(self.config.codec);
+        let mut latency = super::fit_latency_budget(&mut self.config, super::EncoderBackend::Qsv);
 
         // Find the encoder
         let codec = ffmpeg::encoder::find_by_name(encoder_name).ok_or_else(|| {
             Error::EncoderInit(format!("QSV encoder {} not found", encoder_name))
         })?;
 
-        // Determine output resolution
-        let (out_width, out_height) = if let Some(res) = self.config.resolution {
-            (res.width, res.height)
-        } else {
-            (input_width, input_height)
-        };
+        // Determine output resolution, fitted to the codec's alignment
+        let fit = super::FrameFit::new(
+            (input_width, input_height),
+            self.config.resolution,
+            self.config.chroma_format,
+            self.config.dimension_alignment,
+        );
+        let (out_width, out_height) = fit.encoded;
 
         // Create encoder context
         let context = ffmpeg::codec::context::Context::new_with_codec(codec);
@@ -95,7 +108,9 @@ impl QsvEncoder {
         // Set basic parameters
         encoder.set_width(out_width);
         encoder.set_height(out_height);
-        encoder.set_format(Pixel::NV12); // QSV prefers NV12
+        // NV12, or P010 for 10-bit
+        let pixel = super::encoder_pixel_format(&self.config, super::EncoderBackend::Qsv);
+        encoder.set_format(pixel);
         encoder.set_time_base(ffmpeg::Rational::new(1, 1000));
         self.time_base = ffmpeg::Rational::new(1, 1000);
 
@@ -110,14 +125,7 @@ impl QsvEncoder {
         let mut opts = Dictionary::new();
 
         // QSV preset mapping
-        let preset = match self.config.preset {
-            crate::config::EncoderPreset::Fastest => "veryfast",
-            crate::config::EncoderPreset::Fast => "fast",
-            crate::config::EncoderPreset::Medium => "medium",
-            crate::config::EncoderPreset::Slow => "slow",
-            crate::config::EncoderPreset::Slowest => "veryslow",
-        };
-        opts.set("preset", preset);
+        opts.set("preset", self.config.preset.to_qsv_preset());
 
         // Rate control
         match self.config.rate_control {
@@ -138,6 +146,14 @@ impl QsvEncoder {
             crate::config::RateControl::Crf { crf } => {
                 opts.set("global_quality", &crf.to_string());
             }
+            crate::config::RateControl::Quality { quality } => {
+                let icq = super::quality_parameter(
+                    quality,
+                    self.config.codec,
+                    super::EncoderBackend::Qsv,
+                );
+                opts.set("global_quality", &icq.to_string());
+            }
         }
 
         // Low latency mode
@@ -150,23 +166,53 @@ impl QsvEncoder {
             opts.set("look_ahead", "0");
         }
 
+        // Lookahead and pipelining within the latency budget; QSV keeps at
+        // least one frame in flight
+        if let Some(latency) = latency.as_mut() {
+            if latency.lookahead == 0 {
+                opts.set("look_ahead", "0");
+            } else {
+                opts.set("look_ahead_depth", &latency.lookahead.to_string());
+            }
+            latency.queue = latency.queue.max(1);
+            opts.set("async_depth", &latency.queue.to_string());
+        }
+
+        // No adaptive I-frame placement on scene changes
+        if !self.config.scene_cut {
+            opts.set("adaptive_i", "0");
+        }
+
+        // Resend PPS with every frame (SPS follows each IDR already)
+        if self.config.repeat_headers && self.config.codec == Codec::H264 {
+            opts.set("repeat_pps", "1");
+        }
+
+        // Forced keyframes are IDRs
+        opts.set("forced_idr", "1");
+
+        super::apply_vbv(&mut opts, &self.config);
+        super::apply_extra_options(&mut opts, &self.config, encoder_name);
+        super::set_colorimetry(&mut encoder, &self.config, frame);
+
         // Open encoder
         let opened = encoder
             .open_with(opts)
             .map_err(|e| Error::EncoderInit(format!("Failed to open QSV encoder: {}", e)))?;
 
         self.encoder = Some(opened);
-        self.input_resolution = Some(Resolution::new(input_width, input_height));
+        self.fit = fit;
 
-        // Create scaler if needed
-        if input_width != out_width || input_height != out_height {
+        // Create scaler if frames are resized, padded or converted
+        let input = Self::to_ffmpeg_format(self.config.input_format());
+        if fit.needs_scaler() || input != pixel {
             let scaler = Scaler::get(
-                Pixel::NV12,
-                input_width,
-                input_height,
-                Pixel::NV12,
-                out_width,
-                out_height,
+                input,
+                fit.source.0,
+                fit.source.1,
+                pixel,
+                fit.scaled.0,
+                fit.scaled.1,
                 ScalerFlags::BILINEAR,
             )
             .map_err(|e| Error::EncoderInit(format!("Failed to create scaler: {}", e)))?;
@@ -183,6 +229,8 @@ impl QsvEncoder {
             self.config.bitrate_kbps,
         );
 
+        super::report_latency(latency, &mut self.stats);
+
         Ok(())
     }
 
@@ -190,6 +238,7 @@ impl QsvEncoder {
         match format {
             FrameFormat::Nv12 => Pixel::NV12,
             FrameFormat::Yuv420p => Pixel::YUV420P,
+            FrameFormat::Yuv422p => Pixel::YUV422P,
             FrameFormat::Yuv444p => Pixel::YUV444P,
             FrameFormat::Bgra => Pixel::BGRA,
             FrameFormat::Rgba => Pixel::RGBA,
@@ -205,8 +254,17 @@ impl Encoder for QsvEncoder {
     }
 
     fn encode(&mut self, frame: &Frame) -> Result<Option<Packet>> {
+        // Warmed up without seeing a frame: re-open if the first one differs
+        if let Some(size) = self.warmed_up.take() {
+            if !super::fits_warm_up(&self.config, frame, size) {
+                tracing::debug!("Re-opening encoder for the first frame's size and colorimetry");
+                self.encoder = None;
+                self.scaler = None;
+            }
+        }
+
         if self.encoder.is_none() {
-            self.init_encoder(frame.width, frame.height)?;
+            self.init_encoder(frame)?;
         }
 
         let encoder = self.encoder.as_mut().unwrap();
@@ -219,33 +277,30 @@ impl Encoder for QsvEncoder {
         );
 
         // Copy frame data
-        if frame.format == FrameFormat::Nv12 {
-            let y_size = (frame.width * frame.height) as usize;
-            let uv_size = y_size / 2;
-
-            if frame.data.len() >= y_size + uv_size {
-                video_frame.data_mut(0)[..y_size].copy_from_slice(&frame.data[..y_size]);
-                video_frame.data_mut(1)[..uv_size]
-                    .copy_from_slice(&frame.data[y_size..y_size + uv_size]);
-            }
+        if matches!(frame.format, FrameFormat::Nv12 | FrameFormat::P010) {
+            super::copy_semi_planar(frame, &mut video_frame);
         } else {
             let plane_size = video_frame.data(0).len().min(frame.data.len());
             video_frame.data_mut(0)[..plane_size].copy_from_slice(&frame.data[..plane_size]);
         }
 
-        video_frame.set_pts(Some(frame.pts));
+        // Capture stamps µs; the encoder counts in its own time base
+        video_frame.set_pts(Some(super::pts_to_time_base(frame.pts, self.time_base)));
 
-        let frame_to_encode = if let Some(ref mut scaler) = self.scaler {
-            let mut scaled = ffmpeg::frame::Video::empty();
-            scaler
-                .run(&video_frame, &mut scaled)
-                .map_err(|e| Error::EncodingFailed(format!("Scaling failed: {}", e)))?;
-            scaled.set_pts(video_frame.pts());
-            scaled
-        } else {
-            video_frame
+        self.fit.crop(&mut video_frame);
+        let mut frame_to_encode = match self.scaler {
+            Some(ref mut scaler) => self.fit.scale(scaler, &video_frame)?,
+            None => video_frame,
         };
 
+        super::attach_hdr_side_data(&mut frame_to_encode, &self.config);
+
+        super::attach_timecode(&mut frame_to_encode, &self.config, frame.pts);
+
+        if std::mem::take(&mut self.force_keyframe) {
+            frame_to_encode.set_kind(ffmpeg::picture::Type::I);
+        }
+
         encoder
             .send_frame(&frame_to_encode)
             .map_err(|e| Error::EncodingFailed(format!("Failed to send frame: {}", e)))?;
@@ -271,14 +326,7 @@ impl Encoder for QsvEncoder {
                     }
                 }
 
-                Ok(Some(Packet {
-                    data: ffmpeg_packet.data().map(|d| d.to_vec()).unwrap_or_default(),
-                    pts: ffmpeg_packet.pts().unwrap_or(0),
-                    dts: ffmpeg_packet.dts().unwrap_or(0),
-                    duration: ffmpeg_packet.duration(),
-                    is_keyframe: ffmpeg_packet.is_key(),
-                    flags: 0,
-                }))
+                Ok(Some(packet_from_ffmpeg(&ffmpeg_packet, self.config.codec)))
             }
             Err(ffmpeg::Error::Other { errno }) if errno == ffmpeg::error::EAGAIN => Ok(None),
             Err(e) => Err(Error::EncodingFailed(format!(
@@ -304,14 +352,7 @@ impl Encoder for QsvEncoder {
             match encoder.receive_packet(&mut ffmpeg_packet) {
                 Ok(_) => {
                     self.stats.bytes_output += ffmpeg_packet.size() as u64;
-                    packets.push(Packet {
-                        data: ffmpeg_packet.data().map(|d| d.to_vec()).unwrap_or_default(),
-                        pts: ffmpeg_packet.pts().unwrap_or(0),
-                        dts: ffmpeg_packet.dts().unwrap_or(0),
-                        duration: ffmpeg_packet.duration(),
-                        is_keyframe: ffmpeg_packet.is_key(),
-                        flags: 0,
-                    });
+                    packets.push(packet_from_ffmpeg(&ffmpeg_packet, self.config.codec));
                 }
                 Err(ffmpeg::Error::Eof) => break,
                 Err(ffmpeg::Error::Other { errno }) if errno == ffmpeg::error::EAGAIN => continue,
@@ -348,13 +389,10 @@ impl Encoder for QsvEncoder {
             }
         };
 
-        let resolution = if let Some(res) = self.config.resolution {
-            res
-        } else if let Some(res) = self.input_resolution {
-            res
-        } else {
-            Resolution::new(encoder.width(), encoder.height())
-        };
+        let resolution = Resolution::new(encoder.width(), encoder.height());
+
+        let pixel = super::encoder_pixel_format(&self.config, super::EncoderBackend::Qsv);
+        let (chroma_format, bit_depth) = super::coded_format(pixel);
 
         Some(CodecParams {
             codec: self.config.codec,
@@ -364,14 +402,33 @@ impl Encoder for QsvEncoder {
             time_base_num: self.time_base.numerator(),
             time_base_den: self.time_base.denominator(),
             bitrate: (self.config.bitrate_kbps as i64) * 1000,
+            hdr: self.config.hdr.clone(),
+            chroma_format,
+            bit_depth,
         })
     }
 
     fn reconfigure(&mut self, config: &EncoderConfig) -> Result<()> {
+        // Bitrate can change live; other settings apply on the next init
+        if let Some(encoder) = self.encoder.as_mut() {
+            super::apply_bitrate_change(encoder, &self.config, config);
+        }
         self.config = config.clone();
         tracing::info!("QSV encoder config updated: {}kbps", config.bitrate_kbps);
         Ok(())
     }
+
+    fn force_keyframe(&mut self) {
+        self.force_keyframe = true;
+    }
+
+    fn warm_up(&mut self, width: u32, height: u32) -> Result<()> {
+        if self.encoder.is_none() {
+            self.init_encoder(&super::warm_up_frame(&self.config, width, height))?;
+            self.warmed_up = Some((width, height));
+        }
+        Ok(())
+    }
 }
 
 // ============================================================================
@@ -426,7 +483,7 @@ pub fn get_capabilities() -> QsvCapabilities {
 }
 
 /// QSV capabilities
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, serde::Serialize)]
 pub struct QsvCapabilities {
     pub available: bool,
     pub h264: bool,
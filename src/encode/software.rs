@@ -5,11 +5,11 @@
 //! - libx265 for H.265/HEVC (excellent quality)
 //! - libsvtav1 for AV1 (best for AMD Zen4/5 with AVX-512)
 
-use crate::config::EncoderConfig;
+use crate::config::{EncoderConfig, EncoderPreset};
 use crate::error::{Error, Result};
 use crate::types::{CodecParams, Frame, FrameFormat, Packet, Resolution};
 
-use super::{Codec, Encoder, EncoderStats};
+use super::{packet_from_ffmpeg, topology, Codec, Encoder, EncoderStats};
 
 use ffmpeg_next as ffmpeg;
 use ffmpeg_next::format::Pixel;
@@ -76,6 +76,21 @@ impl CpuPreset {
         }
     }
 
+    /// The x264/x265 preset matching an encoder-agnostic `EncoderPreset`
+    ///
+    /// Not applied on its own: `EncoderConfig::preset` is tuned for the
+    /// hardware encoders, and the slow end of this mapping can't keep up
+    /// with live capture on a CPU. Opt in with `SoftwareEncoder::with_preset`.
+    pub fn from_encoder_preset(preset: EncoderPreset) -> Self {
+        match preset {
+            EncoderPreset::Fastest => CpuPreset::Veryfast,
+            EncoderPreset::Fast => CpuPreset::Fast,
+            EncoderPreset::Medium => CpuPreset::Medium,
+            EncoderPreset::Slow => CpuPreset::Slow,
+            EncoderPreset::Slowest => CpuPreset::Veryslow,
+        }
+    }
+
     /// Recommended preset for realtime encoding at given FPS
     pub fn for_realtime(fps: u32, resolution: Resolution) -> Self {
         let pixels_per_second = resolution.pixels() as u64 * fps as u64;
@@ -107,17 +122,23 @@ pub struct SoftwareEncoder {
     scaler: Option<Scaler>,
     stats: EncoderStats,
     frame_count: u64,
+    force_keyframe: bool,
     start_time: Option<Instant>,
-    input_resolution: Option<Resolution>,
+    /// Frame sizes through the scaler, set on init
+    fit: super::FrameFit,
+    /// Input size `warm_up` opened the encoder for, until the first frame
+    warmed_up: Option<(u32, u32)>,
     time_base: ffmpeg::Rational,
     threads: usize,
+    /// CPU group the encoder's workers run on (`numa_aware`)
+    cpu_group: Option<topology::GroupLease>,
 }
 
 impl SoftwareEncoder {
     /// Create a new software encoder
     pub fn new(config: EncoderConfig) -> Result<Self> {
         // Initialize FFmpeg
-        ffmpeg::init().map_err(|e| Error::FFmpeg(e.to_string()))?;
+        ffmpeg::init().map_err(|e| Error::Ffmpeg(e.to_string()))?;
 
         // Verify codec is supported
         let encoder_name = Self::get_encoder_name(config.codec);
@@ -128,6 +149,9 @@ impl SoftwareEncoder {
                 config.codec.display_name()
             )));
         }
+        super::check_chroma(&config, super::EncoderBackend::Software)?;
+        super::check_pixel_format(&config, super::EncoderBackend::Software)?;
+        super::check_film_grain(&config, super::EncoderBackend::Software)?;
 
         // Determine optimal thread count for AMD CPUs
         let threads = Self::optimal_thread_count();
@@ -139,10 +163,13 @@ impl SoftwareEncoder {
             scaler: None,
             stats: EncoderStats::default(),
             frame_count: 0,
+            force_keyframe: false,
             start_time: None,
-            input_resolution: None,
+            fit: super::FrameFit::default(),
+            warmed_up: None,
             time_base: ffmpeg::Rational::new(1, 1000),
             threads,
+            cpu_group: None,
         })
     }
 
@@ -170,6 +197,36 @@ impl SoftwareEncoder {
         cpus.saturating_sub(2).max(4)
     }
 
+    /// Claim a CPU group for the encoder's workers and size the thread
+    /// pool to it
+    ///
+    /// x264/x265/SVT-AV1 create their workers when the encoder opens, which
+    /// happens under `topology::with_affinity` so only they get the mask.
+    fn pin_threads(&mut self) {
+        if self.cpu_group.is_none() {
+            let allowed = match topology::current_affinity() {
+                Ok(allowed) => allowed,
+                Err(e) => {
+                    tracing::warn!("Not pinning encoder threads: {}", e);
+                    return;
+                }
+            };
+            let Some(group) = topology::claim_group(&allowed) else {
+                tracing::debug!("Single CCD/NUMA node, not pinning encoder threads");
+                return;
+            };
+            self.cpu_group = Some(group);
+        }
+        if let Some(group) = &self.cpu_group {
+            self.threads = self.threads.min(group.cpus.len());
+            tracing::info!(
+                "Pinned software encoder to CPUs {:?} ({} threads)",
+                group.cpus,
+                self.threads
+            );
+        }
+    }
+
     /// Get FFmpeg encoder name for codec
     fn get_encoder_name(codec: Codec) -> &'static str {
         match codec {
@@ -179,20 +236,25 @@ impl SoftwareEncoder {
         }
     }
 
-    /// Initialize encoder with specific input resolution
-    fn init_encoder(&mut self, input_width: u32, input_height: u32) -> Result<()> {
+    /// Initialize encoder for the first frame's resolution and colorimetry
+    fn init_encoder(&mut self, frame: &Frame) -> Result<()> {
+        let (input_width, input_height) = (frame.width, frame.height);
         let encoder_name = Self::get_encoder_name(self.config.codec);
+        let mut latency =
+            super::fit_latency_budget(&mut self.config, super::EncoderBackend::Software);
 
         // Find the encoder
         let codec = ffmpeg::encoder::find_by_name(encoder_name)
             .ok_or_else(|| Error::EncoderInit(format!("Encoder {} not found", encoder_name)))?;
 
-        // Determine output resolution
-        let (out_width, out_height) = if let Some(res) = self.config.resolution {
-            (res.width, res.height)
-        } else {
-            (input_width, input_height)
-        };
+        // Determine output resolution, fitted to the codec's alignment
+        let fit = super::FrameFit::new(
+            (input_width, input_height),
+            self.config.resolution,
+            self.config.chroma_format,
+            self.config.dimension_alignment,
+        );
+        let (out_width, out_height) = fit.encoded;
 
         // Create encoder context
         let context = ffmpeg::codec::context::Context::new_with_codec(codec);
@@ -205,8 +267,10 @@ impl SoftwareEncoder {
         encoder.set_width(out_width);
         encoder.set_height(out_height);
 
-        // Use YUV420P for software encoders (most compatible)
-        encoder.set_format(Pixel::YUV420P);
+        // Planar YUV of the configured chroma, 10-bit for P010; x264/x265
+        // pick the matching High 10 / Main 10 / RExt profile from it
+        let pixel = super::encoder_pixel_format(&self.config, super::EncoderBackend::Software);
+        encoder.set_format(pixel);
         encoder.set_time_base(ffmpeg::Rational::new(1, 1000));
         self.time_base = ffmpeg::Rational::new(1, 1000);
 
@@ -222,6 +286,11 @@ impl SoftwareEncoder {
         // Set max B-frames
         encoder.set_max_b_frames(self.config.b_frames as usize);
 
+        // Keep encoder threads on one CCD / NUMA node
+        if self.config.numa_aware {
+            self.pin_threads();
+        }
+
         // Build encoder options
         let mut opts = Dictionary::new();
 
@@ -281,28 +350,60 @@ impl SoftwareEncoder {
             crate::config::RateControl::Crf { crf } => {
                 opts.set("crf", &crf.to_string());
             }
+            crate::config::RateControl::Quality { quality } => {
+                let crf = super::quality_parameter(
+                    quality,
+                    self.config.codec,
+                    super::EncoderBackend::Software,
+                );
+                opts.set("crf", &crf.to_string());
+            }
         }
 
         // Codec-specific optimizations for AMD
+        let mut svtav1_params: Vec<String> = Vec::new();
         match self.config.codec {
             Codec::H264 => {
                 // x264 AMD optimizations
                 opts.set("tune", "zerolatency"); // Low latency for streaming
                 // Enable SIMD optimizations (auto-detected, but explicit)
+                let mut x264_params = Vec::new();
+                if self.config.repeat_headers {
+                    x264_params.push("repeat-headers=1:annexb=1");
+                }
+                if !self.config.scene_cut {
+                    x264_params.push("scenecut=0");
+                }
+                if !x264_params.is_empty() {
+                    opts.set("x264-params", &x264_params.join(":"));
+                }
             }
             Codec::Hevc => {
                 // x265 AMD optimizations
+                // x265 frame-threads max is typically 8-16; each one past the
+                // first delays output by a frame
+                let mut frame_threads = thread_count.min(8);
+                if let Some(latency) = latency.as_mut() {
+                    frame_threads = frame_threads.min(latency.queue as usize + 1);
+                    latency.queue = frame_threads as u32 - 1;
+                }
                 // Use x265-params for specific settings
-                let x265_params = format!(
-                    "log-level=warning:frame-threads={}:lookahead-slices=4:rc-lookahead=20",
-                    thread_count.min(8) // x265 frame-threads max is typically 8-16
+                let mut x265_params = format!(
+                    "log-level=warning:frame-threads={}:lookahead-slices=4:rc-lookahead={}",
+                    frame_threads,
+                    self.config.lookahead.unwrap_or(20)
                 );
+                if self.config.repeat_headers {
+                    x265_params.push_str(":repeat-headers=1:annexb=1");
+                }
+                if !self.config.scene_cut {
+                    x265_params.push_str(":scenecut=0");
+                }
                 opts.set("x265-params", &x265_params);
             }
             Codec::Av1 => {
                 // SVT-AV1 is excellent on AMD Zen4/5 with AVX-512
-                opts.set("svtav1-params", "tune=0"); // PSNR tuning
-                // Enable film grain synthesis for better quality
+                svtav1_params.push("tune=0".into()); // PSNR tuning
             }
         }
 
@@ -318,27 +419,52 @@ impl SoftwareEncoder {
                 }
                 Codec::Av1 => {
                     // SVT-AV1 low latency
-                    opts.set("svtav1-params", "rc=1:pred-struct=1");
+                    svtav1_params.push("rc=1".into());
+                    svtav1_params.push("pred-struct=1".into());
                 }
             }
         }
 
-        // Open encoder
-        let opened = encoder
-            .open_with(opts)
-            .map_err(|e| Error::EncoderInit(format!("Failed to open encoder: {}", e)))?;
+        if self.config.codec == Codec::Av1 {
+            // Random access holds a whole mini-GOP; a latency budget needs
+            // the low-delay structure
+            if latency.is_some() && !svtav1_params.iter().any(|p| p == "pred-struct=1") {
+                svtav1_params.push("pred-struct=1".into());
+            }
+            if let Some(lookahead) = self.config.lookahead {
+                svtav1_params.push(format!("lookahead={}", lookahead));
+            }
+            // Film grain synthesis: SVT-AV1 denoises the source and signals
+            // the grain for the decoder to add back
+            if let Some(strength) = self.config.film_grain {
+                svtav1_params.push(format!("film-grain={}", strength));
+            }
+            opts.set("svtav1-params", &svtav1_params.join(":"));
+        }
+
+        super::apply_vbv(&mut opts, &self.config);
+        super::apply_extra_options(&mut opts, &self.config, encoder_name);
+        super::set_colorimetry(&mut encoder, &self.config, frame);
+
+        // Open encoder; its workers start on the claimed CPU group
+        let open = || encoder.open_with(opts);
+        let opened = match &self.cpu_group {
+            Some(group) => topology::with_affinity(&group.cpus, open),
+            None => open(),
+        }
+        .map_err(|e| Error::EncoderInit(format!("Failed to open encoder: {}", e)))?;
 
         self.encoder = Some(opened);
-        self.input_resolution = Some(Resolution::new(input_width, input_height));
+        self.fit = fit;
 
-        // Create scaler if input != output resolution or format conversion needed
+        // Create scaler for format conversion, resizing and padding
         let scaler = Scaler::get(
-            Pixel::NV12, // Input from capture is typically NV12 or BGRA
-            input_width,
-            input_height,
-            Pixel::YUV420P, // Software encoders prefer YUV420P
-            out_width,
-            out_height,
+            Self::to_ffmpeg_format(self.config.input_format()),
+            fit.source.0,
+            fit.source.1,
+            pixel,
+            fit.scaled.0,
+            fit.scaled.1,
             ScalerFlags::BILINEAR,
         )
         .map_err(|e| Error::EncoderInit(format!("Failed to create scaler: {}", e)))?;
@@ -359,6 +485,8 @@ impl SoftwareEncoder {
             self.threads
         );
 
+        super::report_latency(latency, &mut self.stats);
+
         Ok(())
     }
 
@@ -367,6 +495,7 @@ impl SoftwareEncoder {
         match format {
             FrameFormat::Nv12 => Pixel::NV12,
             FrameFormat::Yuv420p => Pixel::YUV420P,
+            FrameFormat::Yuv422p => Pixel::YUV422P,
             FrameFormat::Yuv444p => Pixel::YUV444P,
             FrameFormat::Bgra => Pixel::BGRA,
             FrameFormat::Rgba => Pixel::RGBA,
@@ -383,9 +512,18 @@ impl Encoder for SoftwareEncoder {
     }
 
     fn encode(&mut self, frame: &Frame) -> Result<Option<Packet>> {
+        // Warmed up without seeing a frame: re-open if the first one differs
+        if let Some(size) = self.warmed_up.take() {
+            if !super::fits_warm_up(&self.config, frame, size) {
+                tracing::debug!("Re-opening encoder for the first frame's size and colorimetry");
+                self.encoder = None;
+                self.scaler = None;
+            }
+        }
+
         // Initialize encoder on first frame
         if self.encoder.is_none() {
-            self.init_encoder(frame.width, frame.height)?;
+            self.init_encoder(frame)?;
         }
 
         let encoder = self.encoder.as_mut().unwrap();
@@ -400,25 +538,11 @@ impl Encoder for SoftwareEncoder {
 
         // Copy frame data based on format
         match frame.format {
-            FrameFormat::Nv12 => {
-                let y_size = (frame.width * frame.height) as usize;
-                let uv_size = y_size / 2;
-                if frame.data.len() >= y_size + uv_size {
-                    video_frame.data_mut(0)[..y_size].copy_from_slice(&frame.data[..y_size]);
-                    video_frame.data_mut(1)[..uv_size]
-                        .copy_from_slice(&frame.data[y_size..y_size + uv_size]);
-                }
+            FrameFormat::Nv12 | FrameFormat::P010 => {
+                super::copy_semi_planar(frame, &mut video_frame);
             }
-            FrameFormat::Yuv420p => {
-                let y_size = (frame.width * frame.height) as usize;
-                let uv_size = y_size / 4;
-                if frame.data.len() >= y_size + uv_size * 2 {
-                    video_frame.data_mut(0)[..y_size].copy_from_slice(&frame.data[..y_size]);
-                    video_frame.data_mut(1)[..uv_size]
-                        .copy_from_slice(&frame.data[y_size..y_size + uv_size]);
-                    video_frame.data_mut(2)[..uv_size]
-                        .copy_from_slice(&frame.data[y_size + uv_size..y_size + uv_size * 2]);
-                }
+            FrameFormat::Yuv420p | FrameFormat::Yuv422p | FrameFormat::Yuv444p => {
+                super::copy_planar_yuv(frame, &mut video_frame);
             }
             _ => {
                 let plane_size = video_frame.data(0).len().min(frame.data.len());
@@ -426,20 +550,22 @@ impl Encoder for SoftwareEncoder {
             }
         }
 
-        video_frame.set_pts(Some(frame.pts));
+        // Capture stamps µs; the encoder counts in its own time base
+        video_frame.set_pts(Some(super::pts_to_time_base(frame.pts, self.time_base)));
 
-        // Scale/convert to YUV420P for encoding
-        let frame_to_encode = if let Some(ref mut scaler) = self.scaler {
-            let mut scaled = ffmpeg::frame::Video::empty();
-            scaler
-                .run(&video_frame, &mut scaled)
-                .map_err(|e| Error::EncodingFailed(format!("Scaling failed: {}", e)))?;
-            scaled.set_pts(video_frame.pts());
-            scaled
-        } else {
-            video_frame
+        // Scale/convert to the encoder's pixel format
+        self.fit.crop(&mut video_frame);
+        let mut frame_to_encode = match self.scaler {
+            Some(ref mut scaler) => self.fit.scale(scaler, &video_frame)?,
+            None => video_frame,
         };
 
+        super::attach_timecode(&mut frame_to_encode, &self.config, frame.pts);
+
+        if std::mem::take(&mut self.force_keyframe) {
+            frame_to_encode.set_kind(ffmpeg::picture::Type::I);
+        }
+
         // Send frame to encoder
         encoder
             .send_frame(&frame_to_encode)
@@ -467,14 +593,7 @@ impl Encoder for SoftwareEncoder {
                     }
                 }
 
-                let packet = Packet {
-                    data: ffmpeg_packet.data().map(|d| d.to_vec()).unwrap_or_default(),
-                    pts: ffmpeg_packet.pts().unwrap_or(0),
-                    dts: ffmpeg_packet.dts().unwrap_or(0),
-                    duration: ffmpeg_packet.duration(),
-                    is_keyframe: ffmpeg_packet.is_key(),
-                    flags: 0,
-                };
+                let packet = packet_from_ffmpeg(&ffmpeg_packet, self.config.codec);
 
                 Ok(Some(packet))
             }
@@ -502,14 +621,7 @@ impl Encoder for SoftwareEncoder {
             match encoder.receive_packet(&mut ffmpeg_packet) {
                 Ok(_) => {
                     self.stats.bytes_output += ffmpeg_packet.size() as u64;
-                    packets.push(Packet {
-                        data: ffmpeg_packet.data().map(|d| d.to_vec()).unwrap_or_default(),
-                        pts: ffmpeg_packet.pts().unwrap_or(0),
-                        dts: ffmpeg_packet.dts().unwrap_or(0),
-                        duration: ffmpeg_packet.duration(),
-                        is_keyframe: ffmpeg_packet.is_key(),
-                        flags: 0,
-                    });
+                    packets.push(packet_from_ffmpeg(&ffmpeg_packet, self.config.codec));
                 }
                 Err(ffmpeg::Error::Eof) => break,
                 Err(ffmpeg::Error::Other { errno }) if errno == ffmpeg::error::EAGAIN => continue,
@@ -547,13 +659,10 @@ impl Encoder for SoftwareEncoder {
             }
         };
 
-        let resolution = if let Some(res) = self.config.resolution {
-            res
-        } else if let Some(res) = self.input_resolution {
-            res
-        } else {
-            Resolution::new(encoder.width(), encoder.height())
-        };
+        let resolution = Resolution::new(encoder.width(), encoder.height());
+
+        let pixel = super::encoder_pixel_format(&self.config, super::EncoderBackend::Software);
+        let (chroma_format, bit_depth) = super::coded_format(pixel);
 
         Some(CodecParams {
             codec: self.config.codec,
@@ -563,14 +672,33 @@ impl Encoder for SoftwareEncoder {
             time_base_num: self.time_base.numerator(),
             time_base_den: self.time_base.denominator(),
             bitrate: (self.config.bitrate_kbps as i64) * 1000,
+            hdr: self.config.hdr.clone(),
+            chroma_format,
+            bit_depth,
         })
     }
 
     fn reconfigure(&mut self, config: &EncoderConfig) -> Result<()> {
+        // Bitrate can change live; other settings apply on the next init
+        if let Some(encoder) = self.encoder.as_mut() {
+            super::apply_bitrate_change(encoder, &self.config, config);
+        }
         self.config = config.clone();
         tracing::info!("Software encoder config updated: {}kbps", config.bitrate_kbps);
         Ok(())
     }
+
+    fn force_keyframe(&mut self) {
+        self.force_keyframe = true;
+    }
+
+    fn warm_up(&mut self, width: u32, height: u32) -> Result<()> {
+        if self.encoder.is_none() {
+            self.init_encoder(&super::warm_up_frame(&self.config, width, height))?;
+            self.warmed_up = Some((width, height));
+        }
+        Ok(())
+    }
 }
 
 impl Drop for SoftwareEncoder {
@@ -647,7 +775,7 @@ fn detect_amd_cpu() -> (bool, Option<String>) {
 }
 
 /// CPU information
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct CpuInfo {
     pub cores: usize,
     pub is_amd: bool,
@@ -680,4 +808,66 @@ mod tests {
         let encoder = SoftwareEncoder::new(config);
         assert!(encoder.is_ok());
     }
+
+    #[test]
+    fn test_packet_pts_match_capture_time() {
+        if !has_x264() {
+            println!("x264 not available, skipping test");
+            return;
+        }
+
+        let config = EncoderConfig::default().with_resolution(64, 64);
+        let mut encoder = SoftwareEncoder::new(config).unwrap();
+        encoder.init().unwrap();
+
+        // 60 fps with capture timestamps in µs, starting mid-session
+        let capture_pts: Vec<i64> = (0..30).map(|i| 5_000_000 + i * 16_667).collect();
+        let mut packets = Vec::new();
+        for &pts in &capture_pts {
+            let mut frame = Frame::new(64, 64, FrameFormat::Nv12);
+            frame.pts = pts;
+            packets.extend(encoder.encode(&frame).unwrap());
+        }
+        packets.extend(encoder.flush().unwrap());
+        assert_eq!(packets.len(), capture_pts.len());
+
+        // Packet PTS in the advertised time base land on the capture times
+        let params = encoder.codec_params().unwrap();
+        let mut seconds: Vec<f64> = packets
+            .iter()
+            .map(|p| p.pts as f64 * params.time_base_num as f64 / params.time_base_den as f64)
+            .collect();
+        seconds.sort_by(f64::total_cmp);
+        for (secs, pts) in seconds.iter().zip(&capture_pts) {
+            let expected = *pts as f64 / 1e6;
+            assert!((secs - expected).abs() < 0.001, "{} vs {}", secs, expected);
+        }
+    }
+
+    #[test]
+    fn test_warm_up_opens_encoder() {
+        if !has_x264() {
+            println!("x264 not available, skipping test");
+            return;
+        }
+
+        let config = EncoderConfig::default().with_resolution(64, 64);
+        let mut encoder = SoftwareEncoder::new(config).unwrap();
+        encoder.init().unwrap();
+        assert!(encoder.codec_params().is_none());
+
+        // Open before any frame; a matching first frame keeps that encoder
+        encoder.warm_up(64, 64).unwrap();
+        assert!(encoder.codec_params().is_some());
+        let frame = Frame::new(64, 64, FrameFormat::Nv12);
+        encoder.encode(&frame).unwrap();
+        assert_eq!(encoder.fit.source, (64, 64));
+
+        // A first frame of another size re-opens it
+        let mut encoder = SoftwareEncoder::new(EncoderConfig::default()).unwrap();
+        encoder.warm_up(64, 64).unwrap();
+        let frame = Frame::new(32, 32, FrameFormat::Nv12);
+        encoder.encode(&frame).unwrap();
+        assert_eq!(encoder.fit.source, (32, 32));
+    }
 }
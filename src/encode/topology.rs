@@ -0,0 +1,289 @@
+//! CPU topology for software encoder thread placement
+//!
+//! On multi-CCD Ryzen and Threadripper parts each CCD has its own L3 cache;
+//! encoder threads spread across CCDs pay cross-die latency on every shared
+//! reference frame. Cores are grouped by shared L3 (falling back to NUMA
+//! nodes) from `/sys/devices/system`. While an encoder opens and spawns its
+//! workers, the opening thread is pinned to one group so the workers inherit
+//! the mask; the thread gets its own mask back afterwards. Concurrent
+//! encoders are spread over the groups.
+
+use crate::error::{Error, Result};
+
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Encoders placed on each group, keyed by the group's first CPU
+static GROUP_USERS: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+/// Cores sharing a last-level cache (one CCD) or a NUMA node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CpuGroup {
+    pub cpus: Vec<usize>,
+    /// L3 size in KiB (0 if unknown)
+    pub l3_kb: u64,
+}
+
+/// Parse a sysfs CPU list such as `0-7,16-23`
+fn parse_cpu_list(list: &str) -> Vec<usize> {
+    let mut cpus = Vec::new();
+    for part in list.trim().split(',').filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                if let (Ok(start), Ok(end)) = (start.parse::<usize>(), end.parse::<usize>()) {
+                    cpus.extend(start..=end);
+                }
+            }
+            None => cpus.extend(part.parse::<usize>().ok()),
+        }
+    }
+    cpus
+}
+
+/// Parse a sysfs cache size such as `32768K`
+fn parse_cache_size(size: &str) -> u64 {
+    let size = size.trim();
+    match size.strip_suffix('K') {
+        Some(kb) => kb.parse().unwrap_or(0),
+        None => size
+            .strip_suffix('M')
+            .and_then(|mb| mb.parse::<u64>().ok())
+            .map_or(0, |mb| mb * 1024),
+    }
+}
+
+/// L3 groups of online CPUs, or NUMA nodes if no L3 info is exposed
+pub(crate) fn cpu_groups() -> Vec<CpuGroup> {
+    let read = |path: &Path| std::fs::read_to_string(path).ok();
+
+    let cpu_root = Path::new("/sys/devices/system/cpu");
+    let online = read(&cpu_root.join("online"))
+        .map(|list| parse_cpu_list(&list))
+        .unwrap_or_default();
+
+    let mut groups: Vec<CpuGroup> = Vec::new();
+    for cpu in &online {
+        let cache = cpu_root.join(format!("cpu{}/cache/index3", cpu));
+        let Some(shared) = read(&cache.join("shared_cpu_list")) else {
+            continue;
+        };
+        let cpus = parse_cpu_list(&shared);
+        if !groups.iter().any(|g| g.cpus == cpus) {
+            let l3_kb = read(&cache.join("size")).map_or(0, |s| parse_cache_size(&s));
+            groups.push(CpuGroup { cpus, l3_kb });
+        }
+    }
+    if !groups.is_empty() {
+        return groups;
+    }
+
+    let Ok(nodes) = std::fs::read_dir("/sys/devices/system/node") else {
+        return groups;
+    };
+    for node in nodes.flatten() {
+        let is_node = node
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.starts_with("node"));
+        if let Some(list) = read(&node.path().join("cpulist")).filter(|_| is_node) {
+            groups.push(CpuGroup {
+                cpus: parse_cpu_list(&list),
+                l3_kb: 0,
+            });
+        }
+    }
+    groups
+}
+
+/// Pick the group to encode on: fewest encoders already on it (`users`,
+/// per group), then most usable cores, then the larger L3 (the V-Cache CCD
+/// on X3D parts); returns its index and usable CPUs
+///
+/// Returns `None` on single-group systems, where pinning gains nothing.
+pub(crate) fn pick_group(
+    groups: &[CpuGroup],
+    allowed: &[usize],
+    users: &[usize],
+) -> Option<(usize, Vec<usize>)> {
+    let usable: Vec<(usize, Vec<usize>, u64)> = groups
+        .iter()
+        .enumerate()
+        .map(|(index, g)| {
+            let cpus: Vec<usize> = g
+                .cpus
+                .iter()
+                .copied()
+                .filter(|c| allowed.contains(c))
+                .collect();
+            (index, cpus, g.l3_kb)
+        })
+        .filter(|(_, cpus, _)| !cpus.is_empty())
+        .collect();
+    if usable.len() < 2 {
+        return None;
+    }
+
+    usable
+        .into_iter()
+        .max_by_key(|(index, cpus, l3_kb)| {
+            let busy = users.get(*index).copied().unwrap_or(0);
+            (
+                std::cmp::Reverse(busy),
+                cpus.len(),
+                *l3_kb,
+                std::cmp::Reverse(*index),
+            )
+        })
+        .map(|(index, cpus, _)| (index, cpus))
+}
+
+/// A CPU group claimed by one encoder; released on drop
+#[derive(Debug)]
+pub(crate) struct GroupLease {
+    key: usize,
+    pub cpus: Vec<usize>,
+}
+
+impl Drop for GroupLease {
+    fn drop(&mut self) {
+        let mut users = GROUP_USERS.lock();
+        if let Some(count) = users.get_mut(&self.key) {
+            *count -= 1;
+            if *count == 0 {
+                users.remove(&self.key);
+            }
+        }
+    }
+}
+
+/// Claim the least used group among the CPUs in `allowed`
+///
+/// `None` on single-group systems (see `pick_group`).
+pub(crate) fn claim_group(allowed: &[usize]) -> Option<GroupLease> {
+    let groups = cpu_groups();
+    let key = |group: &CpuGroup| group.cpus.first().copied().unwrap_or(0);
+
+    let mut users = GROUP_USERS.lock();
+    let counts: Vec<usize> = groups
+        .iter()
+        .map(|g| users.get(&key(g)).copied().unwrap_or(0))
+        .collect();
+    let (index, cpus) = pick_group(&groups, allowed, &counts)?;
+    let key = key(&groups[index]);
+    *users.entry(key).or_default() += 1;
+    Some(GroupLease { key, cpus })
+}
+
+/// Run `f` with the current thread pinned to `cpus`, then restore the
+/// thread's previous mask
+///
+/// Threads spawned inside `f` keep the pinned mask. If the mask can't be
+/// changed, `f` runs unpinned.
+pub(crate) fn with_affinity<T>(cpus: &[usize], f: impl FnOnce() -> T) -> T {
+    let previous = match current_affinity().and_then(|previous| {
+        pin_current_thread(cpus)?;
+        Ok(previous)
+    }) {
+        Ok(previous) => previous,
+        Err(e) => {
+            tracing::warn!("Failed to pin encoder threads: {}", e);
+            return f();
+        }
+    };
+    let result = f();
+    if let Err(e) = pin_current_thread(&previous) {
+        tracing::warn!("Failed to restore thread affinity: {}", e);
+    }
+    result
+}
+
+/// CPUs the current thread may run on
+pub(crate) fn current_affinity() -> Result<Vec<usize>> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+            return Err(Error::Internal(format!(
+                "sched_getaffinity failed: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        Ok((0..libc::CPU_SETSIZE as usize)
+            .filter(|&cpu| libc::CPU_ISSET(cpu, &set))
+            .collect())
+    }
+}
+
+/// Restrict the current thread (and threads it spawns later) to `cpus`
+pub(crate) fn pin_current_thread(cpus: &[usize]) -> Result<()> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for &cpu in cpus.iter().filter(|&&cpu| cpu < libc::CPU_SETSIZE as usize) {
+            libc::CPU_SET(cpu, &mut set);
+        }
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(Error::Internal(format!(
+                "sched_setaffinity failed: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sysfs_values() {
+        assert_eq!(parse_cpu_list("0-3,8,10-11\n"), vec![0, 1, 2, 3, 8, 10, 11]);
+        assert_eq!(parse_cpu_list(""), Vec::<usize>::new());
+        assert_eq!(parse_cache_size("32768K\n"), 32768);
+        assert_eq!(parse_cache_size("96M"), 96 * 1024);
+    }
+
+    #[test]
+    fn test_pick_group_prefers_larger_cache() {
+        // 7950X3D: CCD0 has the 96MB V-Cache, SMT siblings in the upper half
+        let groups = [
+            CpuGroup {
+                cpus: parse_cpu_list("0-7,16-23"),
+                l3_kb: 98304,
+            },
+            CpuGroup {
+                cpus: parse_cpu_list("8-15,24-31"),
+                l3_kb: 32768,
+            },
+        ];
+        let all: Vec<usize> = (0..32).collect();
+        assert_eq!(
+            pick_group(&groups, &all, &[]),
+            Some((0, groups[0].cpus.clone()))
+        );
+
+        // Most of CCD0 excluded by the affinity mask
+        let mostly_ccd1: Vec<usize> = (6..32).collect();
+        assert_eq!(
+            pick_group(&groups, &mostly_ccd1, &[]),
+            Some((1, groups[1].cpus.clone()))
+        );
+
+        // A second encoder goes to the other CCD
+        assert_eq!(
+            pick_group(&groups, &all, &[1, 0]),
+            Some((1, groups[1].cpus.clone()))
+        );
+
+        // Single CCD: nothing to gain
+        assert_eq!(pick_group(&groups[..1], &all, &[]), None);
+    }
+
+    #[test]
+    fn test_with_affinity_restores_mask() {
+        let before = current_affinity().unwrap();
+        let inside = with_affinity(&before[..1], || current_affinity().unwrap());
+        assert_eq!(inside, before[..1]);
+        assert_eq!(current_affinity().unwrap(), before);
+    }
+}
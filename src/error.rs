@@ -6,7 +6,11 @@ use thiserror::Error;
 pub type Result<T> = std::result::Result<T, Error>;
 
 /// GhostStream error type
+///
+/// New variants may be added in minor releases; match with a wildcard arm
+/// and use `is_recoverable` / `is_hardware_issue` for coarse decisions.
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum Error {
     // Capture errors
     #[error("Portal error: {0}")]
@@ -15,6 +19,12 @@ pub enum Error {
     #[error("PipeWire error: {0}")]
     PipeWire(String),
 
+    #[error("X11 capture error: {0}")]
+    X11(String),
+
+    #[error("V4L2 capture error: {0}")]
+    V4l2(String),
+
     #[error("No capture source selected")]
     NoCaptureSource,
 
@@ -37,6 +47,15 @@ pub enum Error {
     #[error("Invalid encoder configuration: {0}")]
     InvalidEncoderConfig(String),
 
+    /// The GPU couldn't allocate the encoder's buffers; a lower resolution
+    /// or frame rate may fit (see `EncoderConfig::adaptive_on_oom`)
+    #[error("Encoder out of GPU memory: {0}")]
+    EncoderOutOfMemory(String),
+
+    /// Every hardware encoder session is taken
+    #[error("Encoder session limit reached: {0}")]
+    EncoderSessionLimit(String),
+
     // Output errors
     #[error("Output initialization failed: {0}")]
     OutputInit(String),
@@ -51,6 +70,8 @@ pub enum Error {
     Streaming(String),
 
     // Pipeline errors
+    /// A `Pipeline` method that needs a running session was called while
+    /// stopped
     #[error("Pipeline not started")]
     PipelineNotStarted,
 
@@ -68,9 +89,6 @@ pub enum Error {
     ColorspaceConversion(String),
 
     // FFmpeg errors
-    #[error("FFmpeg error: {0}")]
-    FFmpeg(String),
-
     #[error("FFmpeg error: {0}")]
     Ffmpeg(String),
 
@@ -81,9 +99,18 @@ pub enum Error {
     #[error("Audio encoder error: {0}")]
     AudioEncoder(String),
 
+    #[error("Audio source not found: {requested} (available: {})", .available.join(", "))]
+    AudioSourceNotFound {
+        requested: String,
+        available: Vec<String>,
+    },
+
+    /// `next_frame` was called on a capture that is not running
     #[error("Capture not started")]
     CaptureNotStarted,
 
+    /// The capture source has no more frames (end of file, sender closed,
+    /// screen sharing stopped)
     #[error("Capture ended")]
     CaptureEnded,
 
@@ -120,10 +147,20 @@ pub enum Error {
 
 impl Error {
     /// Check if this error is recoverable
+    ///
+    /// Transient failures (a dropped connection, a timeout, a frame that
+    /// failed to encode) are worth retrying; configuration, capability and
+    /// permission errors will fail the same way again.
     pub fn is_recoverable(&self) -> bool {
         matches!(
             self,
-            Error::EncodingFailed(_) | Error::Streaming(_) | Error::Pipeline(_)
+            Error::EncodingFailed(_)
+                | Error::Streaming(_)
+                | Error::Pipeline(_)
+                | Error::Timeout(_)
+                | Error::Rtmp(_)
+                | Error::Srt(_)
+                | Error::ConnectionFailed(_)
         )
     }
 
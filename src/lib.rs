@@ -31,6 +31,7 @@
 pub mod audio;
 pub mod capture;
 pub mod config;
+pub mod decode;
 pub mod encode;
 pub mod error;
 pub mod output;
@@ -39,13 +40,19 @@ pub mod processing;
 pub mod types;
 
 // Re-exports for convenience
-pub use config::{CaptureConfig, EncoderConfig, Preset};
+pub use config::{CaptureConfig, EncoderConfig, Preset, ProcessingConfig};
 pub use encode::Codec;
 pub use error::{Error, Result};
-pub use output::{AvMuxer, Container, MuxerPacket, Output, StreamType};
-pub use pipeline::{AudioConfig, Pipeline, PipelineBuilder};
+pub use output::{
+    AvMuxer, Chapter, Container, FlushPolicy, MuxerPacket, Output, RecordingManifest, StreamType,
+    TsOptions, ValidationSink, WriteTiming,
+};
+pub use pipeline::{
+    ActivityConfig, AudioConfig, AudioTrackConfig, CursorEvent, Input, Pipeline, PipelineBuilder, PipelineEvent,
+    ValidationReport, VideoTrack,
+};
 pub use processing::{HdrConfig, Hdr10Metadata, ContentLightLevel, TransferFunction, ColorPrimaries};
-pub use types::{Frame, FrameFormat, Resolution};
+pub use types::{Frame, FrameFormat, MemoryReport, Resolution, Timecode, TimingReport};
 
 /// Library version
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
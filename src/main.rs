@@ -6,7 +6,7 @@ use clap::{Parser, Subcommand, ValueEnum};
 use ghoststream::{
     config::{EncoderConfig, Preset},
     encode::{get_info, Codec, EncoderBackend},
-    output::{Container, Output},
+    output::{Container, Output, OutputSink, RtmpOutput, SrtOutput},
     PipelineBuilder,
 };
 
@@ -50,7 +50,11 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Show system information and encoder capabilities
-    Info,
+    Info {
+        /// Print capabilities as JSON instead of the human-readable report
+        #[arg(long)]
+        json: bool,
+    },
 
     /// Start screen capture and encoding
     Capture {
@@ -96,10 +100,58 @@ enum Commands {
         /// Encoder backend (auto, nvenc, cpu)
         #[arg(short, long, value_enum, default_value = "auto")]
         encoder: Backend,
+
+        /// Compare every available backend and codec
+        #[arg(long)]
+        all: bool,
     },
 
     /// List available presets
     Presets,
+
+    /// List audio capture devices and application streams
+    AudioSources,
+
+    /// Check a capture configuration without starting it
+    Validate {
+        /// Output file path (or "camera" for virtual camera)
+        #[arg(short, long, default_value = "camera")]
+        output: String,
+
+        /// Video codec (h264, hevc, av1)
+        #[arg(short, long, default_value = "h264")]
+        codec: String,
+
+        /// Bitrate in kbps
+        #[arg(short, long, default_value = "6000")]
+        bitrate: u32,
+
+        /// Resolution (e.g., 1920x1080)
+        #[arg(short, long)]
+        resolution: Option<String>,
+
+        /// Framerate
+        #[arg(short, long, default_value = "60")]
+        fps: u32,
+
+        /// Use preset instead of manual settings
+        #[arg(short, long)]
+        preset: Option<String>,
+    },
+
+    /// Check that an RTMP/SRT destination accepts a connection
+    TestOutput {
+        /// Destination URL (rtmp://, rtmps:// or srt://)
+        url: String,
+
+        /// Video codec for the test stream (h264, hevc, av1)
+        #[arg(short, long, default_value = "h264")]
+        codec: String,
+
+        /// SRT latency in ms
+        #[arg(short, long, default_value = "200")]
+        latency: u32,
+    },
 }
 
 #[tokio::main]
@@ -115,7 +167,7 @@ async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Info => cmd_info(),
+        Commands::Info { json } => cmd_info(json),
         Commands::Capture {
             output,
             codec,
@@ -125,17 +177,46 @@ async fn main() -> anyhow::Result<()> {
             preset,
             encoder,
         } => cmd_capture(output, codec, bitrate, resolution, fps, preset, encoder).await,
-        Commands::Bench { codec, frames, encoder } => cmd_bench(codec, frames, encoder).await,
+        Commands::Bench {
+            codec,
+            frames,
+            encoder,
+            all,
+        } => {
+            if all {
+                cmd_bench_all(frames).await
+            } else {
+                cmd_bench(codec, frames, encoder).await
+            }
+        }
         Commands::Presets => cmd_presets(),
+        Commands::AudioSources => cmd_audio_sources(),
+        Commands::Validate {
+            output,
+            codec,
+            bitrate,
+            resolution,
+            fps,
+            preset,
+        } => cmd_validate(output, codec, bitrate, resolution, fps, preset).await,
+        Commands::TestOutput {
+            url,
+            codec,
+            latency,
+        } => cmd_test_output(url, codec, latency).await,
     }
 }
 
-fn cmd_info() -> anyhow::Result<()> {
+fn cmd_info(json: bool) -> anyhow::Result<()> {
+    let info = get_info();
+    if json {
+        println!("{}", info.to_json()?);
+        return Ok(());
+    }
+
     println!("GhostStream System Information");
     println!("==============================\n");
 
-    let info = get_info();
-
     // GPU / NVENC Info
     println!("=== NVIDIA NVENC ===");
     println!(
@@ -143,7 +224,12 @@ fn cmd_info() -> anyhow::Result<()> {
         if info.nvenc_available { "Yes" } else { "No" }
     );
 
-    if let Some(gpu) = &info.gpu_name {
+    if info.nvidia_gpus.len() > 1 {
+        println!("GPUs:");
+        for gpu in &info.nvidia_gpus {
+            println!("  [{}] {} ({})", gpu.index, gpu.name, gpu.pci_bus_id);
+        }
+    } else if let Some(gpu) = &info.gpu_name {
         println!("GPU: {}", gpu);
     }
 
@@ -258,6 +344,45 @@ fn cmd_info() -> anyhow::Result<()> {
     Ok(())
 }
 
+fn cmd_audio_sources() -> anyhow::Result<()> {
+    use ghoststream::audio::{self, AudioDeviceKind};
+
+    println!("Audio Sources");
+    println!("=============\n");
+
+    println!("Generic:");
+    println!("  desktop          All desktop audio (default output monitor)");
+    println!("  default-input    Default microphone");
+    println!("  default-output   Default output monitor");
+
+    let devices = audio::list_audio_devices()?;
+    for (kind, title) in [
+        (AudioDeviceKind::Input, "Input devices"),
+        (AudioDeviceKind::Output, "Output devices (monitor)"),
+        (AudioDeviceKind::Application, "Application streams"),
+    ] {
+        let matching: Vec<_> = devices.iter().filter(|d| d.kind == kind).collect();
+        if matching.is_empty() {
+            continue;
+        }
+        println!("\n{}:", title);
+        for device in matching {
+            println!(
+                "  [{:>4}] {}{}",
+                device.node_id,
+                device.name,
+                device
+                    .description
+                    .as_deref()
+                    .map(|d| format!("  ({})", d))
+                    .unwrap_or_default()
+            );
+        }
+    }
+
+    Ok(())
+}
+
 async fn cmd_capture(
     output: String,
     codec: String,
@@ -270,8 +395,42 @@ async fn cmd_capture(
     println!("Starting capture...\n");
     let _encoder_backend: EncoderBackend = backend.into();
 
-    // Parse codec
-    let codec = match codec.to_lowercase().as_str() {
+    let codec = parse_codec(&codec);
+    let Some(builder) = build_pipeline(&output, codec, bitrate, resolution, fps, preset) else {
+        return Ok(());
+    };
+
+    let pipeline = builder.build()?;
+
+    println!("Configuration:");
+    println!("  Codec: {}", codec);
+    println!("  Bitrate: {} kbps", bitrate);
+    println!("  FPS: {}", fps);
+    println!();
+
+    // Start pipeline
+    pipeline.start().await?;
+
+    println!("Capture started. Press Ctrl+C to stop.\n");
+
+    // Wait for Ctrl+C
+    tokio::signal::ctrl_c().await?;
+
+    println!("\nStopping...");
+    pipeline.stop().await?;
+
+    let stats = pipeline.stats().await;
+    println!("\nStatistics:");
+    println!("  Frames captured: {}", stats.frames_captured);
+    println!("  Frames encoded: {}", stats.frames_encoded);
+    println!("  Bytes written: {}", stats.bytes_written);
+
+    Ok(())
+}
+
+/// Parse a codec name, falling back to H.264
+fn parse_codec(codec: &str) -> Codec {
+    match codec.to_lowercase().as_str() {
         "h264" | "avc" => Codec::H264,
         "h265" | "hevc" => Codec::Hevc,
         "av1" => Codec::Av1,
@@ -279,8 +438,20 @@ async fn cmd_capture(
             eprintln!("Unknown codec: {}. Using H.264.", codec);
             Codec::H264
         }
-    };
+    }
+}
 
+/// Build a pipeline from capture-style CLI arguments
+///
+/// Returns None (after printing an error) for an unknown preset.
+fn build_pipeline(
+    output: &str,
+    codec: Codec,
+    bitrate: u32,
+    resolution: Option<String>,
+    fps: u32,
+    preset: Option<String>,
+) -> Option<PipelineBuilder> {
     // Build pipeline
     let mut builder = PipelineBuilder::new()
         .codec(codec)
@@ -289,22 +460,12 @@ async fn cmd_capture(
 
     // Apply preset if specified
     if let Some(preset_name) = preset {
-        let preset = match preset_name.to_lowercase().as_str() {
-            "discord" | "discord720p" => Preset::Discord720p,
-            "stream" | "stream1080p60" => Preset::Stream1080p60,
-            "quality" | "quality1440p60" => Preset::Quality1440p60,
-            "gaming" | "gaming1440p120" => Preset::Gaming1440p120,
-            "4k" | "ultra4k60" => Preset::Ultra4K60,
-            "max" | "maximum4k120" => Preset::Maximum4K120,
-            "lowlatency" => Preset::LowLatency,
-            "recording" => Preset::Recording,
-            _ => {
-                eprintln!(
-                    "Unknown preset: {}. Use 'ghoststream presets' to see available.",
-                    preset_name
-                );
-                return Ok(());
-            }
+        let Some(preset) = Preset::from_name(&preset_name) else {
+            eprintln!(
+                "Unknown preset: {}. Use 'ghoststream presets' to see available.",
+                preset_name
+            );
+            return None;
         };
         builder = builder.preset(preset);
     }
@@ -323,44 +484,123 @@ async fn cmd_capture(
     let output = if output == "camera" {
         Output::virtual_camera("GhostStream Camera")
     } else if output.ends_with(".mkv") {
-        Output::file(&output, Container::Matroska)
+        Output::file(output, Container::Matroska)
     } else if output.ends_with(".mp4") {
-        Output::file(&output, Container::Mp4)
+        Output::file(output, Container::Mp4)
     } else if output.ends_with(".webm") {
-        Output::file(&output, Container::WebM)
-    } else if output.starts_with("rtmp://") {
-        Output::rtmp(&output)
+        Output::file(output, Container::WebM)
+    } else if output.ends_with(".ts") {
+        Output::file(output, Container::Ts)
+    } else if matches!(output.split_once(':'), Some(("rtmp" | "rtmps", _))) {
+        // Includes typos like rtmp:/host, which validate() reports
+        Output::rtmp(output)
     } else {
-        Output::file(&output, Container::Matroska)
+        Output::file(output, Container::Matroska)
     };
+    if let Err(e) = output.validate() {
+        eprintln!("Invalid output: {}", e);
+        return None;
+    }
+
+    Some(builder.output(output))
+}
+
+async fn cmd_validate(
+    output: String,
+    codec: String,
+    bitrate: u32,
+    resolution: Option<String>,
+    fps: u32,
+    preset: Option<String>,
+) -> anyhow::Result<()> {
+    println!("Validating configuration...\n");
 
-    builder = builder.output(output);
+    let codec = parse_codec(&codec);
+    let Some(builder) = build_pipeline(&output, codec, bitrate, resolution, fps, preset) else {
+        return Ok(());
+    };
 
     let pipeline = builder.build()?;
+    let report = pipeline.validate().await?;
 
-    println!("Configuration:");
-    println!("  Codec: {}", codec);
-    println!("  Bitrate: {} kbps", bitrate);
-    println!("  FPS: {}", fps);
-    println!();
+    for issue in &report.issues {
+        println!("  {}", issue);
+    }
 
-    // Start pipeline
-    pipeline.start().await?;
+    if report.is_ok() {
+        println!("\nConfiguration OK");
+        Ok(())
+    } else {
+        anyhow::bail!("configuration has {} error(s)", report.errors().count())
+    }
+}
 
-    println!("Capture started. Press Ctrl+C to stop.\n");
+async fn cmd_test_output(url: String, codec: String, latency: u32) -> anyhow::Result<()> {
+    let codec = parse_codec(&codec);
+
+    let (mut sink, masked): (Box<dyn OutputSink>, String) = if url.starts_with("srt://") {
+        Output::srt(url.clone(), latency).validate()?;
+        let srt = SrtOutput::new(url, latency);
+        let masked = srt.url_masked();
+        (Box::new(srt), masked)
+    } else if url.starts_with("rtmp://") || url.starts_with("rtmps://") {
+        Output::rtmp(url.clone()).validate()?;
+        let rtmp = RtmpOutput::new(url).with_enhanced_rtmp(codec != Codec::H264);
+        let masked = rtmp.url_masked();
+        (Box::new(rtmp), masked)
+    } else {
+        anyhow::bail!("URL must start with rtmp://, rtmps:// or srt://");
+    };
 
-    // Wait for Ctrl+C
-    tokio::signal::ctrl_c().await?;
+    println!("Testing output: {}\n", masked);
 
-    println!("\nStopping...");
-    pipeline.stop().await?;
+    // One second of a small synthetic stream, encoded up front so the
+    // connection only has to carry the header and a few packets
+    let fps = 30;
+    let config = EncoderConfig::default()
+        .with_codec(codec)
+        .with_resolution(640, 360)
+        .with_framerate(fps)
+        .with_bitrate_kbps(500);
+    let mut encoder = ghoststream::encode::create_encoder(config)?;
+    encoder.init()?;
 
-    let stats = pipeline.stats().await;
-    println!("\nStatistics:");
-    println!("  Frames captured: {}", stats.frames_captured);
-    println!("  Frames encoded: {}", stats.frames_encoded);
-    println!("  Bytes written: {}", stats.bytes_written);
+    let mut packets = Vec::new();
+    for i in 0..fps {
+        let mut frame =
+            ghoststream::types::Frame::new(640, 360, ghoststream::types::FrameFormat::Nv12);
+        frame.pts = i as i64 * 1_000_000 / fps as i64;
+        packets.extend(encoder.encode(&frame)?);
+    }
+    packets.extend(encoder.flush()?);
+    let params = encoder.codec_params();
+
+    let start = std::time::Instant::now();
+    if let Err(e) = sink.init_with_codec(params.as_ref()).await {
+        println!("  Connect: FAILED");
+        anyhow::bail!("could not connect to {}: {}", masked, e);
+    }
+    let handshake = start.elapsed();
+    println!("  Connect: OK ({} ms)", handshake.as_millis());
+
+    for packet in &packets {
+        if let Err(e) = sink.write(packet).await {
+            println!("  Send: FAILED");
+            anyhow::bail!("server closed the connection: {}", e);
+        }
+    }
+    sink.finish().await?;
+    println!(
+        "  Send: OK ({} packets, {} bytes)",
+        packets.len(),
+        sink.bytes_written()
+    );
 
+    if masked.starts_with("srt://") {
+        println!("  SRT latency: {} ms requested", latency);
+    }
+
+    println!("\nOutput OK");
     Ok(())
 }
 
@@ -441,47 +681,147 @@ async fn cmd_bench(codec: String, frames: u32, backend: Backend) -> anyhow::Resu
     Ok(())
 }
 
+/// Result of one backend/codec run of `bench --all`
+struct BenchResult {
+    fps: f64,
+    ms_per_frame: f64,
+    bytes: u64,
+}
+
+/// Whether `get_info` reports `backend` as able to encode `codec`
+fn backend_supports(
+    info: &ghoststream::encode::EncoderInfo,
+    backend: Backend,
+    codec: Codec,
+) -> bool {
+    let pick = |h264: bool, hevc: bool, av1: bool| match codec {
+        Codec::H264 => h264,
+        Codec::Hevc => hevc,
+        Codec::Av1 => av1,
+    };
+    match backend {
+        Backend::Auto => false,
+        Backend::Nvenc => info.nvenc_codecs.contains(&codec),
+        Backend::Qsv => info.qsv.available && pick(info.qsv.h264, info.qsv.hevc, info.qsv.av1),
+        Backend::Amf => info.amf.available && pick(info.amf.h264, info.amf.hevc, info.amf.av1),
+        Backend::Cpu => pick(info.software.x264, info.software.x265, info.software.svtav1),
+    }
+}
+
+/// Encode `frames` frames cycling through `pattern` and time it
+fn bench_backend(
+    config: EncoderConfig,
+    backend: EncoderBackend,
+    pattern: &[ghoststream::Frame],
+    frames: u32,
+) -> anyhow::Result<BenchResult> {
+    // Convert up front so only encoding is timed
+    let input_format = config.input_format();
+    let pattern = pattern
+        .iter()
+        .map(|frame| ghoststream::processing::process_frame(frame, None, Some(input_format)))
+        .collect::<ghoststream::Result<Vec<_>>>()?;
+
+    let mut encoder = ghoststream::encode::create_encoder_with_backend(config, backend)?;
+    encoder.init()?;
+
+    let start = std::time::Instant::now();
+    for i in 0..frames {
+        let mut frame = pattern[i as usize % pattern.len()].clone();
+        frame.pts = i as i64 * 16667; // ~60fps
+        encoder.encode(&frame)?;
+    }
+    encoder.flush()?;
+    let elapsed = start.elapsed();
+
+    Ok(BenchResult {
+        fps: frames as f64 / elapsed.as_secs_f64(),
+        ms_per_frame: elapsed.as_secs_f64() * 1000.0 / frames as f64,
+        bytes: encoder.stats().bytes_output,
+    })
+}
+
+async fn cmd_bench_all(frames: u32) -> anyhow::Result<()> {
+    use ghoststream::capture::{Capture, TestCapture};
+    use ghoststream::types::{Framerate, Resolution};
+
+    println!("GhostStream Encoder Comparison");
+    println!("==============================\n");
+    println!("Frames: {}", frames);
+    println!("Resolution: 1920x1080");
+    println!();
+
+    // One second of moving test pattern, reused for every run
+    let mut capture =
+        TestCapture::new(Resolution::new(1920, 1080), Framerate::FPS_60, 60).with_pacing(false);
+    capture.start().await?;
+    let mut pattern = Vec::with_capacity(60);
+    while let Ok(frame) = capture.next_frame().await {
+        pattern.push(frame);
+    }
+    capture.stop().await?;
+
+    let info = get_info();
+    let frames = frames.max(1);
+    let backends = [
+        (Backend::Nvenc, "NVENC"),
+        (Backend::Amf, "AMD AMF"),
+        (Backend::Qsv, "Intel QSV"),
+        (Backend::Cpu, "Software (CPU)"),
+    ];
+
+    println!(
+        "  {:<16} {:<6} {:>8} {:>9} {:>10}  {}",
+        "Backend", "Codec", "FPS", "ms/frame", "Size", "Realtime"
+    );
+    for (backend, name) in backends {
+        for codec in [Codec::H264, Codec::Hevc, Codec::Av1] {
+            if !backend_supports(&info, backend, codec) {
+                continue;
+            }
+            let config = EncoderConfig::default()
+                .with_codec(codec)
+                .with_resolution(1920, 1080)
+                .with_bitrate_kbps(10000);
+
+            match bench_backend(config, backend.into(), &pattern, frames) {
+                Ok(result) => {
+                    let realtime = if result.fps >= 120.0 {
+                        "120fps"
+                    } else if result.fps >= 60.0 {
+                        "60fps"
+                    } else {
+                        "no"
+                    };
+                    println!(
+                        "  {:<16} {:<6} {:>8.1} {:>9.2} {:>7.1} MB  {}",
+                        name,
+                        codec.to_string(),
+                        result.fps,
+                        result.ms_per_frame,
+                        result.bytes as f64 / 1_000_000.0,
+                        realtime
+                    );
+                }
+                Err(e) => println!("  {:<16} {:<6} skipped: {}", name, codec.to_string(), e),
+            }
+        }
+    }
+
+    Ok(())
+}
+
 fn cmd_presets() -> anyhow::Result<()> {
     println!("Available Presets");
     println!("=================\n");
 
-    let presets = [
-        (
-            "discord",
-            "Discord720p",
-            "720p30, H.264, 3 Mbps, low latency",
-        ),
-        (
-            "stream",
-            "Stream1080p60",
-            "1080p60, H.264, 6 Mbps, balanced",
-        ),
-        (
-            "quality",
-            "Quality1440p60",
-            "1440p60, HEVC, 12 Mbps, high quality",
-        ),
-        (
-            "gaming",
-            "Gaming1440p120",
-            "1440p120, HEVC, 15 Mbps, low latency",
-        ),
-        ("4k", "Ultra4K60", "4K60, AV1, 25 Mbps, high quality"),
-        ("max", "Maximum4K120", "4K120, AV1, 35 Mbps, RTX 40/50"),
-        (
-            "lowlatency",
-            "LowLatency",
-            "Native res, H.264, 8 Mbps, ultra low latency",
-        ),
-        (
-            "recording",
-            "Recording",
-            "Native res, HEVC, 50 Mbps, max quality",
-        ),
-    ];
-
-    for (name, full_name, description) in presets {
-        println!("  {:<12} ({}) - {}", name, full_name, description);
+    for preset in Preset::ALL {
+        println!(
+            "  {:<12} ({:?}) - {}",
+            preset.name(),
+            preset,
+            preset.description()
+        );
     }
 
     println!("\nUsage: ghoststream capture --preset <name>");
@@ -0,0 +1,160 @@
+//! Adaptive bitrate control for streaming outputs
+//!
+//! A small AIMD-style controller driven by periodic `SrtStats` samples:
+//! on congestion (the socket blocking on a full send buffer) the bitrate
+//! is cut multiplicatively, and after several clear intervals it ramps
+//! back up slowly. FFmpeg doesn't expose libsrt's RTT and loss counters,
+//! so send-buffer back-pressure is the only congestion signal.
+
+use super::SrtStats;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Adaptive bitrate configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AbrConfig {
+    /// Lowest bitrate the controller will request (kbps)
+    pub min_bitrate_kbps: u32,
+    /// Highest bitrate the controller will request (kbps)
+    pub max_bitrate_kbps: u32,
+    /// Fraction of the interval spent blocked in writes that counts as congestion
+    pub max_blocked_ratio: f64,
+    /// Multiplier applied on congestion (e.g. 0.75 = cut by 25%)
+    pub backoff_factor: f64,
+    /// Multiplier applied when ramping up (e.g. 1.05 = +5%)
+    pub ramp_up_factor: f64,
+    /// Consecutive clear intervals required before ramping up
+    pub clear_intervals: u32,
+    /// How often stats are sampled
+    pub interval: Duration,
+}
+
+impl Default for AbrConfig {
+    fn default() -> Self {
+        Self {
+            min_bitrate_kbps: 1000,
+            max_bitrate_kbps: 8000,
+            max_blocked_ratio: 0.1,
+            backoff_factor: 0.75,
+            ramp_up_factor: 1.05,
+            clear_intervals: 3,
+            interval: Duration::from_secs(1),
+        }
+    }
+}
+
+impl AbrConfig {
+    /// Create a config bounded to the given bitrate range
+    pub fn new(min_bitrate_kbps: u32, max_bitrate_kbps: u32) -> Self {
+        Self {
+            min_bitrate_kbps: min_bitrate_kbps.min(max_bitrate_kbps),
+            max_bitrate_kbps,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+}
+
+/// Adaptive bitrate controller state
+#[derive(Debug, Clone)]
+pub struct AbrController {
+    config: AbrConfig,
+    current_kbps: u32,
+    clear_streak: u32,
+}
+
+impl AbrController {
+    /// Create a controller starting at the configured maximum
+    pub fn new(config: AbrConfig) -> Self {
+        let current_kbps = config.max_bitrate_kbps;
+        Self {
+            config,
+            current_kbps,
+            clear_streak: 0,
+        }
+    }
+
+    /// Reset the controller to a known starting bitrate
+    pub fn reset(&mut self, bitrate_kbps: u32) {
+        self.current_kbps =
+            bitrate_kbps.clamp(self.config.min_bitrate_kbps, self.config.max_bitrate_kbps);
+        self.clear_streak = 0;
+    }
+
+    /// Current target bitrate (kbps)
+    pub fn current_bitrate_kbps(&self) -> u32 {
+        self.current_kbps
+    }
+
+    pub fn config(&self) -> &AbrConfig {
+        &self.config
+    }
+
+    /// Whether a stats sample indicates congestion
+    pub fn is_congested(&self, stats: &SrtStats) -> bool {
+        let interval_ms = self.config.interval.as_secs_f64() * 1000.0;
+        interval_ms > 0.0 && stats.send_blocked_ms / interval_ms > self.config.max_blocked_ratio
+    }
+
+    /// Feed one stats sample; returns a new bitrate if it should change
+    pub fn update(&mut self, stats: &SrtStats) -> Option<u32> {
+        let previous = self.current_kbps;
+
+        if self.is_congested(stats) {
+            self.clear_streak = 0;
+            let reduced = (self.current_kbps as f64 * self.config.backoff_factor) as u32;
+            self.current_kbps = reduced.max(self.config.min_bitrate_kbps);
+        } else {
+            self.clear_streak += 1;
+            if self.clear_streak >= self.config.clear_intervals {
+                self.clear_streak = 0;
+                let raised = (self.current_kbps as f64 * self.config.ramp_up_factor).ceil() as u32;
+                self.current_kbps = raised.min(self.config.max_bitrate_kbps);
+            }
+        }
+
+        if self.current_kbps != previous {
+            tracing::info!(
+                "Adaptive bitrate: {} -> {} kbps (blocked {:.0}ms, {:.1} Mbps)",
+                previous,
+                self.current_kbps,
+                stats.send_blocked_ms,
+                stats.bandwidth_mbps
+            );
+            Some(self.current_kbps)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_abr_backoff_and_recovery() {
+        let mut abr = AbrController::new(AbrConfig::new(1000, 6000));
+        abr.reset(6000);
+
+        // Half of each 1s interval spent blocked on a full send buffer
+        let blocked = SrtStats {
+            send_blocked_ms: 500.0,
+            ..Default::default()
+        };
+        assert_eq!(abr.update(&blocked), Some(4500));
+        for _ in 0..10 {
+            abr.update(&blocked);
+        }
+        assert_eq!(abr.current_bitrate_kbps(), 1000);
+
+        let clear = SrtStats::default();
+        assert_eq!(abr.update(&clear), None);
+        assert_eq!(abr.update(&clear), None);
+        assert_eq!(abr.update(&clear), Some(1050));
+    }
+}
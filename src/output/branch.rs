@@ -0,0 +1,477 @@
+//! Isolated stream branches of a `MultiOutput`
+//!
+//! A record+stream `MultiOutput` writes its destinations one after another,
+//! so a stream that stalls or drops would hold up the recording with it.
+//! `StreamBranch` moves a stream destination (RTMP, SRT caller) onto a task
+//! of its own behind a packet buffer: writes only queue the packet, and the
+//! task sends them on. When the connection drops the task keeps
+//! reconnecting with backoff while the buffer fills; once the stream is
+//! back it re-sends from the last keyframe it had started and catches up,
+//! so viewers see a blip instead of a broken stream and the recording
+//! never notices.
+//!
+//! The buffer is bounded: during a long outage (or on a link too slow for
+//! the bitrate) whole GOPs are dropped from the front, so it always starts
+//! at a keyframe. `SendBuffer` exposes it to the pipeline's memory
+//! accounting, which can lower the cap.
+
+use crate::error::{Error, Result};
+use crate::types::{CodecParams, Packet};
+
+use super::srt::reconnect_backoff;
+use super::{create_branch_sink, Output, OutputSink, WriteTiming};
+
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+/// Most a stream branch holds while it is behind
+const MAX_BRANCH_BUFFER_BYTES: usize = 64 * 1024 * 1024;
+
+/// How long `finish` waits for a branch to send what it has buffered
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Packets of a stream branch, from the keyframe it last started
+///
+/// Packets before `sent` have gone out on the current connection and are
+/// kept only to be re-sent after a reconnect; the rest are still queued.
+#[derive(Debug)]
+struct BranchBuffer {
+    packets: VecDeque<Arc<Packet>>,
+    bytes: usize,
+    max_bytes: usize,
+    sent: usize,
+    /// Everything was dropped: skip deltas until the next keyframe
+    await_keyframe: bool,
+    /// Packets dropped unsent for lack of space
+    dropped: u64,
+}
+
+impl BranchBuffer {
+    fn new(max_bytes: usize) -> Self {
+        Self {
+            packets: VecDeque::new(),
+            bytes: 0,
+            max_bytes,
+            sent: 0,
+            await_keyframe: true,
+            dropped: 0,
+        }
+    }
+
+    /// Queue a packet, dropping the oldest GOPs when over the cap
+    fn push(&mut self, packet: Arc<Packet>) {
+        if self.await_keyframe {
+            if !packet.is_keyframe() {
+                self.dropped += 1;
+                return;
+            }
+            self.await_keyframe = false;
+        }
+        self.bytes += packet.size();
+        self.packets.push_back(packet);
+        self.trim();
+    }
+
+    /// Drop the oldest GOPs until the buffer is within its cap
+    fn trim(&mut self) {
+        while self.bytes > self.max_bytes {
+            let next_keyframe = self.packets.iter().skip(1).position(|p| p.is_keyframe());
+            match next_keyframe {
+                Some(index) => self.drop_front(index + 1),
+                None => {
+                    // A single GOP over the cap: resume at the next keyframe
+                    self.drop_front(self.packets.len());
+                    self.await_keyframe = true;
+                }
+            }
+        }
+    }
+
+    /// Remove packets from the front; the unsent ones count as dropped
+    fn drop_front(&mut self, count: usize) {
+        for packet in self.packets.drain(..count) {
+            self.bytes -= packet.size();
+        }
+        self.dropped += count.saturating_sub(self.sent) as u64;
+        self.sent = self.sent.saturating_sub(count);
+    }
+
+    /// Next packet to send, if any is queued
+    ///
+    /// Starting a keyframe retires the packets sent before it.
+    fn next(&mut self) -> Option<Arc<Packet>> {
+        let packet = self.packets.get(self.sent)?.clone();
+        if packet.is_keyframe() {
+            self.drop_front(self.sent);
+        }
+        self.sent += 1;
+        Some(packet)
+    }
+
+    /// A new connection: send again from the first buffered keyframe
+    fn rewind(&mut self) {
+        self.sent = 0;
+    }
+
+    /// Packets waiting to be sent
+    fn queued(&self) -> usize {
+        self.packets.len() - self.sent
+    }
+}
+
+/// State shared between a `StreamBranch` and its task
+#[derive(Debug)]
+struct BranchState {
+    buffer: BranchBuffer,
+    closed: bool,
+    /// Bytes sent over all connections
+    bytes_written: u64,
+    bitrate_request: Option<u32>,
+    /// Time the last send took
+    send_time: Duration,
+}
+
+/// Handle to a stream branch's packet buffer
+///
+/// Cheap to clone; all clones share the branch's buffer. Obtain them from
+/// `OutputSink::send_buffers`.
+#[derive(Clone)]
+pub struct SendBuffer {
+    state: Arc<Mutex<BranchState>>,
+}
+
+impl SendBuffer {
+    /// Memory used by buffered packets, including sent ones kept for
+    /// re-sending after a reconnect
+    pub fn buffered_bytes(&self) -> usize {
+        self.state.lock().buffer.bytes
+    }
+
+    /// Cap the memory used by buffered packets, dropping the oldest GOPs
+    /// once it is reached; None (and the most allowed) is 64 MiB
+    pub fn set_max_bytes(&self, max_bytes: Option<usize>) {
+        let mut state = self.state.lock();
+        state.buffer.max_bytes = max_bytes.map_or(MAX_BRANCH_BUFFER_BYTES, |max| {
+            max.min(MAX_BRANCH_BUFFER_BYTES)
+        });
+        state.buffer.trim();
+    }
+}
+
+/// A stream destination of a `MultiOutput`, written on its own task and
+/// reconnected behind a bounded buffer when it drops
+pub(crate) struct StreamBranch {
+    config: Output,
+    comment: Option<String>,
+    state: Arc<Mutex<BranchState>>,
+    wake: Arc<Notify>,
+    task: Option<JoinHandle<Result<()>>>,
+}
+
+impl StreamBranch {
+    pub fn new(config: Output) -> Self {
+        Self {
+            config,
+            comment: None,
+            state: Arc::new(Mutex::new(BranchState {
+                buffer: BranchBuffer::new(MAX_BRANCH_BUFFER_BYTES),
+                closed: false,
+                bytes_written: 0,
+                bitrate_request: None,
+                send_time: Duration::ZERO,
+            })),
+            wake: Arc::new(Notify::new()),
+            task: None,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl OutputSink for StreamBranch {
+    async fn init_with_codec(&mut self, codec_params: Option<&CodecParams>) -> Result<()> {
+        if self.task.is_some() {
+            return Ok(());
+        }
+
+        // Connect up front so a bad destination still fails the init; the
+        // task keeps retrying it either way
+        let mut sink = open_sink(&self.config, self.comment.as_deref())?;
+        let result = sink.init_with_codec(codec_params).await;
+        let task = BranchTask {
+            config: self.config.clone(),
+            comment: self.comment.clone(),
+            codec_params: codec_params.cloned(),
+            state: self.state.clone(),
+            wake: self.wake.clone(),
+        };
+        self.task = Some(tokio::spawn(task.run(result.is_ok().then_some(sink))));
+        result
+    }
+
+    async fn write(&mut self, packet: &Packet) -> Result<()> {
+        if self.task.is_none() {
+            // Not connected yet is handled by the task's reconnects
+            let _ = self.init_with_codec(None).await;
+        }
+
+        let packet = Arc::new(Packet {
+            data: packet.data.clone(),
+            ..*packet
+        });
+        self.state.lock().buffer.push(packet);
+        self.wake.notify_one();
+        Ok(())
+    }
+
+    async fn finish(&mut self) -> Result<()> {
+        let Some(mut task) = self.task.take() else {
+            return Ok(());
+        };
+        self.state.lock().closed = true;
+        self.wake.notify_one();
+
+        match tokio::time::timeout(DRAIN_TIMEOUT, &mut task).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(e)) => Err(Error::Internal(format!("Stream branch task failed: {}", e))),
+            Err(_) => {
+                task.abort();
+                let queued = self.state.lock().buffer.queued();
+                tracing::warn!(
+                    "Stream branch still behind after {:?}, dropping {} packets",
+                    DRAIN_TIMEOUT,
+                    queued
+                );
+                Ok(())
+            }
+        }
+    }
+
+    fn bytes_written(&self) -> u64 {
+        self.state.lock().bytes_written
+    }
+
+    fn take_bitrate_request(&mut self) -> Option<u32> {
+        self.state.lock().bitrate_request.take()
+    }
+
+    fn set_comment(&mut self, comment: &str) {
+        self.comment = Some(comment.to_string());
+    }
+
+    fn send_buffers(&self) -> Vec<SendBuffer> {
+        vec![SendBuffer {
+            state: self.state.clone(),
+        }]
+    }
+
+    fn destination_writes(&self) -> Vec<WriteTiming> {
+        let state = self.state.lock();
+        vec![WriteTiming {
+            write_time: state.send_time,
+            backlog: Some(state.buffer.queued()),
+            dropped: state.buffer.dropped,
+        }]
+    }
+}
+
+/// Create the destination's sink (not yet connected)
+fn open_sink(config: &Output, comment: Option<&str>) -> Result<Box<dyn OutputSink>> {
+    let mut sink = create_branch_sink(config.clone())
+        .ok_or_else(|| Error::OutputInit("Stream branch needs a stream output".into()))?;
+    if let Some(comment) = comment {
+        sink.set_comment(comment);
+    }
+    Ok(sink)
+}
+
+/// The task sending a branch's packets
+struct BranchTask {
+    config: Output,
+    comment: Option<String>,
+    codec_params: Option<CodecParams>,
+    state: Arc<Mutex<BranchState>>,
+    wake: Arc<Notify>,
+}
+
+impl BranchTask {
+    /// Send packets as they are queued until the branch is finished,
+    /// reconnecting whenever the connection drops
+    async fn run(self, mut sink: Option<Box<dyn OutputSink>>) -> Result<()> {
+        // Bytes of earlier connections
+        let mut bytes_before = 0;
+        loop {
+            let Some(current) = sink.as_mut() else {
+                sink = self.reconnect().await;
+                if sink.is_none() {
+                    return Ok(());
+                }
+                continue;
+            };
+
+            let next = {
+                let mut state = self.state.lock();
+                let next = state.buffer.next();
+                if next.is_none() && state.closed {
+                    break;
+                }
+                next
+            };
+            let Some(packet) = next else {
+                self.wake.notified().await;
+                continue;
+            };
+
+            let started = Instant::now();
+            match current.write(&packet).await {
+                Ok(()) => {
+                    let mut state = self.state.lock();
+                    state.send_time = started.elapsed();
+                    state.bytes_written = bytes_before + current.bytes_written();
+                    if let Some(kbps) = current.take_bitrate_request() {
+                        state.bitrate_request = Some(kbps);
+                    }
+                }
+                Err(e) => {
+                    let queued = self.state.lock().buffer.queued();
+                    tracing::warn!(
+                        "Stream branch dropped ({}), buffering {} packets while reconnecting",
+                        e,
+                        queued
+                    );
+                    bytes_before += current.bytes_written();
+                    sink = None;
+                }
+            }
+        }
+
+        match sink {
+            Some(mut sink) => sink.finish().await,
+            None => Ok(()),
+        }
+    }
+
+    /// Open a new connection with backoff and rewind the buffer to its
+    /// first keyframe; None once the branch is finished
+    async fn reconnect(&self) -> Option<Box<dyn OutputSink>> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let deadline = Instant::now() + reconnect_backoff(attempt);
+            loop {
+                if self.state.lock().closed {
+                    return None;
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep_until(deadline) => break,
+                    _ = self.wake.notified() => {}
+                }
+            }
+
+            let mut sink = match open_sink(&self.config, self.comment.as_deref()) {
+                Ok(sink) => sink,
+                Err(e) => {
+                    tracing::error!("Stream branch can't reconnect: {}", e);
+                    return None;
+                }
+            };
+            match sink.init_with_codec(self.codec_params.as_ref()).await {
+                Ok(()) => {
+                    let mut state = self.state.lock();
+                    state.buffer.rewind();
+                    tracing::info!(
+                        "Stream branch reconnected after {} attempts, sending {} buffered packets ({} dropped)",
+                        attempt,
+                        state.buffer.queued(),
+                        state.buffer.dropped
+                    );
+                    return Some(sink);
+                }
+                Err(e) => {
+                    tracing::debug!("Stream branch reconnect attempt {} failed: {}", attempt, e)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(pts: i64, keyframe: bool) -> Arc<Packet> {
+        Arc::new(Packet::new(vec![0; 10], pts, pts, keyframe))
+    }
+
+    fn pts(packet: Option<Arc<Packet>>) -> Option<i64> {
+        packet.map(|p| p.pts)
+    }
+
+    #[test]
+    fn test_branch_buffer_rewinds_to_keyframe() {
+        let mut buffer = BranchBuffer::new(1000);
+        buffer.push(packet(0, false)); // Not decodable: skipped
+        for pts in 1..=4 {
+            buffer.push(packet(pts, pts == 1 || pts == 3));
+        }
+
+        assert_eq!(pts(buffer.next()), Some(1));
+        assert_eq!(pts(buffer.next()), Some(2));
+        buffer.rewind();
+        assert_eq!(pts(buffer.next()), Some(1));
+        assert_eq!(pts(buffer.next()), Some(2));
+
+        // Sending the next keyframe retires the GOP before it
+        assert_eq!(pts(buffer.next()), Some(3));
+        buffer.rewind();
+        assert_eq!(pts(buffer.next()), Some(3));
+        assert_eq!(pts(buffer.next()), Some(4));
+        assert_eq!(buffer.next().map(|p| p.pts), None);
+        assert_eq!(buffer.dropped, 1);
+    }
+
+    #[test]
+    fn test_branch_buffer_drops_oldest_gops() {
+        let mut buffer = BranchBuffer::new(45);
+        for pts in 0..6 {
+            buffer.push(packet(pts, pts % 2 == 0));
+        }
+        // 60 bytes queued over a 45 byte cap: the first GOP went
+        assert_eq!(buffer.bytes, 40);
+        assert_eq!(buffer.dropped, 2);
+        assert_eq!(pts(buffer.next()), Some(2));
+
+        // A GOP larger than the cap can't be kept at all
+        let mut buffer = BranchBuffer::new(25);
+        for pts in 0..4 {
+            buffer.push(packet(pts, pts == 0));
+        }
+        assert_eq!(buffer.queued(), 0);
+        assert!(buffer.await_keyframe);
+        buffer.push(packet(4, true));
+        assert_eq!(pts(buffer.next()), Some(4));
+    }
+
+    #[test]
+    fn test_send_buffer_lowers_cap() {
+        let branch = StreamBranch::new(Output::srt("srt://127.0.0.1:9000", 120));
+        let buffers = branch.send_buffers();
+        for pts in 0..6 {
+            branch.state.lock().buffer.push(packet(pts, pts % 2 == 0));
+        }
+        assert_eq!(buffers[0].buffered_bytes(), 60);
+
+        // The oldest GOPs go as soon as the cap is lowered
+        buffers[0].set_max_bytes(Some(25));
+        assert_eq!(buffers[0].buffered_bytes(), 20);
+        assert_eq!(pts(branch.state.lock().buffer.next()), Some(4));
+
+        buffers[0].set_max_bytes(None);
+        let max_bytes = branch.state.lock().buffer.max_bytes;
+        assert_eq!(max_bytes, MAX_BRANCH_BUFFER_BYTES);
+    }
+}
@@ -4,11 +4,14 @@
 //! in applications like Discord, OBS, and video conferencing tools.
 //!
 //! Uses RawOutputSink trait since virtual cameras need raw video frames,
-//! not encoded packets. Use the capture -> camera pipeline directly
-//! for optimal performance (no encoding/decoding overhead).
+//! not encoded packets. A pipeline whose output is `Output::VirtualCamera`
+//! takes this path (see `create_raw_output`): processed frames go straight
+//! to the camera without encoding. Inside `Output::Multiple` the camera is
+//! still fed through `OutputSink`.
 
+use crate::capture::PipeWireConnection;
 use crate::error::{Error, Result};
-use crate::processing::convert_colorspace;
+use crate::processing::{convert_colorspace, scale_frame};
 use crate::types::{CodecParams, Frame, FrameFormat, Packet, Resolution};
 
 use super::{OutputSink, RawOutputSink};
@@ -17,13 +20,29 @@ use pipewire as pw;
 use pw::spa::param::video::VideoFormat;
 use pw::spa::pod::Pod;
 
+use parking_lot::Mutex;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::sync::mpsc;
 
+/// How frames are fitted to the camera's resolution when their size or
+/// aspect ratio differs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScalingMode {
+    /// Scale to fit inside, letterboxed/pillarboxed with black bars
+    #[default]
+    Fit,
+    /// Scale to cover the whole picture, cropping the overflow
+    Fill,
+    /// Scale to exactly the camera size, ignoring aspect ratio
+    Stretch,
+}
+
 /// Virtual camera output
 pub struct VirtualCamera {
     name: String,
+    node_name: Option<String>,
+    description: String,
     initialized: bool,
     bytes_written: Arc<AtomicU64>,
     active: Arc<AtomicBool>,
@@ -32,6 +51,12 @@ pub struct VirtualCamera {
     width: u32,
     height: u32,
     format: FrameFormat,
+    /// Resolution set with `with_resolution` (kept when frames differ)
+    fixed_resolution: bool,
+    scaling_mode: ScalingMode,
+    /// Size the consumer agreed on in `param_changed`; frames are fitted
+    /// to it once known
+    negotiated_size: Arc<Mutex<Option<Resolution>>>,
 }
 
 impl VirtualCamera {
@@ -39,6 +64,8 @@ impl VirtualCamera {
     pub fn new(name: impl Into<String>) -> Self {
         Self {
             name: name.into(),
+            node_name: None,
+            description: "GhostStream Virtual Camera".to_string(),
             initialized: false,
             bytes_written: Arc::new(AtomicU64::new(0)),
             active: Arc::new(AtomicBool::new(false)),
@@ -47,13 +74,28 @@ impl VirtualCamera {
             width: 1920,
             height: 1080,
             format: FrameFormat::Bgra,
+            fixed_resolution: false,
+            scaling_mode: ScalingMode::default(),
+            negotiated_size: Arc::new(Mutex::new(None)),
         }
     }
 
     /// Set the camera resolution
+    ///
+    /// Without this the camera takes the size of the first frame. With it,
+    /// the camera always advertises this size and frames are fitted to it
+    /// according to the scaling mode.
     pub fn with_resolution(mut self, width: u32, height: u32) -> Self {
         self.width = width;
         self.height = height;
+        self.fixed_resolution = true;
+        self
+    }
+
+    /// Set how frames of another size are fitted to the camera
+    /// (default `ScalingMode::Fit`)
+    pub fn with_scaling_mode(mut self, mode: ScalingMode) -> Self {
+        self.scaling_mode = mode;
         self
     }
 
@@ -63,16 +105,40 @@ impl VirtualCamera {
         self
     }
 
+    /// Set the description applications show for the camera
+    /// (default "GhostStream Virtual Camera")
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Set the PipeWire node name (default: the camera name)
+    pub fn with_node_name(mut self, node_name: impl Into<String>) -> Self {
+        self.node_name = Some(node_name.into());
+        self
+    }
+
     /// Start the PipeWire camera thread
     fn start_pipewire_camera(&mut self) -> Result<mpsc::Sender<Vec<u8>>> {
         let (frame_tx, frame_rx) = mpsc::channel::<Vec<u8>>();
         let active = self.active.clone();
         let name = self.name.clone();
-        let width = self.width;
-        let height = self.height;
+        let node_name = self.node_name.clone().unwrap_or_else(|| name.clone());
+        let description = self.description.clone();
+        let size = Resolution::new(self.width, self.height);
+        let negotiated_size = self.negotiated_size.clone();
+        *negotiated_size.lock() = None;
 
         let handle = std::thread::spawn(move || {
-            if let Err(e) = run_virtual_camera(name, width, height, frame_rx, active) {
+            if let Err(e) = run_virtual_camera(
+                name,
+                node_name,
+                description,
+                size,
+                frame_rx,
+                negotiated_size,
+                active,
+            ) {
                 tracing::error!("Virtual camera error: {}", e);
             }
         });
@@ -90,8 +156,10 @@ impl RawOutputSink for VirtualCamera {
             return Ok(());
         }
 
-        self.width = resolution.width;
-        self.height = resolution.height;
+        if !self.fixed_resolution {
+            self.width = resolution.width;
+            self.height = resolution.height;
+        }
         self.format = format;
 
         self.active.store(true, Ordering::SeqCst);
@@ -122,11 +190,28 @@ impl RawOutputSink for VirtualCamera {
         if let Some(ref tx) = self.frame_tx {
             // Convert to BGRA if needed (PipeWire expects BGRx/BGRA)
             let frame_data = if frame.format == FrameFormat::Bgra {
-                frame.data.clone()
+                packed_rows(frame)
             } else {
                 convert_colorspace(&frame.data, frame.format, FrameFormat::Bgra, frame.width, frame.height)?
             };
 
+            // The negotiated camera size is fixed; fit other sizes into it.
+            // Until the consumer has agreed on one, aim for the size offered.
+            let camera = self
+                .negotiated_size
+                .lock()
+                .unwrap_or(Resolution::new(self.width, self.height));
+            let frame_data = if frame.width == camera.width && frame.height == camera.height {
+                frame_data
+            } else {
+                fit_to_camera(
+                    &frame_data,
+                    Resolution::new(frame.width, frame.height),
+                    camera,
+                    self.scaling_mode,
+                )?
+            };
+
             // Send frame data to PipeWire thread
             let _ = tx.send(frame_data);
         }
@@ -158,6 +243,104 @@ impl RawOutputSink for VirtualCamera {
     }
 }
 
+/// BGRA frame data without row padding
+fn packed_rows(frame: &Frame) -> Vec<u8> {
+    let row = frame.width as usize * 4;
+    let stride = frame.stride as usize;
+    if stride <= row {
+        return frame.data.clone();
+    }
+    frame
+        .data
+        .chunks(stride)
+        .take(frame.height as usize)
+        .flat_map(|line| &line[..row.min(line.len())])
+        .copied()
+        .collect()
+}
+
+/// Source crop and destination placement `(x, y, width, height)` for
+/// fitting `src` into `dst`
+fn placement(
+    src: Resolution,
+    dst: Resolution,
+    mode: ScalingMode,
+) -> ((u32, u32, u32, u32), (u32, u32, u32, u32)) {
+    let full_src = (0, 0, src.width, src.height);
+    let full_dst = (0, 0, dst.width, dst.height);
+    // Compare aspect ratios as src.w/src.h vs dst.w/dst.h
+    let src_wider = src.width as u64 * dst.height as u64 > dst.width as u64 * src.height as u64;
+
+    match mode {
+        ScalingMode::Stretch => (full_src, full_dst),
+        ScalingMode::Fit => {
+            let (w, h) = if src_wider {
+                let h = (src.height as u64 * dst.width as u64 / src.width as u64) as u32;
+                (dst.width, h.max(1))
+            } else {
+                let w = (src.width as u64 * dst.height as u64 / src.height as u64) as u32;
+                (w.max(1), dst.height)
+            };
+            (full_src, ((dst.width - w) / 2, (dst.height - h) / 2, w, h))
+        }
+        ScalingMode::Fill => {
+            let (w, h) = if src_wider {
+                let w = (dst.width as u64 * src.height as u64 / dst.height as u64) as u32;
+                (w.max(1), src.height)
+            } else {
+                let h = (dst.height as u64 * src.width as u64 / dst.width as u64) as u32;
+                (src.width, h.max(1))
+            };
+            (((src.width - w) / 2, (src.height - h) / 2, w, h), full_dst)
+        }
+    }
+}
+
+/// Crop/scale/pad packed BGRA data to the camera resolution
+fn fit_to_camera(
+    data: &[u8],
+    src: Resolution,
+    dst: Resolution,
+    mode: ScalingMode,
+) -> Result<Vec<u8>> {
+    let ((cx, cy, cw, ch), (px, py, pw, ph)) = placement(src, dst, mode);
+
+    // Crop
+    let src_row = src.width as usize * 4;
+    let cropped: Vec<u8> = if (cw, ch) == (src.width, src.height) {
+        data.to_vec()
+    } else {
+        let start = cx as usize * 4;
+        let len = cw as usize * 4;
+        data.chunks(src_row)
+            .skip(cy as usize)
+            .take(ch as usize)
+            .flat_map(|line| &line[start..start + len])
+            .copied()
+            .collect()
+    };
+
+    let scaled = scale_frame(&cropped, cw, ch, pw, ph)?;
+    if (pw, ph) == (dst.width, dst.height) {
+        return Ok(scaled);
+    }
+
+    // Pad with opaque black bars
+    let dst_row = dst.width as usize * 4;
+    let mut out: Vec<u8> = [0, 0, 0, 255]
+        .iter()
+        .copied()
+        .cycle()
+        .take(dst_row * dst.height as usize)
+        .collect();
+    let row = pw as usize * 4;
+    for (y, line) in scaled.chunks(row).take(ph as usize).enumerate() {
+        let offset = (py as usize + y) * dst_row + px as usize * 4;
+        out[offset..offset + row].copy_from_slice(line);
+    }
+    Ok(out)
+}
+
 /// OutputSink implementation for backward compatibility
 ///
 /// NOTE: Prefer using RawOutputSink with write_frame() for virtual cameras.
@@ -225,14 +408,90 @@ impl Drop for VirtualCamera {
 // PipeWire Virtual Camera Implementation
 // ============================================================================
 
+/// Frame layout agreed with the consumer in `param_changed`
+#[derive(Debug, Clone, Copy)]
+struct NegotiatedFormat {
+    width: u32,
+    height: u32,
+    stride: u32,
+    /// RGBx/RGBA: swap red and blue from the BGRA frames we get
+    swap_rb: bool,
+}
+
+impl NegotiatedFormat {
+    fn new(format: VideoFormat, width: u32, height: u32) -> Self {
+        // Every format offered is 4 bytes per pixel
+        Self {
+            width,
+            height,
+            stride: width * 4,
+            swap_rb: matches!(format, VideoFormat::RGBx | VideoFormat::RGBA),
+        }
+    }
+
+    fn size(&self) -> usize {
+        (self.stride * self.height) as usize
+    }
+
+    /// Copy a packed BGRA frame into a buffer of this layout
+    fn write(&self, frame: &[u8], dst: &mut [u8]) {
+        if self.swap_rb {
+            for (out, px) in dst.chunks_exact_mut(4).zip(frame.chunks_exact(4)) {
+                out.copy_from_slice(&[px[2], px[1], px[0], px[3]]);
+            }
+        } else {
+            dst.copy_from_slice(frame);
+        }
+    }
+}
+
+/// SPA_PARAM_Buffers for one plane of `size` bytes with `stride`
+fn buffers_param(stride: u32, size: usize) -> Result<Vec<u8>> {
+    use pw::spa::pod::{ChoiceValue, Property, Value};
+    use pw::spa::sys as spa_sys;
+    use pw::spa::utils::{Choice, ChoiceEnum, ChoiceFlags};
+
+    let obj = pw::spa::pod::object!(
+        pw::spa::utils::SpaTypes::ObjectParamBuffers,
+        pw::spa::param::ParamType::Buffers,
+        Property::new(
+            spa_sys::SPA_PARAM_BUFFERS_buffers,
+            Value::Choice(ChoiceValue::Int(Choice(
+                ChoiceFlags::empty(),
+                ChoiceEnum::Range {
+                    default: 4,
+                    min: 2,
+                    max: 8,
+                },
+            ))),
+        ),
+        Property::new(spa_sys::SPA_PARAM_BUFFERS_blocks, Value::Int(1)),
+        Property::new(spa_sys::SPA_PARAM_BUFFERS_size, Value::Int(size as i32)),
+        Property::new(spa_sys::SPA_PARAM_BUFFERS_stride, Value::Int(stride as i32)),
+    );
+
+    let values: Vec<u8> = pw::spa::pod::serialize::PodSerializer::serialize(
+        std::io::Cursor::new(Vec::new()),
+        &pw::spa::pod::Value::Object(obj),
+    )
+    .map_err(|e| Error::PipeWire(format!("Failed to serialize buffer params: {:?}", e)))?
+    .0
+    .into_inner();
+
+    Ok(values)
+}
+
 /// Run PipeWire virtual camera output
 fn run_virtual_camera(
     name: String,
-    width: u32,
-    height: u32,
+    node_name: String,
+    description: String,
+    size: Resolution,
     frame_rx: mpsc::Receiver<Vec<u8>>,
+    negotiated_size: Arc<Mutex<Option<Resolution>>>,
     active: Arc<AtomicBool>,
 ) -> Result<()> {
+    let Resolution { width, height } = size;
     tracing::info!(
         "Starting PipeWire virtual camera '{}' ({}x{})",
         name,
@@ -240,18 +499,8 @@ fn run_virtual_camera(
         height
     );
 
-    // Initialize PipeWire
-    pw::init();
-
-    let mainloop = pw::main_loop::MainLoop::new(None)
-        .map_err(|e| Error::PipeWire(format!("Failed to create main loop: {:?}", e)))?;
-
-    let context = pw::context::Context::new(&mainloop)
-        .map_err(|e| Error::PipeWire(format!("Failed to create context: {:?}", e)))?;
-
-    let core = context
-        .connect(None)
-        .map_err(|e| Error::PipeWire(format!("Failed to connect to PipeWire: {:?}", e)))?;
+    let pipewire = PipeWireConnection::new()?;
+    let (mainloop, core) = (&pipewire.mainloop, &pipewire.core);
 
     // Create stream as a video source (camera)
     let stream = pw::stream::Stream::new(
@@ -262,8 +511,8 @@ fn run_virtual_camera(
             *pw::keys::MEDIA_CATEGORY => "Capture",
             *pw::keys::MEDIA_CLASS => "Video/Source",
             *pw::keys::MEDIA_ROLE => "Camera",
-            *pw::keys::NODE_NAME => name.as_str(),
-            *pw::keys::NODE_DESCRIPTION => "GhostStream Virtual Camera",
+            *pw::keys::NODE_NAME => node_name.as_str(),
+            *pw::keys::NODE_DESCRIPTION => description.as_str(),
         },
     )
     .map_err(|e| Error::PipeWire(format!("Failed to create stream: {:?}", e)))?;
@@ -271,16 +520,25 @@ fn run_virtual_camera(
     // State for callbacks
     struct CameraState {
         frame_rx: mpsc::Receiver<Vec<u8>>,
-        frame_size: usize,
-        stride: u32,
+        /// Size offered in EnumFormat
+        width: u32,
+        height: u32,
+        /// Shared with the writer, which fits frames to this size
+        negotiated_size: Arc<Mutex<Option<Resolution>>>,
+        format: pw::spa::param::video::VideoInfoRaw,
+        /// Set once the consumer has agreed on a format
+        negotiated: Option<NegotiatedFormat>,
+        mismatch_logged: bool,
     }
 
-    let stride = width * 4; // BGRx = 4 bytes per pixel
-    let frame_size = (height * stride) as usize;
     let state = CameraState {
         frame_rx,
-        frame_size,
-        stride,
+        width,
+        height,
+        negotiated_size,
+        format: pw::spa::param::video::VideoInfoRaw::new(),
+        negotiated: None,
+        mismatch_logged: false,
     };
 
     let active_clone = active.clone();
@@ -298,7 +556,58 @@ fn run_virtual_camera(
                 }
             }
         })
+        .param_changed(|stream, state, id, param| {
+            let Some(param) = param else { return };
+            if id != pw::spa::param::ParamType::Format.as_raw() {
+                return;
+            }
+            if let Err(e) = state.format.parse(param) {
+                tracing::warn!("Failed to parse camera format: {:?}", e);
+                return;
+            }
+
+            let size = state.format.size();
+            let negotiated = NegotiatedFormat::new(state.format.format(), size.width, size.height);
+            tracing::info!(
+                "Camera format negotiated: {:?} {}x{} (stride {})",
+                state.format.format(),
+                negotiated.width,
+                negotiated.height,
+                negotiated.stride
+            );
+            if (negotiated.width, negotiated.height) != (state.width, state.height) {
+                tracing::info!(
+                    "Camera negotiated {}x{} instead of {}x{}; frames will be scaled",
+                    negotiated.width,
+                    negotiated.height,
+                    state.width,
+                    state.height
+                );
+            }
+
+            // Tell PipeWire the buffer layout we write
+            match buffers_param(negotiated.stride, negotiated.size()) {
+                Ok(bytes) => {
+                    if let Some(pod) = Pod::from_bytes(&bytes) {
+                        if let Err(e) = stream.update_params(&mut [pod]) {
+                            tracing::warn!("Failed to set camera buffer params: {:?}", e);
+                        }
+                    }
+                }
+                Err(e) => tracing::warn!("{}", e),
+            }
+
+            *state.negotiated_size.lock() =
+                Some(Resolution::new(negotiated.width, negotiated.height));
+            state.negotiated = Some(negotiated);
+            state.mismatch_logged = false;
+        })
         .process(|stream, state| {
+            // Nothing can be written before the format is known
+            let Some(negotiated) = state.negotiated else {
+                return;
+            };
+
             // Get buffer to fill
             let Some(mut buffer) = stream.dequeue_buffer() else {
                 return;
@@ -310,41 +619,45 @@ fn run_virtual_camera(
             }
 
             let data = &mut datas[0];
-
-            // Try to get next frame from channel
-            if let Ok(frame_data) = state.frame_rx.try_recv() {
-                // Write frame data to buffer
-                if let Some(slice) = data.data() {
-                    let copy_size = frame_data.len().min(slice.len()).min(state.frame_size);
-
-                    // Safety: we're writing to the PipeWire buffer
-                    unsafe {
-                        let dst = slice.as_ptr() as *mut u8;
-                        std::ptr::copy_nonoverlapping(frame_data.as_ptr(), dst, copy_size);
-                    }
-
-                    // Set chunk metadata
-                    let chunk = data.chunk_mut();
-                    *chunk.offset_mut() = 0;
-                    *chunk.stride_mut() = state.stride as i32;
-                    *chunk.size_mut() = state.frame_size as u32;
+            let frame_size = negotiated.size();
+
+            // Only frames matching the negotiated layout can be copied;
+            // anything else would come out skewed
+            let frame_data = state.frame_rx.try_recv().ok().filter(|frame| {
+                let expected = (negotiated.width * negotiated.height * 4) as usize;
+                let matches = frame.len() == expected;
+                if !matches && !state.mismatch_logged {
+                    tracing::warn!(
+                        "Dropping camera frame of {} bytes (negotiated {}x{} needs {})",
+                        frame.len(),
+                        negotiated.width,
+                        negotiated.height,
+                        expected
+                    );
+                    state.mismatch_logged = true;
                 }
-            } else {
-                // No frame available, output black frame
-                if let Some(slice) = data.data() {
-                    let clear_size = slice.len().min(state.frame_size);
+                matches
+            });
 
-                    unsafe {
-                        let dst = slice.as_ptr() as *mut u8;
-                        std::ptr::write_bytes(dst, 0, clear_size);
-                    }
+            let Some(slice) = data.data() else {
+                return;
+            };
+            if slice.len() < frame_size {
+                return;
+            }
+            let dst = &mut slice[..frame_size];
 
-                    let chunk = data.chunk_mut();
-                    *chunk.offset_mut() = 0;
-                    *chunk.stride_mut() = state.stride as i32;
-                    *chunk.size_mut() = state.frame_size as u32;
-                }
+            match frame_data {
+                Some(frame) => negotiated.write(&frame, dst),
+                // No frame available, output black frame
+                None => dst.fill(0),
             }
+
+            // Set chunk metadata
+            let chunk = data.chunk_mut();
+            *chunk.offset_mut() = 0;
+            *chunk.stride_mut() = negotiated.stride as i32;
+            *chunk.size_mut() = frame_size as u32;
         })
         .register()
         .map_err(|e| Error::PipeWire(format!("Failed to register listener: {:?}", e)))?;
@@ -418,3 +731,22 @@ fn run_virtual_camera(
     tracing::info!("Virtual camera stopped");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scaling_placement() {
+        let src = Resolution::new(1920, 1080);
+        let dst = Resolution::new(1280, 960);
+
+        // 16:9 into 4:3: letterboxed, or cropped at the sides
+        let fit = placement(src, dst, ScalingMode::Fit);
+        assert_eq!(fit, ((0, 0, 1920, 1080), (0, 120, 1280, 720)));
+        let fill = placement(src, dst, ScalingMode::Fill);
+        assert_eq!(fill, ((240, 0, 1440, 1080), (0, 0, 1280, 960)));
+        let stretch = placement(src, dst, ScalingMode::Stretch);
+        assert_eq!(stretch, ((0, 0, 1920, 1080), (0, 0, 1280, 960)));
+    }
+}
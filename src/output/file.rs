@@ -1,14 +1,21 @@
 //! File output (recording)
 //!
 //! Writes encoded video to MKV, MP4, WebM, or TS files using FFmpeg muxer.
+//! TS files repeat their PAT/PMT tables (see `TsOptions`) so they stay
+//! seekable; the pipeline makes the encoder repeat parameter sets in-band
+//! (Annex-B) for them as well.
 
 use crate::encode::Codec;
 use crate::error::{Error, Result};
-use crate::types::{CodecParams, Packet};
+use crate::types::{CodecParams, Packet, Timecode};
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicU64, Ordering};
 
-use super::{Container, OutputSink};
+use super::{
+    set_stream_hdr, write_chapters, write_header_with_options, AvioFlusher, Chapter, Container,
+    FlushPolicy, OutputSink, TsOptions,
+};
 
 use ffmpeg_next as ffmpeg;
 use ffmpeg_next::codec::Id as CodecId;
@@ -24,6 +31,13 @@ pub struct FileOutput {
     stream_index: usize,
     time_base: ffmpeg::Rational,
     frame_count: u64,
+    flusher: AvioFlusher,
+    ts_options: TsOptions,
+    comment: Option<String>,
+    extra_options: HashMap<String, String>,
+    create_dirs: bool,
+    timecode: Option<Timecode>,
+    chapters: Vec<Chapter>,
 }
 
 impl FileOutput {
@@ -38,9 +52,55 @@ impl FileOutput {
             stream_index: 0,
             time_base: ffmpeg::Rational::new(1, 1000),
             frame_count: 0,
+            flusher: AvioFlusher::new(FlushPolicy::RECORDING),
+            ts_options: TsOptions::default(),
+            comment: None,
+            extra_options: HashMap::new(),
+            create_dirs: true,
+            timecode: None,
+            chapters: Vec::new(),
         }
     }
 
+    /// Set how writes are batched (default `FlushPolicy::RECORDING`)
+    pub fn with_flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.flusher = AvioFlusher::new(policy);
+        self
+    }
+
+    /// Set MPEG-TS muxer settings (only used with `Container::Ts`)
+    pub fn with_ts_options(mut self, options: TsOptions) -> Self {
+        self.ts_options = options;
+        self
+    }
+
+    /// Raw FFmpeg muxer options applied after the library's own (e.g.
+    /// `movflags=+faststart` for MP4)
+    pub fn with_extra_options(mut self, options: HashMap<String, String>) -> Self {
+        self.extra_options = options;
+        self
+    }
+
+    /// Create a missing parent directory when opening the file (default
+    /// on); otherwise opening fails with `Error::OutputInit`
+    pub fn with_create_dirs(mut self, enabled: bool) -> Self {
+        self.create_dirs = enabled;
+        self
+    }
+
+    /// Write `comment` into the container's `comment` metadata tag
+    pub fn with_comment(mut self, comment: impl Into<String>) -> Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    /// Tag the video track with SMPTE timecode `start` for its first frame
+    /// (a `tmcd` track in MP4/MOV, a TIMECODE tag in Matroska)
+    pub fn with_timecode(mut self, start: Timecode) -> Self {
+        self.timecode = Some(start);
+        self
+    }
+
     /// Get the output path
     pub fn path(&self) -> &PathBuf {
         &self.path
@@ -58,15 +118,10 @@ impl FileOutput {
     /// Initialize the muxer with codec parameters
     fn init_muxer(&mut self, codec_params: &CodecParams) -> Result<()> {
         // Initialize FFmpeg
-        ffmpeg::init().map_err(|e| Error::FFmpeg(e.to_string()))?;
+        ffmpeg::init().map_err(|e| Error::Ffmpeg(e.to_string()))?;
 
         // Ensure parent directory exists
-        if let Some(parent) = self.path.parent() {
-            if !parent.exists() {
-                std::fs::create_dir_all(parent)
-                    .map_err(|e| Error::FileOutput(format!("Failed to create directory: {}", e)))?;
-            }
-        }
+        super::prepare_output_dir(&self.path, self.create_dirs)?;
 
         // Create output context with format hint
         let mut output_ctx = ffmpeg::format::output_as(
@@ -132,6 +187,8 @@ impl FileOutput {
                     (*codec_ctx).extradata_size = extradata_size as i32;
                 }
             }
+
+            set_stream_hdr(codec_ctx, codec_params);
         }
 
         // Set time base
@@ -145,8 +202,20 @@ impl FileOutput {
         let fps = codec_params.framerate.num as i32;
         stream.set_rate(ffmpeg::Rational::new(fps, 1));
 
+        if let Some(timecode) = self.timecode {
+            super::set_stream_timecode(&mut stream, timecode, codec_params.framerate);
+        }
+
+        if let Some(ref comment) = self.comment {
+            let mut metadata = ffmpeg::Dictionary::new();
+            metadata.set("comment", comment);
+            output_ctx.set_metadata(metadata);
+        }
+
         // Write header
-        output_ctx.write_header()
+        self.flusher.configure(&mut output_ctx);
+        let options = self.container.muxer_options(&self.ts_options);
+        write_header_with_options(&mut output_ctx, options, &self.extra_options)
             .map_err(|e| Error::FileOutput(format!("Failed to write header: {}", e)))?;
 
         self.output_ctx = Some(output_ctx);
@@ -204,7 +273,7 @@ impl OutputSink for FileOutput {
         pkt.set_duration(packet.duration);
         pkt.set_stream(self.stream_index);
 
-        if packet.is_keyframe {
+        if packet.is_keyframe() {
             pkt.set_flags(ffmpeg::codec::packet::Flags::KEY);
         }
 
@@ -217,6 +286,7 @@ impl OutputSink for FileOutput {
         // Write packet (interleaved for proper ordering)
         pkt.write_interleaved(output_ctx)
             .map_err(|e| Error::FileOutput(format!("Failed to write packet: {}", e)))?;
+        self.flusher.written(output_ctx, packet.size());
 
         self.frame_count += 1;
         self.bytes_written.fetch_add(packet.size() as u64, Ordering::Relaxed);
@@ -230,6 +300,7 @@ impl OutputSink for FileOutput {
         }
 
         if let Some(ref mut output_ctx) = self.output_ctx {
+            write_chapters(output_ctx, &self.chapters);
             // Write trailer
             output_ctx.write_trailer()
                 .map_err(|e| Error::FileOutput(format!("Failed to write trailer: {}", e)))?;
@@ -252,6 +323,14 @@ impl OutputSink for FileOutput {
     fn bytes_written(&self) -> u64 {
         self.bytes_written.load(Ordering::Relaxed)
     }
+
+    fn set_comment(&mut self, comment: &str) {
+        self.comment = Some(comment.to_string());
+    }
+
+    fn set_chapters(&mut self, chapters: &[Chapter]) {
+        self.chapters = chapters.to_vec();
+    }
 }
 
 impl Drop for FileOutput {
@@ -268,6 +347,7 @@ impl Drop for FileOutput {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Duration;
 
     #[test]
     fn test_container_extensions() {
@@ -284,4 +364,14 @@ mod tests {
         assert_eq!(Container::WebM.ffmpeg_format(), "webm");
         assert_eq!(Container::Ts.ffmpeg_format(), "mpegts");
     }
+
+    #[test]
+    fn test_ts_muxer_options() {
+        let options = TsOptions::default()
+            .with_pmt_period(Duration::from_millis(250))
+            .muxer_options();
+        assert_eq!(options.get("pat_period"), Some("0.25"));
+        assert_eq!(options.get("pcr_period"), Some("20"));
+        assert_eq!(options.get("mpegts_flags"), Some("+pat_pmt_at_frames"));
+    }
 }
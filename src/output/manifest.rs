@@ -0,0 +1,235 @@
+//! Recording manifest (sidecar JSON)
+//!
+//! With `Output::File { write_manifest: true, .. }` the pipeline writes a
+//! JSON description of the recording next to the media once it is
+//! finalized: codecs and formats, duration, keyframe times, the files a
+//! split recording consists of and the session's final counters. Editing
+//! tools can build a seek index or a cut list from it without parsing the
+//! container.
+//!
+//! Times are in seconds on the recording's own timeline, which starts at
+//! the first video packet and runs across split files.
+
+use crate::audio::AudioParams;
+use crate::error::{Error, Result};
+use crate::types::{CodecParams, Packet, Stats};
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Contents of a recording's manifest file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordingManifest {
+    /// Primary video stream (None if encoding never started)
+    pub video: Option<ManifestVideo>,
+    /// Audio tracks in stream order, the primary audio first
+    pub audio_tracks: Vec<ManifestAudio>,
+    /// Recorded duration in seconds
+    pub duration_secs: f64,
+    /// Start time of every video keyframe
+    pub keyframes: Vec<f64>,
+    /// Files of the recording in order; more than one after splits
+    pub segments: Vec<ManifestSegment>,
+    pub stats: ManifestStats,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestVideo {
+    pub codec: String,
+    pub width: u32,
+    pub height: u32,
+    pub fps: f64,
+    /// Target bitrate in bits/sec
+    pub bitrate: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestAudio {
+    pub codec: String,
+    pub sample_rate: u32,
+    pub channels: u32,
+    /// Bitrate in bits/sec
+    pub bitrate: u32,
+}
+
+/// One file of the recording
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestSegment {
+    pub path: PathBuf,
+    pub start_secs: f64,
+    pub end_secs: f64,
+}
+
+/// Final session counters (see `Stats`)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ManifestStats {
+    pub frames_captured: u64,
+    pub frames_encoded: u64,
+    pub frames_dropped: u64,
+    pub bytes_written: u64,
+}
+
+impl RecordingManifest {
+    /// Manifest path for a recording: `match.mkv` gets `match.json`
+    pub fn path_for(media: &Path) -> PathBuf {
+        media.with_extension("json")
+    }
+
+    /// Read a manifest written by the pipeline
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let data = std::fs::read(path)?;
+        serde_json::from_slice(&data)
+            .map_err(|e| Error::Config(format!("Invalid recording manifest: {}", e)))
+    }
+
+    /// Write the manifest as pretty-printed JSON
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let data = serde_json::to_vec_pretty(self)
+            .map_err(|e| Error::Internal(format!("Failed to serialize manifest: {}", e)))?;
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+}
+
+/// Collects a manifest while the pipeline writes the primary video
+pub(crate) struct ManifestRecorder {
+    video: Option<ManifestVideo>,
+    audio_tracks: Vec<ManifestAudio>,
+    /// Primary video time base (num, den)
+    time_base: (i64, i64),
+    /// PTS of the first packet, the timeline's zero
+    first_pts: Option<i64>,
+    /// End of the last packet (PTS plus duration)
+    end_pts: i64,
+    keyframes: Vec<i64>,
+    /// Files so far with their start PTS
+    segments: Vec<(PathBuf, i64)>,
+}
+
+impl ManifestRecorder {
+    pub fn new<'a>(
+        path: &Path,
+        video: Option<&CodecParams>,
+        audio: impl IntoIterator<Item = &'a AudioParams>,
+    ) -> Self {
+        Self {
+            video: video.map(|params| ManifestVideo {
+                codec: params.codec.to_string(),
+                width: params.resolution.width,
+                height: params.resolution.height,
+                fps: params.framerate.as_f64(),
+                bitrate: params.bitrate,
+            }),
+            audio_tracks: audio
+                .into_iter()
+                .map(|params| ManifestAudio {
+                    codec: format!("{:?}", params.codec),
+                    sample_rate: params.sample_rate,
+                    channels: params.channels,
+                    bitrate: params.bitrate,
+                })
+                .collect(),
+            time_base: video.map_or((1, 1_000_000), |params| {
+                (
+                    params.time_base_num as i64,
+                    params.time_base_den.max(1) as i64,
+                )
+            }),
+            first_pts: None,
+            end_pts: 0,
+            keyframes: Vec::new(),
+            segments: vec![(path.to_path_buf(), 0)],
+        }
+    }
+
+    /// A primary video packet written to the current file
+    pub fn packet(&mut self, packet: &Packet) {
+        let first = *self.first_pts.get_or_insert(packet.pts);
+        let pts = packet.pts - first;
+        if packet.is_keyframe() {
+            self.keyframes.push(pts);
+        }
+        self.end_pts = self.end_pts.max(pts + packet.duration.max(0));
+    }
+
+    /// The recording continues in `path`, starting with the packet `pts`
+    pub fn split(&mut self, pts: i64, path: &Path) {
+        let start = pts - self.first_pts.unwrap_or(pts);
+        self.segments.push((path.to_path_buf(), start));
+    }
+
+    pub fn finish(self, stats: &Stats) -> RecordingManifest {
+        let secs = |pts: i64| (pts * self.time_base.0) as f64 / self.time_base.1 as f64;
+        let ends = self
+            .segments
+            .iter()
+            .skip(1)
+            .map(|(_, start)| *start)
+            .chain(std::iter::once(self.end_pts));
+        let segments = self
+            .segments
+            .iter()
+            .zip(ends)
+            .map(|((path, start), end)| ManifestSegment {
+                path: path.clone(),
+                start_secs: secs(*start),
+                end_secs: secs(end),
+            })
+            .collect();
+
+        RecordingManifest {
+            video: self.video,
+            audio_tracks: self.audio_tracks,
+            duration_secs: secs(self.end_pts),
+            keyframes: self.keyframes.iter().map(|&pts| secs(pts)).collect(),
+            segments,
+            stats: ManifestStats {
+                frames_captured: stats.frames_captured,
+                frames_encoded: stats.frames_encoded,
+                frames_dropped: stats.frames_dropped,
+                bytes_written: stats.bytes_written,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(pts: i64, keyframe: bool) -> Packet {
+        let mut packet = Packet::new(vec![0; 16], pts, pts, keyframe);
+        packet.duration = 10;
+        packet
+    }
+
+    #[test]
+    fn test_manifest_segments_and_keyframes() {
+        let params = CodecParams::default(); // 1/1000 time base
+        let mut recorder = ManifestRecorder::new(Path::new("rec.mkv"), Some(&params), []);
+
+        for pts in (1000..1100).step_by(10) {
+            recorder.packet(&packet(pts, pts % 50 == 0));
+        }
+        recorder.split(1100, Path::new("rec-001.mkv"));
+        for pts in (1100..1200).step_by(10) {
+            recorder.packet(&packet(pts, pts % 50 == 0));
+        }
+
+        let manifest = recorder.finish(&Stats::default());
+        assert_eq!(manifest.keyframes, [0.0, 0.05, 0.1, 0.15]);
+        assert!((manifest.duration_secs - 0.2).abs() < 1e-9);
+        assert_eq!(manifest.segments.len(), 2);
+        assert_eq!(
+            manifest.segments[0].end_secs,
+            manifest.segments[1].start_secs
+        );
+        assert_eq!(manifest.segments[1].path, PathBuf::from("rec-001.mkv"));
+
+        let json = serde_json::to_string(&manifest).unwrap();
+        assert_eq!(
+            serde_json::from_str::<RecordingManifest>(&json).unwrap(),
+            manifest
+        );
+    }
+}
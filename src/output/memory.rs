@@ -0,0 +1,98 @@
+//! In-memory output
+//!
+//! Collects encoded packets instead of writing them anywhere, so a full
+//! pipeline run can be inspected from a test without files, network or a
+//! virtual camera. Pair with `TestCapture` for runs that need no hardware.
+
+use crate::error::Result;
+use crate::types::{CodecParams, Packet};
+
+use super::OutputSink;
+
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    codec_params: Option<CodecParams>,
+    packets: Vec<Packet>,
+    bytes: u64,
+    initialized: bool,
+    finished: bool,
+}
+
+/// Output that keeps every packet in memory
+///
+/// Clones share the same storage: hand one to the pipeline with
+/// `Output::memory` and read the packets from another once the pipeline
+/// has stopped.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryOutput {
+    state: Arc<Mutex<MemoryState>>,
+}
+
+impl MemoryOutput {
+    /// Create an empty output
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Codec parameters the output was initialized with
+    pub fn codec_params(&self) -> Option<CodecParams> {
+        self.state.lock().codec_params.clone()
+    }
+
+    /// Number of packets collected so far
+    pub fn packet_count(&self) -> usize {
+        self.state.lock().packets.len()
+    }
+
+    /// Total size of the collected packets
+    pub fn bytes(&self) -> u64 {
+        self.state.lock().bytes
+    }
+
+    /// Whether the pipeline initialized the output
+    pub fn is_initialized(&self) -> bool {
+        self.state.lock().initialized
+    }
+
+    /// Whether the pipeline finished (flushed) the output
+    pub fn is_finished(&self) -> bool {
+        self.state.lock().finished
+    }
+
+    /// Take the collected packets, leaving the output empty
+    pub fn take_packets(&self) -> Vec<Packet> {
+        std::mem::take(&mut self.state.lock().packets)
+    }
+}
+
+#[async_trait::async_trait]
+impl OutputSink for MemoryOutput {
+    async fn init_with_codec(&mut self, codec_params: Option<&CodecParams>) -> Result<()> {
+        let mut state = self.state.lock();
+        state.codec_params = codec_params.cloned();
+        state.initialized = true;
+        Ok(())
+    }
+
+    async fn write(&mut self, packet: &Packet) -> Result<()> {
+        let mut state = self.state.lock();
+        state.bytes += packet.size() as u64;
+        state.packets.push(Packet {
+            data: packet.data.clone(),
+            ..*packet
+        });
+        Ok(())
+    }
+
+    async fn finish(&mut self) -> Result<()> {
+        self.state.lock().finished = true;
+        Ok(())
+    }
+
+    fn bytes_written(&self) -> u64 {
+        self.state.lock().bytes
+    }
+}
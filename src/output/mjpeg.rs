@@ -0,0 +1,312 @@
+//! MJPEG-over-HTTP live preview
+//!
+//! `MjpegServer` serves the capture as `multipart/x-mixed-replace` JPEG
+//! frames, which browsers show as a live image, for keeping an eye on a
+//! headless capture box. The pipeline hands it captured frames before
+//! encoding (see `Output::MjpegHttp`); they are downscaled and JPEG-encoded
+//! on a thread of their own at the preview rate, and only while someone is
+//! watching. A busy preview drops frames rather than holding up capture,
+//! and slow viewers skip to the newest frame.
+
+use crate::error::{Error, Result};
+use crate::processing::convert_colorspace;
+use crate::types::{Frame, FrameFormat};
+
+use ffmpeg_next as ffmpeg;
+use ffmpeg_next::format::Pixel;
+use ffmpeg_next::software::scaling::{Context as Scaler, Flags as ScalerFlags};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+
+/// Preview frames are scaled down to at most this width
+const PREVIEW_WIDTH: u32 = 640;
+
+const BOUNDARY: &str = "ghoststream-frame";
+
+type LatestJpeg = Option<Arc<Vec<u8>>>;
+
+/// HTTP server streaming a JPEG preview of captured frames
+///
+/// Dropping it stops the server and disconnects viewers.
+pub struct MjpegServer {
+    local_addr: SocketAddr,
+    /// Minimum PTS distance between preview frames (µs)
+    interval_us: i64,
+    /// PTS of the last frame taken
+    last_pts: AtomicI64,
+    frame_tx: crossbeam_channel::Sender<Frame>,
+    viewers: Arc<AtomicUsize>,
+    server: tokio::task::JoinHandle<()>,
+}
+
+impl MjpegServer {
+    /// Listen on `addr`, serving up to `fps` frames per second
+    pub async fn bind(addr: SocketAddr, fps: u32) -> Result<Self> {
+        let listener = TcpListener::bind(addr).await.map_err(|e| {
+            Error::OutputInit(format!("Failed to bind MJPEG preview on {}: {}", addr, e))
+        })?;
+        let local_addr = listener.local_addr()?;
+
+        let (jpeg_tx, jpeg_rx) = watch::channel(LatestJpeg::None);
+        let (frame_tx, frame_rx) = crossbeam_channel::bounded(1);
+        std::thread::Builder::new()
+            .name("ghoststream-mjpeg".into())
+            .spawn(move || run_jpeg_encoder(frame_rx, jpeg_tx))
+            .map_err(|e| Error::Internal(format!("Failed to spawn MJPEG encoder: {}", e)))?;
+
+        let viewers = Arc::new(AtomicUsize::new(0));
+        let server = tokio::spawn(serve(listener, jpeg_rx, viewers.clone()));
+        tracing::info!("MJPEG preview at http://{}/", local_addr);
+
+        Ok(Self {
+            local_addr,
+            interval_us: 1_000_000 / fps.max(1) as i64,
+            last_pts: AtomicI64::new(i64::MIN),
+            frame_tx,
+            viewers,
+            server,
+        })
+    }
+
+    /// Address the server listens on (resolves port 0)
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Number of connected viewers
+    pub fn viewers(&self) -> usize {
+        self.viewers.load(Ordering::Relaxed)
+    }
+
+    /// Offer a captured frame (PTS in µs); copied only when a viewer is
+    /// connected, the preview is due a frame and the encoder is idle
+    pub fn offer(&self, frame: &Frame) {
+        if self.viewers() == 0 {
+            return;
+        }
+        let last = self.last_pts.load(Ordering::Relaxed);
+        if last != i64::MIN && frame.pts.saturating_sub(last) < self.interval_us {
+            return;
+        }
+        if self.frame_tx.try_send(frame.copy_data()).is_ok() {
+            self.last_pts.store(frame.pts, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Drop for MjpegServer {
+    fn drop(&mut self) {
+        // Open connections end once the encoder thread, which exits with
+        // the frame channel, drops the JPEG sender
+        self.server.abort();
+    }
+}
+
+/// Accept viewers until the server is dropped
+async fn serve(
+    listener: TcpListener,
+    jpegs: watch::Receiver<LatestJpeg>,
+    viewers: Arc<AtomicUsize>,
+) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                tracing::warn!("MJPEG preview accept failed: {}", e);
+                continue;
+            }
+        };
+        tracing::debug!("MJPEG preview viewer connected: {}", peer);
+        let jpegs = jpegs.clone();
+        let viewers = viewers.clone();
+        tokio::spawn(async move {
+            viewers.fetch_add(1, Ordering::Relaxed);
+            if let Err(e) = stream_jpegs(stream, jpegs).await {
+                tracing::debug!("MJPEG preview viewer {} left: {}", peer, e);
+            }
+            viewers.fetch_sub(1, Ordering::Relaxed);
+        });
+    }
+}
+
+/// Answer any request with the multipart stream
+async fn stream_jpegs(
+    mut stream: TcpStream,
+    mut jpegs: watch::Receiver<LatestJpeg>,
+) -> std::io::Result<()> {
+    let mut request = [0u8; 1024];
+    let _ = stream.read(&mut request).await?;
+    let header = format!(
+        "HTTP/1.1 200 OK\r\n\
+         Content-Type: multipart/x-mixed-replace; boundary={}\r\n\
+         Cache-Control: no-cache\r\n\
+         Connection: close\r\n\r\n",
+        BOUNDARY
+    );
+    stream.write_all(header.as_bytes()).await?;
+
+    loop {
+        let jpeg = jpegs.borrow_and_update().clone();
+        if let Some(jpeg) = jpeg {
+            let part = format!(
+                "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                BOUNDARY,
+                jpeg.len()
+            );
+            stream.write_all(part.as_bytes()).await?;
+            stream.write_all(&jpeg).await?;
+            stream.write_all(b"\r\n").await?;
+        }
+        if jpegs.changed().await.is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// Encoder thread: JPEG-encode offered frames until the server is dropped
+fn run_jpeg_encoder(frames: crossbeam_channel::Receiver<Frame>, jpegs: watch::Sender<LatestJpeg>) {
+    let mut encoder: Option<JpegEncoder> = None;
+    while let Ok(frame) = frames.recv() {
+        let size = (frame.width, frame.height);
+        if !encoder.as_ref().is_some_and(|e| e.input == size) {
+            encoder = match JpegEncoder::new(size) {
+                Ok(e) => Some(e),
+                Err(e) => {
+                    tracing::warn!("MJPEG preview unavailable: {}", e);
+                    continue;
+                }
+            };
+        }
+        if let Some(encoder) = encoder.as_mut() {
+            match encoder.encode(&frame) {
+                Ok(jpeg) => {
+                    jpegs.send_replace(Some(Arc::new(jpeg)));
+                }
+                Err(e) => tracing::debug!("MJPEG preview frame failed: {}", e),
+            }
+        }
+    }
+}
+
+/// Downscaling JPEG encoder for one input size
+struct JpegEncoder {
+    input: (u32, u32),
+    scaler: Scaler,
+    encoder: ffmpeg::encoder::Video,
+}
+
+impl JpegEncoder {
+    fn new((width, height): (u32, u32)) -> Result<Self> {
+        let out_width = (width.min(PREVIEW_WIDTH) & !1).max(2);
+        let out_height =
+            ((height as u64 * out_width as u64 / width.max(1) as u64) as u32 & !1).max(2);
+
+        let codec = ffmpeg::encoder::find(ffmpeg::codec::Id::MJPEG)
+            .ok_or_else(|| Error::EncoderInit("MJPEG encoder not found".into()))?;
+        let mut encoder = ffmpeg::codec::context::Context::new_with_codec(codec)
+            .encoder()
+            .video()
+            .map_err(|e| Error::EncoderInit(e.to_string()))?;
+        encoder.set_width(out_width);
+        encoder.set_height(out_height);
+        encoder.set_format(Pixel::YUVJ420P);
+        encoder.set_time_base(ffmpeg::Rational::new(1, 1_000_000));
+
+        // Quantizer bounds keep the quality up whatever the frame size
+        let mut opts = ffmpeg::Dictionary::new();
+        opts.set("qmin", "2");
+        opts.set("qmax", "8");
+        let encoder = encoder
+            .open_with(opts)
+            .map_err(|e| Error::EncoderInit(format!("Failed to open MJPEG encoder: {}", e)))?;
+
+        let scaler = Scaler::get(
+            Pixel::BGRA,
+            width,
+            height,
+            Pixel::YUVJ420P,
+            out_width,
+            out_height,
+            ScalerFlags::BILINEAR,
+        )
+        .map_err(|e| Error::EncoderInit(format!("Failed to create scaler: {}", e)))?;
+
+        Ok(Self {
+            input: (width, height),
+            scaler,
+            encoder,
+        })
+    }
+
+    fn encode(&mut self, frame: &Frame) -> Result<Vec<u8>> {
+        let row = frame.width as usize * 4;
+        let (bgra, stride) = if frame.format == FrameFormat::Bgra {
+            (frame.data.clone(), (frame.stride as usize).max(row))
+        } else {
+            let data = convert_colorspace(
+                &frame.data,
+                frame.format,
+                FrameFormat::Bgra,
+                frame.width,
+                frame.height,
+            )?;
+            (data, row)
+        };
+
+        let mut input = ffmpeg::frame::Video::new(Pixel::BGRA, frame.width, frame.height);
+        let dst_stride = input.stride(0);
+        let dst = input.data_mut(0);
+        for (y, line) in bgra.chunks(stride).take(frame.height as usize).enumerate() {
+            let len = row.min(line.len());
+            dst[y * dst_stride..y * dst_stride + len].copy_from_slice(&line[..len]);
+        }
+
+        let mut scaled = ffmpeg::frame::Video::empty();
+        self.scaler
+            .run(&input, &mut scaled)
+            .map_err(|e| Error::EncodingFailed(format!("Scaling failed: {}", e)))?;
+        scaled.set_pts(Some(frame.pts));
+
+        self.encoder
+            .send_frame(&scaled)
+            .map_err(|e| Error::EncodingFailed(format!("Failed to send frame: {}", e)))?;
+        let mut packet = ffmpeg::Packet::empty();
+        self.encoder
+            .receive_packet(&mut packet)
+            .map_err(|e| Error::EncodingFailed(format!("Failed to encode JPEG: {}", e)))?;
+        Ok(packet.data().unwrap_or_default().to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_serves_multipart_stream() {
+        let server = MjpegServer::bind("127.0.0.1:0".parse().unwrap(), 10)
+            .await
+            .unwrap();
+        assert_eq!(server.viewers(), 0);
+
+        let mut client = TcpStream::connect(server.local_addr()).await.unwrap();
+        client.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+        let mut response = vec![0u8; 512];
+        let len = client.read(&mut response).await.unwrap();
+        let response = String::from_utf8_lossy(&response[..len]);
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("multipart/x-mixed-replace; boundary=ghoststream-frame"));
+        assert_eq!(server.viewers(), 1);
+
+        // Rate limited by PTS: the second frame is too early
+        let mut frame = Frame::new(64, 48, FrameFormat::Bgra);
+        server.offer(&frame);
+        frame.pts = 50_000;
+        server.offer(&frame);
+        assert_eq!(server.last_pts.load(Ordering::Relaxed), 0);
+    }
+}
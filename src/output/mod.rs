@@ -2,26 +2,57 @@
 //!
 //! Provides various output destinations:
 //! - Virtual camera (PipeWire)
-//! - File recording (MKV, MP4, WebM)
-//! - Streaming (RTMP, SRT)
-//! - A/V Muxing
+//! - File recording (MKV, MP4, WebM, MPEG-TS)
+//! - Streaming (RTMP, SRT caller and multi-subscriber listener)
+//! - Replay buffer (last N seconds in memory, saved on demand)
+//! - MJPEG-over-HTTP live preview
+//! - In-memory packet collection (for tests)
+//! - Frame checksums for golden-file regression tests
+//! - A/V Muxing, with subtitle and closed-caption passthrough
 
+mod abr;
+mod branch;
 mod camera;
 mod file;
+mod manifest;
+mod memory;
+mod mjpeg;
 mod muxer;
+mod replay;
 mod rtmp;
 mod srt;
+mod srt_listener;
+mod subtitle;
+mod validation;
 
-pub use camera::VirtualCamera;
+pub use abr::{AbrConfig, AbrController};
+pub use branch::SendBuffer;
+use branch::StreamBranch;
+pub use camera::{ScalingMode, VirtualCamera};
 pub use file::FileOutput;
+pub(crate) use manifest::ManifestRecorder;
+pub use manifest::{
+    ManifestAudio, ManifestSegment, ManifestStats, ManifestVideo, RecordingManifest,
+};
+pub use memory::MemoryOutput;
+pub use mjpeg::MjpegServer;
 pub use muxer::{AvMuxer, MuxerPacket, StreamType};
+pub use replay::{ReplayBuffer, ReplayBufferOutput};
 pub use rtmp::{RtmpOutput, RtmpService};
-pub use srt::{SrtMode, SrtOutput, SrtStats};
+pub use srt::{KeyRotation, SrtMode, SrtOutput, SrtStats};
+pub use srt_listener::{SrtListenerOutput, SubscriberStats};
+pub use subtitle::{extract_captions, SubtitleCodec, SubtitlePacket, SubtitleParams};
+pub use validation::{frame_hash, FrameHash, ValidationSink};
 
-use crate::error::Result;
-use crate::types::{CodecParams, Frame, FrameFormat, Packet, Resolution};
+use crate::config::EncoderConfig;
+use crate::error::{Error, Result};
+use crate::types::{CodecParams, Frame, FrameFormat, Framerate, Packet, Resolution, Timecode};
+use ffmpeg_next as ffmpeg;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 /// Output destination configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,12 +69,48 @@ pub enum Output {
         path: PathBuf,
         /// Container format
         container: Container,
+        /// Stop the pipeline and finalize the file after this much recording
+        /// time (safety net for unattended capture)
+        #[serde(default)]
+        max_duration: Option<Duration>,
+        /// Write batching (None = `FlushPolicy::RECORDING`)
+        #[serde(default)]
+        flush_policy: Option<FlushPolicy>,
+        /// MPEG-TS muxer settings (None = `TsOptions::default()`; ignored
+        /// for other containers)
+        #[serde(default)]
+        ts_options: Option<TsOptions>,
+        /// Raw FFmpeg muxer options (see `Output::with_muxer_option`)
+        #[serde(default)]
+        extra_options: HashMap<String, String>,
+        /// Write a `RecordingManifest` next to the file when it is
+        /// finalized (primary file output only)
+        #[serde(default)]
+        write_manifest: bool,
+        /// Create missing parent directories before opening the file;
+        /// when off, a missing directory fails with `Error::OutputInit`
+        #[serde(default = "default_true")]
+        create_dirs: bool,
+        /// SMPTE timecode of the first frame (None = no timecode); see
+        /// `Output::with_timecode`
+        #[serde(default)]
+        timecode_start: Option<Timecode>,
     },
 
     /// RTMP streaming (Twitch, YouTube, etc.)
     Rtmp {
         /// RTMP URL with stream key
         url: String,
+        /// Write batching (None = `FlushPolicy::Immediate`)
+        #[serde(default)]
+        flush_policy: Option<FlushPolicy>,
+        /// Signal HEVC/AV1 with enhanced RTMP FourCCs (see
+        /// `RtmpOutput::with_enhanced_rtmp`)
+        #[serde(default)]
+        enhanced: bool,
+        /// Raw FFmpeg muxer/protocol options
+        #[serde(default)]
+        extra_options: HashMap<String, String>,
     },
 
     /// SRT streaming (low latency)
@@ -52,11 +119,59 @@ pub enum Output {
         url: String,
         /// Latency in ms
         latency_ms: u32,
+        /// Adaptive bitrate control (None = fixed bitrate)
+        #[serde(default)]
+        adaptive_bitrate: Option<AbrConfig>,
+        /// Write batching (None = `FlushPolicy::Immediate`)
+        #[serde(default)]
+        flush_policy: Option<FlushPolicy>,
+        /// Raw FFmpeg muxer/protocol options
+        #[serde(default)]
+        extra_options: HashMap<String, String>,
+    },
+
+    /// SRT listener serving any number of receivers that connect to it
+    SrtListener {
+        /// Local SRT URL to listen on (e.g. "srt://0.0.0.0:9000")
+        url: String,
+        /// Latency in ms
+        latency_ms: u32,
+        /// Callers beyond this many are turned away (0 = no limit)
+        #[serde(default)]
+        max_subscribers: usize,
+    },
+
+    /// Keep the last seconds of video in memory; save clips with
+    /// `Pipeline::save_replay`
+    ReplayBuffer {
+        /// Seconds of video to keep
+        duration_secs: u32,
+    },
+
+    /// Live JPEG preview of the capture over HTTP, viewable in a browser
+    /// (see `MjpegServer`); fed from captured frames, not the encoder
+    MjpegHttp {
+        /// Address to serve on (e.g. "0.0.0.0:8080")
+        bind_addr: SocketAddr,
+        /// Maximum preview frame rate
+        fps: u32,
     },
 
     /// Multiple outputs (e.g., record + stream)
     Multiple(Vec<Output>),
 
+    /// An output encoded with its own settings instead of the pipeline's
+    /// (e.g. stream H.264 while recording HEVC); the pipeline runs one
+    /// encoder per distinct config
+    Encoded {
+        encoder: Box<EncoderConfig>,
+        output: Box<Output>,
+    },
+
+    /// Collect packets in memory (for tests); not serializable
+    #[serde(skip)]
+    Memory(MemoryOutput),
+
     /// Null output (for testing)
     Null,
 }
@@ -72,12 +187,35 @@ impl Output {
         Output::File {
             path: path.into(),
             container,
+            max_duration: None,
+            flush_policy: None,
+            ts_options: None,
+            extra_options: HashMap::new(),
+            write_manifest: false,
+            create_dirs: true,
+            timecode_start: None,
         }
     }
 
     /// Create an RTMP streaming output
     pub fn rtmp(url: impl Into<String>) -> Self {
-        Output::Rtmp { url: url.into() }
+        Output::Rtmp {
+            url: url.into(),
+            flush_policy: None,
+            enhanced: false,
+            extra_options: HashMap::new(),
+        }
+    }
+
+    /// Create an RTMP output that negotiates enhanced RTMP, for streaming
+    /// HEVC or AV1 to servers that accept it (e.g. YouTube)
+    pub fn rtmp_enhanced(url: impl Into<String>) -> Self {
+        Output::Rtmp {
+            url: url.into(),
+            flush_policy: None,
+            enhanced: true,
+            extra_options: HashMap::new(),
+        }
     }
 
     /// Create an SRT streaming output
@@ -85,9 +223,432 @@ impl Output {
         Output::Srt {
             url: url.into(),
             latency_ms,
+            adaptive_bitrate: None,
+            flush_policy: None,
+            extra_options: HashMap::new(),
+        }
+    }
+
+    /// Create an SRT streaming output with adaptive bitrate
+    pub fn srt_adaptive(url: impl Into<String>, latency_ms: u32, abr: AbrConfig) -> Self {
+        Output::Srt {
+            url: url.into(),
+            latency_ms,
+            adaptive_bitrate: Some(abr),
+            flush_policy: None,
+            extra_options: HashMap::new(),
+        }
+    }
+
+    /// Create an SRT listener that several receivers can pull from
+    pub fn srt_listener(url: impl Into<String>, latency_ms: u32, max_subscribers: usize) -> Self {
+        Output::SrtListener {
+            url: url.into(),
+            latency_ms,
+            max_subscribers,
+        }
+    }
+
+    /// Create a replay buffer output keeping the last `duration_secs` seconds
+    pub fn replay_buffer(duration_secs: u32) -> Self {
+        Output::ReplayBuffer { duration_secs }
+    }
+
+    /// Create an MJPEG-over-HTTP preview served on `bind_addr`
+    pub fn mjpeg_http(bind_addr: SocketAddr, fps: u32) -> Self {
+        Output::MjpegHttp { bind_addr, fps }
+    }
+
+    /// Collect packets into `memory` (shared with the caller's handle)
+    pub fn memory(memory: &MemoryOutput) -> Self {
+        Output::Memory(memory.clone())
+    }
+
+    /// Cap file recording length (applies to every file output)
+    pub fn with_max_duration(self, duration: Duration) -> Self {
+        match self {
+            Output::File {
+                path,
+                container,
+                flush_policy,
+                ts_options,
+                extra_options,
+                write_manifest,
+                create_dirs,
+                timecode_start,
+                ..
+            } => Output::File {
+                path,
+                container,
+                max_duration: Some(duration),
+                flush_policy,
+                ts_options,
+                extra_options,
+                write_manifest,
+                create_dirs,
+                timecode_start,
+            },
+            Output::Multiple(outputs) => Output::Multiple(
+                outputs
+                    .into_iter()
+                    .map(|o| o.with_max_duration(duration))
+                    .collect(),
+            ),
+            Output::Encoded { encoder, output } => Output::Encoded {
+                encoder,
+                output: Box::new(output.with_max_duration(duration)),
+            },
+            other => other,
+        }
+    }
+
+    /// Set how file and stream outputs batch their writes
+    ///
+    /// Applies to every file, RTMP and SRT output; see `FlushPolicy`.
+    pub fn with_flush_policy(self, policy: FlushPolicy) -> Self {
+        match self {
+            Output::File {
+                path,
+                container,
+                max_duration,
+                ts_options,
+                extra_options,
+                write_manifest,
+                create_dirs,
+                timecode_start,
+                ..
+            } => Output::File {
+                path,
+                container,
+                max_duration,
+                flush_policy: Some(policy),
+                ts_options,
+                extra_options,
+                write_manifest,
+                create_dirs,
+                timecode_start,
+            },
+            Output::Rtmp {
+                url,
+                enhanced,
+                extra_options,
+                ..
+            } => Output::Rtmp {
+                url,
+                flush_policy: Some(policy),
+                enhanced,
+                extra_options,
+            },
+            Output::Srt {
+                url,
+                latency_ms,
+                adaptive_bitrate,
+                extra_options,
+                ..
+            } => Output::Srt {
+                url,
+                latency_ms,
+                adaptive_bitrate,
+                flush_policy: Some(policy),
+                extra_options,
+            },
+            Output::Multiple(outputs) => Output::Multiple(
+                outputs
+                    .into_iter()
+                    .map(|o| o.with_flush_policy(policy))
+                    .collect(),
+            ),
+            Output::Encoded { encoder, output } => Output::Encoded {
+                encoder,
+                output: Box::new(output.with_flush_policy(policy)),
+            },
+            other => other,
+        }
+    }
+
+    /// Set MPEG-TS muxer settings (applies to every `.ts` file output)
+    pub fn with_ts_options(self, options: TsOptions) -> Self {
+        match self {
+            Output::File {
+                path,
+                container,
+                max_duration,
+                flush_policy,
+                extra_options,
+                write_manifest,
+                create_dirs,
+                timecode_start,
+                ..
+            } => Output::File {
+                path,
+                container,
+                max_duration,
+                flush_policy,
+                ts_options: Some(options),
+                extra_options,
+                write_manifest,
+                create_dirs,
+                timecode_start,
+            },
+            Output::Multiple(outputs) => Output::Multiple(
+                outputs
+                    .into_iter()
+                    .map(|o| o.with_ts_options(options))
+                    .collect(),
+            ),
+            Output::Encoded { encoder, output } => Output::Encoded {
+                encoder,
+                output: Box::new(output.with_ts_options(options)),
+            },
+            other => other,
+        }
+    }
+
+    /// Write a `RecordingManifest` sidecar (`<name>.json`) when a file
+    /// output is finalized
+    ///
+    /// Only the pipeline's primary file output is tracked; file outputs
+    /// inside `Output::Multiple` ignore this.
+    pub fn with_manifest(mut self, enabled: bool) -> Self {
+        if let Output::File { write_manifest, .. } = &mut self {
+            *write_manifest = enabled;
+        }
+        self
+    }
+
+    /// Create missing parent directories of file outputs (default on)
+    pub fn with_create_dirs(mut self, enabled: bool) -> Self {
+        self.set_create_dirs(enabled);
+        self
+    }
+
+    fn set_create_dirs(&mut self, enabled: bool) {
+        match self {
+            Output::File { create_dirs, .. } => *create_dirs = enabled,
+            Output::Multiple(outputs) => {
+                for output in outputs {
+                    output.set_create_dirs(enabled);
+                }
+            }
+            Output::Encoded { output, .. } => output.set_create_dirs(enabled),
+            _ => {}
+        }
+    }
+
+    /// Stamp file outputs with SMPTE timecode, `start` on the first frame
+    ///
+    /// The video track gets a `timecode` tag (a `tmcd` track in MP4/MOV, a
+    /// TIMECODE tag in Matroska; MPEG-TS has no place for it) and every
+    /// frame its own timecode (see `EncoderConfig::timecode`), so editors
+    /// can line up recordings. For wall-clock timecode pass
+    /// `Timecode::time_of_day` taken right before starting.
+    pub fn with_timecode(mut self, start: Timecode) -> Self {
+        self.set_timecode(start);
+        self
+    }
+
+    fn set_timecode(&mut self, start: Timecode) {
+        match self {
+            Output::File { timecode_start, .. } => *timecode_start = Some(start),
+            Output::Multiple(outputs) => {
+                for output in outputs {
+                    output.set_timecode(start);
+                }
+            }
+            Output::Encoded { output, .. } => output.set_timecode(start),
+            _ => {}
+        }
+    }
+
+    /// Timecode of the first frame of the first file output, if set
+    pub fn timecode_start(&self) -> Option<Timecode> {
+        match self {
+            Output::File { timecode_start, .. } => *timecode_start,
+            Output::Multiple(outputs) => outputs.iter().find_map(|o| o.timecode_start()),
+            Output::Encoded { output, .. } => output.timecode_start(),
+            _ => None,
+        }
+    }
+
+    /// Pass a raw FFmpeg muxer option to every file, RTMP and SRT output
+    ///
+    /// Applied when the header is written, after the library's own options
+    /// so user values win (e.g. `movflags=+faststart` for web-playable MP4,
+    /// `flvflags`). RTMP and SRT outputs also pass them when opening the
+    /// connection, so protocol options such as SRT socket settings work.
+    /// Options aren't validated; unrecognized ones are logged at debug level.
+    pub fn with_muxer_option(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.set_muxer_option(&key.into(), &value.into());
+        self
+    }
+
+    fn set_muxer_option(&mut self, key: &str, value: &str) {
+        match self {
+            Output::File { extra_options, .. }
+            | Output::Rtmp { extra_options, .. }
+            | Output::Srt { extra_options, .. } => {
+                extra_options.insert(key.to_string(), value.to_string());
+            }
+            Output::Multiple(outputs) => {
+                for output in outputs {
+                    output.set_muxer_option(key, value);
+                }
+            }
+            Output::Encoded { output, .. } => output.set_muxer_option(key, value),
+            _ => {}
         }
     }
 
+    /// Shortest recording cap across all file outputs
+    pub fn max_duration(&self) -> Option<Duration> {
+        match self {
+            Output::File { max_duration, .. } => *max_duration,
+            Output::Multiple(outputs) => outputs.iter().filter_map(|o| o.max_duration()).min(),
+            Output::Encoded { output, .. } => output.max_duration(),
+            _ => None,
+        }
+    }
+
+    /// Encode this output with its own encoder settings
+    ///
+    /// Inside `Output::Multiple`, destinations with different settings each
+    /// get a separate encoder fed from the same processed frames.
+    pub fn with_encoder(self, encoder: EncoderConfig) -> Self {
+        Output::Encoded {
+            encoder: Box::new(encoder),
+            output: Box::new(self.without_encoder().1),
+        }
+    }
+
+    /// Per-output encoder settings, if any
+    pub fn encoder(&self) -> Option<&EncoderConfig> {
+        match self {
+            Output::Encoded { encoder, .. } => Some(encoder),
+            _ => None,
+        }
+    }
+
+    /// Split off the per-output encoder settings
+    pub(crate) fn without_encoder(self) -> (Option<EncoderConfig>, Output) {
+        match self {
+            Output::Encoded { encoder, output } => (Some(*encoder), output.without_encoder().1),
+            other => (None, other),
+        }
+    }
+
+    /// Whether the destination takes raw frames instead of encoded packets
+    /// (the virtual camera); see `create_raw_output`
+    pub fn is_raw(&self) -> bool {
+        match self {
+            Output::VirtualCamera { .. } => true,
+            Output::Encoded { output, .. } => output.is_raw(),
+            _ => false,
+        }
+    }
+
+    /// Whether the destination is a live stream to a server (RTMP, SRT
+    /// caller); in a `MultiOutput` these reconnect on their own behind a
+    /// packet buffer
+    pub fn is_stream(&self) -> bool {
+        matches!(self, Output::Rtmp { .. } | Output::Srt { .. })
+    }
+
+    /// Whether the transport is Annex-B and needs SPS/PPS repeated in-band
+    /// for receivers that join mid-stream (SRT, MPEG-TS)
+    pub fn needs_repeated_headers(&self) -> bool {
+        match self {
+            Output::Srt { .. } | Output::SrtListener { .. } => true,
+            Output::File { container, .. } => *container == Container::Ts,
+            Output::Multiple(outputs) => outputs.iter().any(|o| o.needs_repeated_headers()),
+            Output::Encoded { output, .. } => output.needs_repeated_headers(),
+            _ => false,
+        }
+    }
+
+    /// Whether the output can carry AAC-ELD audio
+    ///
+    /// MP4 and Matroska signal it in the AudioSpecificConfig. ADTS (MPEG-TS,
+    /// SRT) has no way to, and FLV/RTMP receivers only expect AAC-LC or
+    /// HE-AAC.
+    pub fn accepts_aac_eld(&self) -> bool {
+        match self {
+            Output::File { container, .. } => {
+                matches!(container, Container::Matroska | Container::Mp4)
+            }
+            Output::Rtmp { .. } | Output::Srt { .. } | Output::SrtListener { .. } => false,
+            Output::Multiple(outputs) => outputs.iter().all(|o| o.accepts_aac_eld()),
+            Output::Encoded { output, .. } => output.accepts_aac_eld(),
+            _ => true,
+        }
+    }
+
+    /// Check the configuration without side effects
+    ///
+    /// Catches malformed URLs (`rtmp:/host`) and unwritable recording
+    /// directories before anything is opened; a file extension that doesn't
+    /// match the container is only warned about, as the file is still
+    /// written in that container. No network connections are made and
+    /// nothing is created.
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Output::VirtualCamera { name } => {
+                if name.trim().is_empty() {
+                    return Err(Error::Config("Virtual camera name is empty".into()));
+                }
+            }
+            Output::File {
+                path, container, ..
+            } => {
+                let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+                if !extension.eq_ignore_ascii_case(container.extension()) {
+                    tracing::warn!(
+                        "File extension '.{}' does not match container {:?} (.{})",
+                        extension,
+                        container,
+                        container.extension()
+                    );
+                }
+                check_writable_dir(path)?;
+            }
+            Output::Rtmp { url, .. } => check_url(url, &["rtmp", "rtmps"], false)?,
+            Output::Srt {
+                url,
+                latency_ms,
+                extra_options,
+                ..
+            } => {
+                check_url(url, &["srt"], true)?;
+                SrtOutput::new(url.clone(), *latency_ms)
+                    .with_extra_options(extra_options.clone())
+                    .validate()?;
+            }
+            Output::SrtListener { url, .. } => check_url(url, &["srt"], true)?,
+            Output::ReplayBuffer { duration_secs } => {
+                if *duration_secs == 0 {
+                    return Err(Error::Config(
+                        "Replay buffer duration must be non-zero".into(),
+                    ));
+                }
+            }
+            Output::MjpegHttp { fps, .. } => {
+                if *fps == 0 {
+                    return Err(Error::Config("MJPEG preview fps must be non-zero".into()));
+                }
+            }
+            Output::Multiple(outputs) => {
+                if outputs.is_empty() {
+                    return Err(Error::Config("Multi-output has no destinations".into()));
+                }
+                for output in outputs {
+                    output.validate()?;
+                }
+            }
+            Output::Encoded { output, .. } => output.validate()?,
+            Output::Memory(_) | Output::Null => {}
+        }
+        Ok(())
+    }
+
     /// Create a multi-output (record + stream, etc.)
     pub fn multiple(outputs: Vec<Output>) -> Self {
         Output::Multiple(outputs)
@@ -105,6 +666,62 @@ impl Output {
     }
 }
 
+/// Check that `url` is `<scheme>://host[:port]...` for one of `schemes`
+fn check_url(url: &str, schemes: &[&str], needs_port: bool) -> Result<()> {
+    let expected = schemes
+        .iter()
+        .map(|s| format!("{}://", s))
+        .collect::<Vec<_>>()
+        .join(" or ");
+    let rest = schemes
+        .iter()
+        .find_map(|scheme| url.strip_prefix(scheme).and_then(|r| r.strip_prefix("://")))
+        .ok_or_else(|| Error::Config(format!("URL '{}' must start with {}", url, expected)))?;
+
+    let authority = rest.split(['/', '?']).next().unwrap_or("");
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, Some(port)),
+        None => (authority, None),
+    };
+    if host.is_empty() && !needs_port {
+        return Err(Error::Config(format!("URL '{}' has no host", url)));
+    }
+    match port {
+        Some(port) if port.parse::<u16>().is_err() => Err(Error::Config(format!(
+            "URL '{}' has an invalid port '{}'",
+            url, port
+        ))),
+        None if needs_port => Err(Error::Config(format!("URL '{}' has no port", url))),
+        _ => Ok(()),
+    }
+}
+
+/// Check that a file can be created at `path`: the closest existing
+/// ancestor must be a writable directory (missing ones are created later)
+fn check_writable_dir(path: &std::path::Path) -> Result<()> {
+    let parent = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(std::path::Path::new("."));
+    let Some(existing) = parent.ancestors().find(|p| p.exists()) else {
+        return Ok(());
+    };
+    let metadata = std::fs::metadata(existing)?;
+    if !metadata.is_dir() {
+        return Err(Error::Config(format!(
+            "{} is not a directory",
+            existing.display()
+        )));
+    }
+    if metadata.permissions().readonly() {
+        return Err(Error::Config(format!(
+            "Directory {} is not writable",
+            existing.display()
+        )));
+    }
+    Ok(())
+}
+
 impl Default for Output {
     fn default() -> Self {
         Output::VirtualCamera {
@@ -119,7 +736,7 @@ pub enum Container {
     /// Matroska (.mkv) - Best for recording
     #[default]
     Matroska,
-    /// MP4 (.mp4) - Wide compatibility
+    /// MP4 (.mp4) - Wide compatibility, written with faststart
     Mp4,
     /// WebM (.webm) - Web-friendly
     WebM,
@@ -138,6 +755,17 @@ impl Container {
         }
     }
 
+    /// Container for a file extension (case-insensitive)
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "mkv" => Some(Container::Matroska),
+            "mp4" => Some(Container::Mp4),
+            "webm" => Some(Container::WebM),
+            "ts" => Some(Container::Ts),
+            _ => None,
+        }
+    }
+
     /// Get FFmpeg format name
     pub fn ffmpeg_format(&self) -> &'static str {
         match self {
@@ -147,6 +775,349 @@ impl Container {
             Container::Ts => "mpegts",
         }
     }
+
+    /// Muxer options every file in this container is written with
+    pub(crate) fn muxer_options(&self, ts_options: &TsOptions) -> ffmpeg::Dictionary<'static> {
+        match self {
+            Container::Ts => ts_options.muxer_options(),
+            Container::Mp4 => mp4_muxer_options(),
+            Container::Matroska | Container::WebM => ffmpeg::Dictionary::new(),
+        }
+    }
+}
+
+/// When muxed data is pushed out of FFmpeg's AVIO buffer
+///
+/// `Immediate` flushes after every packet so each frame reaches the socket
+/// or file as soon as it is muxed: lowest latency, but one write syscall
+/// per packet. `Buffered` stops per-packet flushing and lets FFmpeg batch
+/// writes in its AVIO buffer, forcing a flush at least every `bytes` of
+/// muxed data. That bounds how much stream is held back (256 KiB at 6 Mbps
+/// is about 350 ms), so it suits recordings rather than live streams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FlushPolicy {
+    /// Flush after every packet (streaming default)
+    Immediate,
+    /// Flush once this many bytes have been muxed since the last flush
+    Buffered { bytes: usize },
+}
+
+impl FlushPolicy {
+    /// Default for file recording
+    pub const RECORDING: Self = FlushPolicy::Buffered { bytes: 1024 * 1024 };
+}
+
+/// MPEG-TS muxer settings
+///
+/// A transport stream has no index: players find their footing from the
+/// PAT/PMT tables and PCR repeated through the stream. Repeating the tables
+/// at every video keyframe means a seek (or a receiver joining mid-stream)
+/// lands on a point where tables, parameter sets and an IDR arrive together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TsOptions {
+    /// Maximum interval between PAT/PMT repetitions
+    pub pmt_period: Duration,
+    /// Maximum interval between SDT repetitions
+    pub sdt_period: Duration,
+    /// Maximum interval between PCR updates
+    pub pcr_period: Duration,
+    /// Also write PAT/PMT before every video keyframe
+    pub tables_at_keyframes: bool,
+}
+
+impl Default for TsOptions {
+    fn default() -> Self {
+        Self {
+            pmt_period: Duration::from_millis(100),
+            sdt_period: Duration::from_millis(500),
+            pcr_period: Duration::from_millis(20),
+            tables_at_keyframes: true,
+        }
+    }
+}
+
+impl TsOptions {
+    /// Set the PAT/PMT repetition interval
+    pub fn with_pmt_period(mut self, period: Duration) -> Self {
+        self.pmt_period = period;
+        self
+    }
+
+    /// Set the PCR update interval
+    pub fn with_pcr_period(mut self, period: Duration) -> Self {
+        self.pcr_period = period;
+        self
+    }
+
+    /// Muxer options for `write_header_with`
+    pub(crate) fn muxer_options(&self) -> ffmpeg::Dictionary<'static> {
+        let mut options = ffmpeg::Dictionary::new();
+        options.set("pat_period", &self.pmt_period.as_secs_f64().to_string());
+        options.set("sdt_period", &self.sdt_period.as_secs_f64().to_string());
+        options.set("pcr_period", &self.pcr_period.as_millis().to_string());
+        if self.tables_at_keyframes {
+            options.set("mpegts_flags", "+pat_pmt_at_frames");
+        }
+        options
+    }
+}
+
+/// A chapter marker of a recording (see `Pipeline::add_chapter`)
+///
+/// Times are in microseconds from the start of the file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Chapter {
+    pub title: String,
+    pub start: i64,
+    pub end: i64,
+}
+
+impl Chapter {
+    pub fn new(title: impl Into<String>, start: i64, end: i64) -> Self {
+        Self {
+            title: title.into(),
+            start,
+            end,
+        }
+    }
+}
+
+/// Make sure the directory a file output goes into exists, creating it
+/// (and its parents) if `create` is set
+///
+/// FFmpeg's own error for a missing directory is a bare "No such file or
+/// directory"; this names the directory instead.
+pub(crate) fn prepare_output_dir(path: &Path, create: bool) -> Result<()> {
+    let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) else {
+        return Ok(());
+    };
+    if parent.is_dir() {
+        return Ok(());
+    }
+    if !create {
+        return Err(Error::OutputInit(format!(
+            "directory does not exist: {}",
+            parent.display()
+        )));
+    }
+    std::fs::create_dir_all(parent).map_err(|e| {
+        Error::OutputInit(format!(
+            "Failed to create directory {}: {}",
+            parent.display(),
+            e
+        ))
+    })
+}
+
+/// Muxer options for MP4 files
+///
+/// By default the MP4 muxer writes the moov atom (the index) after the media
+/// data, so a player can't start or seek until it has the whole file.
+/// `+faststart` moves it to the front when the file is finalized; that
+/// rewrites the file once in `write_trailer`, which takes a moment for long
+/// recordings. A `movflags` extra option replaces this default.
+pub(crate) fn mp4_muxer_options() -> ffmpeg::Dictionary<'static> {
+    let mut options = ffmpeg::Dictionary::new();
+    options.set("movflags", "+faststart");
+    options
+}
+
+/// Tag a video stream with the SMPTE timecode of its first frame
+///
+/// The MP4/MOV muxer turns the `timecode` tag into a `tmcd` track, which
+/// takes its rate from the stream's average frame rate; Matroska writes it
+/// as a TIMECODE tag.
+pub(crate) fn set_stream_timecode(
+    stream: &mut ffmpeg::format::stream::StreamMut,
+    timecode: Timecode,
+    framerate: Framerate,
+) {
+    let rate = framerate.clamped();
+    stream.set_avg_frame_rate(ffmpeg::Rational::new(rate.num as i32, rate.den as i32));
+    let mut metadata = ffmpeg::Dictionary::new();
+    metadata.set("timecode", &timecode.to_string());
+    stream.set_metadata(metadata);
+}
+
+/// Pixel format of the decoded pictures of a video stream, for its
+/// codec parameters
+pub(crate) fn stream_pixel_format(params: &CodecParams) -> ffmpeg::ffi::AVPixelFormat {
+    use crate::config::ChromaFormat;
+    use ffmpeg::ffi::AVPixelFormat;
+
+    match (params.chroma_format, params.bit_depth > 8) {
+        (ChromaFormat::Yuv420, false) => AVPixelFormat::AV_PIX_FMT_YUV420P,
+        (ChromaFormat::Yuv420, true) => AVPixelFormat::AV_PIX_FMT_YUV420P10LE,
+        (ChromaFormat::Yuv422, false) => AVPixelFormat::AV_PIX_FMT_YUV422P,
+        (ChromaFormat::Yuv422, true) => AVPixelFormat::AV_PIX_FMT_YUV422P10LE,
+        (ChromaFormat::Yuv444, false) => AVPixelFormat::AV_PIX_FMT_YUV444P,
+        (ChromaFormat::Yuv444, true) => AVPixelFormat::AV_PIX_FMT_YUV444P10LE,
+    }
+}
+
+/// Add chapter markers to a muxer before its trailer is written
+///
+/// Matroska writes chapters added after the header with its trailer, and
+/// MP4 keeps them in the moov atom (Nero `chpl`), written on finish as
+/// well. MPEG-TS has no chapters and ignores them.
+pub(crate) fn write_chapters(
+    output_ctx: &mut ffmpeg::format::context::Output,
+    chapters: &[Chapter],
+) {
+    for (id, chapter) in chapters.iter().enumerate() {
+        let end = chapter.end.max(chapter.start);
+        let time_base = ffmpeg::Rational::new(1, 1_000_000);
+        if let Err(e) =
+            output_ctx.add_chapter(id as i64, time_base, chapter.start, end, &chapter.title)
+        {
+            tracing::warn!("Failed to add chapter \"{}\": {}", chapter.title, e);
+        }
+    }
+}
+
+/// Write HDR colorimetry and static metadata into a video stream's parameters
+///
+/// Matroska stores it in the track's `Colour` element and MP4 in `colr`,
+/// `mdcv` and `clli` boxes, so players can tone-map without parsing the
+/// bitstream. SDR streams are left alone.
+///
+/// # Safety
+/// `par` must point to the codec parameters of a stream that has not had
+/// its header written yet.
+pub(crate) unsafe fn set_stream_hdr(
+    par: *mut ffmpeg::ffi::AVCodecParameters,
+    params: &CodecParams,
+) {
+    use crate::processing::ColorRange;
+    use ffmpeg::ffi;
+
+    let Some(hdr) = &params.hdr else {
+        return;
+    };
+    let color =
+        crate::encode::ffmpeg_color(hdr.matrix, hdr.primaries, ColorRange::Limited, hdr.transfer);
+    (*par).color_space = color.space.into();
+    (*par).color_range = color.range.into();
+    (*par).color_primaries = color.primaries.into();
+    (*par).color_trc = color.transfer.into();
+
+    let add = |kind: ffi::AVPacketSideDataType, size: usize| {
+        let sd = ffi::av_packet_side_data_new(
+            &mut (*par).coded_side_data,
+            &mut (*par).nb_coded_side_data,
+            kind,
+            size,
+            0,
+        );
+        if sd.is_null() {
+            tracing::warn!("Failed to add {:?} to the stream", kind);
+            std::ptr::null_mut()
+        } else {
+            (*sd).data
+        }
+    };
+    if let Some(meta) = &hdr.hdr10_metadata {
+        let data = add(
+            ffi::AVPacketSideDataType::AV_PKT_DATA_MASTERING_DISPLAY_METADATA,
+            std::mem::size_of::<ffi::AVMasteringDisplayMetadata>(),
+        ) as *mut ffi::AVMasteringDisplayMetadata;
+        if !data.is_null() {
+            data.write(meta.to_ffmpeg());
+        }
+    }
+    if let Some(light) = &hdr.content_light {
+        let data = add(
+            ffi::AVPacketSideDataType::AV_PKT_DATA_CONTENT_LIGHT_LEVEL,
+            std::mem::size_of::<ffi::AVContentLightMetadata>(),
+        ) as *mut ffi::AVContentLightMetadata;
+        if !data.is_null() {
+            data.write(light.to_ffmpeg());
+        }
+    }
+}
+
+/// Write the muxer header with `base` options plus the user's `extra`
+/// options, which take precedence
+pub(crate) fn write_header_with_options(
+    output_ctx: &mut ffmpeg::format::context::Output,
+    base: ffmpeg::Dictionary<'static>,
+    extra: &HashMap<String, String>,
+) -> std::result::Result<(), ffmpeg::Error> {
+    let unused = output_ctx.write_header_with(merge_options(base, extra))?;
+    for (key, value) in unused.iter() {
+        tracing::debug!("Muxer ignored option {}={}", key, value);
+    }
+    Ok(())
+}
+
+/// `base` with the user's `extra` options set on top, in a stable order
+pub(crate) fn merge_options(
+    base: ffmpeg::Dictionary<'static>,
+    extra: &HashMap<String, String>,
+) -> ffmpeg::Dictionary<'static> {
+    let mut options = base;
+    let mut extra: Vec<_> = extra.iter().collect();
+    extra.sort();
+    for (key, value) in extra {
+        options.set(key, value);
+    }
+    options
+}
+
+/// Applies a `FlushPolicy` to an FFmpeg muxer
+pub(crate) struct AvioFlusher {
+    policy: FlushPolicy,
+    pending: usize,
+}
+
+impl AvioFlusher {
+    pub(crate) fn new(policy: FlushPolicy) -> Self {
+        Self { policy, pending: 0 }
+    }
+
+    /// Turn off libavformat's own per-packet flushing; call before
+    /// `write_header`
+    pub(crate) fn configure(&self, output_ctx: &mut ffmpeg::format::context::Output) {
+        unsafe {
+            (*output_ctx.as_mut_ptr()).flush_packets = 0;
+        }
+    }
+
+    /// Account for a written packet and flush if the policy says so
+    pub(crate) fn written(
+        &mut self,
+        output_ctx: &mut ffmpeg::format::context::Output,
+        bytes: usize,
+    ) {
+        self.pending += bytes;
+        let due = match self.policy {
+            FlushPolicy::Immediate => true,
+            FlushPolicy::Buffered { bytes: limit } => self.pending >= limit,
+        };
+        if !due {
+            return;
+        }
+        unsafe {
+            let pb = (*output_ctx.as_mut_ptr()).pb;
+            if !pb.is_null() {
+                ffmpeg::ffi::avio_flush(pb);
+            }
+        }
+        self.pending = 0;
+    }
+}
+
+/// How long the last write to one destination took
+///
+/// See `OutputSink::destination_writes`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WriteTiming {
+    /// Time spent writing the last packet
+    pub write_time: Duration,
+    /// Packets the destination has queued itself, when it buffers
+    pub backlog: Option<usize>,
+    /// Packets the destination has dropped for lack of buffer space
+    pub dropped: u64,
 }
 
 /// Trait for output sinks (encoded packets)
@@ -168,6 +1139,49 @@ pub trait OutputSink: Send {
 
     /// Get bytes written
     fn bytes_written(&self) -> u64;
+
+    /// Take a pending encoder bitrate change requested by the output (kbps)
+    ///
+    /// Outputs with adaptive bitrate control return `Some` after deciding
+    /// the stream should go faster or slower; the pipeline forwards it to
+    /// the encoder.
+    fn take_bitrate_request(&mut self) -> Option<u32> {
+        None
+    }
+
+    /// Replay buffer fed by this output, if it is (or contains) one
+    fn replay_buffer(&self) -> Option<ReplayBuffer> {
+        None
+    }
+
+    /// Packet buffers of the stream branches behind this output
+    fn send_buffers(&self) -> Vec<SendBuffer> {
+        Vec::new()
+    }
+
+    /// Text for the container's `comment` tag; call before initializing.
+    /// Outputs without container metadata ignore it.
+    fn set_comment(&mut self, _comment: &str) {}
+
+    /// Chapters written into the container on `finish`; call before it.
+    /// Outputs without chapters ignore them.
+    fn set_chapters(&mut self, _chapters: &[Chapter]) {}
+
+    /// Timing of each destination behind this output, for outputs whose
+    /// `write` doesn't show the cost of one destination on its own: one
+    /// entry per destination of a multi-output, or the background sender
+    /// of a stream branch. Empty for everything else.
+    fn destination_writes(&self) -> Vec<WriteTiming> {
+        Vec::new()
+    }
+
+    /// Receivers connected to each destination behind this output, in the
+    /// order of `destination_writes` (a single list for an output that is
+    /// one destination). Only outputs serving several receivers at once
+    /// (`Output::SrtListener`) have any.
+    fn destination_subscribers(&self) -> Vec<Vec<SubscriberStats>> {
+        Vec::new()
+    }
 }
 
 /// Trait for raw frame output sinks (uncompressed frames)
@@ -191,6 +1205,20 @@ pub trait RawOutputSink: Send {
     fn bytes_written(&self) -> u64;
 }
 
+/// Create a raw frame sink from configuration
+///
+/// Only outputs for which `Output::is_raw` holds have one; the pipeline
+/// hands them processed frames and skips encoding.
+pub async fn create_raw_output(output: Output) -> Result<Box<dyn RawOutputSink>> {
+    match output {
+        Output::VirtualCamera { name } => Ok(Box::new(VirtualCamera::new(name))),
+        Output::Encoded { output, .. } => Box::pin(create_raw_output(*output)).await,
+        _ => Err(Error::Config(
+            "Output takes encoded packets, not raw frames".into(),
+        )),
+    }
+}
+
 /// Create an output sink from configuration
 pub async fn create_output(output: Output) -> Result<Box<dyn OutputSink>> {
     match output {
@@ -198,29 +1226,169 @@ pub async fn create_output(output: Output) -> Result<Box<dyn OutputSink>> {
             let camera = VirtualCamera::new(name);
             Ok(Box::new(camera))
         }
-        Output::File { path, container } => {
-            let file = FileOutput::new(path, container);
+        Output::File {
+            path,
+            container,
+            flush_policy,
+            ts_options,
+            extra_options,
+            create_dirs,
+            timecode_start,
+            ..
+        } => {
+            let mut file = FileOutput::new(path, container)
+                .with_extra_options(extra_options)
+                .with_create_dirs(create_dirs);
+            if let Some(start) = timecode_start {
+                file = file.with_timecode(start);
+            }
+            if let Some(policy) = flush_policy {
+                file = file.with_flush_policy(policy);
+            }
+            if let Some(options) = ts_options {
+                file = file.with_ts_options(options);
+            }
             Ok(Box::new(file))
         }
-        Output::Rtmp { url } => {
-            let rtmp = RtmpOutput::new(url);
+        Output::Rtmp {
+            url,
+            flush_policy,
+            enhanced,
+            extra_options,
+        } => {
+            let mut rtmp = RtmpOutput::new(url)
+                .with_enhanced_rtmp(enhanced)
+                .with_extra_options(extra_options);
+            if let Some(policy) = flush_policy {
+                rtmp = rtmp.with_flush_policy(policy);
+            }
             Ok(Box::new(rtmp))
         }
-        Output::Srt { url, latency_ms } => {
-            let srt = SrtOutput::new(url, latency_ms);
+        Output::Srt {
+            url,
+            latency_ms,
+            adaptive_bitrate,
+            flush_policy,
+            extra_options,
+        } => {
+            let mut srt = SrtOutput::new(url, latency_ms).with_extra_options(extra_options);
+            if let Some(abr) = adaptive_bitrate {
+                srt = srt.with_adaptive_bitrate(abr);
+            }
+            if let Some(policy) = flush_policy {
+                srt = srt.with_flush_policy(policy);
+            }
             Ok(Box::new(srt))
         }
+        Output::SrtListener {
+            url,
+            latency_ms,
+            max_subscribers,
+        } => Ok(Box::new(SrtListenerOutput::new(
+            url,
+            latency_ms,
+            max_subscribers,
+        ))),
+        Output::ReplayBuffer { duration_secs } => {
+            Ok(Box::new(ReplayBufferOutput::new(duration_secs)))
+        }
         Output::Multiple(outputs) => {
             let multi = MultiOutput::new(outputs).await?;
             Ok(Box::new(multi))
         }
-        Output::Null => Ok(Box::new(NullOutput::default())),
+        Output::Encoded { output, .. } => Box::pin(create_output(*output)).await,
+        Output::Memory(memory) => Ok(Box::new(memory)),
+        // Served from captured frames by the pipeline; packets aren't used
+        Output::MjpegHttp { .. } | Output::Null => Ok(Box::new(NullOutput::default())),
     }
 }
 
+/// Create the sink for one destination of a `MultiOutput`
+///
+/// Built directly rather than through `create_output` to avoid async
+/// recursion; None for nested multi-outputs, which aren't supported.
+fn create_branch_sink(config: Output) -> Option<Box<dyn OutputSink>> {
+    let output: Box<dyn OutputSink> = match config {
+        Output::VirtualCamera { name } => Box::new(VirtualCamera::new(name)),
+        Output::File {
+            path,
+            container,
+            flush_policy,
+            ts_options,
+            extra_options,
+            create_dirs,
+            timecode_start,
+            ..
+        } => {
+            let mut file = FileOutput::new(path, container)
+                .with_extra_options(extra_options)
+                .with_create_dirs(create_dirs);
+            if let Some(start) = timecode_start {
+                file = file.with_timecode(start);
+            }
+            if let Some(policy) = flush_policy {
+                file = file.with_flush_policy(policy);
+            }
+            if let Some(options) = ts_options {
+                file = file.with_ts_options(options);
+            }
+            Box::new(file)
+        }
+        Output::Rtmp {
+            url,
+            flush_policy,
+            enhanced,
+            extra_options,
+        } => {
+            let mut rtmp = RtmpOutput::new(url)
+                .with_enhanced_rtmp(enhanced)
+                .with_extra_options(extra_options);
+            if let Some(policy) = flush_policy {
+                rtmp = rtmp.with_flush_policy(policy);
+            }
+            Box::new(rtmp)
+        }
+        Output::Srt {
+            url,
+            latency_ms,
+            adaptive_bitrate,
+            flush_policy,
+            extra_options,
+        } => {
+            let mut srt = SrtOutput::new(url, latency_ms).with_extra_options(extra_options);
+            if let Some(abr) = adaptive_bitrate {
+                srt = srt.with_adaptive_bitrate(abr);
+            }
+            if let Some(policy) = flush_policy {
+                srt = srt.with_flush_policy(policy);
+            }
+            Box::new(srt)
+        }
+        Output::SrtListener {
+            url,
+            latency_ms,
+            max_subscribers,
+        } => Box::new(SrtListenerOutput::new(url, latency_ms, max_subscribers)),
+        Output::ReplayBuffer { duration_secs } => Box::new(ReplayBufferOutput::new(duration_secs)),
+        Output::Multiple(_) | Output::Encoded { .. } => {
+            tracing::warn!("Nested multi-output not supported, skipping");
+            return None;
+        }
+        Output::Memory(memory) => Box::new(memory),
+        Output::MjpegHttp { .. } | Output::Null => Box::new(NullOutput::default()),
+    };
+    Some(output)
+}
+
 /// Multi-output that writes to multiple destinations simultaneously
+///
+/// Stream destinations (see `Output::is_stream`) are written on a task of
+/// their own and reconnect behind a bounded packet buffer, so a stream
+/// dropping doesn't interrupt a recording next to it.
 pub struct MultiOutput {
     outputs: Vec<Box<dyn OutputSink>>,
+    /// Time the last write to each output took
+    write_times: Vec<Duration>,
 }
 
 impl MultiOutput {
@@ -229,17 +1397,19 @@ impl MultiOutput {
         let mut outputs = Vec::with_capacity(configs.len());
 
         for config in configs {
-            // Create each output directly to avoid async recursion
-            let output: Box<dyn OutputSink> = match config {
-                Output::VirtualCamera { name } => Box::new(VirtualCamera::new(name)),
-                Output::File { path, container } => Box::new(FileOutput::new(path, container)),
-                Output::Rtmp { url } => Box::new(RtmpOutput::new(url)),
-                Output::Srt { url, latency_ms } => Box::new(SrtOutput::new(url, latency_ms)),
-                Output::Multiple(_) => {
-                    tracing::warn!("Nested multi-output not supported, skipping");
-                    continue;
+            // Encoder settings are handled by the pipeline; packets arriving
+            // here are already encoded for this destination
+            let (_, config) = config.without_encoder();
+
+            // Streams run on a task of their own so a stalled or dropped
+            // connection doesn't hold up the other destinations
+            let output: Box<dyn OutputSink> = if config.is_stream() {
+                Box::new(StreamBranch::new(config))
+            } else {
+                match create_branch_sink(config) {
+                    Some(output) => output,
+                    None => continue,
                 }
-                Output::Null => Box::new(NullOutput::default()),
             };
             outputs.push(output);
         }
@@ -251,7 +1421,11 @@ impl MultiOutput {
         }
 
         tracing::info!("Multi-output created with {} destinations", outputs.len());
-        Ok(Self { outputs })
+        let write_times = vec![Duration::ZERO; outputs.len()];
+        Ok(Self {
+            outputs,
+            write_times,
+        })
     }
 }
 
@@ -278,10 +1452,12 @@ impl OutputSink for MultiOutput {
     async fn write(&mut self, packet: &Packet) -> Result<()> {
         // Write to all outputs, continuing even if some fail
         for (i, output) in self.outputs.iter_mut().enumerate() {
+            let started = std::time::Instant::now();
             if let Err(e) = output.write(packet).await {
                 tracing::error!("Output {} write error: {}", i, e);
                 // Continue writing to other outputs
             }
+            self.write_times[i] = started.elapsed();
         }
         Ok(())
     }
@@ -308,6 +1484,59 @@ impl OutputSink for MultiOutput {
         // Return max bytes across all outputs (they should all be roughly the same)
         self.outputs.iter().map(|o| o.bytes_written()).max().unwrap_or(0)
     }
+
+    fn take_bitrate_request(&mut self) -> Option<u32> {
+        // All destinations here share one encoder; honor the most conservative request
+        self.outputs
+            .iter_mut()
+            .filter_map(|o| o.take_bitrate_request())
+            .min()
+    }
+
+    fn replay_buffer(&self) -> Option<ReplayBuffer> {
+        self.outputs.iter().find_map(|o| o.replay_buffer())
+    }
+
+    fn send_buffers(&self) -> Vec<SendBuffer> {
+        self.outputs.iter().flat_map(|o| o.send_buffers()).collect()
+    }
+
+    fn set_comment(&mut self, comment: &str) {
+        for output in &mut self.outputs {
+            output.set_comment(comment);
+        }
+    }
+
+    fn set_chapters(&mut self, chapters: &[Chapter]) {
+        for output in &mut self.outputs {
+            output.set_chapters(chapters);
+        }
+    }
+
+    fn destination_writes(&self) -> Vec<WriteTiming> {
+        // Stream branches only queue here; their sender knows the real cost
+        self.outputs
+            .iter()
+            .zip(&self.write_times)
+            .map(|(output, &write_time)| {
+                let own = output.destination_writes();
+                match own[..] {
+                    [timing] => timing,
+                    _ => WriteTiming {
+                        write_time,
+                        ..Default::default()
+                    },
+                }
+            })
+            .collect()
+    }
+
+    fn destination_subscribers(&self) -> Vec<Vec<SubscriberStats>> {
+        self.outputs
+            .iter()
+            .map(|o| o.destination_subscribers().concat())
+            .collect()
+    }
 }
 
 /// Null output (discards all packets)
@@ -335,3 +1564,96 @@ impl OutputSink for NullOutput {
         self.bytes
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid(output: Output) -> bool {
+        output.validate().is_ok()
+    }
+
+    #[test]
+    fn test_validate_urls() {
+        assert!(valid(Output::rtmp("rtmp://live.twitch.tv/app/key")));
+        assert!(valid(Output::rtmp("rtmps://host:443/app")));
+        assert!(!valid(Output::rtmp("rtmp:/host/app")));
+        assert!(!valid(Output::rtmp("rtmp:///app")));
+
+        assert!(valid(Output::srt("srt://127.0.0.1:9000?latency=200", 200)));
+        assert!(valid(Output::srt_listener("srt://:9000", 200, 0)));
+        assert!(!valid(Output::srt("srt://host", 200)));
+        assert!(!valid(Output::srt("srt://host:port", 200)));
+    }
+
+    #[test]
+    fn test_prepare_output_dir() {
+        let root = std::env::temp_dir().join(format!("ghoststream-dirs-{}", std::process::id()));
+        let file = root.join("a/b/clip.mkv");
+
+        let err = prepare_output_dir(&file, false).unwrap_err();
+        assert!(err.to_string().contains("directory does not exist"));
+        prepare_output_dir(&file, true).unwrap();
+        assert!(root.join("a/b").is_dir());
+        assert!(prepare_output_dir(Path::new("clip.mkv"), false).is_ok());
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_validate_file_extension() {
+        assert!(valid(Output::file("clip.mkv", Container::Matroska)));
+        assert!(valid(Output::file("clip.MP4", Container::Mp4)));
+        // Mismatches are written anyway (the CLI falls back to Matroska)
+        assert!(valid(Output::file("clip.mp4", Container::Matroska)));
+        assert!(valid(Output::file("recording", Container::Matroska)));
+    }
+
+    #[test]
+    fn test_mp4_faststart() {
+        let ts = TsOptions::default();
+        let options = Container::Matroska.muxer_options(&ts);
+        assert!(options.get("movflags").is_none());
+
+        let options = Container::Mp4.muxer_options(&ts);
+        assert_eq!(options.get("movflags"), Some("+faststart"));
+
+        // User movflags replace the default
+        let extra = HashMap::from([("movflags".to_string(), "+frag_keyframe".to_string())]);
+        let merged = merge_options(options, &extra);
+        assert_eq!(merged.get("movflags"), Some("+frag_keyframe"));
+    }
+
+    #[test]
+    fn test_stream_pixel_format() {
+        use crate::config::ChromaFormat;
+        use ffmpeg::ffi::AVPixelFormat;
+        use ffmpeg::format::Pixel;
+
+        let stream_format = |pixel| {
+            let (chroma_format, bit_depth) = crate::encode::coded_format(pixel);
+            stream_pixel_format(&CodecParams {
+                chroma_format,
+                bit_depth,
+                ..Default::default()
+            })
+        };
+        let expected = [
+            (Pixel::NV12, AVPixelFormat::AV_PIX_FMT_YUV420P),
+            (Pixel::P010LE, AVPixelFormat::AV_PIX_FMT_YUV420P10LE),
+            (Pixel::YUV444P, AVPixelFormat::AV_PIX_FMT_YUV444P),
+            (Pixel::YUV444P10LE, AVPixelFormat::AV_PIX_FMT_YUV444P10LE),
+        ];
+        for (pixel, format) in expected {
+            assert_eq!(stream_format(pixel), format, "{:?}", pixel);
+        }
+        assert_eq!(CodecParams::default().chroma_format, ChromaFormat::Yuv420);
+    }
+
+    #[tokio::test]
+    async fn test_raw_outputs() {
+        assert!(Output::virtual_camera("cam").is_raw());
+        assert!(!Output::file("clip.mkv", Container::Matroska).is_raw());
+        assert!(create_raw_output(Output::Null).await.is_err());
+    }
+}
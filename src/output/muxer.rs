@@ -5,10 +5,17 @@
 use crate::audio::{AudioParams, AudioPacket};
 use crate::encode::Codec;
 use crate::error::{Error, Result};
-use crate::types::{CodecParams, Packet};
+use crate::types::{CodecParams, Packet, Timecode};
+
+use super::subtitle::{SubtitlePacket, SubtitleParams};
+use super::{
+    mp4_muxer_options, set_stream_hdr, set_stream_timecode, write_chapters,
+    write_header_with_options, AvioFlusher, Chapter, FlushPolicy, TsOptions,
+};
 
 use ffmpeg_next as ffmpeg;
 use ffmpeg_next::codec::Id as CodecId;
+use std::collections::HashMap;
 use std::path::Path;
 use std::sync::atomic::{AtomicU64, Ordering};
 
@@ -17,13 +24,15 @@ use std::sync::atomic::{AtomicU64, Ordering};
 pub enum StreamType {
     Video,
     Audio,
+    Subtitle,
 }
 
-/// Muxer packet (either video or audio)
+/// Muxer packet (video, audio or subtitle)
 #[derive(Debug)]
 pub enum MuxerPacket {
     Video(Packet),
     Audio(AudioPacket),
+    Subtitle(SubtitlePacket),
 }
 
 impl MuxerPacket {
@@ -31,6 +40,7 @@ impl MuxerPacket {
         match self {
             MuxerPacket::Video(_) => StreamType::Video,
             MuxerPacket::Audio(_) => StreamType::Audio,
+            MuxerPacket::Subtitle(_) => StreamType::Subtitle,
         }
     }
 
@@ -38,27 +48,50 @@ impl MuxerPacket {
         match self {
             MuxerPacket::Video(p) => p.pts,
             MuxerPacket::Audio(p) => p.pts,
+            MuxerPacket::Subtitle(p) => p.pts,
         }
     }
 }
 
 /// A/V Muxer for file output
+///
+/// Holds one or more video tracks (e.g. screen and webcam, for editing
+/// later) and any number of audio tracks (e.g. desktop audio and a
+/// microphone). Matroska and MP4 both take any number of streams; all
+/// tracks share the session's zero, so their timestamps line up. Subtitle
+/// tracks are passed through as-is (see `add_subtitle_stream`).
 pub struct AvMuxer {
     output_ctx: ffmpeg::format::context::Output,
-    video_stream_index: usize,
-    audio_stream_index: Option<usize>,
-    video_time_base: ffmpeg::Rational,
-    audio_time_base: Option<ffmpeg::Rational>,
+    /// Stream index and time base of each video track, in the order added
+    video_streams: Vec<(usize, ffmpeg::Rational)>,
+    /// Stream index and time base of each audio track, in the order added
+    audio_streams: Vec<(usize, ffmpeg::Rational)>,
+    /// Stream index of each subtitle track, in the order added
+    subtitle_streams: Vec<usize>,
     initialized: bool,
     bytes_written: AtomicU64,
     video_frames: u64,
     audio_frames: u64,
+    subtitle_packets: u64,
+    flusher: AvioFlusher,
+    ts_options: Option<TsOptions>,
+    /// MP4 output: move the moov atom to the front on finish
+    faststart: bool,
+    comment: Option<String>,
+    /// SMPTE timecode of the first video track's first frame
+    timecode: Option<Timecode>,
+    /// Chapter markers written on finish
+    chapters: Vec<Chapter>,
+    extra_options: HashMap<String, String>,
 }
 
 impl AvMuxer {
     /// Create a new muxer for a file
+    ///
+    /// The file's directory has to exist (see `Output::File::create_dirs`).
     pub fn new(path: impl AsRef<Path>, format: &str) -> Result<Self> {
         ffmpeg::init().map_err(|e| Error::Ffmpeg(e.to_string()))?;
+        super::prepare_output_dir(path.as_ref(), false)?;
 
         let path_str = path.as_ref().to_string_lossy();
         let output_ctx = ffmpeg::format::output_as(&*path_str, format)
@@ -66,19 +99,72 @@ impl AvMuxer {
 
         Ok(Self {
             output_ctx,
-            video_stream_index: 0,
-            audio_stream_index: None,
-            video_time_base: ffmpeg::Rational::new(1, 1000),
-            audio_time_base: None,
+            video_streams: Vec::new(),
+            audio_streams: Vec::new(),
+            subtitle_streams: Vec::new(),
             initialized: false,
             bytes_written: AtomicU64::new(0),
             video_frames: 0,
             audio_frames: 0,
+            subtitle_packets: 0,
+            flusher: AvioFlusher::new(FlushPolicy::RECORDING),
+            ts_options: (format == "mpegts").then(TsOptions::default),
+            faststart: format == "mp4",
+            comment: None,
+            timecode: None,
+            chapters: Vec::new(),
+            extra_options: HashMap::new(),
         })
     }
 
-    /// Add video stream
-    pub fn add_video_stream(&mut self, params: &CodecParams) -> Result<()> {
+    /// Set how writes are batched (default `FlushPolicy::RECORDING`)
+    pub fn with_flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.flusher = AvioFlusher::new(policy);
+        self
+    }
+
+    /// Set MPEG-TS muxer settings (only used with the "mpegts" format)
+    pub fn with_ts_options(mut self, options: TsOptions) -> Self {
+        if self.ts_options.is_some() {
+            self.ts_options = Some(options);
+        }
+        self
+    }
+
+    /// Raw FFmpeg muxer options applied after the library's own (e.g.
+    /// `movflags=+faststart` for MP4)
+    pub fn with_extra_options(mut self, options: HashMap<String, String>) -> Self {
+        self.extra_options = options;
+        self
+    }
+
+    /// Write `comment` into the container's `comment` metadata tag
+    pub fn with_comment(mut self, comment: impl Into<String>) -> Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    /// Tag the first video track with SMPTE timecode `start` for its first
+    /// frame (a `tmcd` track in MP4/MOV, a TIMECODE tag in Matroska); set
+    /// before adding streams
+    pub fn with_timecode(mut self, start: Timecode) -> Self {
+        self.timecode = Some(start);
+        self
+    }
+
+    /// Chapter markers to write when the file is finished; replaces any
+    /// set before
+    pub fn set_chapters(&mut self, chapters: &[Chapter]) {
+        self.chapters = chapters.to_vec();
+    }
+
+    /// Add a video track; returns its track index for `write_video_to`
+    ///
+    /// The first video track (index 0) is the one `write_video` writes to.
+    pub fn add_video_stream(&mut self, params: &CodecParams) -> Result<usize> {
+        if self.initialized {
+            return Err(Error::Muxer("Cannot add streams after start".into()));
+        }
         let codec_id = Self::video_codec_to_ffmpeg(params.codec);
         let codec = ffmpeg::encoder::find(codec_id)
             .ok_or_else(|| Error::Muxer(format!("Video codec {:?} not found", codec_id)))?;
@@ -87,7 +173,7 @@ impl AvMuxer {
             .add_stream(codec)
             .map_err(|e| Error::Muxer(format!("Failed to add video stream: {}", e)))?;
 
-        self.video_stream_index = stream.index();
+        let stream_index = stream.index();
 
         // Configure stream parameters
         unsafe {
@@ -101,54 +187,43 @@ impl AvMuxer {
             (*codec_ctx).format = ffmpeg_next::ffi::AVPixelFormat::AV_PIX_FMT_YUV420P as i32;
             (*codec_ctx).bit_rate = params.bitrate;
 
-            // Set extradata
-            if !params.extradata.is_empty() {
-                let extradata_size = params.extradata.len();
-                let extradata_ptr = ffmpeg_next::ffi::av_malloc(
-                    extradata_size + ffmpeg_next::ffi::AV_INPUT_BUFFER_PADDING_SIZE as usize,
-                ) as *mut u8;
-
-                if !extradata_ptr.is_null() {
-                    std::ptr::copy_nonoverlapping(
-                        params.extradata.as_ptr(),
-                        extradata_ptr,
-                        extradata_size,
-                    );
-                    std::ptr::write_bytes(
-                        extradata_ptr.add(extradata_size),
-                        0,
-                        ffmpeg_next::ffi::AV_INPUT_BUFFER_PADDING_SIZE as usize,
-                    );
-                    (*codec_ctx).extradata = extradata_ptr;
-                    (*codec_ctx).extradata_size = extradata_size as i32;
-                }
-            }
+            set_extradata(codec_ctx, &params.extradata);
+            set_stream_hdr(codec_ctx, params);
         }
 
         // Set time base
-        self.video_time_base = ffmpeg::Rational::new(
-            params.time_base_num,
-            params.time_base_den,
-        );
-        stream.set_time_base(self.video_time_base);
+        let time_base = ffmpeg::Rational::new(params.time_base_num, params.time_base_den);
+        stream.set_time_base(time_base);
 
         // Set framerate
         let fps = params.framerate.num as i32;
         stream.set_rate(ffmpeg::Rational::new(fps, 1));
 
+        if let Some(timecode) = self.timecode.filter(|_| self.video_streams.is_empty()) {
+            set_stream_timecode(&mut stream, timecode, params.framerate);
+        }
+
+        self.video_streams.push((stream_index, time_base));
+        let track = self.video_streams.len() - 1;
         tracing::info!(
-            "Added video stream: {:?} {}x{} @ {}fps",
+            "Added video track {}: {:?} {}x{} @ {}fps",
+            track,
             params.codec,
             params.resolution.width,
             params.resolution.height,
             fps
         );
 
-        Ok(())
+        Ok(track)
     }
 
-    /// Add audio stream
-    pub fn add_audio_stream(&mut self, params: &AudioParams) -> Result<()> {
+    /// Add an audio track; returns its track index for `write_audio_to`
+    ///
+    /// The first audio track (index 0) is the one `write_audio` writes to.
+    pub fn add_audio_stream(&mut self, params: &AudioParams) -> Result<usize> {
+        if self.initialized {
+            return Err(Error::Muxer("Cannot add streams after start".into()));
+        }
         let codec_id = Self::audio_codec_to_ffmpeg(params.codec);
         let codec = ffmpeg::encoder::find(codec_id)
             .ok_or_else(|| Error::Muxer(format!("Audio codec {:?} not found", codec_id)))?;
@@ -157,7 +232,7 @@ impl AvMuxer {
             .add_stream(codec)
             .map_err(|e| Error::Muxer(format!("Failed to add audio stream: {}", e)))?;
 
-        self.audio_stream_index = Some(stream.index());
+        let stream_index = stream.index();
 
         // Configure stream parameters
         unsafe {
@@ -173,44 +248,72 @@ impl AvMuxer {
             let ch_layout = &mut (*codec_ctx).ch_layout;
             ffmpeg_next::ffi::av_channel_layout_default(ch_layout, params.channels as i32);
 
-            // Set extradata if available
-            if !params.extradata.is_empty() {
-                let extradata_size = params.extradata.len();
-                let extradata_ptr = ffmpeg_next::ffi::av_malloc(
-                    extradata_size + ffmpeg_next::ffi::AV_INPUT_BUFFER_PADDING_SIZE as usize,
-                ) as *mut u8;
-
-                if !extradata_ptr.is_null() {
-                    std::ptr::copy_nonoverlapping(
-                        params.extradata.as_ptr(),
-                        extradata_ptr,
-                        extradata_size,
-                    );
-                    std::ptr::write_bytes(
-                        extradata_ptr.add(extradata_size),
-                        0,
-                        ffmpeg_next::ffi::AV_INPUT_BUFFER_PADDING_SIZE as usize,
-                    );
-                    (*codec_ctx).extradata = extradata_ptr;
-                    (*codec_ctx).extradata_size = extradata_size as i32;
-                }
-            }
+            set_extradata(codec_ctx, &params.extradata);
         }
 
         // Set audio time base (1/sample_rate)
         let audio_time_base = ffmpeg::Rational::new(1, params.sample_rate as i32);
-        self.audio_time_base = Some(audio_time_base);
         stream.set_time_base(audio_time_base);
+        let track = self.audio_streams.len();
+        self.audio_streams.push((stream_index, audio_time_base));
 
         tracing::info!(
-            "Added audio stream: {:?} {}Hz {}ch @ {}kbps",
+            "Added audio track {}: {:?} {}Hz {}ch @ {}kbps",
+            track,
             params.codec,
             params.sample_rate,
             params.channels,
             params.bitrate / 1000
         );
 
-        Ok(())
+        Ok(track)
+    }
+
+    /// Add a subtitle track; returns its track index for `write_subtitle_to`
+    ///
+    /// Packets are passed through without decoding, so the container has to
+    /// take the codec as-is (e.g. SubRip/ASS in MKV, mov_text or EIA-608 in
+    /// MP4, DVB subtitles in MPEG-TS); otherwise `start` fails.
+    pub fn add_subtitle_stream(&mut self, params: &SubtitleParams) -> Result<usize> {
+        if self.initialized {
+            return Err(Error::Muxer("Cannot add streams after start".into()));
+        }
+        let codec_id = params.codec.to_ffmpeg();
+        // Most subtitle codecs have no encoder; the stream only needs the id
+        let mut stream = self
+            .output_ctx
+            .add_stream(codec_id)
+            .map_err(|e| Error::Muxer(format!("Failed to add subtitle stream: {}", e)))?;
+
+        let stream_index = stream.index();
+
+        unsafe {
+            let mut stream_params = stream.parameters();
+            let codec_ctx = stream_params.as_mut_ptr();
+
+            (*codec_ctx).codec_type = ffmpeg_next::ffi::AVMediaType::AVMEDIA_TYPE_SUBTITLE;
+            (*codec_ctx).codec_id = codec_id.into();
+            set_extradata(codec_ctx, &params.extradata);
+        }
+
+        // Microseconds, like the packet timestamps; the muxer may pick its own
+        stream.set_time_base(ffmpeg::Rational::new(1, 1_000_000));
+        if let Some(ref language) = params.language {
+            let mut metadata = ffmpeg::Dictionary::new();
+            metadata.set("language", language);
+            stream.set_metadata(metadata);
+        }
+
+        let track = self.subtitle_streams.len();
+        self.subtitle_streams.push(stream_index);
+        tracing::info!(
+            "Added subtitle track {}: {:?} ({})",
+            track,
+            params.codec,
+            params.language.as_deref().unwrap_or("und")
+        );
+
+        Ok(track)
     }
 
     /// Start muxing (write header)
@@ -219,8 +322,19 @@ impl AvMuxer {
             return Ok(());
         }
 
-        self.output_ctx
-            .write_header()
+        if let Some(ref comment) = self.comment {
+            let mut metadata = ffmpeg::Dictionary::new();
+            metadata.set("comment", comment);
+            self.output_ctx.set_metadata(metadata);
+        }
+
+        self.flusher.configure(&mut self.output_ctx);
+        let options = match self.ts_options {
+            Some(options) => options.muxer_options(),
+            None if self.faststart => mp4_muxer_options(),
+            None => ffmpeg::Dictionary::new(),
+        };
+        write_header_with_options(&mut self.output_ctx, options, &self.extra_options)
             .map_err(|e| Error::Muxer(format!("Failed to write header: {}", e)))?;
 
         self.initialized = true;
@@ -229,30 +343,40 @@ impl AvMuxer {
         Ok(())
     }
 
-    /// Write a video packet
+    /// Write a packet to the first video track
     pub fn write_video(&mut self, packet: &Packet) -> Result<()> {
+        self.write_video_to(0, packet)
+    }
+
+    /// Write a packet to the video track returned by `add_video_stream`
+    pub fn write_video_to(&mut self, track: usize, packet: &Packet) -> Result<()> {
         if !self.initialized {
             return Err(Error::Muxer("Muxer not started".into()));
         }
+        let (stream_index, time_base) = *self
+            .video_streams
+            .get(track)
+            .ok_or_else(|| Error::Muxer(format!("No video track {}", track)))?;
 
         let mut pkt = ffmpeg::Packet::copy(&packet.data);
         pkt.set_pts(Some(packet.pts));
         pkt.set_dts(Some(packet.dts));
         pkt.set_duration(packet.duration);
-        pkt.set_stream(self.video_stream_index);
+        pkt.set_stream(stream_index);
 
-        if packet.is_keyframe {
+        if packet.is_keyframe() {
             pkt.set_flags(ffmpeg::codec::packet::Flags::KEY);
         }
 
         // Rescale timestamps
-        let stream = self.output_ctx.stream(self.video_stream_index)
+        let stream = self.output_ctx.stream(stream_index)
             .ok_or_else(|| Error::Muxer("Video stream not found".into()))?;
-        pkt.rescale_ts(self.video_time_base, stream.time_base());
+        pkt.rescale_ts(time_base, stream.time_base());
 
         // Write packet
         pkt.write_interleaved(&mut self.output_ctx)
             .map_err(|e| Error::Muxer(format!("Failed to write video packet: {}", e)))?;
+        self.flusher.written(&mut self.output_ctx, packet.data.len());
 
         self.video_frames += 1;
         self.bytes_written.fetch_add(packet.data.len() as u64, Ordering::Relaxed);
@@ -260,17 +384,20 @@ impl AvMuxer {
         Ok(())
     }
 
-    /// Write an audio packet
+    /// Write a packet to the first audio track
     pub fn write_audio(&mut self, packet: &AudioPacket) -> Result<()> {
+        self.write_audio_to(0, packet)
+    }
+
+    /// Write a packet to the audio track returned by `add_audio_stream`
+    pub fn write_audio_to(&mut self, track: usize, packet: &AudioPacket) -> Result<()> {
         if !self.initialized {
             return Err(Error::Muxer("Muxer not started".into()));
         }
-
-        let stream_index = self.audio_stream_index
-            .ok_or_else(|| Error::Muxer("No audio stream configured".into()))?;
-
-        let audio_time_base = self.audio_time_base
-            .ok_or_else(|| Error::Muxer("Audio time base not set".into()))?;
+        let (stream_index, audio_time_base) = *self
+            .audio_streams
+            .get(track)
+            .ok_or_else(|| Error::Muxer(format!("No audio track {}", track)))?;
 
         let mut pkt = ffmpeg::Packet::copy(&packet.data);
         pkt.set_pts(Some(packet.pts));
@@ -286,6 +413,7 @@ impl AvMuxer {
         // Write packet
         pkt.write_interleaved(&mut self.output_ctx)
             .map_err(|e| Error::Muxer(format!("Failed to write audio packet: {}", e)))?;
+        self.flusher.written(&mut self.output_ctx, packet.data.len());
 
         self.audio_frames += 1;
         self.bytes_written.fetch_add(packet.data.len() as u64, Ordering::Relaxed);
@@ -293,11 +421,50 @@ impl AvMuxer {
         Ok(())
     }
 
-    /// Write a muxer packet (either video or audio)
+    /// Write a packet to the first subtitle track
+    pub fn write_subtitle(&mut self, packet: &SubtitlePacket) -> Result<()> {
+        self.write_subtitle_to(0, packet)
+    }
+
+    /// Write a packet to the subtitle track returned by `add_subtitle_stream`
+    pub fn write_subtitle_to(&mut self, track: usize, packet: &SubtitlePacket) -> Result<()> {
+        if !self.initialized {
+            return Err(Error::Muxer("Muxer not started".into()));
+        }
+        let stream_index = *self
+            .subtitle_streams
+            .get(track)
+            .ok_or_else(|| Error::Muxer(format!("No subtitle track {}", track)))?;
+
+        let mut pkt = ffmpeg::Packet::copy(&packet.data);
+        pkt.set_pts(Some(packet.pts));
+        pkt.set_dts(Some(packet.pts));
+        pkt.set_duration(packet.duration);
+        pkt.set_stream(stream_index);
+        pkt.set_flags(ffmpeg::codec::packet::Flags::KEY);
+
+        let stream = self
+            .output_ctx
+            .stream(stream_index)
+            .ok_or_else(|| Error::Muxer("Subtitle stream not found".into()))?;
+        pkt.rescale_ts(ffmpeg::Rational::new(1, 1_000_000), stream.time_base());
+
+        pkt.write_interleaved(&mut self.output_ctx)
+            .map_err(|e| Error::Muxer(format!("Failed to write subtitle packet: {}", e)))?;
+        self.flusher.written(&mut self.output_ctx, packet.data.len());
+
+        self.subtitle_packets += 1;
+        self.bytes_written.fetch_add(packet.data.len() as u64, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Write a muxer packet to the first track of its type
     pub fn write_packet(&mut self, packet: &MuxerPacket) -> Result<()> {
         match packet {
             MuxerPacket::Video(p) => self.write_video(p),
             MuxerPacket::Audio(p) => self.write_audio(p),
+            MuxerPacket::Subtitle(p) => self.write_subtitle(p),
         }
     }
 
@@ -307,15 +474,17 @@ impl AvMuxer {
             return Ok(());
         }
 
+        write_chapters(&mut self.output_ctx, &self.chapters);
         self.output_ctx
             .write_trailer()
             .map_err(|e| Error::Muxer(format!("Failed to write trailer: {}", e)))?;
 
         let bytes = self.bytes_written.load(Ordering::Relaxed);
         tracing::info!(
-            "Muxer finished: {} video frames, {} audio frames, {:.2} MB",
+            "Muxer finished: {} video frames, {} audio frames, {} subtitle packets, {:.2} MB",
             self.video_frames,
             self.audio_frames,
+            self.subtitle_packets,
             bytes as f64 / 1_000_000.0
         );
 
@@ -330,7 +499,22 @@ impl AvMuxer {
 
     /// Check if audio is configured
     pub fn has_audio(&self) -> bool {
-        self.audio_stream_index.is_some()
+        !self.audio_streams.is_empty()
+    }
+
+    /// Number of audio tracks
+    pub fn audio_tracks(&self) -> usize {
+        self.audio_streams.len()
+    }
+
+    /// Number of video tracks
+    pub fn video_tracks(&self) -> usize {
+        self.video_streams.len()
+    }
+
+    /// Number of subtitle tracks
+    pub fn subtitle_tracks(&self) -> usize {
+        self.subtitle_streams.len()
     }
 
     fn video_codec_to_ffmpeg(codec: Codec) -> CodecId {
@@ -351,6 +535,22 @@ impl AvMuxer {
     }
 }
 
+/// Copy `data` into a stream's codec parameters as padded extradata
+unsafe fn set_extradata(par: *mut ffmpeg_next::ffi::AVCodecParameters, data: &[u8]) {
+    if data.is_empty() {
+        return;
+    }
+    let padding = ffmpeg_next::ffi::AV_INPUT_BUFFER_PADDING_SIZE as usize;
+    let ptr = ffmpeg_next::ffi::av_malloc(data.len() + padding) as *mut u8;
+    if ptr.is_null() {
+        return;
+    }
+    std::ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len());
+    std::ptr::write_bytes(ptr.add(data.len()), 0, padding);
+    (*par).extradata = ptr;
+    (*par).extradata_size = data.len() as i32;
+}
+
 impl Drop for AvMuxer {
     fn drop(&mut self) {
         if self.initialized {
@@ -358,3 +558,57 @@ impl Drop for AvMuxer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{
+        is_codec_available, AudioCodec, AudioEncoder, AudioEncoderConfig, AudioFrame,
+        FfmpegAudioEncoder, SampleFormat,
+    };
+
+    #[test]
+    fn test_audio_tracks_round_trip() {
+        if !is_codec_available(AudioCodec::Opus) {
+            return;
+        }
+        let mut encoder =
+            FfmpegAudioEncoder::new(AudioEncoderConfig::default().with_codec(AudioCodec::Opus))
+                .unwrap();
+        encoder.init().unwrap();
+        let mut packets = Vec::new();
+        for i in 0..10 {
+            let mut frame = AudioFrame::new(960, 2, SampleFormat::F32, 48000);
+            frame.pts = i * 20_000;
+            frame.duration = frame.calculated_duration_us();
+            packets.extend(encoder.encode(&frame).unwrap());
+        }
+        packets.extend(encoder.flush().unwrap());
+        assert!(!packets.is_empty());
+        let params = encoder.params().unwrap();
+
+        let name = format!("ghoststream-audio-tracks-{}.mkv", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        let mut muxer = AvMuxer::new(&path, "matroska").unwrap();
+        for track in 0..3 {
+            assert_eq!(muxer.add_audio_stream(&params).unwrap(), track);
+        }
+        muxer.start().unwrap();
+        for track in 0..3 {
+            for packet in &packets {
+                muxer.write_audio_to(track, packet).unwrap();
+            }
+        }
+        assert!(muxer.write_audio_to(3, &packets[0]).is_err());
+        muxer.finish().unwrap();
+        assert_eq!(muxer.audio_tracks(), 3);
+
+        let input = ffmpeg::format::input(&path).unwrap();
+        let audio = input
+            .streams()
+            .filter(|s| s.parameters().medium() == ffmpeg::media::Type::Audio)
+            .count();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(audio, 3);
+    }
+}
@@ -0,0 +1,307 @@
+//! Replay buffer output
+//!
+//! Keeps the last N seconds of encoded video in memory and writes them to
+//! a file on demand (instant replay / clip capture). The buffer always
+//! starts at a keyframe, so a saved clip is decodable and seekable from its
+//! first packet; it may therefore reach up to one GOP further back than the
+//! requested window.
+
+use crate::error::{Error, Result};
+use crate::types::{CodecParams, Packet};
+
+use super::{Container, FileOutput, OutputSink};
+
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Packet held in the ring buffer
+struct BufferedPacket {
+    packet: Packet,
+    received: Instant,
+}
+
+struct ReplayState {
+    window: Duration,
+    codec_params: Option<CodecParams>,
+    packets: VecDeque<BufferedPacket>,
+    bytes: usize,
+    /// Memory cap; whole GOPs are dropped from the front to stay under it
+    max_bytes: Option<usize>,
+}
+
+impl ReplayState {
+    fn push(&mut self, packet: Packet, now: Instant) {
+        self.bytes += packet.size();
+        self.packets.push_back(BufferedPacket {
+            packet,
+            received: now,
+        });
+        self.trim(now);
+    }
+
+    /// Drop everything before the newest keyframe that still covers the
+    /// start of the window
+    fn trim(&mut self, now: Instant) {
+        let cutoff = now.checked_sub(self.window);
+        let start = self
+            .packets
+            .iter()
+            .rposition(|p| p.packet.is_keyframe() && cutoff.is_some_and(|c| p.received <= c))
+            // Nothing old enough yet: only drop packets before the first keyframe
+            .or_else(|| self.packets.iter().position(|p| p.packet.is_keyframe()))
+            .unwrap_or(0);
+
+        for dropped in self.packets.drain(..start) {
+            self.bytes -= dropped.packet.size();
+        }
+
+        // Over the memory cap: cut at the first keyframe that brings it
+        // under, keeping at least the newest GOP
+        let excess = match self.max_bytes {
+            Some(max) if self.bytes > max => self.bytes - max,
+            _ => return,
+        };
+        let mut cut = 0;
+        let mut before = 0;
+        for (index, p) in self.packets.iter().enumerate() {
+            if index > 0 && p.packet.is_keyframe() {
+                cut = index;
+                if before >= excess {
+                    break;
+                }
+            }
+            before += p.packet.size();
+        }
+        for dropped in self.packets.drain(..cut) {
+            self.bytes -= dropped.packet.size();
+        }
+    }
+
+    /// Time span covered by the buffered packets
+    fn duration(&self) -> Duration {
+        match (self.packets.front(), self.packets.back()) {
+            (Some(first), Some(last)) => last.received.duration_since(first.received),
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+/// Handle to a replay buffer for saving clips
+///
+/// Cheap to clone; all clones share the same buffer. Obtain one from
+/// `Pipeline::replay_buffer()` or `ReplayBufferOutput::handle()`.
+#[derive(Clone)]
+pub struct ReplayBuffer {
+    state: Arc<Mutex<ReplayState>>,
+}
+
+impl ReplayBuffer {
+    /// Create an empty buffer holding roughly `window` of video
+    pub fn new(window: Duration) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(ReplayState {
+                window,
+                codec_params: None,
+                packets: VecDeque::new(),
+                bytes: 0,
+                max_bytes: None,
+            })),
+        }
+    }
+
+    /// Configured window
+    pub fn window(&self) -> Duration {
+        self.state.lock().window
+    }
+
+    /// Span of video currently buffered
+    pub fn buffered_duration(&self) -> Duration {
+        self.state.lock().duration()
+    }
+
+    /// Memory used by buffered packets
+    pub fn buffered_bytes(&self) -> usize {
+        self.state.lock().bytes
+    }
+
+    /// Cap the memory used by buffered packets, dropping the oldest GOPs
+    /// (and so shortening the window) once it is reached; None for no cap
+    pub fn set_max_bytes(&self, max_bytes: Option<usize>) {
+        let mut state = self.state.lock();
+        state.max_bytes = max_bytes;
+        state.trim(Instant::now());
+    }
+
+    /// Discard everything buffered so far
+    pub fn clear(&self) {
+        let mut state = self.state.lock();
+        state.packets.clear();
+        state.bytes = 0;
+    }
+
+    /// Write the buffered packets to a file, starting at the oldest keyframe
+    ///
+    /// The container is picked from the file extension (Matroska if
+    /// unknown). Recording continues while the clip is written.
+    pub async fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+
+        // Snapshot under the lock, write without it
+        let (codec_params, packets) = {
+            let state = self.state.lock();
+            let packets: Vec<Packet> = state
+                .packets
+                .iter()
+                .skip_while(|p| !p.packet.is_keyframe())
+                .map(|p| copy_packet(&p.packet))
+                .collect();
+            (state.codec_params.clone(), packets)
+        };
+
+        let first_dts = match packets.first() {
+            Some(first) => first.dts,
+            None => return Err(Error::FileOutput("Replay buffer is empty".into())),
+        };
+
+        let container = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .and_then(Container::from_extension)
+            .unwrap_or_default();
+        let mut file = FileOutput::new(path, container);
+        file.init_with_codec(codec_params.as_ref()).await?;
+
+        // Clip timestamps start at zero
+        let count = packets.len();
+        for mut packet in packets {
+            packet.pts -= first_dts;
+            packet.dts -= first_dts;
+            file.write(&packet).await?;
+        }
+        file.finish().await?;
+
+        tracing::info!("Saved replay ({} packets) to {}", count, path.display());
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for ReplayBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.state.lock();
+        f.debug_struct("ReplayBuffer")
+            .field("window", &state.window)
+            .field("packets", &state.packets.len())
+            .field("bytes", &state.bytes)
+            .finish()
+    }
+}
+
+fn copy_packet(packet: &Packet) -> Packet {
+    Packet {
+        data: packet.data.clone(),
+        ..*packet
+    }
+}
+
+/// Output that feeds a `ReplayBuffer` instead of writing anywhere
+pub struct ReplayBufferOutput {
+    buffer: ReplayBuffer,
+}
+
+impl ReplayBufferOutput {
+    /// Keep the last `duration_secs` seconds of video
+    pub fn new(duration_secs: u32) -> Self {
+        Self {
+            buffer: ReplayBuffer::new(Duration::from_secs(duration_secs as u64)),
+        }
+    }
+
+    /// Handle for saving clips from this output
+    pub fn handle(&self) -> ReplayBuffer {
+        self.buffer.clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl OutputSink for ReplayBufferOutput {
+    async fn init_with_codec(&mut self, codec_params: Option<&CodecParams>) -> Result<()> {
+        let mut state = self.buffer.state.lock();
+        state.codec_params = codec_params.cloned();
+        tracing::info!("Replay buffer ready ({:?} window)", state.window);
+        Ok(())
+    }
+
+    async fn write(&mut self, packet: &Packet) -> Result<()> {
+        self.buffer
+            .state
+            .lock()
+            .push(copy_packet(packet), Instant::now());
+        Ok(())
+    }
+
+    async fn finish(&mut self) -> Result<()> {
+        // Keep the buffer so a clip can still be saved after stopping
+        Ok(())
+    }
+
+    fn bytes_written(&self) -> u64 {
+        self.buffer.buffered_bytes() as u64
+    }
+
+    fn replay_buffer(&self) -> Option<ReplayBuffer> {
+        Some(self.handle())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_starts_at_keyframe_covering_window() {
+        let buffer = ReplayBuffer::new(Duration::from_secs(2));
+        let start = Instant::now();
+        let mut state = buffer.state.lock();
+
+        // One packet per 100ms, keyframe every second
+        for i in 0..50u64 {
+            let packet = Packet::new(vec![0; 10], i as i64, i as i64, i % 10 == 0);
+            state.push(packet, start + Duration::from_millis(i * 100));
+        }
+
+        // Last packet at 4.9s; the window starts at 2.9s, covered by the
+        // keyframe at 2.0s
+        let first = state.packets.front().unwrap();
+        assert!(first.packet.is_keyframe());
+        assert_eq!(first.packet.pts, 20);
+        assert_eq!(state.packets.len(), 30);
+        assert_eq!(state.bytes, 300);
+    }
+
+    #[test]
+    fn test_memory_cap_drops_whole_gops() {
+        let buffer = ReplayBuffer::new(Duration::from_secs(60));
+        buffer.set_max_bytes(Some(250));
+        let start = Instant::now();
+        let mut state = buffer.state.lock();
+
+        for i in 0..50u64 {
+            let packet = Packet::new(vec![0; 10], i as i64, i as i64, i % 10 == 0);
+            state.push(packet, start + Duration::from_millis(i * 100));
+        }
+
+        // 500 bytes over 5 GOPs; three are dropped to get under 250
+        let first = state.packets.front().unwrap();
+        assert_eq!(first.packet.pts, 30);
+        assert_eq!(state.bytes, 200);
+
+        // A single GOP over the cap is kept
+        state.max_bytes = Some(50);
+        state.trim(start + Duration::from_secs(5));
+        assert_eq!(state.packets.front().unwrap().packet.pts, 40);
+        assert_eq!(state.bytes, 100);
+    }
+}
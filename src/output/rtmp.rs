@@ -1,13 +1,20 @@
 //! RTMP streaming output
 //!
 //! Streams video to RTMP servers (Twitch, YouTube, Facebook, etc.)
+//!
+//! H.264 uses the legacy FLV codec IDs every server understands. HEVC and
+//! AV1 need enhanced RTMP (E-RTMP): the client advertises the codec's
+//! FourCC in the connect command's `fourCcList`, and the FLV muxer writes
+//! extended video tags (`PacketTypeSequenceStart`/`CodedFrames`) carrying
+//! that FourCC instead of a legacy codec ID.
 
 use crate::encode::Codec;
 use crate::error::{Error, Result};
 use crate::types::{CodecParams, Packet};
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicU64, Ordering};
 
-use super::OutputSink;
+use super::{merge_options, write_header_with_options, AvioFlusher, FlushPolicy, OutputSink};
 
 use ffmpeg_next as ffmpeg;
 use ffmpeg_next::codec::Id as CodecId;
@@ -28,6 +35,9 @@ pub struct RtmpOutput {
     connected: bool,
     reconnect_attempts: u32,
     max_reconnect_attempts: u32,
+    flusher: AvioFlusher,
+    enhanced: bool,
+    extra_options: HashMap<String, String>,
 }
 
 impl RtmpOutput {
@@ -45,15 +55,40 @@ impl RtmpOutput {
             connected: false,
             reconnect_attempts: 0,
             max_reconnect_attempts: 5,
+            flusher: AvioFlusher::new(FlushPolicy::Immediate),
+            enhanced: false,
+            extra_options: HashMap::new(),
         }
     }
 
+    /// Negotiate enhanced RTMP for HEVC/AV1 (default false)
+    ///
+    /// Only takes effect for non-H.264 codecs; H.264 always uses the legacy
+    /// path. The server must support E-RTMP or it will reject the stream.
+    pub fn with_enhanced_rtmp(mut self, enabled: bool) -> Self {
+        self.enhanced = enabled;
+        self
+    }
+
+    /// Raw FFmpeg options passed when connecting and to the muxer, after
+    /// the library's own
+    pub fn with_extra_options(mut self, options: HashMap<String, String>) -> Self {
+        self.extra_options = options;
+        self
+    }
+
     /// Set maximum reconnection attempts
     pub fn with_max_reconnects(mut self, attempts: u32) -> Self {
         self.max_reconnect_attempts = attempts;
         self
     }
 
+    /// Set how writes are batched (default `FlushPolicy::Immediate`)
+    pub fn with_flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.flusher = AvioFlusher::new(policy);
+        self
+    }
+
     /// Get the RTMP URL (without stream key for privacy)
     pub fn url_masked(&self) -> String {
         // Mask the stream key portion for logging
@@ -73,6 +108,15 @@ impl RtmpOutput {
         }
     }
 
+    /// Enhanced RTMP FourCC for a codec (`None` = legacy FLV codec ID)
+    fn enhanced_fourcc(codec: Codec) -> Option<&'static str> {
+        match codec {
+            Codec::H264 => None,
+            Codec::Hevc => Some("hvc1"),
+            Codec::Av1 => Some("av01"),
+        }
+    }
+
     /// Initialize the RTMP connection
     fn init_rtmp(&mut self, codec_params: &CodecParams) -> Result<()> {
         // Validate URL
@@ -80,11 +124,12 @@ impl RtmpOutput {
             return Err(Error::Rtmp("URL must start with rtmp:// or rtmps://".into()));
         }
 
-        // RTMP only supports H.264 and AAC natively
-        // HEVC/AV1 require enhanced RTMP (not widely supported)
-        if codec_params.codec != Codec::H264 {
+        // Legacy RTMP only carries H.264; HEVC/AV1 need enhanced RTMP
+        let fourcc = Self::enhanced_fourcc(codec_params.codec);
+        if fourcc.is_some() && !self.enhanced {
             tracing::warn!(
-                "RTMP typically only supports H.264. {} may not work with all servers.",
+                "Legacy RTMP only supports H.264. {} may not work with all servers; \
+                 enable enhanced RTMP if the server supports it.",
                 codec_params.codec.display_name()
             );
         }
@@ -96,8 +141,14 @@ impl RtmpOutput {
         let mut options = ffmpeg::Dictionary::new();
         options.set("flvflags", "no_duration_filesize");
         options.set("rtmp_live", "live");
+        if let (Some(fourcc), true) = (fourcc, self.enhanced) {
+            // Advertised as fourCcList in the connect command
+            options.set("rtmp_enhanced_codecs", fourcc);
+            tracing::debug!("Enhanced RTMP: advertising {}", fourcc);
+        }
 
         // Create output context for RTMP (FLV format)
+        let options = merge_options(options, &self.extra_options);
         let mut output_ctx = ffmpeg::format::output_as_with(&self.url, "flv", options)
             .map_err(|e| Error::Rtmp(format!("Failed to create RTMP output: {}", e)))?;
 
@@ -163,19 +214,25 @@ impl RtmpOutput {
         // Write header (this initiates the RTMP connection)
         tracing::info!("Connecting to RTMP server: {}", self.url_masked());
 
-        output_ctx
-            .write_header()
+        self.flusher.configure(&mut output_ctx);
+        let header_options = ffmpeg::Dictionary::new();
+        write_header_with_options(&mut output_ctx, header_options, &self.extra_options)
             .map_err(|e| Error::Rtmp(format!("Failed to connect to RTMP server: {}", e)))?;
 
         self.output_ctx = Some(output_ctx);
         self.connected = true;
 
+        let mode = match (fourcc, self.enhanced) {
+            (Some(_), true) => ", enhanced",
+            _ => "",
+        };
         tracing::info!(
-            "RTMP connected: {} ({:?}, {}x{})",
+            "RTMP connected: {} ({:?}, {}x{}{})",
             self.url_masked(),
             codec_params.codec,
             codec_params.resolution.width,
             codec_params.resolution.height,
+            mode,
         );
 
         Ok(())
@@ -247,7 +304,7 @@ impl OutputSink for RtmpOutput {
         pkt.set_duration(packet.duration);
         pkt.set_stream(self.video_stream_index);
 
-        if packet.is_keyframe {
+        if packet.is_keyframe() {
             pkt.set_flags(ffmpeg::codec::packet::Flags::KEY);
         }
 
@@ -261,6 +318,7 @@ impl OutputSink for RtmpOutput {
         // Write packet
         match pkt.write_interleaved(output_ctx) {
             Ok(()) => {
+                self.flusher.written(output_ctx, packet.size());
                 self.frame_count += 1;
                 self.bytes_written
                     .fetch_add(packet.size() as u64, Ordering::Relaxed);
@@ -5,13 +5,57 @@
 use crate::encode::Codec;
 use crate::error::{Error, Result};
 use crate::types::{CodecParams, Packet};
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
-use super::OutputSink;
+use super::abr::{AbrConfig, AbrController};
+use super::{
+    merge_options, write_header_with_options, AvioFlusher, FlushPolicy, OutputSink, TsOptions,
+};
 
 use ffmpeg_next as ffmpeg;
 use ffmpeg_next::codec::Id as CodecId;
 
+/// Cap on the packets kept for re-sending after a reconnect
+///
+/// A GOP larger than this (very long keyframe intervals at high bitrates)
+/// is not buffered; after a reconnect the stream resumes at the next
+/// keyframe instead.
+const MAX_RESEND_BYTES: usize = 32 * 1024 * 1024;
+
+/// Passphrase lengths libsrt accepts
+pub(super) const PASSPHRASE_LEN: std::ops::RangeInclusive<usize> = 10..=79;
+
+/// Longest stream ID libsrt accepts (bytes)
+const MAX_STREAMID_LEN: usize = 512;
+
+/// Key rotation of an encrypted stream
+///
+/// libsrt switches to a fresh key every `refresh_packets` packets and
+/// announces the next key `preannounce_packets` before and retires the old
+/// one as many after the switch, so the receiver always holds the key in
+/// use. Long-lived feeds rotate so that no key protects too much traffic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyRotation {
+    /// Packets sent with one key (libsrt `kmrefreshrate`)
+    pub refresh_packets: u32,
+    /// Overlap around a switch (libsrt `kmpreannounce`); at most
+    /// `(refresh_packets - 1) / 2`
+    pub preannounce_packets: u32,
+}
+
+impl KeyRotation {
+    /// Rotate every `refresh_packets` packets with libsrt's default
+    /// overlap of 4096 packets, reduced to fit short periods
+    pub fn every(refresh_packets: u32) -> Self {
+        Self {
+            refresh_packets,
+            preannounce_packets: (refresh_packets.saturating_sub(1) / 2).min(4096),
+        }
+    }
+}
+
 /// SRT connection mode
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum SrtMode {
@@ -40,11 +84,31 @@ pub struct SrtOutput {
     frame_count: u64,
     // Connection state
     connected: bool,
+    codec_params: Option<CodecParams>,
+    reconnect_attempts: u32,
+    max_reconnect_attempts: u32,
+    /// Packets since the last keyframe, re-sent after a reconnect
+    resend: Vec<Packet>,
+    resend_bytes: usize,
+    /// The current GOP outgrew `MAX_RESEND_BYTES` and isn't buffered
+    resend_overflow: bool,
+    /// Reconnected mid-GOP without its packets: wait for a keyframe
+    await_keyframe: bool,
     // SRT-specific options
     passphrase: Option<String>,
     streamid: Option<String>,
     pbkeylen: Option<u32>,
+    key_rotation: Option<KeyRotation>,
     max_bandwidth: Option<i64>,
+    // Adaptive bitrate
+    abr: Option<AbrController>,
+    pending_bitrate: Option<u32>,
+    window_start: Instant,
+    window_bytes: u64,
+    window_blocked: Duration,
+    last_stats: SrtStats,
+    flusher: AvioFlusher,
+    extra_options: HashMap<String, String>,
 }
 
 impl SrtOutput {
@@ -66,10 +130,26 @@ impl SrtOutput {
             time_base: ffmpeg::Rational::new(1, 1000),
             frame_count: 0,
             connected: false,
+            codec_params: None,
+            reconnect_attempts: 0,
+            max_reconnect_attempts: 5,
+            resend: Vec::new(),
+            resend_bytes: 0,
+            resend_overflow: false,
+            await_keyframe: false,
             passphrase: None,
             streamid: None,
             pbkeylen: None,
+            key_rotation: None,
             max_bandwidth: None,
+            abr: None,
+            pending_bitrate: None,
+            window_start: Instant::now(),
+            window_bytes: 0,
+            window_blocked: Duration::ZERO,
+            last_stats: SrtStats::default(),
+            flusher: AvioFlusher::new(FlushPolicy::Immediate),
+            extra_options: HashMap::new(),
         }
     }
 
@@ -79,7 +159,8 @@ impl SrtOutput {
         self
     }
 
-    /// Set encryption passphrase (10-79 characters)
+    /// Set encryption passphrase (10-79 characters, checked before
+    /// connecting)
     pub fn with_passphrase(mut self, passphrase: impl Into<String>) -> Self {
         self.passphrase = Some(passphrase.into());
         self
@@ -91,23 +172,191 @@ impl SrtOutput {
         self
     }
 
-    /// Set encryption key length (0, 16, 24, or 32)
+    /// Set encryption key length in bytes (0 = default, 16, 24 or 32)
     pub fn with_key_length(mut self, pbkeylen: u32) -> Self {
         self.pbkeylen = Some(pbkeylen);
         self
     }
 
+    /// Rotate the encryption key periodically (needs a passphrase)
+    pub fn with_key_rotation(mut self, rotation: KeyRotation) -> Self {
+        self.key_rotation = Some(rotation);
+        self
+    }
+
     /// Set maximum bandwidth in bytes/sec (-1 for unlimited)
     pub fn with_max_bandwidth(mut self, bandwidth: i64) -> Self {
         self.max_bandwidth = Some(bandwidth);
         self
     }
 
+    /// Set maximum reconnection attempts after a write failure (default 5)
+    ///
+    /// Attempts back off exponentially from 500ms to 8s; the counter resets
+    /// after the next successful write. 0 disables reconnection.
+    pub fn with_max_reconnects(mut self, attempts: u32) -> Self {
+        self.max_reconnect_attempts = attempts;
+        self
+    }
+
+    /// Raw FFmpeg options passed when connecting and to the muxer, after
+    /// the library's own
+    pub fn with_extra_options(mut self, options: HashMap<String, String>) -> Self {
+        self.extra_options = options;
+        self
+    }
+
+    /// Set how writes are batched (default `FlushPolicy::Immediate`)
+    pub fn with_flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.flusher = AvioFlusher::new(policy);
+        self
+    }
+
+    /// Enable the adaptive bitrate controller
+    ///
+    /// Stats are sampled every `config.interval`; bitrate changes are
+    /// requested from the pipeline through `OutputSink::take_bitrate_request`.
+    pub fn with_adaptive_bitrate(mut self, config: AbrConfig) -> Self {
+        self.abr = Some(AbrController::new(config));
+        self
+    }
+
     /// Get the SRT URL
     pub fn url(&self) -> &str {
         &self.url
     }
 
+    /// Get the SRT URL without query options (which may carry a
+    /// passphrase or stream ID) for logging
+    pub fn url_masked(&self) -> String {
+        match self.url.split_once('?') {
+            Some((base, _)) => format!("{}?*****", base),
+            None => self.url.clone(),
+        }
+    }
+
+    /// Check the encryption and stream ID settings without connecting
+    ///
+    /// Settings given in the URL query or as extra options are checked
+    /// as well as the builder's: libsrt refuses out-of-spec values at
+    /// connect time with an error that doesn't say which one.
+    pub fn validate(&self) -> Result<()> {
+        let passphrases = self.option_values("passphrase", self.passphrase.clone());
+        for passphrase in &passphrases {
+            let len = passphrase.chars().count();
+            if !PASSPHRASE_LEN.contains(&len) {
+                return Err(Error::Srt(format!(
+                    "SRT passphrase must be 10-79 characters, got {}",
+                    len
+                )));
+            }
+        }
+
+        let key_lengths = self.option_values("pbkeylen", self.pbkeylen.map(|l| l.to_string()));
+        for value in &key_lengths {
+            if !matches!(value.parse::<u32>(), Ok(0 | 16 | 24 | 32)) {
+                return Err(Error::Srt(format!(
+                    "SRT key length must be 0, 16, 24 or 32 bytes, got '{}'",
+                    value
+                )));
+            }
+        }
+        let keyed = key_lengths.iter().any(|l| l != "0") || self.key_rotation.is_some();
+        if keyed && passphrases.is_empty() {
+            return Err(Error::Srt(
+                "SRT key length and key rotation need a passphrase".into(),
+            ));
+        }
+
+        if let Some(rotation) = self.key_rotation {
+            let max_preannounce = rotation.refresh_packets.saturating_sub(1) / 2;
+            if rotation.refresh_packets == 0 || rotation.preannounce_packets > max_preannounce {
+                return Err(Error::Srt(format!(
+                    "SRT key pre-announce ({} packets) must be at most half the refresh \
+                     period ({} packets)",
+                    rotation.preannounce_packets, rotation.refresh_packets
+                )));
+            }
+        }
+
+        for streamid in self.option_values("streamid", self.streamid.clone()) {
+            if streamid.len() > MAX_STREAMID_LEN {
+                return Err(Error::Srt(format!(
+                    "SRT stream ID is {} bytes, at most {} are allowed",
+                    streamid.len(),
+                    MAX_STREAMID_LEN
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Values set for an SRT option: by the builder, in the URL query and
+    /// in the extra options
+    fn option_values(&self, key: &str, builder: Option<String>) -> Vec<String> {
+        let query = self.url.split_once('?').map_or("", |(_, query)| query);
+        let from_url = query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .filter(|(name, _)| *name == key)
+            .map(|(_, value)| percent_decode(value));
+        builder
+            .into_iter()
+            .chain(from_url)
+            .chain(self.extra_options.get(key).cloned())
+            .collect()
+    }
+
+    /// Requested latency in milliseconds
+    ///
+    /// libsrt negotiates the larger of both peers' latencies during the
+    /// handshake; FFmpeg does not report the result, so this is the value
+    /// this side asked for.
+    pub fn latency_ms(&self) -> u32 {
+        self.latency_ms
+    }
+
+    /// Connection statistics for the last completed sampling window
+    ///
+    /// FFmpeg does not expose libsrt's RTT/loss counters, so these are
+    /// measured at the socket boundary: throughput, and time spent blocked
+    /// in writes (libsrt blocks when its send buffer is full).
+    pub fn stats(&self) -> SrtStats {
+        self.last_stats.clone()
+    }
+
+    /// Accumulate a write into the current sampling window and run ABR
+    fn record_write(&mut self, bytes: usize, blocked: Duration) {
+        self.window_bytes += bytes as u64;
+        self.window_blocked += blocked;
+
+        let interval = self
+            .abr
+            .as_ref()
+            .map(|abr| abr.config().interval)
+            .unwrap_or(Duration::from_secs(1));
+        let elapsed = self.window_start.elapsed();
+        if elapsed < interval {
+            return;
+        }
+
+        let secs = elapsed.as_secs_f64();
+        self.last_stats = SrtStats {
+            bandwidth_mbps: self.window_bytes as f64 * 8.0 / secs / 1_000_000.0,
+            send_blocked_ms: self.window_blocked.as_secs_f64() * 1000.0,
+            ..Default::default()
+        };
+        self.window_start = Instant::now();
+        self.window_bytes = 0;
+        self.window_blocked = Duration::ZERO;
+
+        if let Some(ref mut abr) = self.abr {
+            if let Some(kbps) = abr.update(&self.last_stats) {
+                self.pending_bitrate = Some(kbps);
+            }
+        }
+    }
+
     /// Map codec to FFmpeg codec ID
     fn codec_to_ffmpeg(codec: Codec) -> CodecId {
         match codec {
@@ -136,14 +385,15 @@ impl SrtOutput {
         // Latency (in microseconds for SRT)
         params.push(format!("latency={}", self.latency_ms * 1000));
 
-        // Passphrase
+        // Passphrase and stream ID are URL-decoded by FFmpeg, so '&' or
+        // '#' in them (access control IDs use '#!::') survive the query
         if let Some(ref passphrase) = self.passphrase {
-            params.push(format!("passphrase={}", passphrase));
+            params.push(format!("passphrase={}", percent_encode(passphrase)));
         }
 
         // Stream ID
         if let Some(ref streamid) = self.streamid {
-            params.push(format!("streamid={}", streamid));
+            params.push(format!("streamid={}", percent_encode(streamid)));
         }
 
         // Key length
@@ -151,6 +401,12 @@ impl SrtOutput {
             params.push(format!("pbkeylen={}", pbkeylen));
         }
 
+        // Key rotation
+        if let Some(rotation) = self.key_rotation {
+            params.push(format!("kmrefreshrate={}", rotation.refresh_packets));
+            params.push(format!("kmpreannounce={}", rotation.preannounce_packets));
+        }
+
         // Max bandwidth
         if let Some(bandwidth) = self.max_bandwidth {
             params.push(format!("maxbw={}", bandwidth));
@@ -168,6 +424,7 @@ impl SrtOutput {
         if !self.url.starts_with("srt://") {
             return Err(Error::Srt("URL must start with srt://".into()));
         }
+        self.validate()?;
 
         // Initialize FFmpeg
         ffmpeg::init().map_err(|e| Error::Ffmpeg(e.to_string()))?;
@@ -176,7 +433,8 @@ impl SrtOutput {
         let full_url = self.build_srt_url();
 
         // Create output context for MPEG-TS over SRT
-        let mut output_ctx = ffmpeg::format::output_as(&full_url, "mpegts")
+        let open_options = merge_options(ffmpeg::Dictionary::new(), &self.extra_options);
+        let mut output_ctx = ffmpeg::format::output_as_with(&full_url, "mpegts", open_options)
             .map_err(|e| Error::Srt(format!("Failed to create SRT output: {}", e)))?;
 
         // Find encoder for codec parameters
@@ -241,21 +499,30 @@ impl SrtOutput {
         // Write header (this initiates the SRT connection)
         tracing::info!(
             "Connecting via SRT: {} (latency: {}ms, mode: {:?})",
-            self.url,
+            self.url_masked(),
             self.latency_ms,
             self.mode
         );
 
-        output_ctx
-            .write_header()
+        self.flusher.configure(&mut output_ctx);
+        let options = TsOptions::default().muxer_options();
+        write_header_with_options(&mut output_ctx, options, &self.extra_options)
             .map_err(|e| Error::Srt(format!("Failed to connect via SRT: {}", e)))?;
 
         self.output_ctx = Some(output_ctx);
         self.connected = true;
+        self.codec_params = Some(codec_params.clone());
+
+        if let Some(ref mut abr) = self.abr {
+            if codec_params.bitrate > 0 {
+                abr.reset((codec_params.bitrate / 1000) as u32);
+            }
+        }
+        self.window_start = Instant::now();
 
         tracing::info!(
             "SRT connected: {} ({:?}, {}x{}, latency: {}ms)",
-            self.url,
+            self.url_masked(),
             codec_params.codec,
             codec_params.resolution.width,
             codec_params.resolution.height,
@@ -270,29 +537,32 @@ impl SrtOutput {
         let default_params = CodecParams::default();
         self.init_srt(&default_params)
     }
-}
 
-#[async_trait::async_trait]
-impl OutputSink for SrtOutput {
-    async fn init_with_codec(&mut self, codec_params: Option<&CodecParams>) -> Result<()> {
-        if self.initialized {
-            return Ok(());
+    /// Keep packets back to the last keyframe for re-sending
+    fn buffer_for_resend(&mut self, packet: &Packet) {
+        if packet.is_keyframe() {
+            self.resend.clear();
+            self.resend_bytes = 0;
+            self.resend_overflow = false;
+        } else if self.resend_overflow {
+            return;
         }
 
-        match codec_params {
-            Some(params) => self.init_srt(params)?,
-            None => self.init_default()?,
+        if self.resend_bytes + packet.size() > MAX_RESEND_BYTES {
+            self.resend.clear();
+            self.resend_bytes = 0;
+            self.resend_overflow = true;
+            return;
         }
-
-        self.initialized = true;
-        Ok(())
+        self.resend_bytes += packet.size();
+        self.resend.push(Packet {
+            data: packet.data.clone(),
+            ..*packet
+        });
     }
 
-    async fn write(&mut self, packet: &Packet) -> Result<()> {
-        if !self.initialized {
-            self.init_with_codec(None).await?;
-        }
-
+    /// Mux one packet into the current connection
+    fn write_packet(&mut self, packet: &Packet) -> Result<()> {
         let output_ctx = self.output_ctx.as_mut().ok_or_else(|| {
             Error::Srt("SRT output not initialized".into())
         })?;
@@ -305,7 +575,7 @@ impl OutputSink for SrtOutput {
         pkt.set_duration(packet.duration);
         pkt.set_stream(self.video_stream_index);
 
-        if packet.is_keyframe {
+        if packet.is_keyframe() {
             pkt.set_flags(ffmpeg::codec::packet::Flags::KEY);
         }
 
@@ -317,16 +587,157 @@ impl OutputSink for SrtOutput {
         pkt.rescale_ts(self.time_base, stream.time_base());
 
         // Write packet
+        let write_start = Instant::now();
         pkt.write_interleaved(output_ctx)
             .map_err(|e| Error::Srt(format!("Write failed: {}", e)))?;
+        self.flusher.written(output_ctx, packet.size());
 
         self.frame_count += 1;
         self.bytes_written
             .fetch_add(packet.size() as u64, Ordering::Relaxed);
+        self.record_write(packet.size(), write_start.elapsed());
 
         Ok(())
     }
 
+    /// Re-open the connection after a failed write, with backoff
+    ///
+    /// On success the header has been written again and the buffered
+    /// packets back to the last keyframe have been re-sent.
+    async fn reconnect(&mut self, cause: Error) -> Result<()> {
+        let mut cause = cause;
+        loop {
+            if self.reconnect_attempts >= self.max_reconnect_attempts {
+                return Err(Error::Srt(format!(
+                    "Connection lost ({}), max reconnection attempts ({}) exceeded",
+                    cause, self.max_reconnect_attempts
+                )));
+            }
+
+            self.reconnect_attempts += 1;
+            let backoff = reconnect_backoff(self.reconnect_attempts);
+            tracing::warn!(
+                "SRT connection lost ({}), reconnecting to {} in {:?} ({}/{})",
+                cause,
+                self.url_masked(),
+                backoff,
+                self.reconnect_attempts,
+                self.max_reconnect_attempts
+            );
+
+            // Abandon the dead connection without a trailer
+            self.output_ctx = None;
+            self.connected = false;
+            tokio::time::sleep(backoff).await;
+
+            let params = self.codec_params.clone().unwrap_or_default();
+            if let Err(e) = self.init_srt(&params) {
+                cause = e;
+                continue;
+            }
+
+            let resend = std::mem::take(&mut self.resend);
+            let resent = resend.iter().try_for_each(|p| self.write_packet(p));
+            self.resend = resend;
+            match resent {
+                Ok(()) => {
+                    self.await_keyframe = self.resend_overflow;
+                    tracing::info!(
+                        "SRT reconnected, re-sent {} packets from the last keyframe",
+                        self.resend.len()
+                    );
+                    return Ok(());
+                }
+                Err(e) => cause = e,
+            }
+        }
+    }
+}
+
+/// Escape everything but unreserved characters for a URL query value
+pub(super) fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Undo `%XX` escapes in a URL query value
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match value.get(i + 1..i + 3) {
+            Some(hex) if bytes[i] == b'%' && hex.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                u8::from_str_radix(hex, 16).ok()
+            }
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Delay before reconnect attempt `attempt` (1-based): 500ms doubling to 8s
+pub(super) fn reconnect_backoff(attempt: u32) -> Duration {
+    Duration::from_millis(500 << attempt.saturating_sub(1).min(4))
+}
+
+#[async_trait::async_trait]
+impl OutputSink for SrtOutput {
+    async fn init_with_codec(&mut self, codec_params: Option<&CodecParams>) -> Result<()> {
+        if self.initialized {
+            return Ok(());
+        }
+
+        match codec_params {
+            Some(params) => self.init_srt(params)?,
+            None => self.init_default()?,
+        }
+
+        self.initialized = true;
+        Ok(())
+    }
+
+    async fn write(&mut self, packet: &Packet) -> Result<()> {
+        if !self.initialized {
+            self.init_with_codec(None).await?;
+        }
+
+        self.buffer_for_resend(packet);
+        if self.await_keyframe {
+            // Reconnected without a buffered GOP: deltas aren't decodable
+            if !packet.is_keyframe() {
+                return Ok(());
+            }
+            self.await_keyframe = false;
+        }
+
+        match self.write_packet(packet) {
+            Ok(()) => {
+                self.reconnect_attempts = 0; // Reset on successful write
+                Ok(())
+            }
+            // The failed packet is in the resend buffer
+            Err(e) => self.reconnect(e).await,
+        }
+    }
+
     async fn finish(&mut self) -> Result<()> {
         if !self.initialized {
             return Ok(());
@@ -357,6 +768,10 @@ impl OutputSink for SrtOutput {
     fn bytes_written(&self) -> u64 {
         self.bytes_written.load(Ordering::Relaxed)
     }
+
+    fn take_bitrate_request(&mut self) -> Option<u32> {
+        self.pending_bitrate.take()
+    }
 }
 
 impl Drop for SrtOutput {
@@ -370,6 +785,9 @@ impl Drop for SrtOutput {
 }
 
 /// SRT connection statistics (if available)
+///
+/// `SrtOutput` fills in the throughput and blocked time; RTT, loss and the
+/// libsrt buffer counters stay zero as FFmpeg doesn't expose them.
 #[derive(Debug, Clone, Default)]
 pub struct SrtStats {
     /// Round-trip time in milliseconds
@@ -382,4 +800,57 @@ pub struct SrtStats {
     pub send_buffer_bytes: u64,
     /// Packets retransmitted
     pub packets_retransmitted: u64,
+    /// Time spent blocked in writes during the sampling window (ms)
+    pub send_blocked_ms: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resend_buffer_restarts_at_keyframes() {
+        let mut srt = SrtOutput::new("srt://127.0.0.1:9000", 200);
+        srt.buffer_for_resend(&Packet::new(vec![0; 10], 0, 0, true));
+        srt.buffer_for_resend(&Packet::new(vec![0; 10], 1, 1, false));
+        assert_eq!(srt.resend.len(), 2);
+
+        srt.buffer_for_resend(&Packet::new(vec![0; 10], 2, 2, true));
+        assert_eq!(srt.resend.len(), 1);
+
+        assert_eq!(reconnect_backoff(1), Duration::from_millis(500));
+        assert_eq!(reconnect_backoff(3), Duration::from_secs(2));
+        assert_eq!(reconnect_backoff(10), Duration::from_secs(8));
+    }
+
+    #[test]
+    fn test_validate_encryption() {
+        let srt = || SrtOutput::new("srt://127.0.0.1:9000", 200);
+        assert!(srt().validate().is_ok());
+        assert!(srt().with_passphrase("short").validate().is_err());
+        assert!(srt().with_passphrase("x".repeat(80)).validate().is_err());
+
+        let keyed = srt().with_passphrase("correct horse battery");
+        assert!(keyed.validate().is_ok());
+        assert!(srt().with_key_length(24).validate().is_err());
+        let keyed = keyed.with_key_length(20);
+        assert!(keyed.validate().is_err());
+        let keyed = keyed.with_key_length(32);
+        assert!(keyed.validate().is_ok());
+
+        let rotating = keyed.with_key_rotation(KeyRotation::every(1000));
+        assert_eq!(rotating.key_rotation.unwrap().preannounce_packets, 499);
+        assert!(rotating.validate().is_ok());
+        let rotating = rotating.with_key_rotation(KeyRotation {
+            refresh_packets: 1000,
+            preannounce_packets: 500,
+        });
+        assert!(rotating.validate().is_err());
+
+        // Settings in the URL are checked too, after decoding
+        let url = SrtOutput::new("srt://127.0.0.1:9000?passphrase=abc%26def", 200);
+        assert!(url.validate().is_err());
+        let streamid = "#!::r=live,m=publish";
+        assert_eq!(percent_decode(&percent_encode(streamid)), streamid);
+    }
 }
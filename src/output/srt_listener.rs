@@ -0,0 +1,413 @@
+//! SRT listener output with multiple subscribers
+//!
+//! FFmpeg's SRT protocol serves a single peer per output context: in
+//! listener mode it accepts one caller and closes the listening socket.
+//! This output keeps an acceptor thread re-opening the listener, so any
+//! number of receivers can pull the same encoded stream. Each subscriber
+//! gets its own MPEG-TS mux context and joins on the next keyframe.
+
+use crate::encode::Codec;
+use crate::error::{Error, Result};
+use crate::types::{CodecParams, Packet};
+
+use super::srt::{percent_encode, PASSPHRASE_LEN};
+use super::{OutputSink, TsOptions};
+
+use ffmpeg_next as ffmpeg;
+use ffmpeg_next::codec::Id as CodecId;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How long one listen attempt blocks before re-checking for shutdown
+const LISTEN_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Statistics for one connected subscriber
+#[derive(Debug, Clone)]
+pub struct SubscriberStats {
+    /// Connection number (1 = first subscriber of this output)
+    pub id: u64,
+    /// When the subscriber connected
+    pub connected_at: Instant,
+    /// Packets sent
+    pub packets: u64,
+    /// Bytes sent
+    pub bytes: u64,
+    /// Time spent blocked in writes to this subscriber
+    pub send_blocked: Duration,
+}
+
+struct Subscriber {
+    output_ctx: ffmpeg::format::context::Output,
+    stream_index: usize,
+    /// Nothing is sent until a keyframe, so receivers can decode from the
+    /// first packet they get
+    waiting_for_keyframe: bool,
+    stats: SubscriberStats,
+}
+
+/// SRT output accepting several callers at once
+///
+/// Subscribers are served in turn from the pipeline's output task; one
+/// that stops reading holds up the others until libsrt's peer idle timeout
+/// drops it.
+pub struct SrtListenerOutput {
+    url: String,
+    latency_ms: u32,
+    max_subscribers: usize,
+    passphrase: Option<String>,
+    codec_params: Option<CodecParams>,
+    time_base: ffmpeg::Rational,
+    subscribers: Vec<Subscriber>,
+    subscriber_count: Arc<AtomicUsize>,
+    accepted_rx: Option<crossbeam_channel::Receiver<ffmpeg::format::context::Output>>,
+    accepting: Arc<AtomicBool>,
+    acceptor: Option<std::thread::JoinHandle<()>>,
+    next_id: u64,
+    bytes_written: u64,
+}
+
+impl SrtListenerOutput {
+    /// Listen on `url` (e.g. "srt://0.0.0.0:9000")
+    ///
+    /// * `latency_ms` - Latency requested from each subscriber
+    /// * `max_subscribers` - Further callers are turned away (0 = no limit)
+    pub fn new(url: impl Into<String>, latency_ms: u32, max_subscribers: usize) -> Self {
+        Self {
+            url: url.into(),
+            latency_ms: latency_ms.clamp(20, 8000),
+            max_subscribers,
+            passphrase: None,
+            codec_params: None,
+            time_base: ffmpeg::Rational::new(1, 1000),
+            subscribers: Vec::new(),
+            subscriber_count: Arc::new(AtomicUsize::new(0)),
+            accepted_rx: None,
+            accepting: Arc::new(AtomicBool::new(false)),
+            acceptor: None,
+            next_id: 1,
+            bytes_written: 0,
+        }
+    }
+
+    /// Require subscribers to use this passphrase (10-79 characters)
+    pub fn with_passphrase(mut self, passphrase: impl Into<String>) -> Self {
+        self.passphrase = Some(passphrase.into());
+        self
+    }
+
+    /// Check the passphrase without listening
+    ///
+    /// libsrt refuses an out-of-spec passphrase only once a caller
+    /// connects, with an error that doesn't say why.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(ref passphrase) = self.passphrase {
+            let len = passphrase.chars().count();
+            if !PASSPHRASE_LEN.contains(&len) {
+                return Err(Error::Srt(format!(
+                    "SRT passphrase must be 10-79 characters, got {}",
+                    len
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Statistics of the currently connected subscribers
+    pub fn subscribers(&self) -> Vec<SubscriberStats> {
+        self.subscribers.iter().map(|s| s.stats.clone()).collect()
+    }
+
+    /// Listener URL with SRT options
+    fn listener_url(&self) -> String {
+        let separator = if self.url.contains('?') { '&' } else { '?' };
+        let mut params = vec![
+            "mode=listener".to_string(),
+            format!("latency={}", self.latency_ms * 1000),
+            format!("listen_timeout={}", LISTEN_TIMEOUT.as_micros()),
+            "transtype=live".to_string(),
+        ];
+        if let Some(ref passphrase) = self.passphrase {
+            params.push(format!("passphrase={}", percent_encode(passphrase)));
+        }
+        format!("{}{}{}", self.url, separator, params.join("&"))
+    }
+
+    /// Map codec to FFmpeg codec ID
+    fn codec_to_ffmpeg(codec: Codec) -> CodecId {
+        match codec {
+            Codec::H264 => CodecId::H264,
+            Codec::Hevc => CodecId::HEVC,
+            Codec::Av1 => CodecId::AV1,
+        }
+    }
+
+    /// Start the acceptor thread
+    fn start_accepting(&mut self) {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        self.accepted_rx = Some(rx);
+        self.accepting.store(true, Ordering::SeqCst);
+
+        let url = self.listener_url();
+        let accepting = self.accepting.clone();
+        let count = self.subscriber_count.clone();
+        let max = self.max_subscribers;
+
+        self.acceptor = Some(std::thread::spawn(move || {
+            while accepting.load(Ordering::SeqCst) {
+                // Blocks until a caller connects or the listen timeout expires
+                match ffmpeg::format::output_as(&url, "mpegts") {
+                    Ok(ctx) if max > 0 && count.load(Ordering::SeqCst) >= max => {
+                        tracing::warn!("SRT listener full ({} subscribers), rejecting caller", max);
+                        drop(ctx);
+                    }
+                    Ok(ctx) => {
+                        count.fetch_add(1, Ordering::SeqCst);
+                        if tx.send(ctx).is_err() {
+                            break;
+                        }
+                    }
+                    Err(ffmpeg::Error::Other {
+                        errno: ffmpeg::util::error::ETIMEDOUT,
+                    }) => {}
+                    Err(e) => {
+                        tracing::warn!("SRT listener error: {}", e);
+                        std::thread::sleep(Duration::from_secs(1));
+                    }
+                }
+            }
+            tracing::debug!("SRT acceptor stopped");
+        }));
+    }
+
+    /// Set up the mux context of a newly connected subscriber
+    fn add_subscriber(&mut self, mut output_ctx: ffmpeg::format::context::Output) -> Result<()> {
+        let params = self
+            .codec_params
+            .clone()
+            .ok_or_else(|| Error::Srt("SRT listener not initialized".into()))?;
+
+        let codec_id = Self::codec_to_ffmpeg(params.codec);
+        let codec = ffmpeg::encoder::find(codec_id)
+            .ok_or_else(|| Error::Srt(format!("Codec {:?} not found", codec_id)))?;
+
+        let mut stream = output_ctx
+            .add_stream(codec)
+            .map_err(|e| Error::Srt(format!("Failed to add video stream: {}", e)))?;
+        let stream_index = stream.index();
+
+        unsafe {
+            let mut stream_params = stream.parameters();
+            let codec_ctx = stream_params.as_mut_ptr();
+
+            (*codec_ctx).codec_type = ffmpeg_next::ffi::AVMediaType::AVMEDIA_TYPE_VIDEO;
+            (*codec_ctx).codec_id = codec_id.into();
+            (*codec_ctx).width = params.resolution.width as i32;
+            (*codec_ctx).height = params.resolution.height as i32;
+            (*codec_ctx).format = super::stream_pixel_format(&params) as i32;
+            (*codec_ctx).bit_rate = params.bitrate;
+
+            if !params.extradata.is_empty() {
+                let padding = ffmpeg_next::ffi::AV_INPUT_BUFFER_PADDING_SIZE as usize;
+                let size = params.extradata.len();
+                let extradata = ffmpeg_next::ffi::av_mallocz(size + padding) as *mut u8;
+                if !extradata.is_null() {
+                    std::ptr::copy_nonoverlapping(params.extradata.as_ptr(), extradata, size);
+                    (*codec_ctx).extradata = extradata;
+                    (*codec_ctx).extradata_size = size as i32;
+                }
+            }
+        }
+
+        stream.set_time_base(self.time_base);
+        stream.set_rate(ffmpeg::Rational::new(params.framerate.num as i32, 1));
+
+        output_ctx
+            .write_header_with(TsOptions::default().muxer_options())
+            .map_err(|e| Error::Srt(format!("Failed to start subscriber stream: {}", e)))?;
+
+        let id = self.next_id;
+        self.next_id += 1;
+        tracing::info!(
+            "SRT subscriber {} connected ({} total)",
+            id,
+            self.subscribers.len() + 1
+        );
+
+        self.subscribers.push(Subscriber {
+            output_ctx,
+            stream_index,
+            waiting_for_keyframe: true,
+            stats: SubscriberStats {
+                id,
+                connected_at: Instant::now(),
+                packets: 0,
+                bytes: 0,
+                send_blocked: Duration::ZERO,
+            },
+        });
+        Ok(())
+    }
+
+    /// Take subscribers the acceptor thread has connected since the last call
+    fn accept_pending(&mut self) {
+        let Some(rx) = self.accepted_rx.clone() else {
+            return;
+        };
+        for ctx in rx.try_iter() {
+            if let Err(e) = self.add_subscriber(ctx) {
+                tracing::warn!("Dropping SRT subscriber: {}", e);
+                self.subscriber_count.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    fn remove_subscriber(&mut self, index: usize, reason: &str) {
+        let subscriber = self.subscribers.swap_remove(index);
+        self.subscriber_count.fetch_sub(1, Ordering::SeqCst);
+        tracing::info!(
+            "SRT subscriber {} disconnected ({}): {} packets, {} bytes in {:.1}s",
+            subscriber.stats.id,
+            reason,
+            subscriber.stats.packets,
+            subscriber.stats.bytes,
+            subscriber.stats.connected_at.elapsed().as_secs_f64()
+        );
+    }
+
+    /// Signal the acceptor thread to stop; it exits within `LISTEN_TIMEOUT`
+    fn stop_accepting(&mut self) -> Option<std::thread::JoinHandle<()>> {
+        self.accepting.store(false, Ordering::SeqCst);
+        self.accepted_rx = None;
+        self.acceptor.take()
+    }
+}
+
+#[async_trait::async_trait]
+impl OutputSink for SrtListenerOutput {
+    async fn init_with_codec(&mut self, codec_params: Option<&CodecParams>) -> Result<()> {
+        if self.codec_params.is_some() {
+            return Ok(());
+        }
+        if !self.url.starts_with("srt://") {
+            return Err(Error::Srt("URL must start with srt://".into()));
+        }
+        self.validate()?;
+
+        ffmpeg::init().map_err(|e| Error::Ffmpeg(e.to_string()))?;
+
+        let params = codec_params.cloned().unwrap_or_default();
+        self.time_base = ffmpeg::Rational::new(params.time_base_num, params.time_base_den);
+        self.codec_params = Some(params);
+        self.start_accepting();
+
+        tracing::info!(
+            "SRT listening on {} (latency: {}ms, max subscribers: {})",
+            self.url,
+            self.latency_ms,
+            if self.max_subscribers == 0 {
+                "unlimited".to_string()
+            } else {
+                self.max_subscribers.to_string()
+            }
+        );
+        Ok(())
+    }
+
+    async fn write(&mut self, packet: &Packet) -> Result<()> {
+        self.accept_pending();
+
+        let mut index = 0;
+        while index < self.subscribers.len() {
+            let subscriber = &mut self.subscribers[index];
+            if subscriber.waiting_for_keyframe {
+                if !packet.is_keyframe() {
+                    index += 1;
+                    continue;
+                }
+                subscriber.waiting_for_keyframe = false;
+            }
+
+            let mut pkt = ffmpeg::Packet::copy(&packet.data);
+            pkt.set_pts(Some(packet.pts));
+            pkt.set_dts(Some(packet.dts));
+            pkt.set_duration(packet.duration);
+            pkt.set_stream(subscriber.stream_index);
+            if packet.is_keyframe() {
+                pkt.set_flags(ffmpeg::codec::packet::Flags::KEY);
+            }
+            if let Some(stream) = subscriber.output_ctx.stream(subscriber.stream_index) {
+                pkt.rescale_ts(self.time_base, stream.time_base());
+            }
+
+            let write_start = Instant::now();
+            match pkt.write_interleaved(&mut subscriber.output_ctx) {
+                Ok(()) => {
+                    subscriber.stats.packets += 1;
+                    subscriber.stats.bytes += packet.size() as u64;
+                    subscriber.stats.send_blocked += write_start.elapsed();
+                    self.bytes_written += packet.size() as u64;
+                    index += 1;
+                }
+                Err(e) => self.remove_subscriber(index, &e.to_string()),
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn finish(&mut self) -> Result<()> {
+        // Wait for the listening socket to close so the port can be reused
+        if let Some(acceptor) = self.stop_accepting() {
+            let _ = tokio::task::spawn_blocking(move || acceptor.join()).await;
+        }
+        while !self.subscribers.is_empty() {
+            let _ = self.subscribers[0].output_ctx.write_trailer();
+            self.remove_subscriber(0, "stream ended");
+        }
+        self.codec_params = None;
+
+        tracing::info!(
+            "SRT listener stopped: {} ({} bytes sent)",
+            self.url,
+            self.bytes_written
+        );
+        Ok(())
+    }
+
+    fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    fn destination_subscribers(&self) -> Vec<Vec<SubscriberStats>> {
+        vec![self.subscribers()]
+    }
+}
+
+impl Drop for SrtListenerOutput {
+    fn drop(&mut self) {
+        // Not joined here: the thread exits on its own within LISTEN_TIMEOUT
+        let _ = self.stop_accepting();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listener_passphrase() {
+        let listener = SrtListenerOutput::new("srt://0.0.0.0:9000", 120, 0);
+        assert!(listener.validate().is_ok());
+        assert!(listener.listener_url().contains("mode=listener"));
+
+        let listener = listener.with_passphrase("a&b c#100%");
+        assert!(listener.validate().is_ok());
+        assert!(listener
+            .listener_url()
+            .ends_with("&passphrase=a%26b%20c%23100%25"));
+
+        let short = SrtListenerOutput::new("srt://0.0.0.0:9000", 120, 0).with_passphrase("short");
+        assert!(short.validate().is_err());
+    }
+}
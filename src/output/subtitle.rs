@@ -0,0 +1,220 @@
+//! Subtitle and closed-caption passthrough
+//!
+//! Subtitles are never rendered: `AvMuxer::add_subtitle_stream` adds a
+//! track and `write_subtitle` copies already-encoded packets (SRT cues, ASS
+//! events, PGS bitmaps, ...) into it unchanged.
+//!
+//! CEA-608/708 captions usually travel inside the video itself, as ATSC
+//! A/53 SEI messages in H.264/HEVC. Writing the video packets unchanged
+//! keeps them; `extract_captions` reads the caption data out of a packet
+//! so it can also go to a separate `SubtitleCodec::Eia608` track or a
+//! sidecar file.
+
+use crate::encode::Codec;
+use crate::types::Packet;
+
+use ffmpeg_next::codec::Id as CodecId;
+
+/// Subtitle formats that can be passed through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubtitleCodec {
+    /// SubRip text (MKV)
+    SubRip,
+    /// WebVTT text (MKV, WebM)
+    WebVtt,
+    /// ASS/SSA events; the script header goes into `extradata`
+    Ass,
+    /// 3GPP timed text (MP4)
+    MovText,
+    /// DVB bitmap subtitles (MPEG-TS, MKV)
+    DvbSub,
+    /// Blu-ray PGS bitmap subtitles (MKV)
+    Pgs,
+    /// CEA-608 caption data as `cc_data` triplets (MP4/MOV)
+    Eia608,
+}
+
+impl SubtitleCodec {
+    pub(crate) fn to_ffmpeg(self) -> CodecId {
+        match self {
+            SubtitleCodec::SubRip => CodecId::SUBRIP,
+            SubtitleCodec::WebVtt => CodecId::WEBVTT,
+            SubtitleCodec::Ass => CodecId::ASS,
+            SubtitleCodec::MovText => CodecId::MOV_TEXT,
+            SubtitleCodec::DvbSub => CodecId::DVB_SUBTITLE,
+            SubtitleCodec::Pgs => CodecId::HDMV_PGS_SUBTITLE,
+            SubtitleCodec::Eia608 => CodecId::EIA_608,
+        }
+    }
+}
+
+/// Subtitle track parameters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubtitleParams {
+    pub codec: SubtitleCodec,
+    /// ISO 639-2 language code written to the track (e.g. "eng")
+    pub language: Option<String>,
+    /// Codec header (the ASS script header, DVB page setup); usually empty
+    pub extradata: Vec<u8>,
+}
+
+impl SubtitleParams {
+    pub fn new(codec: SubtitleCodec) -> Self {
+        Self {
+            codec,
+            language: None,
+            extradata: Vec::new(),
+        }
+    }
+
+    pub fn with_language(mut self, language: impl Into<String>) -> Self {
+        self.language = Some(language.into());
+        self
+    }
+
+    pub fn with_extradata(mut self, extradata: Vec<u8>) -> Self {
+        self.extradata = extradata;
+        self
+    }
+}
+
+/// One encoded subtitle event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubtitlePacket {
+    /// Encoded payload (cue text, ASS event line, bitmap segment, ...)
+    pub data: Vec<u8>,
+    /// Presentation timestamp in microseconds, on the session's clock
+    pub pts: i64,
+    /// Display duration in microseconds (0 = until the next event)
+    pub duration: i64,
+}
+
+impl SubtitlePacket {
+    pub fn new(data: Vec<u8>, pts: i64, duration: i64) -> Self {
+        Self {
+            data,
+            pts,
+            duration,
+        }
+    }
+}
+
+/// SEI payload type of ITU-T T.35 registered user data
+const SEI_USER_DATA_T35: u32 = 4;
+/// T.35 country code (United States), ATSC provider code and "GA94"
+const A53_PREFIX: [u8; 7] = [0xb5, 0x00, 0x31, b'G', b'A', b'9', b'4'];
+/// A/53 `user_data_type_code` for caption data
+const A53_CC_DATA: u8 = 0x03;
+
+/// CEA-608/708 caption data carried in an H.264/HEVC packet's A/53 SEI
+///
+/// Returns the `cc_data` triplets (`cc_valid`/`cc_type` byte plus two data
+/// bytes each) of all caption SEI messages, in the layout FFmpeg uses for
+/// `AV_FRAME_DATA_A53_CC`; None if the packet carries no captions. The
+/// packet must be Annex-B; AV1 (captions in metadata OBUs) is not handled.
+pub fn extract_captions(packet: &Packet, codec: Codec) -> Option<Vec<u8>> {
+    if codec == Codec::Av1 {
+        return None;
+    }
+
+    let mut cc_data = Vec::new();
+    let data = &packet.data;
+    let mut starts = data
+        .windows(3)
+        .enumerate()
+        .filter(|(_, w)| *w == [0, 0, 1])
+        .map(|(i, _)| i + 3)
+        .peekable();
+    while let Some(start) = starts.next() {
+        let end = starts.peek().map_or(data.len(), |&next| next - 3);
+        let nal = &data[start..end.max(start)];
+        let header_len = match codec {
+            Codec::H264 if nal.first().is_some_and(|b| b & 0x1f == 6) => 1,
+            // Prefix SEI
+            Codec::Hevc if nal.first().is_some_and(|b| (b >> 1) & 0x3f == 39) => 2,
+            _ => continue,
+        };
+        if nal.len() > header_len {
+            sei_captions(&unescape_rbsp(&nal[header_len..]), &mut cc_data);
+        }
+    }
+    (!cc_data.is_empty()).then_some(cc_data)
+}
+
+/// Drop the emulation prevention bytes (00 00 03 -> 00 00)
+fn unescape_rbsp(nal: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(nal.len());
+    let mut zeros = 0;
+    for &byte in nal {
+        if zeros >= 2 && byte == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        out.push(byte);
+    }
+    out
+}
+
+/// Append the `cc_data` of every A/53 caption message in an SEI RBSP
+fn sei_captions(rbsp: &[u8], cc_data: &mut Vec<u8>) {
+    let mut pos = 0;
+    // Each message: ff-extended type and size, then the payload; the RBSP
+    // ends with the 0x80 stop bit
+    while pos < rbsp.len() && rbsp[pos] != 0x80 {
+        let mut read_value = || {
+            let mut value = 0u32;
+            while let Some(&byte) = rbsp.get(pos) {
+                pos += 1;
+                value += byte as u32;
+                if byte != 0xff {
+                    return Some(value);
+                }
+            }
+            None
+        };
+        let (Some(payload_type), Some(size)) = (read_value(), read_value()) else {
+            return;
+        };
+        let Some(payload) = rbsp.get(pos..pos + size as usize) else {
+            return;
+        };
+        pos += size as usize;
+
+        if payload_type != SEI_USER_DATA_T35 || !payload.starts_with(&A53_PREFIX) {
+            continue;
+        }
+        let body = &payload[A53_PREFIX.len()..];
+        // user_data_type_code, process_cc_data_flag + cc_count, em_data
+        if body.len() < 3 || body[0] != A53_CC_DATA || body[1] & 0x40 == 0 {
+            continue;
+        }
+        let count = (body[1] & 0x1f) as usize;
+        let triplets = &body[3..];
+        cc_data.extend_from_slice(&triplets[..(count * 3).min(triplets.len() / 3 * 3)]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_a53_captions() {
+        // SEI with one T.35 message: GA94 cc_data, two triplets; the second
+        // triplet's 00 00 03 is emulation-prevented on the wire
+        let mut sei = vec![0, 0, 0, 1, 0x06, 0x04, 0x11];
+        sei.extend_from_slice(&A53_PREFIX);
+        sei.extend_from_slice(&[0x03, 0x40 | 2, 0xff]);
+        sei.extend_from_slice(&[0xfc, 0x94, 0x20, 0x00, 0x00, 0x03, 0x01]);
+        sei.extend_from_slice(&[0xff, 0x80]);
+        let slice = [0, 0, 0, 1, 0x65, 0x88, 0x84];
+        let packet = Packet::new([sei, slice.to_vec()].concat(), 0, 0, true);
+
+        let cc_data = extract_captions(&packet, Codec::H264).unwrap();
+        assert_eq!(cc_data, [0xfc, 0x94, 0x20, 0x00, 0x00, 0x01]);
+
+        let plain = Packet::new(slice.to_vec(), 0, 0, true);
+        assert_eq!(extract_captions(&plain, Codec::H264), None);
+    }
+}
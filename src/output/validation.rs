@@ -0,0 +1,231 @@
+//! Frame checksums for regression testing
+//!
+//! `ValidationSink` records a deterministic hash of every frame it sees.
+//! Attached to a pipeline with `PipelineBuilder::validate_frames` it hashes
+//! the processed frames (after transform, scaling, conversion and overlays,
+//! right before encoding), so a `TestCapture` run can be compared against a
+//! reference file from a known-good build to catch conversion or stride
+//! regressions without decoding any video.
+//!
+//! Reference files are plain text, one `<pts> <hash>` line per frame.
+
+use crate::error::{Error, Result};
+use crate::types::{Frame, FrameFormat, Resolution};
+
+use super::RawOutputSink;
+
+use parking_lot::Mutex;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::sync::Arc;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Hash of one frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FrameHash {
+    /// Presentation timestamp in microseconds
+    pub pts: i64,
+    pub hash: u64,
+}
+
+/// Deterministic 64-bit hash (FNV-1a) of a frame's pixels
+///
+/// Covers the size, format and the visible bytes of each row; the padding
+/// of packed RGB rows with a larger stride is skipped, so the hash doesn't
+/// depend on how a buffer happened to be allocated. Planar YUV data is
+/// hashed as a whole.
+pub fn frame_hash(frame: &Frame) -> u64 {
+    let mut hash = FNV_OFFSET;
+    let mut feed = |bytes: &[u8]| {
+        for &byte in bytes {
+            hash = (hash ^ byte as u64).wrapping_mul(FNV_PRIME);
+        }
+    };
+    feed(&frame.width.to_le_bytes());
+    feed(&frame.height.to_le_bytes());
+    feed(format!("{:?}", frame.format).as_bytes());
+
+    let row = match frame.format {
+        FrameFormat::Bgra | FrameFormat::Rgba => frame.width as usize * 4,
+        FrameFormat::Rgb24 => frame.width as usize * 3,
+        _ => 0,
+    };
+    let stride = frame.stride as usize;
+    if row > 0 && stride > row {
+        for line in frame.data.chunks(stride).take(frame.height as usize) {
+            feed(&line[..row.min(line.len())]);
+        }
+    } else {
+        feed(&frame.data);
+    }
+    hash
+}
+
+#[derive(Debug, Default)]
+struct ValidationState {
+    hashes: Vec<FrameHash>,
+    bytes: u64,
+}
+
+/// Sink recording a `FrameHash` per frame
+///
+/// Clones share the same storage: hand one to the pipeline and read the
+/// hashes from another once it has stopped.
+#[derive(Debug, Clone, Default)]
+pub struct ValidationSink {
+    state: Arc<Mutex<ValidationState>>,
+}
+
+impl ValidationSink {
+    /// Create an empty sink
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hash a frame and record it
+    pub fn record(&self, frame: &Frame) {
+        let hash = FrameHash {
+            pts: frame.pts,
+            hash: frame_hash(frame),
+        };
+        let mut state = self.state.lock();
+        state.bytes += frame.data.len() as u64;
+        state.hashes.push(hash);
+    }
+
+    /// Hashes recorded so far, in frame order
+    pub fn hashes(&self) -> Vec<FrameHash> {
+        self.state.lock().hashes.clone()
+    }
+
+    /// Number of frames recorded so far
+    pub fn frame_count(&self) -> usize {
+        self.state.lock().hashes.len()
+    }
+
+    /// Forget the recorded hashes
+    pub fn clear(&self) {
+        *self.state.lock() = ValidationState::default();
+    }
+
+    /// Write the recorded hashes as a reference file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut text = String::from("# ghoststream frame hashes: <pts> <hash>\n");
+        for hash in self.state.lock().hashes.iter() {
+            let _ = writeln!(text, "{} {:016x}", hash.pts, hash.hash);
+        }
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// Read a reference file written by `save`
+    pub fn load(path: impl AsRef<Path>) -> Result<Vec<FrameHash>> {
+        let text = std::fs::read_to_string(path)?;
+        text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let parsed = line.split_once(' ').and_then(|(pts, hash)| {
+                    Some(FrameHash {
+                        pts: pts.parse().ok()?,
+                        hash: u64::from_str_radix(hash.trim(), 16).ok()?,
+                    })
+                });
+                parsed.ok_or_else(|| Error::Config(format!("Invalid frame hash line: {}", line)))
+            })
+            .collect()
+    }
+
+    /// Index of the first frame that differs from `reference`, including
+    /// a frame missing on either side; None if all match
+    pub fn first_mismatch(&self, reference: &[FrameHash]) -> Option<usize> {
+        let hashes = &self.state.lock().hashes;
+        hashes
+            .iter()
+            .zip(reference)
+            .position(|(a, b)| a != b)
+            .or_else(|| {
+                (hashes.len() != reference.len()).then_some(hashes.len().min(reference.len()))
+            })
+    }
+
+    /// Compare against a reference file, failing on the first difference
+    pub fn verify(&self, path: impl AsRef<Path>) -> Result<()> {
+        let reference = Self::load(path)?;
+        match self.first_mismatch(&reference) {
+            None => Ok(()),
+            Some(index) => Err(Error::Pipeline(format!(
+                "Frame {} differs from the reference ({} frames recorded, {} expected)",
+                index,
+                self.frame_count(),
+                reference.len()
+            ))),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RawOutputSink for ValidationSink {
+    async fn init_raw(&mut self, _resolution: Resolution, _format: FrameFormat) -> Result<()> {
+        Ok(())
+    }
+
+    async fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+        self.record(frame);
+        Ok(())
+    }
+
+    async fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn bytes_written(&self) -> u64 {
+        self.state.lock().bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_ignores_row_padding() {
+        let tight = Frame::from_data((0..32).collect(), 2, 4, 8, FrameFormat::Bgra);
+        let padded_data = tight
+            .data
+            .chunks(8)
+            .flat_map(|row| row.iter().copied().chain([0xaa; 8]))
+            .collect();
+        let padded = Frame::from_data(padded_data, 2, 4, 16, FrameFormat::Bgra);
+        assert_eq!(frame_hash(&tight), frame_hash(&padded));
+
+        let mut changed = tight.copy_data();
+        changed.data[5] ^= 1;
+        assert_ne!(frame_hash(&tight), frame_hash(&changed));
+    }
+
+    #[test]
+    fn test_reference_round_trip() {
+        let sink = ValidationSink::new();
+        for pts in 0..3 {
+            let mut frame = Frame::from_data(vec![pts as u8; 16], 2, 2, 8, FrameFormat::Bgra);
+            frame.pts = pts * 16_667;
+            sink.record(&frame);
+        }
+
+        let path =
+            std::env::temp_dir().join(format!("ghoststream-hashes-{}.txt", std::process::id()));
+        sink.save(&path).unwrap();
+        let reference = ValidationSink::load(&path).unwrap();
+        assert_eq!(reference, sink.hashes());
+        assert!(sink.verify(&path).is_ok());
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(sink.first_mismatch(&reference[..2]), Some(2));
+        let mut altered = reference.clone();
+        altered[1].hash ^= 1;
+        assert_eq!(sink.first_mismatch(&altered), Some(1));
+    }
+}
@@ -3,6 +3,8 @@
 use crate::error::{Error, Result};
 use crate::types::FrameFormat;
 
+use super::hdr::{ColorMatrix, ColorRange};
+
 use ffmpeg_next as ffmpeg;
 use ffmpeg_next::format::Pixel;
 use ffmpeg_next::software::scaling::{Context as SwsContext, Flags as SwsFlags};
@@ -15,11 +17,55 @@ fn format_to_pixel(format: FrameFormat) -> Option<Pixel> {
         FrameFormat::Nv12 => Some(Pixel::NV12),
         FrameFormat::P010 => Some(Pixel::P010LE),
         FrameFormat::Yuv420p => Some(Pixel::YUV420P),
+        FrameFormat::Yuv422p => Some(Pixel::YUV422P),
         FrameFormat::Yuv444p => Some(Pixel::YUV444P),
         FrameFormat::Rgb24 => Some(Pixel::RGB24),
     }
 }
 
+/// Chroma plane size (width, height) for planar YUV formats
+fn chroma_size(pixel: Pixel, width: u32, height: u32) -> (usize, usize) {
+    let format = match pixel {
+        Pixel::YUV422P => FrameFormat::Yuv422p,
+        Pixel::YUV444P => FrameFormat::Yuv444p,
+        _ => FrameFormat::Yuv420p,
+    };
+    let (w, h) = format.chroma_size(width, height).unwrap_or((width, height));
+    (w as usize, h as usize)
+}
+
+/// Bytes per sample of the semi-planar formats
+fn sample_bytes(pixel: Pixel) -> usize {
+    match pixel {
+        Pixel::P010LE => 2,
+        _ => 1,
+    }
+}
+
+/// Matrix and ranges used by swscale between RGB and YUV
+///
+/// The default (BT.709, limited YUV) matches what the encoders signal for
+/// frames without capture colorimetry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ColorConversion {
+    /// Matrix of the YUV side
+    pub matrix: ColorMatrix,
+    /// Matrix of a YUV source when it differs from the destination's
+    /// (None = `matrix`)
+    pub src_matrix: Option<ColorMatrix>,
+    /// Range of the source samples (ignored for RGB)
+    pub src_range: ColorRange,
+    /// Range of the destination samples (ignored for RGB)
+    pub dst_range: ColorRange,
+}
+
+impl ColorConversion {
+    /// Whether YUV to YUV samples change with this conversion
+    fn changes_yuv(&self) -> bool {
+        self.src_matrix.is_some_and(|m| m != self.matrix) || self.src_range != self.dst_range
+    }
+}
+
 /// Colorspace converter using FFmpeg swscale
 pub struct ColorspaceConverter {
     // Cached scaler context (could be extended to cache multiple contexts)
@@ -59,12 +105,37 @@ pub fn convert_colorspace(
     width: u32,
     height: u32,
 ) -> Result<Vec<u8>> {
-    if src_format == dst_format {
+    convert_colorspace_with(
+        input,
+        src_format,
+        dst_format,
+        width,
+        height,
+        ColorConversion::default(),
+    )
+}
+
+/// Convert frame colorspace with an explicit matrix and ranges
+pub fn convert_colorspace_with(
+    input: &[u8],
+    src_format: FrameFormat,
+    dst_format: FrameFormat,
+    width: u32,
+    height: u32,
+    conversion: ColorConversion,
+) -> Result<Vec<u8>> {
+    // A YUV matrix or range change is left to swscale, which converts
+    // between the two tables directly at the source's bit depth
+    let yuv_to_yuv = !src_format.is_rgb() && !dst_format.is_rgb();
+    let changes_yuv = yuv_to_yuv && conversion.changes_yuv();
+
+    if src_format == dst_format && !changes_yuv {
         return Ok(input.to_vec());
     }
 
     // Simple swaps don't need swscale
     match (src_format, dst_format) {
+        _ if changes_yuv => {}
         (FrameFormat::Bgra, FrameFormat::Rgba) | (FrameFormat::Rgba, FrameFormat::Bgra) => {
             return bgra_rgba_swap(input);
         }
@@ -91,7 +162,7 @@ pub fn convert_colorspace(
         Error::ColorspaceConversion(format!("Unsupported destination format: {:?}", dst_format))
     })?;
 
-    convert_with_swscale(input, src_pixel, dst_pixel, width, height)
+    convert_with_swscale(input, src_pixel, dst_pixel, width, height, conversion)
 }
 
 /// Convert using FFmpeg swscale
@@ -101,6 +172,7 @@ fn convert_with_swscale(
     dst_pixel: Pixel,
     width: u32,
     height: u32,
+    conversion: ColorConversion,
 ) -> Result<Vec<u8>> {
     let _ = ffmpeg::init();
 
@@ -116,6 +188,25 @@ fn convert_with_swscale(
     )
     .map_err(|e| Error::ColorspaceConversion(format!("Failed to create scaler: {}", e)))?;
 
+    // swscale defaults to BT.601 limited range; use the frame's colorimetry
+    unsafe {
+        let table = ffmpeg::ffi::sws_getCoefficients(conversion.matrix.sws_colorspace());
+        let src_table = match conversion.src_matrix {
+            Some(matrix) => ffmpeg::ffi::sws_getCoefficients(matrix.sws_colorspace()),
+            None => table,
+        };
+        ffmpeg::ffi::sws_setColorspaceDetails(
+            scaler.as_mut_ptr(),
+            src_table,
+            (conversion.src_range == ColorRange::Full) as i32,
+            table,
+            (conversion.dst_range == ColorRange::Full) as i32,
+            0,
+            1 << 16,
+            1 << 16,
+        );
+    }
+
     // Create source frame
     let mut src_frame = ffmpeg::frame::Video::new(src_pixel, width, height);
 
@@ -160,9 +251,10 @@ fn copy_to_frame(
                 }
             }
         }
-        Pixel::NV12 => {
-            // Y plane + interleaved UV plane
-            let y_size = (width * height) as usize;
+        Pixel::NV12 | Pixel::P010LE => {
+            // Y plane + interleaved UV plane (16-bit samples for P010)
+            let row = width as usize * sample_bytes(pixel);
+            let y_size = row * height as usize;
             let y_stride = frame.stride(0);
             let uv_stride = frame.stride(1);
 
@@ -170,9 +262,9 @@ fn copy_to_frame(
             {
                 let y_plane = frame.data_mut(0);
                 for y in 0..height as usize {
-                    let src_start = y * width as usize;
+                    let src_start = y * row;
                     let dst_start = y * y_stride;
-                    let copy_len = width as usize;
+                    let copy_len = row;
 
                     if src_start + copy_len <= input.len() && src_start + copy_len <= y_size {
                         y_plane[dst_start..dst_start + copy_len]
@@ -185,12 +277,11 @@ fn copy_to_frame(
             {
                 let uv_plane = frame.data_mut(1);
                 let uv_height = height as usize / 2;
-                let uv_width = width as usize;
 
                 for y in 0..uv_height {
-                    let src_start = y_size + y * uv_width;
+                    let src_start = y_size + y * row;
                     let dst_start = y * uv_stride;
-                    let copy_len = uv_width;
+                    let copy_len = row;
 
                     if src_start + copy_len <= input.len() {
                         uv_plane[dst_start..dst_start + copy_len]
@@ -199,12 +290,11 @@ fn copy_to_frame(
                 }
             }
         }
-        Pixel::YUV420P => {
+        Pixel::YUV420P | Pixel::YUV422P | Pixel::YUV444P => {
             // Three separate planes
             let y_size = (width * height) as usize;
-            let uv_size = y_size / 4;
-            let half_height = height as usize / 2;
-            let half_width = width as usize / 2;
+            let (chroma_width, chroma_height) = chroma_size(pixel, width, height);
+            let uv_size = chroma_width * chroma_height;
 
             let y_stride = frame.stride(0);
             let u_stride = frame.stride(1);
@@ -227,12 +317,12 @@ fn copy_to_frame(
             // U plane
             {
                 let u_plane = frame.data_mut(1);
-                for y in 0..half_height {
-                    let src_start = y_size + y * half_width;
+                for y in 0..chroma_height {
+                    let src_start = y_size + y * chroma_width;
                     let dst_start = y * u_stride;
-                    if src_start + half_width <= input.len() {
-                        u_plane[dst_start..dst_start + half_width]
-                            .copy_from_slice(&input[src_start..src_start + half_width]);
+                    if src_start + chroma_width <= input.len() {
+                        u_plane[dst_start..dst_start + chroma_width]
+                            .copy_from_slice(&input[src_start..src_start + chroma_width]);
                     }
                 }
             }
@@ -240,12 +330,12 @@ fn copy_to_frame(
             // V plane
             {
                 let v_plane = frame.data_mut(2);
-                for y in 0..half_height {
-                    let src_start = y_size + uv_size + y * half_width;
+                for y in 0..chroma_height {
+                    let src_start = y_size + uv_size + y * chroma_width;
                     let dst_start = y * v_stride;
-                    if src_start + half_width <= input.len() {
-                        v_plane[dst_start..dst_start + half_width]
-                            .copy_from_slice(&input[src_start..src_start + half_width]);
+                    if src_start + chroma_width <= input.len() {
+                        v_plane[dst_start..dst_start + chroma_width]
+                            .copy_from_slice(&input[src_start..src_start + chroma_width]);
                     }
                 }
             }
@@ -285,9 +375,10 @@ fn copy_from_frame(
 
             Ok(output)
         }
-        Pixel::NV12 => {
-            // Y plane + interleaved UV plane
-            let y_size = (width * height) as usize;
+        Pixel::NV12 | Pixel::P010LE => {
+            // Y plane + interleaved UV plane (16-bit samples for P010)
+            let row = width as usize * sample_bytes(pixel);
+            let y_size = row * height as usize;
             let uv_size = y_size / 2;
             let mut output = vec![0u8; y_size + uv_size];
 
@@ -296,9 +387,9 @@ fn copy_from_frame(
             let y_stride = frame.stride(0);
             for y in 0..height as usize {
                 let src_start = y * y_stride;
-                let dst_start = y * width as usize;
-                output[dst_start..dst_start + width as usize]
-                    .copy_from_slice(&y_plane[src_start..src_start + width as usize]);
+                let dst_start = y * row;
+                output[dst_start..dst_start + row]
+                    .copy_from_slice(&y_plane[src_start..src_start + row]);
             }
 
             // Copy UV plane
@@ -307,17 +398,18 @@ fn copy_from_frame(
             let uv_height = height as usize / 2;
             for y in 0..uv_height {
                 let src_start = y * uv_stride;
-                let dst_start = y_size + y * width as usize;
-                output[dst_start..dst_start + width as usize]
-                    .copy_from_slice(&uv_plane[src_start..src_start + width as usize]);
+                let dst_start = y_size + y * row;
+                output[dst_start..dst_start + row]
+                    .copy_from_slice(&uv_plane[src_start..src_start + row]);
             }
 
             Ok(output)
         }
-        Pixel::YUV420P => {
+        Pixel::YUV420P | Pixel::YUV422P | Pixel::YUV444P => {
             // Three separate planes
             let y_size = (width * height) as usize;
-            let uv_size = y_size / 4;
+            let (chroma_width, chroma_height) = chroma_size(pixel, width, height);
+            let uv_size = chroma_width * chroma_height;
             let mut output = vec![0u8; y_size + uv_size * 2];
 
             // Y plane
@@ -333,23 +425,21 @@ fn copy_from_frame(
             // U plane
             let u_plane = frame.data(1);
             let u_stride = frame.stride(1);
-            let half_height = height as usize / 2;
-            let half_width = width as usize / 2;
-            for y in 0..half_height {
+            for y in 0..chroma_height {
                 let src_start = y * u_stride;
-                let dst_start = y_size + y * half_width;
-                output[dst_start..dst_start + half_width]
-                    .copy_from_slice(&u_plane[src_start..src_start + half_width]);
+                let dst_start = y_size + y * chroma_width;
+                output[dst_start..dst_start + chroma_width]
+                    .copy_from_slice(&u_plane[src_start..src_start + chroma_width]);
             }
 
             // V plane
             let v_plane = frame.data(2);
             let v_stride = frame.stride(2);
-            for y in 0..half_height {
+            for y in 0..chroma_height {
                 let src_start = y * v_stride;
-                let dst_start = y_size + uv_size + y * half_width;
-                output[dst_start..dst_start + half_width]
-                    .copy_from_slice(&v_plane[src_start..src_start + half_width]);
+                let dst_start = y_size + uv_size + y * chroma_width;
+                output[dst_start..dst_start + chroma_width]
+                    .copy_from_slice(&v_plane[src_start..src_start + chroma_width]);
             }
 
             Ok(output)
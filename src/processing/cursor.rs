@@ -0,0 +1,284 @@
+//! Custom cursor rendering
+//!
+//! With `CursorMode::Metadata` the compositor leaves the pointer out of the
+//! captured frames and reports its position in `Frame::cursor` instead.
+//! `CursorRenderer` draws a configurable cursor bitmap at that position,
+//! optionally with a highlight halo or a spotlight that dims the rest of
+//! the screen, which is handy for tutorials and screencasts.
+
+use super::overlay::{fill_rect, OverlayColor};
+use crate::types::Frame;
+
+/// Built-in arrow cursor: `X` = outline, `.` = fill, space = transparent
+const ARROW: [&str; 19] = [
+    "X",
+    "XX",
+    "X.X",
+    "X..X",
+    "X...X",
+    "X....X",
+    "X.....X",
+    "X......X",
+    "X.......X",
+    "X........X",
+    "X.........X",
+    "X......XXXXX",
+    "X...X..X",
+    "X..XX..X",
+    "X.X  X..X",
+    "XX   X..X",
+    "X     X..X",
+    "      X..X",
+    "       XX",
+];
+
+/// Cursor image with its hotspot
+#[derive(Debug, Clone)]
+pub struct CursorBitmap {
+    width: u32,
+    height: u32,
+    hotspot_x: u32,
+    hotspot_y: u32,
+    pixels: Vec<OverlayColor>,
+}
+
+impl CursorBitmap {
+    /// Classic arrow pointer in the given outline/fill colors
+    pub fn arrow(outline: OverlayColor, fill: OverlayColor) -> Self {
+        let width = ARROW.iter().map(|row| row.len()).max().unwrap_or(0) as u32;
+        let height = ARROW.len() as u32;
+        let transparent = OverlayColor::rgba(0, 0, 0, 0);
+        let mut pixels = vec![transparent; (width * height) as usize];
+
+        for (y, row) in ARROW.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                pixels[y * width as usize + x] = match c {
+                    'X' => outline,
+                    '.' => fill,
+                    _ => transparent,
+                };
+            }
+        }
+
+        Self {
+            width,
+            height,
+            hotspot_x: 0,
+            hotspot_y: 0,
+            pixels,
+        }
+    }
+
+    /// Cursor from straight-alpha RGBA pixels (`width * height * 4` bytes)
+    pub fn from_rgba(
+        width: u32,
+        height: u32,
+        rgba: &[u8],
+        hotspot_x: u32,
+        hotspot_y: u32,
+    ) -> Option<Self> {
+        if rgba.len() < (width * height * 4) as usize {
+            return None;
+        }
+        let pixels = rgba
+            .chunks_exact(4)
+            .take((width * height) as usize)
+            .map(|px| OverlayColor::rgba(px[0], px[1], px[2], px[3]))
+            .collect();
+        Some(Self {
+            width,
+            height,
+            hotspot_x: hotspot_x.min(width.saturating_sub(1)),
+            hotspot_y: hotspot_y.min(height.saturating_sub(1)),
+            pixels,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+impl Default for CursorBitmap {
+    fn default() -> Self {
+        Self::arrow(OverlayColor::BLACK, OverlayColor::WHITE)
+    }
+}
+
+/// Emphasis drawn around the cursor
+///
+/// Cursor metadata carries position only (no button state), so the
+/// highlight marks the pointer continuously rather than on click.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CursorHighlight {
+    /// Cursor only
+    #[default]
+    None,
+    /// Translucent disc under the cursor
+    Halo { radius: u32, color: OverlayColor },
+    /// Dim everything outside a circle around the cursor
+    Spotlight { radius: u32, dim: u8 },
+}
+
+/// Draws a custom cursor at the position reported by cursor metadata
+#[derive(Debug, Clone)]
+pub struct CursorRenderer {
+    bitmap: CursorBitmap,
+    /// Integer upscale factor for the bitmap
+    scale: u32,
+    highlight: CursorHighlight,
+}
+
+impl CursorRenderer {
+    pub fn new() -> Self {
+        Self {
+            bitmap: CursorBitmap::default(),
+            scale: 1,
+            highlight: CursorHighlight::None,
+        }
+    }
+
+    pub fn with_bitmap(mut self, bitmap: CursorBitmap) -> Self {
+        self.bitmap = bitmap;
+        self
+    }
+
+    /// Upscale the cursor bitmap (e.g. 2 for a large tutorial pointer)
+    pub fn with_scale(mut self, scale: u32) -> Self {
+        self.scale = scale.max(1);
+        self
+    }
+
+    pub fn with_highlight(mut self, highlight: CursorHighlight) -> Self {
+        self.highlight = highlight;
+        self
+    }
+
+    /// Yellow halo around the pointer
+    pub fn with_halo(self, radius: u32) -> Self {
+        self.with_highlight(CursorHighlight::Halo {
+            radius,
+            color: OverlayColor::YELLOW.with_alpha(96),
+        })
+    }
+
+    /// Dim the frame outside `radius` pixels of the pointer
+    pub fn with_spotlight(self, radius: u32, dim: u8) -> Self {
+        self.with_highlight(CursorHighlight::Spotlight { radius, dim })
+    }
+
+    /// Draw the cursor onto a frame (no-op without visible cursor metadata)
+    pub fn apply(&mut self, frame: &mut Frame) {
+        let Some(cursor) = frame.cursor else {
+            return;
+        };
+        if !cursor.visible {
+            return;
+        }
+        let (cx, cy) = (cursor.x as i64, cursor.y as i64);
+
+        match self.highlight {
+            CursorHighlight::None => {}
+            CursorHighlight::Halo { radius, color } => {
+                fill_circle(frame, cx, cy, radius, color);
+            }
+            CursorHighlight::Spotlight { radius, dim } => {
+                dim_outside_circle(frame, cx, cy, radius, OverlayColor::BLACK.with_alpha(dim));
+            }
+        }
+
+        let scale = self.scale;
+        let origin_x = cx - (self.bitmap.hotspot_x * scale) as i64;
+        let origin_y = cy - (self.bitmap.hotspot_y * scale) as i64;
+        for y in 0..self.bitmap.height {
+            for x in 0..self.bitmap.width {
+                let color = self.bitmap.pixels[(y * self.bitmap.width + x) as usize];
+                if color.a == 0 {
+                    continue;
+                }
+                fill_rect(
+                    frame,
+                    origin_x + (x * scale) as i64,
+                    origin_y + (y * scale) as i64,
+                    scale,
+                    scale,
+                    color,
+                );
+            }
+        }
+    }
+}
+
+impl Default for CursorRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Horizontal half-width of a circle at vertical offset `dy`
+fn half_span(radius: u32, dy: i64) -> i64 {
+    let r = radius as i64;
+    ((r * r - dy * dy) as f64).sqrt() as i64
+}
+
+fn fill_circle(frame: &mut Frame, cx: i64, cy: i64, radius: u32, color: OverlayColor) {
+    let r = radius as i64;
+    for dy in -r..=r {
+        let half = half_span(radius, dy);
+        fill_rect(frame, cx - half, cy + dy, (half * 2 + 1) as u32, 1, color);
+    }
+}
+
+fn dim_outside_circle(frame: &mut Frame, cx: i64, cy: i64, radius: u32, color: OverlayColor) {
+    let r = radius as i64;
+    let width = frame.width;
+    for y in 0..frame.height as i64 {
+        let dy = y - cy;
+        if dy.abs() > r {
+            fill_rect(frame, 0, y, width, 1, color);
+            continue;
+        }
+        let half = half_span(radius, dy);
+        let left = (cx - half).max(0);
+        fill_rect(frame, 0, y, left as u32, 1, color);
+        let right = cx + half + 1;
+        if right < width as i64 {
+            fill_rect(frame, right, y, (width as i64 - right) as u32, 1, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{CursorInfo, FrameFormat};
+
+    #[test]
+    fn test_cursor_drawn_at_metadata_position() {
+        let mut renderer = CursorRenderer::new().with_spotlight(20, 128);
+
+        let mut frame = Frame::new(64, 64, FrameFormat::Bgra);
+        frame.data.fill(200);
+        renderer.apply(&mut frame);
+        assert!(frame.data.iter().all(|&b| b == 200));
+
+        frame.cursor = Some(CursorInfo {
+            x: 32,
+            y: 32,
+            visible: true,
+            ..Default::default()
+        });
+        renderer.apply(&mut frame);
+
+        let px = |x: usize, y: usize| frame.data[(y * 64 + x) * 4];
+        // Outline pixel at the hotspot, white fill next to it, dimmed corner
+        assert_eq!(px(32, 32), 0);
+        assert_eq!(px(33, 34), 255);
+        assert!(px(0, 0) < 200);
+        assert_eq!(px(45, 32), 200);
+    }
+}
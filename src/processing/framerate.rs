@@ -0,0 +1,419 @@
+//! Frame rate conversion
+//!
+//! Resamples captured frames onto the output frame rate grid. `Blend`
+//! averages every frame that lands in an output interval (high-FPS capture
+//! delivered at a standard rate) and cross-fades between neighbours for
+//! intervals that got no frame of their own. `Interpolate` hands the frames
+//! to FFmpeg's `minterpolate` filter for motion-compensated in-between frames.
+
+use crate::config::FramerateConversion;
+use crate::error::{Error, Result};
+use crate::types::{Frame, FrameFormat, Framerate};
+
+use ffmpeg_next as ffmpeg;
+use ffmpeg_next::format::Pixel;
+
+/// Longest gap (in output frames) filled with cross-faded frames; longer
+/// gaps are an idle screen, not a slow source, and are left alone
+const MAX_FILL_FRAMES: i64 = 60;
+
+/// Converts a stream of captured frames to a fixed output frame rate
+///
+/// Frames go in with `push` in capture order; converted frames come back
+/// stamped on the output grid. Both methods delay output by one input
+/// frame, so call `flush` at end of stream.
+pub struct FramerateConverter {
+    mode: FramerateConversion,
+    framerate: Framerate,
+    origin: Option<i64>,
+    blend: Option<BlendSlot>,
+    interpolator: Option<Interpolator>,
+}
+
+/// Frames accumulated for one output interval
+struct BlendSlot {
+    index: i64,
+    sums: Vec<u32>,
+    count: u32,
+    template: Frame,
+}
+
+impl FramerateConverter {
+    /// Create a converter targeting `framerate` (clamped to 1-1000 fps)
+    pub fn new(mode: FramerateConversion, framerate: Framerate) -> Self {
+        Self {
+            mode,
+            framerate: framerate.clamped(),
+            origin: None,
+            blend: None,
+            interpolator: None,
+        }
+    }
+
+    /// Feed a captured frame; returns the output frames now complete
+    pub fn push(&mut self, frame: Frame) -> Result<Vec<Frame>> {
+        // DMA-BUF frames have no CPU copy to combine
+        if frame.data.is_empty() || self.framerate.num == 0 {
+            return Ok(vec![frame]);
+        }
+        match self.mode {
+            FramerateConversion::Nearest => Ok(vec![frame]),
+            FramerateConversion::Blend => Ok(self.push_blend(frame)),
+            FramerateConversion::Interpolate => {
+                if self.interpolator.is_none() {
+                    self.interpolator = Some(Interpolator::new(&frame, self.framerate)?);
+                }
+                let interpolator = self.interpolator.as_mut().unwrap();
+                if !interpolator.accepts(&frame) {
+                    // Source resolution changed: drain and start over
+                    let mut out = interpolator.finish()?;
+                    let mut next = Interpolator::new(&frame, self.framerate)?;
+                    out.extend(next.push(&frame)?);
+                    self.interpolator = Some(next);
+                    return Ok(out);
+                }
+                interpolator.push(&frame)
+            }
+        }
+    }
+
+    /// Emit whatever is still held back
+    pub fn flush(&mut self) -> Result<Vec<Frame>> {
+        let mut out = Vec::new();
+        if let Some(slot) = self.blend.take() {
+            out.push(self.finish_slot(slot));
+        }
+        if let Some(mut interpolator) = self.interpolator.take() {
+            out.extend(interpolator.finish()?);
+        }
+        Ok(out)
+    }
+
+    /// Output interval a timestamp falls in
+    fn slot_index(&self, pts: i64) -> i64 {
+        let origin = self.origin.unwrap_or(pts);
+        let elapsed = (pts - origin) as i128 * self.framerate.num as i128;
+        (elapsed / (self.framerate.den as i128 * 1_000_000)) as i64
+    }
+
+    /// Start time of an output interval
+    fn slot_pts(&self, index: i64) -> i64 {
+        let offset = index as i128 * self.framerate.den as i128 * 1_000_000;
+        self.origin.unwrap_or(0) + (offset / self.framerate.num as i128) as i64
+    }
+
+    fn push_blend(&mut self, frame: Frame) -> Vec<Frame> {
+        let origin = *self.origin.get_or_insert(frame.pts);
+        let index = self.slot_index(frame.pts.max(origin));
+
+        let Some(mut slot) = self.blend.take() else {
+            self.blend = Some(BlendSlot::new(index, frame));
+            return Vec::new();
+        };
+
+        let same_layout = slot.template.format == frame.format
+            && slot.template.width == frame.width
+            && slot.template.height == frame.height
+            && slot.template.data.len() == frame.data.len();
+        if same_layout && index <= slot.index {
+            slot.add(&frame);
+            self.blend = Some(slot);
+            return Vec::new();
+        }
+
+        let finished_index = slot.index;
+        let blended = self.finish_slot(slot);
+        let mut fills = Vec::new();
+
+        // Cross-fade into the new frame for intervals nothing landed in
+        let gap = index - finished_index - 1;
+        if same_layout && gap > 0 && gap <= MAX_FILL_FRAMES {
+            let span = (frame.pts - blended.pts).max(1);
+            for fill in finished_index + 1..index {
+                let pts = self.slot_pts(fill);
+                let weight = ((pts - blended.pts) * 256 + span / 2) / span;
+                let mut mixed = crossfade(&blended, &frame, weight.clamp(0, 256) as u32);
+                mixed.pts = pts;
+                mixed.duration = self.framerate.frame_duration_us();
+                fills.push(mixed);
+            }
+        }
+
+        self.blend = Some(BlendSlot::new(index, frame));
+        let mut out = vec![blended];
+        out.extend(fills);
+        out
+    }
+
+    fn finish_slot(&self, slot: BlendSlot) -> Frame {
+        let pts = self.slot_pts(slot.index);
+        let mut frame = slot.finish();
+        frame.pts = pts;
+        frame.duration = self.framerate.frame_duration_us();
+        frame
+    }
+}
+
+impl BlendSlot {
+    fn new(index: i64, frame: Frame) -> Self {
+        let mut sums = Vec::new();
+        accumulate(&mut sums, &frame);
+        Self {
+            index,
+            sums,
+            count: 1,
+            template: frame,
+        }
+    }
+
+    fn add(&mut self, frame: &Frame) {
+        accumulate(&mut self.sums, frame);
+        self.count += 1;
+        // Cursor and colorimetry follow the most recent frame
+        let data = std::mem::take(&mut self.template.data);
+        self.template = Frame {
+            data,
+            ..metadata_of(frame)
+        };
+    }
+
+    fn finish(self) -> Frame {
+        let mut frame = self.template;
+        if self.count > 1 {
+            let count = self.count;
+            store_samples(&mut frame, self.sums.iter().map(|&sum| sum / count));
+        }
+        frame
+    }
+}
+
+/// A frame's metadata without its pixel data
+fn metadata_of(frame: &Frame) -> Frame {
+    Frame {
+        data: Vec::new(),
+        dmabuf_fd: None,
+        ..*frame
+    }
+}
+
+/// Add a frame's samples to running sums
+fn accumulate(sums: &mut Vec<u32>, frame: &Frame) {
+    let samples = samples(frame);
+    if sums.is_empty() {
+        sums.extend(samples);
+    } else {
+        for (sum, sample) in sums.iter_mut().zip(samples) {
+            *sum += sample;
+        }
+    }
+}
+
+/// Mix two frames of the same layout; `weight` of 256 is all `b`
+fn crossfade(a: &Frame, b: &Frame, weight: u32) -> Frame {
+    let mut out = b.copy_data();
+    let mixed: Vec<u32> = samples(a)
+        .zip(samples(b))
+        .map(|(x, y)| (x * (256 - weight) + y * weight + 128) / 256)
+        .collect();
+    store_samples(&mut out, mixed.into_iter());
+    out
+}
+
+/// Frame samples widened to u32 (16-bit little endian for P010)
+fn samples(frame: &Frame) -> Box<dyn Iterator<Item = u32> + '_> {
+    if frame.format == FrameFormat::P010 {
+        Box::new(
+            frame
+                .data
+                .chunks_exact(2)
+                .map(|s| u16::from_le_bytes([s[0], s[1]]) as u32),
+        )
+    } else {
+        Box::new(frame.data.iter().map(|&s| s as u32))
+    }
+}
+
+fn store_samples(frame: &mut Frame, values: impl Iterator<Item = u32>) {
+    if frame.format == FrameFormat::P010 {
+        for (dst, value) in frame.data.chunks_exact_mut(2).zip(values) {
+            dst.copy_from_slice(&(value as u16).to_le_bytes());
+        }
+    } else {
+        for (dst, value) in frame.data.iter_mut().zip(values) {
+            *dst = value as u8;
+        }
+    }
+}
+
+/// `minterpolate` filter graph for one input layout
+struct Interpolator {
+    graph: ffmpeg::filter::Graph,
+    pixel: Pixel,
+    format: FrameFormat,
+    width: u32,
+    height: u32,
+    template: Frame,
+}
+
+impl Interpolator {
+    fn new(frame: &Frame, framerate: Framerate) -> Result<Self> {
+        let pixel = match frame.format {
+            FrameFormat::Bgra => Pixel::BGRA,
+            FrameFormat::Rgba => Pixel::RGBA,
+            FrameFormat::Rgb24 => Pixel::RGB24,
+            other => {
+                return Err(Error::Pipeline(format!(
+                    "Motion interpolation needs packed RGB frames, got {:?}",
+                    other
+                )))
+            }
+        };
+
+        let filter_err = |e: ffmpeg::Error| Error::Pipeline(format!("minterpolate: {}", e));
+        let mut graph = ffmpeg::filter::Graph::new();
+        let args = format!(
+            "video_size={}x{}:pix_fmt={}:time_base=1/1000000:pixel_aspect=1/1",
+            frame.width,
+            frame.height,
+            ffmpeg::ffi::AVPixelFormat::from(pixel) as i32
+        );
+        let buffer = ffmpeg::filter::find("buffer")
+            .ok_or_else(|| Error::Pipeline("FFmpeg buffer filter not found".into()))?;
+        let buffersink = ffmpeg::filter::find("buffersink")
+            .ok_or_else(|| Error::Pipeline("FFmpeg buffersink filter not found".into()))?;
+        graph.add(&buffer, "in", &args).map_err(filter_err)?;
+        graph.add(&buffersink, "out", "").map_err(filter_err)?;
+
+        let spec = format!(
+            "minterpolate=fps={}/{}:mi_mode=mci:mc_mode=aobmc:vsbmc=1",
+            framerate.num, framerate.den
+        );
+        graph
+            .output("in", 0)
+            .and_then(|p| p.input("out", 0))
+            .and_then(|p| p.parse(&spec))
+            .map_err(filter_err)?;
+        graph.validate().map_err(filter_err)?;
+
+        Ok(Self {
+            graph,
+            pixel,
+            format: frame.format,
+            width: frame.width,
+            height: frame.height,
+            template: metadata_of(frame),
+        })
+    }
+
+    fn accepts(&self, frame: &Frame) -> bool {
+        frame.format == self.format && frame.width == self.width && frame.height == self.height
+    }
+
+    fn push(&mut self, frame: &Frame) -> Result<Vec<Frame>> {
+        let mut video = ffmpeg::frame::Video::new(self.pixel, self.width, self.height);
+        let row_bytes = (self.width * self.bytes_per_pixel()) as usize;
+        let src_stride = frame.stride as usize;
+        let dst_stride = video.stride(0);
+        let dst = video.data_mut(0);
+        for (row, line) in frame
+            .data
+            .chunks(src_stride)
+            .take(self.height as usize)
+            .enumerate()
+        {
+            let len = row_bytes.min(line.len());
+            dst[row * dst_stride..row * dst_stride + len].copy_from_slice(&line[..len]);
+        }
+        video.set_pts(Some(frame.pts));
+
+        self.template = metadata_of(frame);
+        self.input()
+            .source()
+            .add(&video)
+            .map_err(|e| Error::Pipeline(format!("minterpolate: {}", e)))?;
+        Ok(self.drain())
+    }
+
+    fn finish(&mut self) -> Result<Vec<Frame>> {
+        self.input()
+            .source()
+            .flush()
+            .map_err(|e| Error::Pipeline(format!("minterpolate: {}", e)))?;
+        Ok(self.drain())
+    }
+
+    fn input(&mut self) -> ffmpeg::filter::Context {
+        // Added in new()
+        self.graph.get("in").unwrap()
+    }
+
+    /// Collect the frames the filter has ready
+    fn drain(&mut self) -> Vec<Frame> {
+        let mut out = Vec::new();
+        let mut sink_ctx = self.graph.get("out").unwrap();
+        let time_base = sink_ctx.sink().time_base();
+        let mut filtered = ffmpeg::frame::Video::empty();
+        while sink_ctx.sink().frame(&mut filtered).is_ok() {
+            let pts =
+                filtered.pts().unwrap_or(0) as i128 * time_base.numerator() as i128 * 1_000_000
+                    / time_base.denominator().max(1) as i128;
+
+            let row_bytes = (self.width * self.bytes_per_pixel()) as usize;
+            let stride = filtered.stride(0);
+            let mut data = Vec::with_capacity(row_bytes * self.height as usize);
+            for line in filtered.data(0).chunks(stride).take(self.height as usize) {
+                data.extend_from_slice(&line[..row_bytes]);
+            }
+
+            out.push(Frame {
+                data,
+                stride: row_bytes as u32,
+                pts: pts as i64,
+                ..metadata_of(&self.template)
+            });
+        }
+        out
+    }
+
+    fn bytes_per_pixel(&self) -> u32 {
+        if self.format == FrameFormat::Rgb24 {
+            3
+        } else {
+            4
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame_at(pts: i64, value: u8) -> Frame {
+        let mut frame = Frame::from_data(vec![value; 16], 2, 2, 8, FrameFormat::Bgra);
+        frame.pts = pts;
+        frame
+    }
+
+    #[test]
+    fn test_blend_averages_and_fills() {
+        let mut converter =
+            FramerateConverter::new(FramerateConversion::Blend, Framerate::new(30, 1));
+
+        // Two frames inside the first 33ms interval are averaged
+        assert!(converter.push(frame_at(0, 10)).unwrap().is_empty());
+        assert!(converter.push(frame_at(16_666, 30)).unwrap().is_empty());
+        let out = converter.push(frame_at(66_667, 120)).unwrap();
+
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[0].pts, 0);
+        assert_eq!(out[0].data[0], 20);
+        // The empty interval at 33ms is half way to the next frame
+        assert_eq!(out[1].pts, 33_333);
+        assert_eq!(out[1].data[0], 70);
+
+        let rest = converter.flush().unwrap();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].pts, 66_666);
+        assert_eq!(rest[0].data[0], 120);
+    }
+}
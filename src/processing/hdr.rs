@@ -8,9 +8,10 @@
 
 use crate::error::{Error, Result};
 use crate::types::FrameFormat;
+use serde::{Deserialize, Serialize};
 
 /// HDR transfer function
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum TransferFunction {
     /// SDR (BT.709 gamma)
     #[default]
@@ -33,7 +34,7 @@ impl TransferFunction {
 }
 
 /// Color primaries (color gamut)
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum ColorPrimaries {
     /// BT.709 (SDR, HD)
     #[default]
@@ -42,6 +43,8 @@ pub enum ColorPrimaries {
     Bt2020,
     /// DCI-P3 (Cinema)
     DciP3,
+    /// BT.601 / SMPTE 170M (SD)
+    Bt601,
 }
 
 impl ColorPrimaries {
@@ -51,12 +54,13 @@ impl ColorPrimaries {
             ColorPrimaries::Bt709 => 1,   // AVCOL_PRI_BT709
             ColorPrimaries::Bt2020 => 9,  // AVCOL_PRI_BT2020
             ColorPrimaries::DciP3 => 11,  // AVCOL_PRI_SMPTE432 (DCI-P3)
+            ColorPrimaries::Bt601 => 6,   // AVCOL_PRI_SMPTE170M
         }
     }
 }
 
 /// Color matrix coefficients
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum ColorMatrix {
     /// BT.709
     #[default]
@@ -65,6 +69,8 @@ pub enum ColorMatrix {
     Bt2020Ncl,
     /// BT.2020 CL (Constant Luminance)
     Bt2020Cl,
+    /// BT.601 / SMPTE 170M (SD)
+    Bt601,
 }
 
 impl ColorMatrix {
@@ -74,12 +80,42 @@ impl ColorMatrix {
             ColorMatrix::Bt709 => 1,      // AVCOL_SPC_BT709
             ColorMatrix::Bt2020Ncl => 9,  // AVCOL_SPC_BT2020_NCL
             ColorMatrix::Bt2020Cl => 10,  // AVCOL_SPC_BT2020_CL
+            ColorMatrix::Bt601 => 6,      // AVCOL_SPC_SMPTE170M
+        }
+    }
+
+    /// swscale colorspace (SWS_CS_*) for RGB <-> YUV conversion
+    pub fn sws_colorspace(&self) -> i32 {
+        match self {
+            ColorMatrix::Bt709 => 1,                             // SWS_CS_ITU709
+            ColorMatrix::Bt2020Ncl | ColorMatrix::Bt2020Cl => 9, // SWS_CS_BT2020
+            ColorMatrix::Bt601 => 5,                             // SWS_CS_ITU601
+        }
+    }
+}
+
+/// Quantization range of YUV samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ColorRange {
+    /// Limited / TV range (16-235 for 8-bit luma)
+    #[default]
+    Limited,
+    /// Full / PC range (0-255)
+    Full,
+}
+
+impl ColorRange {
+    /// FFmpeg color_range value
+    pub fn ffmpeg_range(&self) -> i32 {
+        match self {
+            ColorRange::Limited => 1, // AVCOL_RANGE_MPEG
+            ColorRange::Full => 2,    // AVCOL_RANGE_JPEG
         }
     }
 }
 
 /// HDR10 static metadata (SMPTE ST 2086)
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct Hdr10Metadata {
     /// Red primary X (0.0-1.0)
     pub red_primary_x: f32,
@@ -129,10 +165,40 @@ impl Hdr10Metadata {
         meta.max_luminance = max_nits;
         meta
     }
+
+    /// FFmpeg mastering display side data
+    ///
+    /// Uses the ST 2086 units (0.00002 for chromaticity, 0.0001 cd/m² for
+    /// luminance), so values survive the trip into SEI / OBU / container
+    /// fields unchanged.
+    pub(crate) fn to_ffmpeg(&self) -> ffmpeg_next::ffi::AVMasteringDisplayMetadata {
+        use ffmpeg_next::ffi::AVRational;
+
+        let chroma = |v: f32| AVRational {
+            num: (v * 50000.0).round() as i32,
+            den: 50000,
+        };
+        let luma = |v: f32| AVRational {
+            num: (v * 10000.0).round() as i32,
+            den: 10000,
+        };
+        ffmpeg_next::ffi::AVMasteringDisplayMetadata {
+            display_primaries: [
+                [chroma(self.red_primary_x), chroma(self.red_primary_y)],
+                [chroma(self.green_primary_x), chroma(self.green_primary_y)],
+                [chroma(self.blue_primary_x), chroma(self.blue_primary_y)],
+            ],
+            white_point: [chroma(self.white_point_x), chroma(self.white_point_y)],
+            min_luminance: luma(self.min_luminance),
+            max_luminance: luma(self.max_luminance),
+            has_primaries: 1,
+            has_luminance: 1,
+        }
+    }
 }
 
 /// Content Light Level Info (MaxCLL, MaxFALL)
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct ContentLightLevel {
     /// Maximum Content Light Level (nits)
     pub max_cll: u16,
@@ -152,10 +218,18 @@ impl ContentLightLevel {
             max_fall: 400,
         }
     }
+
+    /// FFmpeg content light level side data
+    pub(crate) fn to_ffmpeg(&self) -> ffmpeg_next::ffi::AVContentLightMetadata {
+        ffmpeg_next::ffi::AVContentLightMetadata {
+            MaxCLL: self.max_cll as u32,
+            MaxFALL: self.max_fall as u32,
+        }
+    }
 }
 
 /// Complete HDR configuration
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct HdrConfig {
     /// Transfer function
     pub transfer: TransferFunction,
@@ -325,6 +399,89 @@ pub fn nv12_to_p010(input: &[u8], width: usize, height: usize) -> Result<Vec<u8>
     Ok(output)
 }
 
+/// Reference white of PQ content (ITU-R BT.2408), mapped to SDR 1.0 before
+/// the highlights are rolled off
+const PQ_REFERENCE_WHITE_NITS: f32 = 203.0;
+
+/// Tonemap P010 HDR10 (BT.2020, PQ) to NV12 SDR (BT.709, limited range)
+///
+/// Luma goes through a 1024-entry table: PQ decoded to linear light, rolled
+/// off with an extended Reinhard curve that maps `peak_nits` to SDR white
+/// and gamma-encoded. Chroma is scaled by the same roll-off as the mean luma
+/// of its 2x2 block, so per pixel the work is table lookups and integer
+/// math. The BT.2020 gamut is not remapped to BT.709; saturated colours come
+/// out a little muted. Odd sizes are fine, chroma covers `div_ceil(2)`.
+pub fn p010_to_nv12_tonemap(
+    input: &[u8],
+    width: usize,
+    height: usize,
+    peak_nits: f32,
+) -> Result<Vec<u8>> {
+    let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+    let y_size = width * height;
+    let uv_size = chroma_width * chroma_height * 2;
+    if input.len() < (y_size + uv_size) * 2 {
+        return Err(Error::ColorspaceConversion(
+            "Input buffer too small for P010".into(),
+        ));
+    }
+
+    let (luma_lut, chroma_gain) = tonemap_luts(peak_nits);
+    let sample = |index: usize| {
+        let value = u16::from_le_bytes([input[index * 2], input[index * 2 + 1]]);
+        (value >> 6) as usize
+    };
+
+    let mut output = vec![0u8; y_size + uv_size];
+    let (out_y, out_uv) = output.split_at_mut(y_size);
+    for (i, out) in out_y.iter_mut().enumerate() {
+        *out = luma_lut[sample(i)];
+    }
+    for cy in 0..chroma_height {
+        let rows = cy * 2..(cy * 2 + 2).min(height);
+        for cx in 0..chroma_width {
+            let cols = cx * 2..(cx * 2 + 2).min(width);
+            let mut sum = 0;
+            for y in rows.clone() {
+                sum += cols.clone().map(|x| sample(y * width + x)).sum::<usize>();
+            }
+            let gain = chroma_gain[sum / (rows.len() * cols.len())];
+
+            let index = (cy * chroma_width + cx) * 2;
+            for (c, out) in out_uv[index..index + 2].iter_mut().enumerate() {
+                let value = sample(y_size + index + c) as i32 - 512;
+                *out = (128 + ((value * gain + 2048) >> 12)).clamp(16, 240) as u8;
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+/// Tables for `p010_to_nv12_tonemap`, indexed by 10-bit limited-range luma:
+/// the 8-bit SDR luma, and the chroma gain (4.12 fixed point, including the
+/// 10- to 8-bit step)
+fn tonemap_luts(peak_nits: f32) -> (Vec<u8>, Vec<i32>) {
+    let white = (peak_nits / PQ_REFERENCE_WHITE_NITS).max(1.0);
+    let white_sq = white * white;
+
+    let mut luma = Vec::with_capacity(1024);
+    let mut gain = Vec::with_capacity(1024);
+    for code in 0..1024 {
+        let hdr = ((code as f32 - 64.0) / 876.0).clamp(0.0, 1.0);
+        // Relative to reference white, then rolled off and gamma-encoded
+        let linear = pq_to_linear(hdr) / PQ_REFERENCE_WHITE_NITS;
+        let sdr = (linear * (1.0 + linear / white_sq) / (1.0 + linear))
+            .clamp(0.0, 1.0)
+            .powf(1.0 / 2.4);
+        luma.push((16.0 + 219.0 * sdr).round() as u8);
+
+        let ratio = if hdr > 0.0 { (sdr / hdr).min(4.0) } else { 1.0 };
+        gain.push((ratio * 1024.0).round() as i32);
+    }
+    (luma, gain)
+}
+
 /// Apply PQ (SMPTE ST 2084) transfer function
 /// Converts linear light to PQ encoded value
 pub fn linear_to_pq(linear: f32) -> f32 {
@@ -370,3 +527,62 @@ pub fn tonemap_aces(x: f32) -> f32 {
 
     ((x * (A * x + B)) / (x * (C * x + D) + E)).clamp(0.0, 1.0)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hdr10_side_data_units() {
+        let mastering = Hdr10Metadata::bt2020_default().to_ffmpeg();
+        // Red x 0.708 -> 35400 / 50000
+        assert_eq!(mastering.display_primaries[0][0].num, 35400);
+        assert_eq!(mastering.display_primaries[0][0].den, 50000);
+        assert_eq!(mastering.white_point[1].num, 16450);
+        assert_eq!(mastering.max_luminance.num, 10_000_000);
+        assert_eq!(mastering.min_luminance.num, 10);
+
+        let light = ContentLightLevel::new(1000, 400).to_ffmpeg();
+        assert_eq!((light.MaxCLL, light.MaxFALL), (1000, 400));
+    }
+
+    /// P010 frame with one luma value and one Cb/Cr pair throughout
+    fn p010_frame(width: usize, height: usize, y: u16, cb: u16, cr: u16) -> Vec<u8> {
+        let chroma = width.div_ceil(2) * height.div_ceil(2);
+        let mut data = Vec::new();
+        for _ in 0..width * height {
+            data.extend_from_slice(&(y << 6).to_le_bytes());
+        }
+        for _ in 0..chroma {
+            data.extend_from_slice(&(cb << 6).to_le_bytes());
+            data.extend_from_slice(&(cr << 6).to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_tonemap_colour() {
+        // Reddish mid-tone: Cr above neutral, Cb below
+        let input = p010_frame(4, 4, 400, 450, 640);
+        let output = p010_to_nv12_tonemap(&input, 4, 4, 1000.0).unwrap();
+        assert_eq!(output.len(), 4 * 4 + 2 * 2 * 2);
+        let (u, v) = (output[16], output[17]);
+        assert!(u < 128 && v > 128, "u={} v={}", u, v);
+        assert!((16..=235).contains(&output[0]));
+
+        // Brighter HDR input stays brighter after the roll-off
+        let bright = p010_to_nv12_tonemap(&p010_frame(4, 4, 800, 512, 512), 4, 4, 1000.0);
+        assert!(bright.unwrap()[0] > output[0]);
+    }
+
+    #[test]
+    fn test_tonemap_odd_size() {
+        let input = p010_frame(5, 3, 500, 512, 512);
+        let output = p010_to_nv12_tonemap(&input, 5, 3, 1000.0).unwrap();
+        // Chroma covers the last column and row: 3x2 samples
+        assert_eq!(output.len(), 5 * 3 + 3 * 2 * 2);
+        assert!(output[15..].iter().all(|&c| c == 128));
+
+        assert!(p010_to_nv12_tonemap(&input[..input.len() - 2], 5, 3, 1000.0).is_err());
+    }
+}
@@ -5,19 +5,104 @@
 //! - Colorspace conversion
 //! - HDR to SDR tonemapping
 //! - P010 (10-bit) format support
+//! - Overlays (burned-in timecode, custom cursor)
+//! - Cropping, rotation and mirroring
+//! - Static screen detection
+//! - Frame rate conversion (blending, motion interpolation)
+//! - Zoom-to-cursor
+//! - Picture-in-picture (second source as an inset)
 
 mod convert;
+mod cursor;
+mod framerate;
 pub mod hdr;
+mod overlay;
+mod pip;
 mod scale;
+mod static_frame;
+mod timecode;
+mod transform;
+mod zoom;
 
-pub use convert::{convert_colorspace, ColorspaceConverter};
+pub use convert::{
+    convert_colorspace, convert_colorspace_with, ColorConversion, ColorspaceConverter,
+};
+pub use cursor::{CursorBitmap, CursorHighlight, CursorRenderer};
+pub use framerate::FramerateConverter;
 pub use hdr::{
-    ColorMatrix, ColorPrimaries, ContentLightLevel, Hdr10Metadata, HdrConfig, TransferFunction,
+    ColorMatrix, ColorPrimaries, ColorRange, ContentLightLevel, Hdr10Metadata, HdrConfig,
+    TransferFunction,
 };
+pub use overlay::{fill_rect, Overlay, OverlayColor, OverlayPosition};
+pub use pip::{PictureInPicture, PipLayout};
 pub use scale::{scale_frame, scale_nv12, ScaleAlgorithm, Scaler};
+pub use static_frame::StaticFrameDetector;
+pub use timecode::{format_timecode, TimecodeMode, TimecodeOverlay};
+pub use transform::{crop_frame, transform_frame};
+pub use zoom::AutoZoom;
 
+use crate::config::ProcessingConfig;
 use crate::error::Result;
-use crate::types::{Frame, FrameFormat, Resolution};
+use crate::types::{CursorInfo, Frame, FrameFormat, Resolution};
+
+/// Peak brightness assumed for HDR capture without `tonemap_peak_nits`
+const DEFAULT_TONEMAP_PEAK_NITS: u32 = 1000;
+
+/// How a frame's bit depth is brought to the encoder input's
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthConversion {
+    /// Same bit depth: converted as is
+    None,
+    /// 8-bit to 10-bit: samples are shifted up
+    Expand,
+    /// 10-bit SDR to 8-bit: samples are rounded down
+    Reduce,
+    /// 10-bit HDR (BT.2020) to 8-bit: tonemapped to BT.709 SDR
+    Tonemap,
+}
+
+/// Conversion a frame needs for the bit depth of `target`
+///
+/// 10-bit capture counts as HDR when it reports BT.2020 primaries or
+/// matrix; the capture doesn't report its transfer function, so HDR is
+/// taken to be PQ (HDR10).
+pub fn depth_conversion(frame: &Frame, target: FrameFormat) -> DepthConversion {
+    let (source, target) = (frame.format.bit_depth(), target.bit_depth());
+    if source < target {
+        DepthConversion::Expand
+    } else if source == target {
+        DepthConversion::None
+    } else if frame.primaries == Some(ColorPrimaries::Bt2020)
+        || frame.color_space == Some(ColorMatrix::Bt2020Ncl)
+    {
+        DepthConversion::Tonemap
+    } else {
+        DepthConversion::Reduce
+    }
+}
+
+/// Tonemap a P010 HDR frame to NV12 BT.709 SDR
+fn tonemap_frame(frame: &Frame, config: &ProcessingConfig) -> Result<Frame> {
+    let peak = config
+        .tonemap_peak_nits
+        .unwrap_or(DEFAULT_TONEMAP_PEAK_NITS);
+    let data = hdr::p010_to_nv12_tonemap(
+        &frame.data,
+        frame.width as usize,
+        frame.height as usize,
+        peak as f32,
+    )?;
+    Ok(Frame {
+        data,
+        stride: frame.width,
+        format: FrameFormat::Nv12,
+        dmabuf_fd: None,
+        color_space: Some(ColorMatrix::Bt709),
+        color_range: Some(ColorRange::Limited),
+        primaries: Some(ColorPrimaries::Bt709),
+        ..*frame
+    })
+}
 
 /// Process a frame (scale, convert, etc.)
 pub fn process_frame(
@@ -25,6 +110,30 @@ pub fn process_frame(
     target_resolution: Option<Resolution>,
     target_format: Option<FrameFormat>,
 ) -> Result<Frame> {
+    process_frame_with(
+        frame,
+        target_resolution,
+        target_format,
+        &ProcessingConfig::default(),
+    )
+}
+
+/// Process a frame with explicit processing options
+pub fn process_frame_with(
+    frame: &Frame,
+    target_resolution: Option<Resolution>,
+    target_format: Option<FrameFormat>,
+    config: &ProcessingConfig,
+) -> Result<Frame> {
+    // 10-bit HDR into an 8-bit encoder: tonemap before anything else, a
+    // plain conversion would only drop the low bits and leave PQ washed out
+    if let Some(fmt) = target_format {
+        if depth_conversion(frame, fmt) == DepthConversion::Tonemap {
+            let tonemapped = tonemap_frame(frame, config)?;
+            return process_frame_with(&tonemapped, target_resolution, target_format, config);
+        }
+    }
+
     let mut result = frame.data.clone();
     let mut width = frame.width;
     let mut height = frame.height;
@@ -40,13 +149,43 @@ pub fn process_frame(
     }
 
     // Convert colorspace if needed
+    let mut color_space = frame.color_space;
+    let mut color_range = frame.color_range;
+    let mut primaries = frame.primaries;
     if let Some(fmt) = target_format {
-        if fmt != frame.format {
-            result = convert::convert_colorspace(&result, format, fmt, width, height)?;
+        let forced = config.force_conversion;
+        if fmt != frame.format || forced.is_some() {
+            let conversion = color_conversion(frame, fmt, forced);
+            result =
+                convert::convert_colorspace_with(&result, format, fmt, width, height, conversion)?;
             format = fmt;
+            if fmt.is_rgb() {
+                color_space = None;
+                color_range = None;
+            } else {
+                color_space = Some(conversion.matrix);
+                color_range = Some(conversion.dst_range);
+            }
+            if let Some((forced_primaries, _, _)) = forced {
+                primaries = Some(forced_primaries);
+            }
         }
     }
 
+    // Keep cursor metadata in output coordinates
+    let cursor = frame.cursor.map(|c| {
+        if width == frame.width && height == frame.height {
+            return c;
+        }
+        let sx = width as f64 / frame.width.max(1) as f64;
+        let sy = height as f64 / frame.height.max(1) as f64;
+        CursorInfo {
+            x: (c.x as f64 * sx) as i32,
+            y: (c.y as f64 * sy) as i32,
+            ..c
+        }
+    });
+
     Ok(Frame {
         data: result,
         width,
@@ -57,5 +196,150 @@ pub fn process_frame(
         duration: frame.duration,
         is_keyframe: frame.is_keyframe,
         dmabuf_fd: None, // Processing breaks zero-copy
+        cursor,
+        color_space,
+        color_range,
+        primaries,
     })
 }
+
+/// Matrix and ranges for converting `frame` to `target`
+///
+/// YUV sources keep their own colorimetry. For RGB sources the YUV side is
+/// ours to pick: BT.709 limited range (BT.2020 for 10-bit HDR output).
+/// A forced colorimetry (`ProcessingConfig::force_conversion`) replaces the
+/// RGB pick, or the YUV source's own, which is then converted to ours.
+fn color_conversion(
+    frame: &Frame,
+    target: FrameFormat,
+    forced: Option<(ColorPrimaries, ColorMatrix, ColorRange)>,
+) -> ColorConversion {
+    let default_matrix = if target == FrameFormat::P010 {
+        ColorMatrix::Bt2020Ncl
+    } else {
+        ColorMatrix::Bt709
+    };
+    match (frame.format.is_rgb(), forced) {
+        (true, Some((_, matrix, range))) => ColorConversion {
+            matrix,
+            src_matrix: None,
+            src_range: ColorRange::Full,
+            dst_range: range,
+        },
+        (true, None) => ColorConversion {
+            matrix: default_matrix,
+            src_matrix: None,
+            src_range: ColorRange::Full,
+            dst_range: ColorRange::Limited,
+        },
+        (false, Some((_, matrix, range))) => ColorConversion {
+            matrix: default_matrix,
+            src_matrix: Some(matrix),
+            src_range: range,
+            dst_range: ColorRange::Limited,
+        },
+        (false, None) => {
+            let range = frame.color_range.unwrap_or_default();
+            ColorConversion {
+                matrix: frame.color_space.unwrap_or_default(),
+                src_matrix: None,
+                src_range: range,
+                dst_range: range,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forced_color_conversion() {
+        let rgb = Frame::new(16, 16, FrameFormat::Bgra);
+        let mut yuv = Frame::new(16, 16, FrameFormat::Nv12);
+        yuv.color_space = Some(ColorMatrix::Bt709);
+        yuv.color_range = Some(ColorRange::Limited);
+        let forced = Some((ColorPrimaries::Bt601, ColorMatrix::Bt601, ColorRange::Full));
+
+        // RGB: the forced colorimetry replaces the BT.709 default
+        assert_eq!(
+            color_conversion(&rgb, FrameFormat::Nv12, None).matrix,
+            ColorMatrix::Bt709
+        );
+        let conversion = color_conversion(&rgb, FrameFormat::Nv12, forced);
+        assert_eq!(conversion.matrix, ColorMatrix::Bt601);
+        assert_eq!(conversion.dst_range, ColorRange::Full);
+
+        // YUV: the forced colorimetry replaces the capture's, and is
+        // converted to BT.709 limited
+        let conversion = color_conversion(&yuv, FrameFormat::Nv12, forced);
+        assert_eq!(conversion.src_matrix, Some(ColorMatrix::Bt601));
+        assert_eq!(conversion.src_range, ColorRange::Full);
+        assert_eq!(conversion.matrix, ColorMatrix::Bt709);
+        assert_eq!(conversion.dst_range, ColorRange::Limited);
+    }
+
+    #[test]
+    fn test_yuv_range_change_keeps_depth() {
+        // Full to limited range: 10-bit levels one apart must stay apart,
+        // which an 8-bit detour can't do
+        let mut data = Vec::new();
+        for y in 0..4 * 2 {
+            data.extend_from_slice(&((512u16 + y % 2) << 6).to_le_bytes());
+        }
+        for _ in 0..2 * 2 {
+            data.extend_from_slice(&(512u16 << 6).to_le_bytes());
+        }
+        let conversion = ColorConversion {
+            src_range: ColorRange::Full,
+            dst_range: ColorRange::Limited,
+            ..Default::default()
+        };
+        let p010 = FrameFormat::P010;
+        let output = convert_colorspace_with(&data, p010, p010, 4, 2, conversion).unwrap();
+        assert_eq!(output.len(), data.len());
+        let luma = |i: usize| u16::from_le_bytes([output[i * 2], output[i * 2 + 1]]) >> 6;
+        assert!((495..=510).contains(&luma(0)), "{}", luma(0));
+        assert_ne!(luma(0), luma(1));
+    }
+
+    #[test]
+    fn test_depth_conversion() {
+        let mut hdr = Frame::new(16, 16, FrameFormat::P010);
+        assert_eq!(
+            depth_conversion(&hdr, FrameFormat::P010),
+            DepthConversion::None
+        );
+        assert_eq!(
+            depth_conversion(&hdr, FrameFormat::Nv12),
+            DepthConversion::Reduce
+        );
+        hdr.primaries = Some(ColorPrimaries::Bt2020);
+        assert_eq!(
+            depth_conversion(&hdr, FrameFormat::Nv12),
+            DepthConversion::Tonemap
+        );
+        let sdr = Frame::new(16, 16, FrameFormat::Bgra);
+        assert_eq!(
+            depth_conversion(&sdr, FrameFormat::P010),
+            DepthConversion::Expand
+        );
+
+        // Grey a little above PQ reference white lands in the upper SDR
+        // range, neutral chroma stays neutral
+        let mut data = Vec::new();
+        for _ in 0..16 * 16 {
+            data.extend_from_slice(&(592u16 << 6).to_le_bytes());
+        }
+        for _ in 0..8 * 8 * 2 {
+            data.extend_from_slice(&(512u16 << 6).to_le_bytes());
+        }
+        hdr.data = data;
+        let sdr = process_frame(&hdr, None, Some(FrameFormat::Nv12)).unwrap();
+        assert_eq!(sdr.format, FrameFormat::Nv12);
+        assert_eq!(sdr.primaries, Some(ColorPrimaries::Bt709));
+        assert!((170..=235).contains(&sdr.data[0]));
+        assert_eq!(sdr.data[16 * 16], 128);
+    }
+}
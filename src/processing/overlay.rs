@@ -0,0 +1,276 @@
+//! Frame overlays
+//!
+//! Lightweight CPU compositing of graphics onto processed frames before
+//! they are handed to the encoder. Overlays draw directly into packed
+//! RGB frames or into the planes of NV12/YUV420P frames, so they can run
+//! after scaling/conversion at the final output resolution.
+
+use super::cursor::CursorRenderer;
+use super::timecode::TimecodeOverlay;
+use crate::types::{Frame, FrameFormat};
+
+/// Overlay applied to every frame in the pipeline
+#[derive(Debug, Clone)]
+pub enum Overlay {
+    /// Burned-in timecode / clock
+    Timecode(TimecodeOverlay),
+    /// Custom cursor drawn from cursor metadata
+    Cursor(CursorRenderer),
+}
+
+impl Overlay {
+    /// Draw the overlay onto a frame
+    pub fn apply(&mut self, frame: &mut Frame) {
+        match self {
+            Overlay::Timecode(overlay) => overlay.apply(frame),
+            Overlay::Cursor(renderer) => renderer.apply(frame),
+        }
+    }
+}
+
+impl From<TimecodeOverlay> for Overlay {
+    fn from(overlay: TimecodeOverlay) -> Self {
+        Overlay::Timecode(overlay)
+    }
+}
+
+impl From<CursorRenderer> for Overlay {
+    fn from(renderer: CursorRenderer) -> Self {
+        Overlay::Cursor(renderer)
+    }
+}
+
+/// Anchor position for an overlay
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverlayPosition {
+    /// Top-left corner
+    #[default]
+    TopLeft,
+    /// Top-right corner
+    TopRight,
+    /// Bottom-left corner
+    BottomLeft,
+    /// Bottom-right corner
+    BottomRight,
+    /// Explicit pixel offset from the top-left corner
+    Custom { x: u32, y: u32 },
+}
+
+impl OverlayPosition {
+    /// Resolve the top-left pixel of a `width`x`height` box inside the frame
+    pub fn resolve(
+        &self,
+        frame_width: u32,
+        frame_height: u32,
+        width: u32,
+        height: u32,
+        margin: u32,
+    ) -> (i64, i64) {
+        let right = frame_width as i64 - width as i64 - margin as i64;
+        let bottom = frame_height as i64 - height as i64 - margin as i64;
+        match *self {
+            OverlayPosition::TopLeft => (margin as i64, margin as i64),
+            OverlayPosition::TopRight => (right, margin as i64),
+            OverlayPosition::BottomLeft => (margin as i64, bottom),
+            OverlayPosition::BottomRight => (right, bottom),
+            OverlayPosition::Custom { x, y } => (x as i64, y as i64),
+        }
+    }
+}
+
+/// RGBA color used by overlays (straight alpha)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OverlayColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl OverlayColor {
+    pub const WHITE: Self = Self::rgb(255, 255, 255);
+    pub const BLACK: Self = Self::rgb(0, 0, 0);
+    pub const YELLOW: Self = Self::rgb(255, 220, 0);
+    pub const RED: Self = Self::rgb(230, 30, 30);
+
+    /// Opaque color
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, a: 255 }
+    }
+
+    /// Color with alpha
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// Same color with a different alpha
+    pub const fn with_alpha(self, a: u8) -> Self {
+        Self { a, ..self }
+    }
+
+    /// BT.709 limited-range YUV for drawing into YUV frames
+    fn to_yuv(self) -> (u8, u8, u8) {
+        let (r, g, b) = (self.r as f32, self.g as f32, self.b as f32);
+        let y = 16.0 + 0.1826 * r + 0.6142 * g + 0.0620 * b;
+        let u = 128.0 - 0.1006 * r - 0.3386 * g + 0.4392 * b;
+        let v = 128.0 + 0.4392 * r - 0.3989 * g - 0.0403 * b;
+        (
+            y.round().clamp(0.0, 255.0) as u8,
+            u.round().clamp(0.0, 255.0) as u8,
+            v.round().clamp(0.0, 255.0) as u8,
+        )
+    }
+}
+
+#[inline]
+fn blend(dst: u8, src: u8, alpha: u8) -> u8 {
+    let a = alpha as u32;
+    ((src as u32 * a + dst as u32 * (255 - a) + 127) / 255) as u8
+}
+
+/// Alpha-blend a filled rectangle onto a frame, clipped to the frame bounds
+///
+/// Supports BGRA, RGBA, RGB24, NV12 and YUV420P; other formats are left
+/// untouched. Rows may be padded: `frame.stride` is the row stride of
+/// packed frames and the luma stride of planar ones, whose chroma rows
+/// are padded alike.
+pub fn fill_rect(frame: &mut Frame, x: i64, y: i64, width: u32, height: u32, color: OverlayColor) {
+    if color.a == 0 {
+        return;
+    }
+
+    let x0 = x.max(0);
+    let y0 = y.max(0);
+    let x1 = (x + width as i64).min(frame.width as i64);
+    let y1 = (y + height as i64).min(frame.height as i64);
+    if x0 >= x1 || y0 >= y1 {
+        return;
+    }
+    let (x0, y0, x1, y1) = (x0 as usize, y0 as usize, x1 as usize, y1 as usize);
+    let frame_width = frame.width as usize;
+    let frame_height = frame.height as usize;
+
+    match frame.format {
+        FrameFormat::Bgra | FrameFormat::Rgba | FrameFormat::Rgb24 => {
+            let bpp = frame.format.bytes_per_pixel() as usize;
+            let row_bytes = frame_width * bpp;
+            let stride = row_stride(frame, row_bytes, |stride| {
+                stride * (frame_height - 1) + row_bytes
+            });
+            let (c0, c2) = if frame.format == FrameFormat::Bgra {
+                (color.b, color.r)
+            } else {
+                (color.r, color.b)
+            };
+            for row in y0..y1 {
+                let start = row * stride + x0 * bpp;
+                let end = row * stride + x1 * bpp;
+                let Some(line) = frame.data.get_mut(start..end) else {
+                    return;
+                };
+                for px in line.chunks_exact_mut(bpp) {
+                    px[0] = blend(px[0], c0, color.a);
+                    px[1] = blend(px[1], color.g, color.a);
+                    px[2] = blend(px[2], c2, color.a);
+                }
+            }
+        }
+        FrameFormat::Nv12 | FrameFormat::Yuv420p => {
+            let (cy, cu, cv) = color.to_yuv();
+            let nv12 = frame.format == FrameFormat::Nv12;
+            let chroma_width = frame_width.div_ceil(2);
+            let chroma_height = frame_height.div_ceil(2);
+            // NV12 interleaves U and V in one plane as wide as the luma
+            let chroma_stride = |luma_stride: usize| {
+                if nv12 {
+                    luma_stride.max(chroma_width * 2)
+                } else {
+                    luma_stride.div_ceil(2)
+                }
+            };
+            let chroma_planes = if nv12 { 1 } else { 2 };
+            let size = |luma_stride: usize| {
+                luma_stride * frame_height
+                    + chroma_stride(luma_stride) * chroma_height * chroma_planes
+            };
+            let stride = row_stride(frame, frame_width, size);
+            if frame.data.len() < size(stride) {
+                return;
+            }
+
+            for row in y0..y1 {
+                let line = &mut frame.data[row * stride + x0..row * stride + x1];
+                for px in line {
+                    *px = blend(*px, cy, color.a);
+                }
+            }
+
+            let luma_size = stride * frame_height;
+            let uv_stride = chroma_stride(stride);
+            let (cx0, cx1) = (x0 / 2, x1.div_ceil(2));
+            let (cy0, cy1) = (y0 / 2, y1.div_ceil(2));
+            for row in cy0..cy1 {
+                for col in cx0..cx1 {
+                    let (u_idx, v_idx) = if nv12 {
+                        let idx = luma_size + row * uv_stride + col * 2;
+                        (idx, idx + 1)
+                    } else {
+                        let idx = luma_size + row * uv_stride + col;
+                        (idx, idx + uv_stride * chroma_height)
+                    };
+                    frame.data[u_idx] = blend(frame.data[u_idx], cu, color.a);
+                    frame.data[v_idx] = blend(frame.data[v_idx], cv, color.a);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Row stride of a frame's first plane: `frame.stride` if the data is
+/// `size(stride)` bytes or more, otherwise tight rows of `row_bytes`
+/// (`Frame::new` gives every format a BGRA stride)
+fn row_stride(frame: &Frame, row_bytes: usize, size: impl Fn(usize) -> usize) -> usize {
+    let stride = frame.stride as usize;
+    if stride >= row_bytes && size(stride) <= frame.data.len() {
+        stride
+    } else {
+        row_bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill_rect_padded_rows() {
+        // 2x2 RGB24 with 2 bytes of padding per row
+        let mut frame = Frame::from_data(vec![0; 16], 2, 2, 8, FrameFormat::Rgb24);
+        fill_rect(&mut frame, 1, 0, 1, 2, OverlayColor::RED);
+        assert_eq!(&frame.data[..8], &[0, 0, 0, 230, 30, 30, 0, 0]);
+        assert_eq!(&frame.data[8..], &[0, 0, 0, 230, 30, 30, 0, 0]);
+
+        // `Frame::new` leaves a BGRA stride on other formats: tight rows
+        let mut frame = Frame::new(2, 2, FrameFormat::Rgb24);
+        fill_rect(&mut frame, 0, 1, 2, 1, OverlayColor::RED);
+        assert_eq!(&frame.data[..6], &[0; 6]);
+        assert_eq!(&frame.data[6..], &[230, 30, 30, 230, 30, 30]);
+
+        // 4x2 YUV420P with a luma stride of 8 and chroma stride of 4
+        let (y, u, v) = OverlayColor::RED.to_yuv();
+        let mut frame = Frame::from_data(vec![0; 24], 4, 2, 8, FrameFormat::Yuv420p);
+        fill_rect(&mut frame, 0, 0, 4, 2, OverlayColor::RED);
+        for row in [0, 8] {
+            assert_eq!(&frame.data[row..row + 8], &[y, y, y, y, 0, 0, 0, 0]);
+        }
+        assert_eq!(&frame.data[16..20], &[u, u, 0, 0]);
+        assert_eq!(&frame.data[20..24], &[v, v, 0, 0]);
+
+        // 4x2 NV12 with both strides 8
+        let mut frame = Frame::from_data(vec![0; 24], 4, 2, 8, FrameFormat::Nv12);
+        fill_rect(&mut frame, 2, 0, 2, 2, OverlayColor::RED);
+        assert_eq!(&frame.data[8..16], &[0, 0, y, y, 0, 0, 0, 0]);
+        assert_eq!(&frame.data[16..24], &[0, 0, u, v, 0, 0, 0, 0]);
+    }
+}
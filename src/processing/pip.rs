@@ -0,0 +1,320 @@
+//! Picture-in-picture compositing
+//!
+//! `PictureInPicture` draws the newest frame of a second source (a webcam,
+//! another PipeWire node) as an inset onto the main frame, e.g. the
+//! presenter in a corner of a screen recording. The second source runs at
+//! its own rate: the compositor keeps only its latest frame and reuses it
+//! until a new one arrives, so a slow webcam never holds up the main
+//! capture. The inset is scaled once per source frame, not per main frame.
+
+use super::convert::convert_colorspace;
+use super::overlay::{fill_rect, OverlayColor, OverlayPosition};
+use super::scale::scale_frame;
+use crate::error::{Error, Result};
+use crate::types::{Frame, FrameFormat};
+
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// Where and how large the inset is drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PipLayout {
+    /// Corner (or pixel offset) of the inset including its border
+    pub position: OverlayPosition,
+    /// Inset width as a share of the main frame's width; the height
+    /// follows the source's aspect ratio
+    pub size: f32,
+    /// Distance to the frame edges in pixels (ignored for `Custom`)
+    pub margin: u32,
+    /// Border width in pixels (0 = no border)
+    pub border: u32,
+    pub border_color: OverlayColor,
+}
+
+impl Default for PipLayout {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PipLayout {
+    /// Bottom-right, a quarter of the frame wide, 2px white border
+    pub fn new() -> Self {
+        Self {
+            position: OverlayPosition::BottomRight,
+            size: 0.25,
+            margin: 24,
+            border: 2,
+            border_color: OverlayColor::WHITE,
+        }
+    }
+
+    pub fn with_position(mut self, position: OverlayPosition) -> Self {
+        self.position = position;
+        self
+    }
+
+    /// Inset width as a share of the frame width (0.05-1.0)
+    pub fn with_size(mut self, size: f32) -> Self {
+        self.size = size.clamp(0.05, 1.0);
+        self
+    }
+
+    pub fn with_margin(mut self, margin: u32) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    /// Border around the inset; a width of 0 draws none
+    pub fn with_border(mut self, width: u32, color: OverlayColor) -> Self {
+        self.border = width;
+        self.border_color = color;
+        self
+    }
+}
+
+/// Inset scaled and converted for the main frame
+#[derive(Debug, Clone)]
+struct Inset {
+    /// PTS of the source frame it was made from
+    source_pts: i64,
+    format: FrameFormat,
+    width: u32,
+    height: u32,
+    /// Tightly packed pixels in `format`
+    data: Vec<u8>,
+}
+
+/// Composites a second source as an inset onto the main frame
+///
+/// Works on CPU frames in any `FrameFormat`: the inset is converted to the
+/// main frame's format and copied plane by plane. On subsampled YUV the
+/// inset lands on even coordinates. The border is drawn on BGRA, RGBA,
+/// NV12 and YUV420P. Clones share the source frame: feed one with `update`
+/// and composite with the others.
+#[derive(Debug, Clone)]
+pub struct PictureInPicture {
+    layout: PipLayout,
+    source: Arc<Mutex<Option<Arc<Frame>>>>,
+    inset: Option<Inset>,
+}
+
+impl PictureInPicture {
+    pub fn new(layout: PipLayout) -> Self {
+        Self {
+            layout,
+            source: Arc::default(),
+            inset: None,
+        }
+    }
+
+    pub fn layout(&self) -> PipLayout {
+        self.layout
+    }
+
+    /// Hand over the second source's newest frame (any CPU format)
+    pub fn update(&self, frame: Frame) {
+        *self.source.lock() = Some(Arc::new(frame));
+    }
+
+    /// Draw the inset onto a frame; does nothing until the second source
+    /// has delivered a frame
+    pub fn apply(&mut self, frame: &mut Frame) -> Result<()> {
+        // DMA-BUF frames have no CPU copy to draw on
+        if frame.data.is_empty() {
+            return Ok(());
+        }
+        let Some(source) = self.source.lock().clone() else {
+            return Ok(());
+        };
+
+        let width = ((frame.width as f32 * self.layout.size) as u32 & !1).max(2);
+        let stale = !self.inset.as_ref().is_some_and(|inset| {
+            inset.source_pts == source.pts && inset.width == width && inset.format == frame.format
+        });
+        if stale {
+            self.inset = Some(prepare_inset(&source, width, frame.format)?);
+        }
+        let Some(inset) = self.inset.as_ref() else {
+            return Ok(());
+        };
+
+        let border = self.layout.border;
+        let (box_width, box_height) = (inset.width + 2 * border, inset.height + 2 * border);
+        let (x, y) = self.layout.position.resolve(
+            frame.width,
+            frame.height,
+            box_width,
+            box_height,
+            self.layout.margin,
+        );
+        if border > 0 {
+            fill_rect(frame, x, y, box_width, box_height, self.layout.border_color);
+        }
+
+        // Copy the inset rows of each plane, clipped to the frame. The
+        // inset size is even, so its chroma lines up with an even origin.
+        let even = |v: i64| if frame.format.is_rgb() { v } else { v & !1 };
+        let (left, top) = (even(x + border as i64), even(y + border as i64));
+        let packed = frame.format.is_rgb();
+        let (mut frame_offset, mut inset_offset) = (0, 0);
+        for &(bpp, h_sub, v_sub) in plane_layout(frame.format) {
+            let plane_width = (frame.width as usize).div_ceil(h_sub);
+            let plane_height = (frame.height as usize).div_ceil(v_sub);
+            let row_bytes = if packed {
+                (frame.stride as usize).max(plane_width * bpp)
+            } else {
+                plane_width * bpp
+            };
+            let inset_width = (inset.width as usize).div_ceil(h_sub);
+            let inset_height = (inset.height as usize).div_ceil(v_sub);
+            let inset_row = inset_width * bpp;
+
+            let (plane_left, plane_top) = (left / h_sub as i64, top / v_sub as i64);
+            let x0 = plane_left.max(0);
+            let x1 = (plane_left + inset_width as i64).min(plane_width as i64);
+            if x0 < x1 {
+                let len = (x1 - x0) as usize * bpp;
+                let skip = (x0 - plane_left) as usize * bpp;
+                for row in 0..inset_height as i64 {
+                    let frame_row = plane_top + row;
+                    if frame_row < 0 || frame_row >= plane_height as i64 {
+                        continue;
+                    }
+                    let start = frame_offset + frame_row as usize * row_bytes + x0 as usize * bpp;
+                    let Some(dst) = frame.data.get_mut(start..start + len) else {
+                        break;
+                    };
+                    let src = inset_offset + row as usize * inset_row + skip;
+                    dst.copy_from_slice(&inset.data[src..src + len]);
+                }
+            }
+            frame_offset += row_bytes * plane_height;
+            inset_offset += inset_row * inset_height;
+        }
+        Ok(())
+    }
+}
+
+/// (bytes per sample, horizontal and vertical subsampling) of each plane
+fn plane_layout(format: FrameFormat) -> &'static [(usize, usize, usize)] {
+    match format {
+        FrameFormat::Bgra | FrameFormat::Rgba => &[(4, 1, 1)],
+        FrameFormat::Rgb24 => &[(3, 1, 1)],
+        FrameFormat::Nv12 => &[(1, 1, 1), (2, 2, 2)],
+        FrameFormat::P010 => &[(2, 1, 1), (4, 2, 2)],
+        FrameFormat::Yuv420p => &[(1, 1, 1), (1, 2, 2), (1, 2, 2)],
+        FrameFormat::Yuv422p => &[(1, 1, 1), (1, 2, 1), (1, 2, 1)],
+        FrameFormat::Yuv444p => &[(1, 1, 1), (1, 1, 1), (1, 1, 1)],
+    }
+}
+
+/// Scale a source frame to `width` (keeping its aspect ratio) in `format`
+fn prepare_inset(source: &Frame, width: u32, format: FrameFormat) -> Result<Inset> {
+    if source.width == 0 || source.height == 0 || source.data.is_empty() {
+        return Err(Error::Pipeline(
+            "Picture-in-picture source frame has no pixel data".into(),
+        ));
+    }
+    let row = source.width as usize * 4;
+    let bgra = if source.format == FrameFormat::Bgra {
+        let stride = (source.stride as usize).max(row);
+        source
+            .data
+            .chunks(stride)
+            .take(source.height as usize)
+            .flat_map(|line| &line[..row.min(line.len())])
+            .copied()
+            .collect()
+    } else {
+        convert_colorspace(
+            &source.data,
+            source.format,
+            FrameFormat::Bgra,
+            source.width,
+            source.height,
+        )?
+    };
+
+    let height = ((width as u64 * source.height as u64 / source.width as u64) as u32 & !1).max(2);
+    let scaled = scale_frame(&bgra, source.width, source.height, width, height)?;
+    let data = match format {
+        FrameFormat::Rgba => scaled
+            .chunks_exact(4)
+            .flat_map(|px| [px[2], px[1], px[0], px[3]])
+            .collect(),
+        FrameFormat::Rgb24 => scaled
+            .chunks_exact(4)
+            .flat_map(|px| [px[2], px[1], px[0]])
+            .collect(),
+        FrameFormat::Bgra => scaled,
+        yuv => convert_colorspace(&scaled, FrameFormat::Bgra, yuv, width, height)?,
+    };
+    Ok(Inset {
+        source_pts: source.pts,
+        format,
+        width,
+        height,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inset_placed_in_corner() {
+        let layout = PipLayout::new()
+            .with_size(0.5)
+            .with_margin(1)
+            .with_border(1, OverlayColor::WHITE);
+        let mut pip = PictureInPicture::new(layout);
+
+        // Nothing drawn before the second source delivers
+        let mut frame = Frame::from_data(vec![0; 16 * 8 * 4], 16, 8, 64, FrameFormat::Bgra);
+        pip.apply(&mut frame).unwrap();
+        assert!(frame.data.iter().all(|&b| b == 0));
+
+        // 8x4 source at half the width: no scaling, box 10x6 at (5, 1)
+        let source = Frame::from_data(vec![200; 8 * 4 * 4], 8, 4, 32, FrameFormat::Bgra);
+        pip.update(source);
+        pip.apply(&mut frame).unwrap();
+        let pixel = |x: usize, y: usize| frame.data[y * 64 + x * 4];
+        assert_eq!(pixel(4, 1), 0);
+        assert_eq!(pixel(5, 1), 255);
+        assert_eq!(pixel(6, 2), 200);
+        assert_eq!(pixel(13, 5), 200);
+        assert_eq!(pixel(14, 6), 255);
+        assert_eq!(pixel(15, 7), 0);
+    }
+
+    #[test]
+    fn test_inset_on_nv12() {
+        let layout = PipLayout::new()
+            .with_position(OverlayPosition::TopLeft)
+            .with_size(0.5)
+            .with_margin(2)
+            .with_border(0, OverlayColor::WHITE);
+        let mut pip = PictureInPicture::new(layout);
+
+        // Black 16x8 NV12 frame, white 8x4 source
+        let mut data = vec![16u8; 16 * 8];
+        data.extend(vec![128u8; 16 * 4]);
+        let mut frame = Frame::from_data(data, 16, 8, 16, FrameFormat::Nv12);
+        let source = Frame::from_data(vec![255; 8 * 4 * 4], 8, 4, 32, FrameFormat::Bgra);
+        pip.update(source);
+        pip.apply(&mut frame).unwrap();
+
+        // Luma of the 8x4 inset at (2, 2) is bright, the rest untouched
+        let luma = |x: usize, y: usize| frame.data[y * 16 + x];
+        assert_eq!(luma(1, 2), 16);
+        assert!(luma(2, 2) > 200);
+        assert!(luma(9, 5) > 200);
+        assert_eq!(luma(10, 5), 16);
+        assert_eq!(luma(2, 6), 16);
+        // Chroma stays neutral for a grey inset
+        let chroma = &frame.data[16 * 8..];
+        assert!(chroma.iter().all(|&c| (126..=130).contains(&c)));
+    }
+}
@@ -0,0 +1,126 @@
+//! Static screen detection
+//!
+//! Compares each frame against the last encoded one pixel by pixel, so
+//! identical frames (a paused slideshow, an idle desktop) can be dropped
+//! before scaling and encoding. A minimum rate is kept so players and live
+//! receivers still see regular frames.
+
+use crate::types::Frame;
+
+/// Decides whether a frame differs from the last encoded one
+///
+/// A copy of the first plane (packed pixels or luma) of the last encoded
+/// frame is kept; any differing byte counts as content change, so a typed
+/// character or a one-pixel edit is never dropped. Cursor metadata is
+/// compared too, since the cursor may be drawn after capture.
+pub struct StaticFrameDetector {
+    /// Minimum spacing of encoded frames while static (0 = no floor)
+    max_gap_us: i64,
+    previous: Vec<u8>,
+    cursor: Option<(i32, i32, bool)>,
+    last_encoded_pts: Option<i64>,
+    skipped: u64,
+}
+
+impl StaticFrameDetector {
+    /// Create a detector that still passes at least `min_fps` frames per
+    /// second of static content (0 = drop every repeat)
+    pub fn new(min_fps: u32) -> Self {
+        Self {
+            max_gap_us: if min_fps == 0 {
+                0
+            } else {
+                1_000_000 / min_fps as i64
+            },
+            previous: Vec::new(),
+            cursor: None,
+            last_encoded_pts: None,
+            skipped: 0,
+        }
+    }
+
+    /// Frames skipped so far
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Returns true if the frame should be encoded
+    pub fn should_encode(&mut self, frame: &Frame) -> bool {
+        // DMA-BUF frames have no CPU copy to compare
+        if frame.data.is_empty() {
+            return true;
+        }
+
+        let plane = Self::first_plane(frame);
+        let cursor = frame.cursor.map(|c| (c.x, c.y, c.visible));
+        let changed = plane != self.previous.as_slice() || cursor != self.cursor;
+        let due = match self.last_encoded_pts {
+            Some(last) => self.max_gap_us > 0 && frame.pts - last >= self.max_gap_us,
+            None => true,
+        };
+
+        if !changed && !due {
+            self.skipped += 1;
+            return false;
+        }
+
+        if changed {
+            self.previous.clear();
+            self.previous.extend_from_slice(plane);
+        }
+        self.cursor = cursor;
+        self.last_encoded_pts = Some(frame.pts);
+        true
+    }
+
+    /// Bytes of the first plane
+    ///
+    /// Row padding is included; it doesn't change between frames.
+    fn first_plane(frame: &Frame) -> &[u8] {
+        let len = (frame.stride as usize * frame.height as usize).min(frame.data.len());
+        &frame.data[..len]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::FrameFormat;
+
+    #[test]
+    fn test_static_frames_are_skipped_down_to_floor() {
+        let frame_at = |pts: i64, value: u8| {
+            let mut f = Frame::from_data(vec![value; 64 * 36 * 4], 64, 36, 256, FrameFormat::Bgra);
+            f.pts = pts;
+            f
+        };
+
+        let mut detector = StaticFrameDetector::new(1);
+        assert!(detector.should_encode(&frame_at(0, 10)));
+        assert!(!detector.should_encode(&frame_at(16_666, 10)));
+        assert!(detector.should_encode(&frame_at(33_333, 11)));
+        assert!(!detector.should_encode(&frame_at(500_000, 11)));
+        // One frame per second while static
+        assert!(detector.should_encode(&frame_at(1_033_333, 11)));
+        assert_eq!(detector.skipped(), 2);
+    }
+
+    #[test]
+    fn test_small_changes_are_detected() {
+        let mut data = vec![10u8; 64 * 36 * 4];
+        let mut detector = StaticFrameDetector::new(0);
+        let frame = |data: &[u8], pts: i64| {
+            let mut f = Frame::from_data(data.to_vec(), 64, 36, 256, FrameFormat::Bgra);
+            f.pts = pts;
+            f
+        };
+        assert!(detector.should_encode(&frame(&data, 0)));
+        assert!(!detector.should_encode(&frame(&data, 16_666)));
+
+        // Two neighbouring bytes trade values: same sum, different image
+        data[1000] = 9;
+        data[1001] = 11;
+        assert!(detector.should_encode(&frame(&data, 33_333)));
+        assert!(!detector.should_encode(&frame(&data, 50_000)));
+    }
+}
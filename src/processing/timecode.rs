@@ -0,0 +1,219 @@
+//! Burned-in timecode overlay
+//!
+//! Renders `HH:MM:SS.mmm` onto frames using a small bundled bitmap font,
+//! either as wall-clock time (UTC) or as time elapsed since the first frame
+//! (derived from frame PTS). Useful for syncing multiple recordings and for
+//! proving a stream is live.
+
+use super::overlay::{fill_rect, OverlayColor, OverlayPosition};
+use crate::types::Frame;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Glyph cell size in font pixels (5x7 glyph plus 1px spacing)
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+const CELL_WIDTH: u32 = GLYPH_WIDTH + 1;
+
+/// 5x7 bitmap font for the characters needed by a timecode.
+/// Each row is 5 bits, MSB = leftmost pixel.
+fn glyph(c: char) -> [u8; 7] {
+    match c {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        _ => [0x00; 7],
+    }
+}
+
+/// Time source for the timecode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimecodeMode {
+    /// Current wall-clock time of day (UTC)
+    WallClock,
+    /// Time elapsed since the first frame, from frame PTS
+    #[default]
+    Elapsed,
+}
+
+/// Timecode overlay configuration and state
+#[derive(Debug, Clone)]
+pub struct TimecodeOverlay {
+    mode: TimecodeMode,
+    position: OverlayPosition,
+    /// Glyph height in pixels
+    font_size: u32,
+    color: OverlayColor,
+    background: Option<OverlayColor>,
+    margin: u32,
+    /// PTS of the first frame seen (Elapsed mode)
+    start_pts: Option<i64>,
+}
+
+impl TimecodeOverlay {
+    pub fn new(mode: TimecodeMode) -> Self {
+        Self {
+            mode,
+            position: OverlayPosition::TopLeft,
+            font_size: 28,
+            color: OverlayColor::WHITE,
+            background: Some(OverlayColor::BLACK.with_alpha(160)),
+            margin: 16,
+            start_pts: None,
+        }
+    }
+
+    /// Wall-clock (UTC) timecode
+    pub fn wall_clock() -> Self {
+        Self::new(TimecodeMode::WallClock)
+    }
+
+    /// Elapsed-since-start timecode
+    pub fn elapsed() -> Self {
+        Self::new(TimecodeMode::Elapsed)
+    }
+
+    pub fn with_position(mut self, position: OverlayPosition) -> Self {
+        self.position = position;
+        self
+    }
+
+    /// Set glyph height in pixels (rounded to a multiple of the 7px font)
+    pub fn with_font_size(mut self, size: u32) -> Self {
+        self.font_size = size;
+        self
+    }
+
+    pub fn with_color(mut self, color: OverlayColor) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Background box behind the text (`None` for transparent)
+    pub fn with_background(mut self, background: Option<OverlayColor>) -> Self {
+        self.background = background;
+        self
+    }
+
+    pub fn with_margin(mut self, margin: u32) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    /// Timecode text for a frame with the given PTS (microseconds)
+    pub fn text_for(&mut self, pts: i64) -> String {
+        let micros = match self.mode {
+            TimecodeMode::WallClock => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_micros() as i64)
+                .unwrap_or(0),
+            TimecodeMode::Elapsed => {
+                let start = *self.start_pts.get_or_insert(pts);
+                pts - start
+            }
+        };
+        format_timecode(micros, self.mode == TimecodeMode::WallClock)
+    }
+
+    /// Render the timecode onto a frame
+    pub fn apply(&mut self, frame: &mut Frame) {
+        let text = self.text_for(frame.pts);
+        let scale = (self.font_size / GLYPH_HEIGHT).max(1);
+        let pad = scale * 2;
+        let text_width = text.chars().count() as u32 * CELL_WIDTH * scale - scale;
+        let text_height = GLYPH_HEIGHT * scale;
+        let box_width = text_width + pad * 2;
+        let box_height = text_height + pad * 2;
+
+        let (x, y) = self.position.resolve(
+            frame.width,
+            frame.height,
+            box_width,
+            box_height,
+            self.margin,
+        );
+
+        if let Some(background) = self.background {
+            fill_rect(frame, x, y, box_width, box_height, background);
+        }
+
+        let mut pen_x = x + pad as i64;
+        let pen_y = y + pad as i64;
+        for c in text.chars() {
+            let rows = glyph(c);
+            for (row, bits) in rows.iter().enumerate() {
+                for col in 0..GLYPH_WIDTH {
+                    if bits & (0x10 >> col) != 0 {
+                        fill_rect(
+                            frame,
+                            pen_x + (col * scale) as i64,
+                            pen_y + (row as u32 * scale) as i64,
+                            scale,
+                            scale,
+                            self.color,
+                        );
+                    }
+                }
+            }
+            pen_x += (CELL_WIDTH * scale) as i64;
+        }
+    }
+}
+
+impl Default for TimecodeOverlay {
+    fn default() -> Self {
+        Self::elapsed()
+    }
+}
+
+/// Format microseconds as `HH:MM:SS.mmm`
+///
+/// With `time_of_day`, hours wrap at 24 (for wall-clock display).
+pub fn format_timecode(micros: i64, time_of_day: bool) -> String {
+    let sign = if micros < 0 { "-" } else { "" };
+    let total_ms = micros.unsigned_abs() / 1000;
+    let ms = total_ms % 1000;
+    let total_secs = total_ms / 1000;
+    let secs = total_secs % 60;
+    let mins = (total_secs / 60) % 60;
+    let mut hours = total_secs / 3600;
+    if time_of_day {
+        hours %= 24;
+    }
+    format!("{}{:02}:{:02}:{:02}.{:03}", sign, hours, mins, secs, ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::FrameFormat;
+
+    #[test]
+    fn test_format_timecode() {
+        assert_eq!(format_timecode(0, false), "00:00:00.000");
+        assert_eq!(format_timecode(3_723_456_000, false), "01:02:03.456");
+        assert_eq!(format_timecode(25 * 3_600_000_000, true), "01:00:00.000");
+    }
+
+    #[test]
+    fn test_elapsed_from_first_pts() {
+        let mut overlay = TimecodeOverlay::elapsed();
+        assert_eq!(overlay.text_for(5_000_000), "00:00:00.000");
+        assert_eq!(overlay.text_for(6_500_000), "00:00:01.500");
+
+        let mut frame = Frame::new(320, 240, FrameFormat::Bgra);
+        frame.pts = 7_000_000;
+        overlay.apply(&mut frame);
+        assert!(frame.data.iter().any(|&b| b != 0));
+    }
+}
@@ -0,0 +1,251 @@
+//! Frame rotation, mirroring and cropping
+//!
+//! Applies `Transform` and `Crop` to captured frames by remapping pixels
+//! plane by plane, so packed RGB and planar/semi-planar YUV are handled
+//! without a round trip through swscale. Output planes are tightly packed.
+
+use crate::config::{Crop, Transform};
+use crate::error::{Error, Result};
+use crate::types::{CursorInfo, Frame, FrameFormat};
+
+/// Rotate or flip a frame
+///
+/// Width and height are swapped for 90/270 degree rotations, and cursor
+/// metadata is moved along with the image.
+pub fn transform_frame(frame: &Frame, transform: Transform) -> Result<Frame> {
+    if transform == Transform::None {
+        return Ok(frame.copy_data());
+    }
+
+    let width = frame.width as usize;
+    let height = frame.height as usize;
+    let chroma = |bpp: usize| (width.div_ceil(2), height.div_ceil(2), bpp);
+
+    // (plane width, plane height, bytes per sample) for each plane in order
+    let planes: Vec<(usize, usize, usize)> = match frame.format {
+        FrameFormat::Bgra | FrameFormat::Rgba => vec![(width, height, 4)],
+        FrameFormat::Rgb24 => vec![(width, height, 3)],
+        FrameFormat::Nv12 => vec![(width, height, 1), chroma(2)],
+        FrameFormat::P010 => vec![(width, height, 2), chroma(4)],
+        FrameFormat::Yuv420p | FrameFormat::Yuv444p => {
+            let (cw, ch) = frame
+                .format
+                .chroma_size(frame.width, frame.height)
+                .unwrap_or((frame.width, frame.height));
+            let plane = (cw as usize, ch as usize, 1);
+            vec![(width, height, 1), plane, plane]
+        }
+        FrameFormat::Yuv422p if !transform.swaps_dimensions() => {
+            let plane = (width.div_ceil(2), height, 1);
+            vec![(width, height, 1), plane, plane]
+        }
+        FrameFormat::Yuv422p => {
+            return Err(Error::Pipeline(
+                "4:2:2 frames cannot be rotated by 90/270 degrees".into(),
+            ));
+        }
+    };
+
+    // Packed formats may carry row padding; planar data is tightly packed
+    let packed_stride = if frame.format.is_rgb() {
+        (frame.stride as usize).max(width * planes[0].2)
+    } else {
+        width * planes[0].2
+    };
+
+    let mut data = Vec::with_capacity(frame.data.len());
+    let mut offset = 0;
+    for (index, &(w, h, bpp)) in planes.iter().enumerate() {
+        let stride = if index == 0 { packed_stride } else { w * bpp };
+        let src = frame
+            .data
+            .get(offset..offset + stride * h)
+            .ok_or_else(|| Error::Pipeline("Frame buffer too small for transform".into()))?;
+        transform_plane(src, w, h, stride, bpp, transform, &mut data);
+        offset += stride * h;
+    }
+
+    let out = transform.apply_to(frame.resolution());
+    let stride = if frame.format.is_rgb() {
+        out.width * planes[0].2 as u32
+    } else {
+        out.width
+    };
+
+    let cursor = frame.cursor.map(|c| {
+        let (x, y) = map_point(c.x, c.y, frame.width as i32, frame.height as i32, transform);
+        CursorInfo { x, y, ..c }
+    });
+
+    Ok(Frame {
+        data,
+        width: out.width,
+        height: out.height,
+        stride,
+        dmabuf_fd: None,
+        cursor,
+        ..*frame
+    })
+}
+
+/// Cut a region out of a frame
+///
+/// The region is clipped to the frame, and cursor metadata is moved into
+/// its coordinates. Fails if nothing of it lies inside the frame.
+pub fn crop_frame(frame: &Frame, crop: Crop) -> Result<Frame> {
+    // Subsampled chroma needs even edges
+    let align = match frame.format {
+        FrameFormat::Bgra | FrameFormat::Rgba | FrameFormat::Rgb24 | FrameFormat::Yuv444p => 1,
+        _ => 2,
+    };
+    let x = crop.x.min(frame.width) / align * align;
+    let y = crop.y.min(frame.height) / align * align;
+    let width = crop.width.min(frame.width - x) / align * align;
+    let height = crop.height.min(frame.height - y) / align * align;
+    if width == 0 || height == 0 {
+        return Err(Error::Pipeline(format!(
+            "Crop {}x{}+{}+{} is outside the {}x{} frame",
+            crop.width, crop.height, crop.x, crop.y, frame.width, frame.height
+        )));
+    }
+    if (x, y, width, height) == (0, 0, frame.width, frame.height) {
+        return Ok(frame.copy_data());
+    }
+
+    // (bytes per sample, horizontal and vertical subsampling) of each plane
+    let planes: &[(usize, usize, usize)] = match frame.format {
+        FrameFormat::Bgra | FrameFormat::Rgba => &[(4, 1, 1)],
+        FrameFormat::Rgb24 => &[(3, 1, 1)],
+        FrameFormat::Nv12 => &[(1, 1, 1), (2, 2, 2)],
+        FrameFormat::P010 => &[(2, 1, 1), (4, 2, 2)],
+        FrameFormat::Yuv420p => &[(1, 1, 1), (1, 2, 2), (1, 2, 2)],
+        FrameFormat::Yuv422p => &[(1, 1, 1), (1, 2, 1), (1, 2, 1)],
+        FrameFormat::Yuv444p => &[(1, 1, 1), (1, 1, 1), (1, 1, 1)],
+    };
+
+    let (x, y, width, height) = (x as usize, y as usize, width as usize, height as usize);
+    let mut data = Vec::new();
+    let mut offset = 0;
+    for (index, &(bpp, h_sub, v_sub)) in planes.iter().enumerate() {
+        let plane_width = (frame.width as usize).div_ceil(h_sub);
+        let plane_height = (frame.height as usize).div_ceil(v_sub);
+        // Packed formats may carry row padding; planar data is tightly packed
+        let stride = if index == 0 && frame.format.is_rgb() {
+            (frame.stride as usize).max(plane_width * bpp)
+        } else {
+            plane_width * bpp
+        };
+        let src = frame
+            .data
+            .get(offset..offset + stride * plane_height)
+            .ok_or_else(|| Error::Pipeline("Frame buffer too small for crop".into()))?;
+        let (left, len) = (x / h_sub * bpp, width.div_ceil(h_sub) * bpp);
+        let rows = height.div_ceil(v_sub);
+        for row in src.chunks(stride).skip(y / v_sub).take(rows) {
+            data.extend_from_slice(&row[left..left + len]);
+        }
+        offset += stride * plane_height;
+    }
+
+    let cursor = frame.cursor.map(|c| CursorInfo {
+        x: c.x - x as i32,
+        y: c.y - y as i32,
+        ..c
+    });
+    Ok(Frame {
+        data,
+        width: width as u32,
+        height: height as u32,
+        stride: (width * planes[0].0) as u32,
+        dmabuf_fd: None,
+        cursor,
+        ..*frame
+    })
+}
+
+/// Append one transformed plane to `out`
+fn transform_plane(
+    src: &[u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    bpp: usize,
+    transform: Transform,
+    out: &mut Vec<u8>,
+) {
+    let (out_width, out_height) = if transform.swaps_dimensions() {
+        (height, width)
+    } else {
+        (width, height)
+    };
+
+    for dy in 0..out_height {
+        for dx in 0..out_width {
+            let (sx, sy) = match transform {
+                Transform::None => (dx, dy),
+                Transform::Rotate90 => (dy, height - 1 - dx),
+                Transform::Rotate180 => (width - 1 - dx, height - 1 - dy),
+                Transform::Rotate270 => (width - 1 - dy, dx),
+                Transform::FlipH => (width - 1 - dx, dy),
+                Transform::FlipV => (dx, height - 1 - dy),
+            };
+            let start = sy * stride + sx * bpp;
+            out.extend_from_slice(&src[start..start + bpp]);
+        }
+    }
+}
+
+/// Position of source pixel (x, y) after the transform
+fn map_point(x: i32, y: i32, width: i32, height: i32, transform: Transform) -> (i32, i32) {
+    match transform {
+        Transform::None => (x, y),
+        Transform::Rotate90 => (height - 1 - y, x),
+        Transform::Rotate180 => (width - 1 - x, height - 1 - y),
+        Transform::Rotate270 => (y, width - 1 - x),
+        Transform::FlipH => (width - 1 - x, y),
+        Transform::FlipV => (x, height - 1 - y),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotate_packed_frame() {
+        // 3x2 BGRA frame, pixel value = index
+        let data: Vec<u8> = (0..6u8).flat_map(|i| [i, i, i, 255]).collect();
+        let frame = Frame::from_data(data, 3, 2, 12, FrameFormat::Bgra);
+        let px = |f: &Frame| f.data.chunks(4).map(|p| p[0]).collect::<Vec<_>>();
+
+        // 0 1 2        3 0
+        // 3 4 5   ->   4 1
+        //              5 2
+        let rotated = transform_frame(&frame, Transform::Rotate90).unwrap();
+        assert_eq!((rotated.width, rotated.height, rotated.stride), (2, 3, 8));
+        assert_eq!(px(&rotated), [3, 0, 4, 1, 5, 2]);
+
+        let back = transform_frame(&rotated, Transform::Rotate270).unwrap();
+        assert_eq!(px(&back), [0, 1, 2, 3, 4, 5]);
+
+        let flipped = transform_frame(&frame, Transform::FlipH).unwrap();
+        assert_eq!(px(&flipped), [2, 1, 0, 5, 4, 3]);
+
+        assert_eq!(map_point(0, 0, 3, 2, Transform::Rotate90), (1, 0));
+    }
+
+    #[test]
+    fn test_crop_nv12_frame() {
+        // 4x4 NV12: luma = index, chroma pairs (u, v) = (100 + i, 200 + i)
+        let mut data: Vec<u8> = (0..16).collect();
+        data.extend((0..4).flat_map(|i| [100 + i, 200 + i]));
+        let frame = Frame::from_data(data, 4, 4, 4, FrameFormat::Nv12);
+
+        // Odd offset rounds down to even; 2x2 luma and one chroma pair left
+        let cropped = crop_frame(&frame, Crop::new(3, 3, 2, 2)).unwrap();
+        assert_eq!((cropped.width, cropped.height, cropped.stride), (2, 2, 2));
+        assert_eq!(cropped.data, [10, 11, 14, 15, 103, 203]);
+
+        assert!(crop_frame(&frame, Crop::new(4, 0, 2, 2)).is_err());
+    }
+}
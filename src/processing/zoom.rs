@@ -0,0 +1,274 @@
+//! Zoom-to-cursor effect
+//!
+//! `AutoZoom` crops a window around the cursor reported by
+//! `CursorMode::Metadata` and scales it back up to the frame size, the way
+//! screencast editors zoom in on the action. The window eases toward its
+//! target instead of jumping, and only pans once the cursor leaves a
+//! deadzone in the middle of the view, so small movements don't shake the
+//! picture. When the cursor leaves the captured area the view eases back
+//! out to the full frame. The view is cropped on whole pixels and scaled
+//! back up with swscale.
+
+use super::scale::scale_frame;
+use crate::error::{Error, Result};
+use crate::types::{CursorInfo, Frame, FrameFormat};
+
+use std::time::Duration;
+
+/// Smoothly animated zoom that follows the cursor
+///
+/// Works on packed RGB frames (BGRA/RGBA/RGB24) on the CPU; the output
+/// keeps the input resolution.
+#[derive(Debug, Clone)]
+pub struct AutoZoom {
+    zoom: f64,
+    deadzone: f64,
+    easing: Duration,
+    /// View centre in source pixels
+    center: Option<(f64, f64)>,
+    /// Current magnification
+    current: f64,
+    last_pts: Option<i64>,
+}
+
+impl Default for AutoZoom {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AutoZoom {
+    /// 2x zoom, deadzone over the middle 40% of the view, 300ms easing
+    pub fn new() -> Self {
+        Self {
+            zoom: 2.0,
+            deadzone: 0.4,
+            easing: Duration::from_millis(300),
+            center: None,
+            current: 1.0,
+            last_pts: None,
+        }
+    }
+
+    /// Magnification while the cursor is on screen (at least 1.0)
+    pub fn with_zoom(mut self, zoom: f32) -> Self {
+        self.zoom = (zoom as f64).max(1.0);
+        self
+    }
+
+    /// Share of the view (0.0-0.9) in which the cursor moves without the
+    /// view panning
+    pub fn with_deadzone(mut self, deadzone: f32) -> Self {
+        self.deadzone = (deadzone as f64).clamp(0.0, 0.9);
+        self
+    }
+
+    /// Time constant of the easing; longer is slower and smoother, zero
+    /// follows the cursor instantly
+    pub fn with_easing(mut self, easing: Duration) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    /// Current magnification (1.0 = full frame)
+    pub fn current_zoom(&self) -> f64 {
+        self.current
+    }
+
+    /// Advance the animation to this frame and render the zoomed view
+    ///
+    /// Cursor metadata is moved into the zoomed view's coordinates.
+    pub fn apply(&mut self, frame: &mut Frame) -> Result<()> {
+        let (width, height) = (frame.width as f64, frame.height as f64);
+        let cursor = frame.cursor.filter(|c| c.visible);
+
+        // Exponential ease-out, by the time since the previous frame
+        let elapsed = match self.last_pts.replace(frame.pts) {
+            Some(last) => (frame.pts - last).max(0) as f64 / 1_000_000.0,
+            None => 0.0,
+        };
+        let alpha = if self.easing.is_zero() {
+            1.0
+        } else {
+            1.0 - (-elapsed / self.easing.as_secs_f64()).exp()
+        };
+
+        let target_zoom = if cursor.is_some() { self.zoom } else { 1.0 };
+        self.current += (target_zoom - self.current) * alpha;
+
+        let (mut cx, mut cy) = *self.center.get_or_insert((width / 2.0, height / 2.0));
+        if let Some(c) = cursor {
+            let reach_x = width / target_zoom / 2.0 * self.deadzone;
+            let reach_y = height / target_zoom / 2.0 * self.deadzone;
+            cx += (follow(cx, c.x as f64, reach_x) - cx) * alpha;
+            cy += (follow(cy, c.y as f64, reach_y) - cy) * alpha;
+        }
+
+        // Keep the view inside the frame
+        let half_w = width / self.current / 2.0;
+        let half_h = height / self.current / 2.0;
+        cx = cx.clamp(half_w, (width - half_w).max(half_w));
+        cy = cy.clamp(half_h, (height - half_h).max(half_h));
+        self.center = Some((cx, cy));
+
+        if self.current < 1.001 {
+            return Ok(());
+        }
+        self.render(frame, cx - half_w, cy - half_h)
+    }
+
+    /// Crop the view with top-left corner (x0, y0) and scale it back up to
+    /// the full frame
+    fn render(&self, frame: &mut Frame, x0: f64, y0: f64) -> Result<()> {
+        let bpp = match frame.format {
+            FrameFormat::Bgra | FrameFormat::Rgba => 4,
+            FrameFormat::Rgb24 => 3,
+            other => {
+                return Err(Error::Pipeline(format!(
+                    "Auto-zoom needs packed RGB frames, got {:?}",
+                    other
+                )));
+            }
+        };
+        let width = frame.width as usize;
+        let height = frame.height as usize;
+        let stride = (frame.stride as usize).max(width * bpp);
+        if width == 0 || height == 0 || frame.data.len() < stride * (height - 1) + width * bpp {
+            return Err(Error::Pipeline(
+                "Frame buffer too small for auto-zoom".into(),
+            ));
+        }
+
+        let crop_w = ((width as f64 / self.current).round() as usize).clamp(1, width);
+        let crop_h = ((height as f64 / self.current).round() as usize).clamp(1, height);
+        let left = (x0.round().max(0.0) as usize).min(width - crop_w);
+        let top = (y0.round().max(0.0) as usize).min(height - crop_h);
+
+        // scale_frame takes 4-byte pixels; the channel order doesn't
+        // matter to it
+        let cropped: Vec<u8> = frame
+            .data
+            .chunks(stride)
+            .skip(top)
+            .take(crop_h)
+            .flat_map(|row| row[left * bpp..(left + crop_w) * bpp].chunks_exact(bpp))
+            .flat_map(|px| [px[0], px[1], px[2], if bpp == 4 { px[3] } else { 255 }])
+            .collect();
+        let scaled = scale_frame(
+            &cropped,
+            crop_w as u32,
+            crop_h as u32,
+            frame.width,
+            frame.height,
+        )?;
+
+        frame.data = if bpp == 4 {
+            scaled
+        } else {
+            scaled
+                .chunks_exact(4)
+                .flat_map(|px| [px[0], px[1], px[2]])
+                .collect()
+        };
+        frame.stride = (width * bpp) as u32;
+        let scale_x = width as f64 / crop_w as f64;
+        let scale_y = height as f64 / crop_h as f64;
+        frame.cursor = frame.cursor.map(|c| {
+            let x = ((c.x as f64 - left as f64) * scale_x) as i32;
+            let y = ((c.y as f64 - top as f64) * scale_y) as i32;
+            let inside = (0..width as i32).contains(&x) && (0..height as i32).contains(&y);
+            CursorInfo {
+                x,
+                y,
+                visible: c.visible && inside,
+                ..c
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Where a view centre has to move so `pos` is within `reach` of it
+fn follow(center: f64, pos: f64, reach: f64) -> f64 {
+    if pos > center + reach {
+        pos - reach
+    } else if pos < center - reach {
+        pos + reach
+    } else {
+        center
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(width: u32, height: u32) -> Frame {
+        // Blue channel = 10 * x
+        let data = (0..width * height)
+            .flat_map(|i| [(i % width * 10) as u8, 0, 0, 255])
+            .collect();
+        Frame::from_data(data, width, height, width * 4, FrameFormat::Bgra)
+    }
+
+    #[test]
+    fn test_zoom_follows_cursor() {
+        let mut zoom = AutoZoom::new().with_easing(Duration::ZERO);
+
+        let mut frame = gradient(4, 4);
+        frame.cursor = Some(CursorInfo {
+            x: 0,
+            y: 0,
+            visible: true,
+            ..Default::default()
+        });
+        zoom.apply(&mut frame).unwrap();
+        assert_eq!(zoom.current_zoom(), 2.0);
+
+        // Left half of the frame (blue 0 and 10) stretched over the full
+        // width
+        let blue: Vec<u8> = frame.data[..16].chunks(4).map(|p| p[0]).collect();
+        assert_eq!(blue[0], 0);
+        assert!(blue.windows(2).all(|w| w[0] <= w[1]));
+        assert!(blue[3] >= 9 && blue[3] <= 10);
+        assert_eq!(
+            frame.cursor.map(|c| (c.x, c.y, c.visible)),
+            Some((0, 0, true))
+        );
+
+        // Cursor gone: back to the full frame
+        let mut frame = gradient(4, 4);
+        zoom.apply(&mut frame).unwrap();
+        assert_eq!(zoom.current_zoom(), 1.0);
+        assert_eq!(frame.data, gradient(4, 4).data);
+    }
+
+    #[test]
+    fn test_zoom_eases_in() {
+        let mut zoom = AutoZoom::new().with_easing(Duration::from_millis(100));
+        let cursor = CursorInfo {
+            x: 32,
+            y: 32,
+            visible: true,
+            ..Default::default()
+        };
+
+        let mut previous = 1.0;
+        for pts in (0..10).map(|i| i * 33_333) {
+            let mut frame = gradient(64, 64);
+            frame.pts = pts;
+            frame.cursor = Some(cursor);
+            zoom.apply(&mut frame).unwrap();
+            assert!(zoom.current_zoom() >= previous);
+            previous = zoom.current_zoom();
+        }
+        assert!(previous > 1.9 && previous < 2.0);
+    }
+
+    #[test]
+    fn test_deadzone() {
+        assert_eq!(follow(100.0, 110.0, 20.0), 100.0);
+        assert_eq!(follow(100.0, 150.0, 20.0), 130.0);
+        assert_eq!(follow(100.0, 50.0, 20.0), 70.0);
+    }
+}
@@ -1,5 +1,6 @@
 //! Common types used throughout GhostStream
 
+use crate::processing::{ColorMatrix, ColorPrimaries, ColorRange};
 use serde::{Deserialize, Serialize};
 
 /// Video resolution
@@ -52,6 +53,8 @@ pub enum FrameFormat {
     Nv12,
     /// YUV420P - Planar YUV 4:2:0
     Yuv420p,
+    /// YUV422P - Planar YUV 4:2:2
+    Yuv422p,
     /// YUV444P - Planar YUV 4:4:4
     Yuv444p,
     /// BGRA - 32-bit BGRA (common for desktop capture)
@@ -69,6 +72,7 @@ impl FrameFormat {
     pub fn bytes_per_pixel(&self) -> f32 {
         match self {
             FrameFormat::Nv12 | FrameFormat::Yuv420p => 1.5,
+            FrameFormat::Yuv422p => 2.0,
             FrameFormat::Yuv444p => 3.0,
             FrameFormat::Bgra | FrameFormat::Rgba => 4.0,
             FrameFormat::Rgb24 => 3.0,
@@ -76,10 +80,36 @@ impl FrameFormat {
         }
     }
 
+    /// Chroma plane dimensions for planar YUV formats
+    pub fn chroma_size(&self, width: u32, height: u32) -> Option<(u32, u32)> {
+        match self {
+            FrameFormat::Yuv420p => Some((width.div_ceil(2), height.div_ceil(2))),
+            FrameFormat::Yuv422p => Some((width.div_ceil(2), height)),
+            FrameFormat::Yuv444p => Some((width, height)),
+            _ => None,
+        }
+    }
+
+    /// Is this a packed RGB format?
+    pub fn is_rgb(&self) -> bool {
+        matches!(
+            self,
+            FrameFormat::Bgra | FrameFormat::Rgba | FrameFormat::Rgb24
+        )
+    }
+
     /// Is this a hardware-friendly format for NVENC?
     pub fn is_nvenc_native(&self) -> bool {
         matches!(self, FrameFormat::Nv12 | FrameFormat::P010)
     }
+
+    /// Bits per sample
+    pub fn bit_depth(&self) -> u8 {
+        match self {
+            FrameFormat::P010 => 10,
+            _ => 8,
+        }
+    }
 }
 
 impl Default for FrameFormat {
@@ -109,6 +139,14 @@ pub struct Frame {
     pub is_keyframe: bool,
     /// DMA-BUF file descriptor (for zero-copy)
     pub dmabuf_fd: Option<i32>,
+    /// Cursor position (when captured with `CursorMode::Metadata`)
+    pub cursor: Option<CursorInfo>,
+    /// YUV matrix of the data (None = unknown, treated as BT.709)
+    pub color_space: Option<ColorMatrix>,
+    /// Sample range (None = unknown: limited for YUV, full for RGB)
+    pub color_range: Option<ColorRange>,
+    /// Color primaries (None = unknown)
+    pub primaries: Option<ColorPrimaries>,
 }
 
 impl Frame {
@@ -125,6 +163,10 @@ impl Frame {
             duration: 0,
             is_keyframe: false,
             dmabuf_fd: None,
+            cursor: None,
+            color_space: None,
+            color_range: None,
+            primaries: None,
         }
     }
 
@@ -146,6 +188,10 @@ impl Frame {
             duration: 0,
             is_keyframe: false,
             dmabuf_fd: None,
+            cursor: None,
+            color_space: None,
+            color_range: None,
+            primaries: None,
         }
     }
 
@@ -163,6 +209,33 @@ impl Frame {
     pub fn size_bytes(&self) -> usize {
         self.data.len()
     }
+
+    /// Copy of the frame's pixel data and metadata
+    ///
+    /// The DMA-BUF descriptor (owned by the capture) is not carried over.
+    pub fn copy_data(&self) -> Self {
+        Self {
+            data: self.data.clone(),
+            dmabuf_fd: None,
+            ..*self
+        }
+    }
+}
+
+/// Cursor state delivered as metadata alongside a captured frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CursorInfo {
+    /// Pointer position in frame pixels
+    pub x: i32,
+    pub y: i32,
+    /// Hotspot offset within the cursor image
+    pub hotspot_x: i32,
+    pub hotspot_y: i32,
+    /// Whether the cursor is over the captured area
+    pub visible: bool,
+    /// Pressed mouse buttons (bit 0 = left, 1 = right, 2 = middle), if
+    /// the source reports them; PipeWire cursor metadata does not
+    pub buttons: u32,
 }
 
 /// Encoded packet (output from encoder)
@@ -178,11 +251,18 @@ pub struct Packet {
     pub duration: i64,
     /// Is this a keyframe?
     pub is_keyframe: bool,
-    /// Codec-specific flags
+    /// `Packet::FLAG_*` bits describing the packet for downstream segmenters
     pub flags: u32,
 }
 
 impl Packet {
+    /// Random access point: decoding can start here
+    pub const FLAG_KEYFRAME: u32 = 1 << 0;
+    /// Not referenced by other frames; can be dropped under pressure
+    pub const FLAG_DISPOSABLE: u32 = 1 << 1;
+    /// Carries codec headers in-band (SPS/PPS/VPS or AV1 sequence header)
+    pub const FLAG_HEADERS: u32 = 1 << 2;
+
     pub fn new(data: Vec<u8>, pts: i64, dts: i64, is_keyframe: bool) -> Self {
         Self {
             data,
@@ -198,6 +278,21 @@ impl Packet {
     pub fn size(&self) -> usize {
         self.data.len()
     }
+
+    /// Can a decoder (or a new segment) start at this packet?
+    pub fn is_keyframe(&self) -> bool {
+        self.is_keyframe || self.flags & Self::FLAG_KEYFRAME != 0
+    }
+
+    /// Can this packet be dropped without breaking decoding of the others?
+    pub fn is_droppable(&self) -> bool {
+        self.flags & Self::FLAG_DISPOSABLE != 0
+    }
+
+    /// Does this packet carry the codec headers needed to start decoding?
+    pub fn has_headers(&self) -> bool {
+        self.flags & Self::FLAG_HEADERS != 0
+    }
 }
 
 /// Framerate representation
@@ -208,10 +303,49 @@ pub struct Framerate {
 }
 
 impl Framerate {
+    /// Lowest framerate the pipeline accepts (fps)
+    pub const MIN_FPS: u32 = 1;
+    /// Highest framerate the pipeline accepts (fps)
+    pub const MAX_FPS: u32 = 1000;
+
+    /// Create a framerate without checking it; see `try_new` and `clamped`
     pub const fn new(num: u32, den: u32) -> Self {
         Self { num, den }
     }
 
+    /// Create a framerate, rejecting a zero numerator or denominator
+    pub fn try_new(num: u32, den: u32) -> crate::error::Result<Self> {
+        if num == 0 || den == 0 {
+            return Err(crate::error::Error::Config(format!(
+                "Invalid framerate {}/{}",
+                num, den
+            )));
+        }
+        Ok(Self { num, den })
+    }
+
+    /// Whether this is a usable framerate (non-zero, 1-1000 fps)
+    pub fn is_valid(&self) -> bool {
+        self.num != 0
+            && self.den != 0
+            && self.num as u64 >= Self::MIN_FPS as u64 * self.den as u64
+            && self.num as u64 <= Self::MAX_FPS as u64 * self.den as u64
+    }
+
+    /// This framerate limited to 1-1000 fps
+    ///
+    /// A zero numerator counts as 0 fps and a zero denominator as infinite,
+    /// so they become 1 and 1000 fps respectively.
+    pub fn clamped(&self) -> Self {
+        if self.is_valid() {
+            *self
+        } else if self.den != 0 && self.num as u64 <= Self::MAX_FPS as u64 * self.den as u64 {
+            Self::new(Self::MIN_FPS, 1)
+        } else {
+            Self::new(Self::MAX_FPS, 1)
+        }
+    }
+
     // Common framerates
     pub const FPS_24: Self = Self::new(24, 1);
     pub const FPS_30: Self = Self::new(30, 1);
@@ -220,23 +354,22 @@ impl Framerate {
     pub const FPS_144: Self = Self::new(144, 1);
     pub const FPS_240: Self = Self::new(240, 1);
 
-    /// Get framerate as f64
+    /// Get framerate as f64 (of the clamped framerate if invalid)
     pub fn as_f64(&self) -> f64 {
-        self.num as f64 / self.den as f64
+        let rate = self.clamped();
+        rate.num as f64 / rate.den as f64
     }
 
-    /// Get framerate as integer fps (numerator when den=1)
+    /// Get framerate as integer fps (of the clamped framerate if invalid)
     pub fn fps(&self) -> u32 {
-        if self.den == 0 {
-            self.num
-        } else {
-            self.num / self.den
-        }
+        let rate = self.clamped();
+        rate.num / rate.den
     }
 
-    /// Frame duration in microseconds
+    /// Frame duration in microseconds (of the clamped framerate if invalid)
     pub fn frame_duration_us(&self) -> i64 {
-        (1_000_000 * self.den as i64) / self.num as i64
+        let rate = self.clamped();
+        (1_000_000 * rate.den as i64) / rate.num as i64
     }
 }
 
@@ -256,6 +389,168 @@ impl std::fmt::Display for Framerate {
     }
 }
 
+/// SMPTE timecode (HH:MM:SS:FF)
+///
+/// Drop-frame timecode (29.97/59.94 fps) skips frame numbers 0-1 (0-3 at
+/// 59.94) at the start of every minute but each tenth, which keeps it in
+/// step with the clock; it is written with a `;` before the frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Timecode {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+    #[serde(default)]
+    pub drop_frame: bool,
+}
+
+impl Timecode {
+    pub const fn new(hours: u8, minutes: u8, seconds: u8, frames: u8) -> Self {
+        Self {
+            hours,
+            minutes,
+            seconds,
+            frames,
+            drop_frame: false,
+        }
+    }
+
+    /// Count in drop-frame timecode (only has an effect at 29.97/59.94 fps)
+    pub fn with_drop_frame(mut self, drop_frame: bool) -> Self {
+        self.drop_frame = drop_frame;
+        self
+    }
+
+    /// Parse "HH:MM:SS:FF"; a `;` before the frames means drop-frame
+    pub fn parse(text: &str) -> crate::error::Result<Self> {
+        let invalid = || crate::error::Error::Config(format!("Invalid timecode: {}", text));
+        let drop_frame = text.contains(';');
+        let fields: Vec<u8> = text
+            .split([':', ';'])
+            .map(|field| field.trim().parse().map_err(|_| invalid()))
+            .collect::<crate::error::Result<_>>()?;
+        let [hours, minutes, seconds, frames] = fields[..] else {
+            return Err(invalid());
+        };
+        if hours > 23 || minutes > 59 || seconds > 59 {
+            return Err(invalid());
+        }
+        Ok(Self::new(hours, minutes, seconds, frames).with_drop_frame(drop_frame))
+    }
+
+    /// Time of day (UTC) at `time`, in frames of `framerate`
+    ///
+    /// 29.97 and 59.94 fps count in drop-frame so the timecode follows
+    /// the clock.
+    pub fn time_of_day(time: std::time::SystemTime, framerate: Framerate) -> Self {
+        let rate = framerate.clamped();
+        let since_epoch = time
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        let nanos = since_epoch.as_nanos() % (86_400 * 1_000_000_000);
+        let frame = nanos * rate.num as u128 / (rate.den as u128 * 1_000_000_000);
+        let drop_frame = rate.den == 1001 && Self::nominal_fps(rate).is_multiple_of(30);
+        Self::from_frame_number(frame as u64, framerate, drop_frame)
+    }
+
+    /// Number of the frame this timecode labels, counted from 00:00:00:00
+    pub fn to_frame_number(&self, framerate: Framerate) -> u64 {
+        let fps = Self::nominal_fps(framerate) as u64;
+        let minutes = self.hours as u64 * 60 + self.minutes as u64;
+        let seconds = minutes * 60 + self.seconds as u64;
+        let dropped = self.drops_per_minute(framerate) * (minutes - minutes / 10);
+        (seconds * fps + self.frames as u64).saturating_sub(dropped)
+    }
+
+    /// Timecode of frame number `frame`, wrapping at 24 hours
+    pub fn from_frame_number(frame: u64, framerate: Framerate, drop_frame: bool) -> Self {
+        let fps = Self::nominal_fps(framerate) as u64;
+        let drop = Self::new(0, 0, 0, 0)
+            .with_drop_frame(drop_frame)
+            .drops_per_minute(framerate);
+        let per_day = fps * 86_400 - drop * (1440 - 144);
+        let mut n = frame % per_day;
+        if drop > 0 {
+            // Skip the dropped labels of the minutes passed so far
+            let per_ten_minutes = fps * 600 - drop * 9;
+            let per_minute = fps * 60 - drop;
+            let (tens, rest) = (n / per_ten_minutes, n % per_ten_minutes);
+            n += drop * 9 * tens;
+            if rest > drop {
+                n += drop * ((rest - drop) / per_minute);
+            }
+        }
+        Self {
+            hours: (n / (fps * 3600) % 24) as u8,
+            minutes: (n / (fps * 60) % 60) as u8,
+            seconds: (n / fps % 60) as u8,
+            frames: (n % fps) as u8,
+            drop_frame,
+        }
+    }
+
+    /// This timecode `frames` frames later
+    pub fn offset(&self, frames: u64, framerate: Framerate) -> Self {
+        let start = self.to_frame_number(framerate);
+        Self::from_frame_number(start + frames, framerate, self.drop_frame)
+    }
+
+    /// SMPTE ST 12-1 binary form, as FFmpeg carries it in
+    /// `AV_FRAME_DATA_S12M_TIMECODE`
+    pub fn to_smpte_12m(&self, framerate: Framerate) -> u32 {
+        let rate = framerate.clamped();
+        let mut tc = 0u32;
+        let mut frames = self.frames as u32;
+        // Above 30 fps the frame pair count is stored, with a field flag
+        if rate.num > 30 * rate.den {
+            if frames % 2 == 1 {
+                tc |= if rate.num == 50 * rate.den {
+                    1 << 7
+                } else {
+                    1 << 23
+                };
+            }
+            frames /= 2;
+        }
+        let digits =
+            |value: u32, shift: u32| ((value / 10) << (shift + 4)) | ((value % 10) << shift);
+        tc |= (self.drop_frame as u32) << 30;
+        tc |= digits(frames % 40, 24);
+        tc |= digits(self.seconds.min(59) as u32, 16);
+        tc |= digits(self.minutes.min(59) as u32, 8);
+        tc |= digits(self.hours as u32 % 24, 0);
+        tc
+    }
+
+    /// Frame labels per second (30 for 29.97 fps)
+    fn nominal_fps(framerate: Framerate) -> u32 {
+        let rate = framerate.clamped();
+        rate.num.div_ceil(rate.den)
+    }
+
+    /// Frame numbers skipped each minute (0 unless drop-frame at
+    /// 29.97/59.94 fps)
+    fn drops_per_minute(&self, framerate: Framerate) -> u64 {
+        let fps = Self::nominal_fps(framerate);
+        if self.drop_frame && framerate.clamped().den == 1001 && fps.is_multiple_of(30) {
+            fps as u64 / 15
+        } else {
+            0
+        }
+    }
+}
+
+impl std::fmt::Display for Timecode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let separator = if self.drop_frame { ';' } else { ':' };
+        write!(
+            f,
+            "{:02}:{:02}:{:02}{}{:02}",
+            self.hours, self.minutes, self.seconds, separator, self.frames
+        )
+    }
+}
+
 /// Codec parameters for muxing
 #[derive(Debug, Clone)]
 pub struct CodecParams {
@@ -273,6 +568,12 @@ pub struct CodecParams {
     pub time_base_den: i32,
     /// Bitrate in bits/sec
     pub bitrate: i64,
+    /// HDR colorimetry and static metadata (`None` for SDR)
+    pub hdr: Option<crate::processing::HdrConfig>,
+    /// Chroma subsampling of the coded pictures
+    pub chroma_format: crate::config::ChromaFormat,
+    /// Bits per sample of the coded pictures (8 or 10)
+    pub bit_depth: u8,
 }
 
 impl Default for CodecParams {
@@ -285,6 +586,9 @@ impl Default for CodecParams {
             time_base_num: 1,
             time_base_den: 1000,
             bitrate: 6_000_000,
+            hdr: None,
+            chroma_format: crate::config::ChromaFormat::Yuv420,
+            bit_depth: 8,
         }
     }
 }
@@ -298,6 +602,10 @@ pub struct Stats {
     pub frames_encoded: u64,
     /// Frames dropped
     pub frames_dropped: u64,
+    /// Frames encoded while draining the encoder after capture stopped
+    pub frames_flushed: u64,
+    /// Captured buffers skipped for holding less than a full frame
+    pub partial_frames: u64,
     /// Current encoding FPS
     pub encoding_fps: f64,
     /// Average encoding latency in ms
@@ -308,4 +616,165 @@ pub struct Stats {
     pub bytes_written: u64,
     /// GPU encoder utilization (0-100)
     pub gpu_encoder_util: u8,
+    /// Most recent audio level after gain (None when audio is disabled)
+    pub audio_levels: Option<crate::audio::AudioLevels>,
+    /// Write performance per destination: those of the primary encoder
+    /// first, then those of each additional encoder (`Output::Encoded`);
+    /// every destination of an `Output::Multiple` has its own entry
+    pub outputs: Vec<OutputStats>,
+}
+
+impl Stats {
+    /// Whether every captured frame made it to the output
+    ///
+    /// Only meaningful once the pipeline has stopped; see
+    /// `PipelineBuilder::lossless`.
+    pub fn is_lossless(&self) -> bool {
+        self.frames_dropped == 0
+            && self.packets_dropped() == 0
+            && self.frames_captured == self.frames_encoded + self.frames_flushed
+    }
+
+    /// Encoded packets the outputs dropped unsent (see
+    /// `OutputStats::packets_dropped`)
+    pub fn packets_dropped(&self) -> u64 {
+        self.outputs.iter().map(|o| o.packets_dropped).sum()
+    }
+
+    /// Average write latency of the slowest output (ms)
+    pub fn output_write_latency_ms(&self) -> f64 {
+        self.outputs
+            .iter()
+            .map(|o| o.write_latency_ms)
+            .fold(0.0, f64::max)
+    }
+
+    /// Largest number of packets waiting for any output
+    pub fn output_backlog(&self) -> usize {
+        self.outputs.iter().map(|o| o.backlog).max().unwrap_or(0)
+    }
+}
+
+/// Arrival timing of captured frames (see `Pipeline::capture_timing`)
+///
+/// Intervals are measured when frames reach the pipeline, before encoding,
+/// so jitter here points at the compositor or PipeWire rather than an
+/// overloaded encoder.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TimingReport {
+    /// Frames measured
+    pub frames: u64,
+    /// Interval expected at the capture framerate (ms)
+    pub expected_interval_ms: f64,
+    /// Mean interval between frames (ms)
+    pub mean_interval_ms: f64,
+    /// Standard deviation of the interval, i.e. jitter (ms)
+    pub stddev_ms: f64,
+    /// Shortest interval (ms)
+    pub min_interval_ms: f64,
+    /// Longest interval (ms)
+    pub max_interval_ms: f64,
+    /// Frames estimated missing from gaps over 1.5 expected intervals.
+    /// Damage-driven capture sends nothing while the screen is static, so
+    /// idle periods count here too.
+    pub dropped: u64,
+}
+
+/// Memory held in pipeline buffers (see `Pipeline::memory_report`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// Captured frames waiting for an encoder
+    pub frame_bytes: u64,
+    /// Encoded video packets waiting for an output
+    pub video_packet_bytes: u64,
+    /// Encoded audio packets waiting for the muxer
+    pub audio_packet_bytes: u64,
+    /// Packets held by the replay buffer
+    pub replay_bytes: u64,
+    /// Packets held by stream branches of an `Output::Multiple`, to send
+    /// or to re-send after a reconnect
+    pub send_buffer_bytes: u64,
+    /// Cap set with `PipelineBuilder::max_buffer_memory`
+    pub limit: Option<u64>,
+    /// Captured frames dropped because the cap was reached
+    pub frames_dropped: u64,
+}
+
+impl MemoryReport {
+    /// Bytes held in all buffers together
+    pub fn total(&self) -> u64 {
+        self.frame_bytes
+            + self.video_packet_bytes
+            + self.audio_packet_bytes
+            + self.replay_bytes
+            + self.send_buffer_bytes
+    }
+}
+
+/// Write performance of one output
+#[derive(Debug, Clone, Default)]
+pub struct OutputStats {
+    /// Moving average of the time spent writing one packet (ms)
+    pub write_latency_ms: f64,
+    /// Encoded packets queued for this output after the last write
+    pub backlog: usize,
+    /// Packets written so far
+    pub packets_written: u64,
+    /// Packets dropped unsent because the destination's buffer was full:
+    /// a stream inside `Output::Multiple` that fell too far behind
+    pub packets_dropped: u64,
+    /// Receivers currently connected, for an output serving several
+    /// (`Output::SrtListener`)
+    pub subscribers: Vec<crate::output::SubscriberStats>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_framerates_are_clamped() {
+        assert!(Framerate::try_new(0, 1).is_err());
+        assert!(Framerate::try_new(30, 0).is_err());
+        assert_eq!(Framerate::try_new(30000, 1001).unwrap().fps(), 29);
+
+        assert_eq!(Framerate::new(0, 1).clamped(), Framerate::new(1, 1));
+        assert_eq!(Framerate::new(60, 0).clamped(), Framerate::new(1000, 1));
+        assert_eq!(Framerate::new(5000, 1).clamped(), Framerate::new(1000, 1));
+        assert_eq!(Framerate::FPS_144.clamped(), Framerate::FPS_144);
+
+        // No division by zero in the helpers
+        assert_eq!(Framerate::new(0, 1).frame_duration_us(), 1_000_000);
+        assert_eq!(Framerate::new(60, 0).fps(), 1000);
+    }
+
+    #[test]
+    fn test_drop_frame_timecode() {
+        let ntsc = Framerate::new(30000, 1001);
+        let start = Timecode::parse("00:00:59;29").unwrap();
+        assert!(start.drop_frame);
+
+        // Frames 0 and 1 are skipped at a new minute, but not every tenth
+        assert_eq!(start.offset(1, ntsc).to_string(), "00:01:00;02");
+        let tenth = Timecode::parse("00:09:59;29").unwrap();
+        assert_eq!(tenth.offset(1, ntsc).to_string(), "00:10:00;00");
+
+        // An hour of 29.97 fps video is an hour of drop-frame timecode
+        let hour = Timecode::from_frame_number(107_892, ntsc, true);
+        assert_eq!(hour, Timecode::new(1, 0, 0, 0).with_drop_frame(true));
+        assert_eq!(hour.to_frame_number(ntsc), 107_892);
+
+        // Integer rates have no frames to drop, whatever the flag says
+        let thirty = Framerate::new(30, 1);
+        assert_eq!(start.offset(1, thirty).to_string(), "00:01:00;00");
+        assert_eq!(hour.to_frame_number(thirty), 108_000);
+
+        assert_eq!(Timecode::new(1, 0, 0, 0).to_string(), "01:00:00:00");
+        assert!(Timecode::parse("01:00:60:00").is_err());
+        assert!(Timecode::parse("01:00:00").is_err());
+
+        // 01:23:45:12 with the drop-frame flag
+        let tc = Timecode::new(1, 23, 45, 12).with_drop_frame(true);
+        assert_eq!(tc.to_smpte_12m(ntsc), 0x5245_2301);
+    }
 }